@@ -0,0 +1,197 @@
+/// Hands out ranges of one `VkDeviceMemory` block. Offsets and sizes are in bytes.
+#[derive(Debug)]
+pub enum SubAllocator {
+    Buddy(BuddyAllocator),
+    Linear(LinearAllocator),
+}
+
+impl SubAllocator {
+    /// Returns the offset of the allocation and the value `free` needs to release it.
+    pub fn allocate(&mut self, size: u64, alignment: u64) -> Option<(u64, u32)> {
+        match self {
+            SubAllocator::Buddy(buddy) => buddy.allocate(size, alignment),
+            SubAllocator::Linear(linear) => linear.allocate(size, alignment).map(|offset| (offset, 0)),
+        }
+    }
+
+    pub fn free(&mut self, offset: u64, order: u32) {
+        match self {
+            SubAllocator::Buddy(buddy) => buddy.free(offset, order),
+            SubAllocator::Linear(linear) => linear.free(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            SubAllocator::Buddy(buddy) => buddy.used == 0,
+            SubAllocator::Linear(linear) => linear.live == 0,
+        }
+    }
+}
+
+/// Power-of-two buddy allocator. Every range is aligned to its own size, so any power-of-two alignment up to the
+/// rounded-up request size comes for free. Good for long-lived resources of mixed sizes.
+#[derive(Debug)]
+pub struct BuddyAllocator {
+    min_size: u64,
+    // Free offsets per order, where order `n` is `min_size << n` bytes.
+    free: Vec<Vec<u64>>,
+    used: u64,
+}
+
+impl BuddyAllocator {
+    /// `size` and `min_size` must be powers of two.
+    pub fn new(size: u64, min_size: u64) -> BuddyAllocator {
+        debug_assert!(size.is_power_of_two() && min_size.is_power_of_two() && min_size <= size);
+
+        let orders = (size / min_size).trailing_zeros() as usize + 1;
+        let mut free = vec![vec![]; orders];
+        free[orders - 1].push(0);
+
+        BuddyAllocator { min_size, free, used: 0 }
+    }
+
+    pub fn allocate(&mut self, size: u64, alignment: u64) -> Option<(u64, u32)> {
+        let needed = size.max(alignment).max(self.min_size).next_power_of_two();
+        let order = (needed / self.min_size).trailing_zeros() as usize;
+        if order >= self.free.len() {
+            return None;
+        }
+
+        let available = (order..self.free.len()).find(|&o| !self.free[o].is_empty())?;
+        let offset = self.free[available].pop().unwrap();
+
+        // Split the found range down, keeping the lower half and freeing the upper one each time.
+        for o in (order..available).rev() {
+            self.free[o].push(offset + (self.min_size << o));
+        }
+
+        self.used += needed;
+        Some((offset, order as u32))
+    }
+
+    pub fn free(&mut self, mut offset: u64, order: u32) {
+        let mut order = order as usize;
+        self.used -= self.min_size << order;
+
+        while order + 1 < self.free.len() {
+            let buddy = offset ^ (self.min_size << order);
+            match self.free[order].iter().position(|&o| o == buddy) {
+                Some(index) => {
+                    self.free[order].swap_remove(index);
+                    offset = offset.min(buddy);
+                    order += 1;
+                }
+                None => break,
+            }
+        }
+
+        self.free[order].push(offset);
+    }
+}
+
+/// Bump allocator that starts over once everything in it has been freed. Meant for short-lived data such as
+/// per-frame uniforms, where allocations die together.
+#[derive(Debug)]
+pub struct LinearAllocator {
+    size: u64,
+    head: u64,
+    live: usize,
+}
+
+impl LinearAllocator {
+    pub fn new(size: u64) -> LinearAllocator {
+        LinearAllocator { size, head: 0, live: 0 }
+    }
+
+    pub fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        let offset = self.head.div_ceil(alignment) * alignment;
+        if offset + size > self.size {
+            return None;
+        }
+
+        self.head = offset + size;
+        self.live += 1;
+        Some(offset)
+    }
+
+    pub fn free(&mut self) {
+        self.live -= 1;
+        if self.live == 0 {
+            self.head = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buddies_coalesce() {
+        let mut buddy = BuddyAllocator::new(1024, 64);
+        let allocations: Vec<(u64, u32)> = (0..16).map(|_| buddy.allocate(64, 1).unwrap()).collect();
+        assert!(buddy.allocate(64, 1).is_none());
+
+        let mut offsets: Vec<u64> = allocations.iter().map(|&(offset, _)| offset).collect();
+        offsets.sort_unstable();
+        assert_eq!(offsets, (0..16).map(|i| i * 64).collect::<Vec<_>>());
+
+        // Free every other one first so merges only happen in the second round.
+        for &(offset, order) in allocations.iter().step_by(2).chain(allocations.iter().skip(1).step_by(2)) {
+            buddy.free(offset, order);
+        }
+        assert_eq!(buddy.used, 0);
+        assert!(buddy.free[..buddy.free.len() - 1].iter().all(Vec::is_empty));
+        assert_eq!(buddy.free[buddy.free.len() - 1], vec![0]);
+        assert_eq!(buddy.allocate(1024, 1), Some((0, 4)));
+    }
+
+    #[test]
+    fn buddy_rounds_up() {
+        let mut buddy = BuddyAllocator::new(1024, 64);
+        // 100 bytes take a 128 byte range, and an alignment larger than the size takes a range that large.
+        assert_eq!(buddy.allocate(100, 1), Some((0, 1)));
+        let (offset, order) = buddy.allocate(64, 256).unwrap();
+        assert_eq!(offset % 256, 0);
+        assert_eq!(order, 2);
+        assert_eq!(buddy.used, 128 + 256);
+    }
+
+    #[test]
+    fn buddy_exhaustion() {
+        let mut buddy = BuddyAllocator::new(1024, 64);
+        assert!(buddy.allocate(2048, 1).is_none());
+        assert_eq!(buddy.allocate(512, 1), Some((0, 3)));
+        assert_eq!(buddy.allocate(512, 1), Some((512, 3)));
+        assert!(buddy.allocate(64, 1).is_none());
+        assert_eq!(buddy.used, 1024);
+    }
+
+    #[test]
+    fn linear_aligns_and_resets() {
+        let mut linear = LinearAllocator::new(1024);
+        assert_eq!(linear.allocate(10, 4), Some(0));
+        assert_eq!(linear.allocate(10, 256), Some(256));
+        assert_eq!(linear.allocate(1, 1), Some(266));
+
+        // Space is only reclaimed once everything is freed.
+        linear.free();
+        linear.free();
+        assert_eq!(linear.allocate(1024, 1), None);
+        linear.free();
+        assert_eq!(linear.allocate(1024, 1), Some(0));
+    }
+
+    #[test]
+    fn linear_exhaustion() {
+        let mut linear = SubAllocator::Linear(LinearAllocator::new(512));
+        assert_eq!(linear.allocate(256, 256), Some((0, 0)));
+        assert_eq!(linear.allocate(1, 256), Some((256, 0)));
+        assert_eq!(linear.allocate(1, 256), None);
+        assert!(!linear.is_empty());
+        linear.free(0, 0);
+        linear.free(256, 0);
+        assert!(linear.is_empty());
+    }
+}
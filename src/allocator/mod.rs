@@ -0,0 +1,493 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CString;
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tracing::{debug, warn};
+use vulkano::device::{Device, DeviceExtensions, DeviceOwned, RawDeviceExtensions};
+use vulkano::instance::{InstanceExtensions, MemoryType, PhysicalDevice};
+use vulkano::memory::pool::{AllocLayout, MappingRequirement, MemoryPool, MemoryPoolAlloc};
+use vulkano::memory::{DeviceMemory, DeviceMemoryAllocError, MappedDeviceMemory};
+use vulkano::VulkanObject;
+
+use crate::allocator::block::{BuddyAllocator, LinearAllocator, SubAllocator};
+use crate::logging;
+
+pub use crate::allocator::resources::{PooledBuffer, PooledImage};
+
+mod block;
+mod resources;
+
+const MEMORY_BUDGET_EXTENSION: &str = "VK_EXT_memory_budget";
+
+// Preferred block sizes. Both are capped at an eighth of the heap so small heaps (like the 256 MiB
+// device-local host-visible heap on many discrete cards) aren't taken over by a handful of blocks.
+const DEVICE_LOCAL_BLOCK_SIZE: u64 = 64 << 20;
+const HOST_BLOCK_SIZE: u64 = 16 << 20;
+const MIN_BLOCK_SIZE: u64 = 1 << 20;
+const MIN_ALLOCATION_SIZE: u64 = 256;
+
+/// What an allocation is used for. Categories with the same `strategy` share blocks.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryCategory {
+    Mesh,
+    Texture,
+    RenderTarget,
+    Uniform,
+    Other,
+}
+
+impl MemoryCategory {
+    /// Strategy resources of this category are allocated with. Uniforms are short-lived and freed together,
+    /// everything else lives for as long as the asset or swapchain that owns it. Staging data goes through
+    /// `UploadManager`'s own ring instead.
+    pub fn strategy(self) -> Strategy {
+        match self {
+            MemoryCategory::Uniform => Strategy::Linear,
+            _ => Strategy::Buddy,
+        }
+    }
+}
+
+/// How a block is carved up. See `BuddyAllocator` and `LinearAllocator`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Strategy {
+    Buddy,
+    Linear,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CategoryStats {
+    pub allocations: usize,
+    pub bytes: u64,
+}
+
+/// `reserved` is what the allocator holds in blocks. `budget` and `usage` come from VK_EXT_memory_budget and cover
+/// the whole process, including memory vulkano allocates on its own.
+#[derive(Debug, Clone, Serialize)]
+pub struct HeapStats {
+    pub heap: u32,
+    pub size: u64,
+    pub reserved: u64,
+    pub budget: Option<u64>,
+    pub usage: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryStats {
+    pub categories: BTreeMap<MemoryCategory, CategoryStats>,
+    pub blocks: usize,
+    pub reserved: u64,
+    pub heaps: Vec<HeapStats>,
+}
+
+/// Engine-wide GPU memory allocator.
+///
+/// Memory is allocated from the driver in large blocks per memory type and handed out in pieces, which keeps us far
+/// away from `max_memory_allocation_count` and makes every byte attributable to a `MemoryCategory`. Requests larger
+/// than half a block get a block of their own that is released as soon as they are freed. Cloning is cheap and
+/// clones share the same blocks.
+#[derive(Clone)]
+pub struct GpuAllocator {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    device: Arc<Device>,
+    memory_budget: bool,
+    state: Mutex<State>,
+}
+
+struct State {
+    pools: HashMap<PoolKey, Vec<Block>>,
+    categories: BTreeMap<MemoryCategory, CategoryStats>,
+    reserved: Vec<u64>,
+    next_block: u64,
+}
+
+// Linear and optimal resources never share a block, which sidesteps `buffer_image_granularity` entirely.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct PoolKey {
+    memory_type: u32,
+    layout: AllocLayout,
+    mapped: bool,
+    strategy: Strategy,
+}
+
+struct Block {
+    id: u64,
+    memory: Arc<BlockMemory>,
+    allocator: SubAllocator,
+    size: u64,
+    heap: u32,
+    standalone: bool,
+}
+
+enum BlockMemory {
+    Unmapped(DeviceMemory),
+    Mapped(MappedDeviceMemory),
+}
+
+impl BlockMemory {
+    fn memory(&self) -> &DeviceMemory {
+        match self {
+            BlockMemory::Unmapped(memory) => memory,
+            BlockMemory::Mapped(memory) => memory.as_ref(),
+        }
+    }
+}
+
+/// Layout of `VkPhysicalDeviceMemoryBudgetPropertiesEXT`, which vk-sys doesn't know about.
+#[repr(C)]
+struct MemoryBudgetProperties {
+    s_type: vk_sys::StructureType,
+    p_next: *mut c_void,
+    heap_budget: [vk_sys::DeviceSize; vk_sys::MAX_MEMORY_HEAPS as usize],
+    heap_usage: [vk_sys::DeviceSize; vk_sys::MAX_MEMORY_HEAPS as usize],
+}
+
+/// Instance extensions the allocator wants on top of `extensions`. Querying the memory budget goes through
+/// `vkGetPhysicalDeviceMemoryProperties2KHR`.
+pub fn instance_extensions(extensions: &InstanceExtensions) -> InstanceExtensions {
+    let supported = InstanceExtensions::supported_by_core().unwrap_or_else(|_| InstanceExtensions::none());
+
+    InstanceExtensions {
+        khr_get_physical_device_properties2: supported.khr_get_physical_device_properties2,
+        ..*extensions
+    }
+}
+
+/// Whether `physical` can report its memory budget, given an instance created with `instance_extensions`.
+pub fn supports_memory_budget(physical: PhysicalDevice) -> bool {
+    physical.instance().loaded_extensions().khr_get_physical_device_properties2
+    && RawDeviceExtensions::supported_by_device(physical).iter().any(|name| name.as_bytes() == MEMORY_BUDGET_EXTENSION.as_bytes())
+}
+
+/// `extensions` plus VK_EXT_memory_budget if `memory_budget` is set. vulkano's `DeviceExtensions` has no field for it.
+pub fn device_extensions(extensions: &DeviceExtensions, memory_budget: bool) -> RawDeviceExtensions {
+    let mut raw = RawDeviceExtensions::from(extensions);
+    if memory_budget {
+        raw.insert(CString::new(MEMORY_BUDGET_EXTENSION).unwrap());
+    }
+    raw
+}
+
+impl GpuAllocator {
+    /// `memory_budget` must only be set if the device was created with VK_EXT_memory_budget enabled.
+    pub fn new(device: Arc<Device>, memory_budget: bool) -> GpuAllocator {
+        let heaps = device.physical_device().memory_heaps().len();
+
+        GpuAllocator {
+            inner: Arc::new(Inner {
+                device,
+                memory_budget,
+                state: Mutex::new(State {
+                    pools: HashMap::new(),
+                    categories: BTreeMap::new(),
+                    reserved: vec![0; heaps],
+                    next_block: 0,
+                }),
+            }),
+        }
+    }
+
+    /// A `MemoryPool` for vulkano that attributes everything allocated through it to `category`.
+    pub fn pool(&self, category: MemoryCategory, strategy: Strategy) -> CategoryPool {
+        CategoryPool {
+            inner: self.inner.clone(),
+            category,
+            strategy,
+        }
+    }
+
+    pub fn stats(&self) -> MemoryStats {
+        let budgets = self.inner.heap_budgets();
+        let state = self.inner.state.lock().unwrap();
+
+        MemoryStats {
+            categories: state.categories.clone(),
+            blocks: state.pools.values().map(Vec::len).sum(),
+            reserved: state.reserved.iter().sum(),
+            heaps: self.inner
+                       .device
+                       .physical_device()
+                       .memory_heaps()
+                       .map(|heap| {
+                           let budget = budgets.as_ref().map(|b| b[heap.id() as usize]);
+                           HeapStats {
+                               heap: heap.id(),
+                               size: heap.size() as u64,
+                               reserved: state.reserved[heap.id() as usize],
+                               budget: budget.map(|(budget, _)| budget),
+                               usage: budget.map(|(_, usage)| usage),
+                           }
+                       })
+                       .collect(),
+        }
+    }
+}
+
+impl Inner {
+    #[allow(clippy::too_many_arguments)]
+    fn allocate(self: &Arc<Inner>,
+                category: MemoryCategory,
+                strategy: Strategy,
+                ty: MemoryType,
+                size: usize,
+                alignment: usize,
+                layout: AllocLayout,
+                map: MappingRequirement,
+                exportable: bool)
+                -> Result<Allocation, DeviceMemoryAllocError> {
+        assert!(size != 0 && alignment != 0);
+        assert!(map == MappingRequirement::DoNotMap || ty.is_host_visible());
+
+        let (size, alignment) = (size as u64, alignment as u64);
+        let key = PoolKey {
+            memory_type: ty.id(),
+            layout,
+            mapped: map == MappingRequirement::Map,
+            strategy,
+        };
+        let block_size = self.block_size(ty);
+        let standalone = exportable || size > block_size / 2;
+
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let blocks = state.pools.entry(key).or_default();
+
+        if !standalone {
+            for block in blocks.iter_mut().filter(|block| !block.standalone) {
+                if let Some((offset, order)) = block.allocator.allocate(size, alignment) {
+                    return Ok(self.record(&mut state.categories, key, block, offset, order, size, category));
+                }
+            }
+        }
+
+        let new_size = if standalone {
+            self.fit_to_budget(ty, size, size)
+        } else {
+            self.fit_to_budget(ty, block_size, size.next_power_of_two().max(MIN_BLOCK_SIZE))
+        };
+        let memory = self.allocate_memory(ty, new_size, map, exportable)?;
+        let allocator = match strategy {
+            Strategy::Buddy if !standalone => SubAllocator::Buddy(BuddyAllocator::new(new_size, MIN_ALLOCATION_SIZE)),
+            _ => SubAllocator::Linear(LinearAllocator::new(new_size)),
+        };
+
+        debug!(target: logging::MEMORY,
+               memory_type = ty.id(),
+               heap = ty.heap().id(),
+               size = new_size,
+               ?strategy,
+               standalone,
+               "allocated memory block");
+
+        state.reserved[ty.heap().id() as usize] += new_size;
+        state.next_block += 1;
+        blocks.push(Block {
+            id: state.next_block,
+            memory: Arc::new(memory),
+            allocator,
+            size: new_size,
+            heap: ty.heap().id(),
+            standalone,
+        });
+
+        let block = blocks.last_mut().unwrap();
+        let (offset, order) = block.allocator.allocate(size, alignment).expect("new memory block can't fit the allocation it was made for");
+        Ok(self.record(&mut state.categories, key, block, offset, order, size, category))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn record(self: &Arc<Inner>,
+              categories: &mut BTreeMap<MemoryCategory, CategoryStats>,
+              key: PoolKey,
+              block: &Block,
+              offset: u64,
+              order: u32,
+              size: u64,
+              category: MemoryCategory)
+              -> Allocation {
+        let stats = categories.entry(category).or_default();
+        stats.allocations += 1;
+        stats.bytes += size;
+
+        Allocation {
+            inner: self.clone(),
+            memory: block.memory.clone(),
+            key,
+            block: block.id,
+            offset,
+            order,
+            size,
+            category,
+        }
+    }
+
+    fn free(&self, allocation: &Allocation) {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+
+        let stats = state.categories.get_mut(&allocation.category).unwrap();
+        stats.allocations -= 1;
+        stats.bytes -= allocation.size;
+
+        let blocks = state.pools.get_mut(&allocation.key).unwrap();
+        let index = blocks.iter().position(|block| block.id == allocation.block).unwrap();
+        blocks[index].allocator.free(allocation.offset, allocation.order);
+
+        // Keep one empty block around per pool so a resource that is recreated every so often (render targets on
+        // resize) doesn't allocate and free a whole block each time.
+        let empty = blocks.iter().filter(|block| block.allocator.is_empty()).count();
+        if blocks[index].allocator.is_empty() && (blocks[index].standalone || empty > 1) {
+            let block = blocks.swap_remove(index);
+            state.reserved[block.heap as usize] -= block.size;
+            debug!(target: logging::MEMORY, heap = block.heap, size = block.size, "released memory block");
+        }
+    }
+
+    fn block_size(&self, ty: MemoryType) -> u64 {
+        let heap = ty.heap();
+        let preferred = if heap.is_device_local() { DEVICE_LOCAL_BLOCK_SIZE } else { HOST_BLOCK_SIZE };
+        let eighth = heap.size() as u64 / 8;
+        let cap = if eighth == 0 { MIN_BLOCK_SIZE } else { 1 << (63 - eighth.leading_zeros()) };
+        preferred.min(cap.max(MIN_BLOCK_SIZE))
+    }
+
+    /// Halves `preferred` down to `minimum` until it fits in what's left of the heap's budget.
+    fn fit_to_budget(&self, ty: MemoryType, preferred: u64, minimum: u64) -> u64 {
+        let heap = ty.heap().id();
+        let (budget, usage) = match self.heap_budgets() {
+            Some(budgets) => budgets[heap as usize],
+            None => return preferred,
+        };
+
+        let mut size = preferred;
+        while size > minimum && usage + size > budget {
+            size /= 2;
+        }
+
+        if usage + size > budget {
+            warn!(target: logging::MEMORY, heap, budget, usage, size, "allocating past the memory budget");
+        } else if size < preferred {
+            debug!(target: logging::MEMORY, heap, budget, usage, size, preferred, "shrinking memory block to stay within budget");
+        }
+        size
+    }
+
+    fn allocate_memory(&self, ty: MemoryType, size: u64, map: MappingRequirement, exportable: bool) -> Result<BlockMemory, DeviceMemoryAllocError> {
+        let device = self.device.clone();
+        let size = size as usize;
+
+        #[cfg(target_os = "linux")]
+        {
+            if exportable {
+                return Ok(match map {
+                    MappingRequirement::Map => BlockMemory::Mapped(DeviceMemory::alloc_and_map_with_exportable_fd(device, ty, size)?),
+                    MappingRequirement::DoNotMap => BlockMemory::Unmapped(DeviceMemory::alloc_with_exportable_fd(device, ty, size)?),
+                });
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = exportable;
+
+        Ok(match map {
+            MappingRequirement::Map => BlockMemory::Mapped(DeviceMemory::alloc_and_map(device, ty, size)?),
+            MappingRequirement::DoNotMap => BlockMemory::Unmapped(DeviceMemory::alloc(device, ty, size)?),
+        })
+    }
+
+    /// `(budget, usage)` per heap, or `None` without VK_EXT_memory_budget.
+    fn heap_budgets(&self) -> Option<Vec<(u64, u64)>> {
+        if !self.memory_budget {
+            return None;
+        }
+
+        let physical = self.device.physical_device();
+        let mut budget = MemoryBudgetProperties {
+            s_type: vk_sys::STRUCTURE_TYPE_PHYSICAL_DEVICE_MEMORY_BUDGET_PROPERTIES_EXT,
+            p_next: ptr::null_mut(),
+            heap_budget: [0; vk_sys::MAX_MEMORY_HEAPS as usize],
+            heap_usage: [0; vk_sys::MAX_MEMORY_HEAPS as usize],
+        };
+
+        unsafe {
+            let mut properties = vk_sys::PhysicalDeviceMemoryProperties2KHR {
+                sType: vk_sys::STRUCTURE_TYPE_PHYSICAL_DEVICE_MEMORY_PROPERTIES_2_KHR,
+                pNext: &mut budget as *mut MemoryBudgetProperties as *const c_void,
+                memoryProperties: mem::zeroed(),
+            };
+            physical.instance().pointers().GetPhysicalDeviceMemoryProperties2KHR(physical.internal_object(), &mut properties);
+        }
+
+        Some(physical.memory_heaps().map(|heap| (budget.heap_budget[heap.id() as usize], budget.heap_usage[heap.id() as usize])).collect())
+    }
+}
+
+/// A piece of a block. Returned to its block when dropped.
+pub struct Allocation {
+    inner: Arc<Inner>,
+    memory: Arc<BlockMemory>,
+    key: PoolKey,
+    block: u64,
+    offset: u64,
+    order: u32,
+    size: u64,
+    category: MemoryCategory,
+}
+
+unsafe impl MemoryPoolAlloc for Allocation {
+    fn mapped_memory(&self) -> Option<&MappedDeviceMemory> {
+        match &*self.memory {
+            BlockMemory::Mapped(memory) => Some(memory),
+            BlockMemory::Unmapped(_) => None,
+        }
+    }
+
+    fn memory(&self) -> &DeviceMemory {
+        self.memory.memory()
+    }
+
+    fn offset(&self) -> usize {
+        self.offset as usize
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        self.inner.free(self);
+    }
+}
+
+/// `GpuAllocator` seen through vulkano's `MemoryPool` trait, for one category and strategy.
+#[derive(Clone)]
+pub struct CategoryPool {
+    inner: Arc<Inner>,
+    category: MemoryCategory,
+    strategy: Strategy,
+}
+
+unsafe impl DeviceOwned for CategoryPool {
+    fn device(&self) -> &Arc<Device> {
+        &self.inner.device
+    }
+}
+
+unsafe impl MemoryPool for CategoryPool {
+    type Alloc = Allocation;
+
+    fn alloc_generic(&self, ty: MemoryType, size: usize, alignment: usize, layout: AllocLayout, map: MappingRequirement) -> Result<Allocation, DeviceMemoryAllocError> {
+        self.inner.allocate(self.category, self.strategy, ty, size, alignment, layout, map, false)
+    }
+
+    /// Exportable memory can't be shared with anything else, so it always gets a block of its own.
+    #[cfg(target_os = "linux")]
+    fn alloc_generic_with_exportable_fd(&self, ty: MemoryType, size: usize, alignment: usize, layout: AllocLayout, map: MappingRequirement) -> Result<Allocation, DeviceMemoryAllocError> {
+        self.inner.allocate(self.category, self.strategy, ty, size, alignment, layout, map, true)
+    }
+}
@@ -0,0 +1,430 @@
+use std::hash::{Hash, Hasher};
+use std::iter::Empty;
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use vulkano::buffer::sys::{BufferCreationError, UnsafeBuffer};
+use vulkano::buffer::{BufferAccess, BufferInner, BufferUsage, TypedBufferAccess};
+use vulkano::device::{Device, DeviceOwned, Queue};
+use vulkano::format::{Format, FormatTy};
+use vulkano::image::sys::UnsafeImage;
+use vulkano::image::{ImageAccess, ImageCreateFlags, ImageCreationError, ImageDescriptorLayouts, ImageDimensions, ImageInner, ImageLayout, ImageUsage};
+use vulkano::instance::QueueFamily;
+use vulkano::memory::pool::{AllocFromRequirementsFilter, AllocLayout, MappingRequirement, MemoryPool, MemoryPoolAlloc, PotentialDedicatedAllocation};
+use vulkano::memory::{Content, CpuAccess, DedicatedAlloc, DeviceMemoryAllocError, MemoryRequirements};
+use vulkano::sync::{AccessError, Sharing};
+
+use crate::allocator::{Allocation, GpuAllocator, MemoryCategory};
+
+/// Buffer whose memory comes from a `GpuAllocator`. Locks the whole buffer on the GPU like vulkano's `DeviceLocalBuffer`,
+/// which it stands in for, or `CpuAccessibleBuffer` when created from data on the host.
+pub struct PooledBuffer<T: ?Sized> {
+    inner: UnsafeBuffer,
+    memory: PotentialDedicatedAllocation<Allocation>,
+    gpu_lock: Mutex<GpuAccess>,
+    marker: PhantomData<Box<T>>,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum GpuAccess {
+    None,
+    NonExclusive { num: u32 },
+    Exclusive { num: u32 },
+}
+
+impl<T> PooledBuffer<T> where T: Copy + 'static
+{
+    /// A host-visible buffer holding `data`, for data the CPU writes anew every frame. Only used on the graphics queue.
+    pub fn from_data(allocator: &GpuAllocator, category: MemoryCategory, usage: BufferUsage, data: T) -> Result<Arc<PooledBuffer<T>>, DeviceMemoryAllocError> {
+        unsafe {
+            let buffer = PooledBuffer::host(allocator, category, mem::size_of::<T>(), usage)?;
+            *buffer.write::<T>() = data;
+            Ok(buffer)
+        }
+    }
+}
+
+impl<T> PooledBuffer<[T]> {
+    pub fn array<'a, I>(allocator: &GpuAllocator, category: MemoryCategory, len: usize, usage: BufferUsage, queue_families: I) -> Result<Arc<PooledBuffer<[T]>>, DeviceMemoryAllocError>
+        where I: IntoIterator<Item = QueueFamily<'a>>
+    {
+        unsafe { PooledBuffer::raw(allocator, category, len * mem::size_of::<T>(), usage, queue_families) }
+    }
+
+    /// A host-visible buffer holding `data`, like `from_data`.
+    pub fn from_iter<I>(allocator: &GpuAllocator, category: MemoryCategory, usage: BufferUsage, data: I) -> Result<Arc<PooledBuffer<[T]>>, DeviceMemoryAllocError>
+        where I: ExactSizeIterator<Item = T>,
+              T: Copy + 'static
+    {
+        unsafe {
+            let buffer = PooledBuffer::host(allocator, category, data.len() * mem::size_of::<T>(), usage)?;
+            for (slot, value) in buffer.write::<[T]>().iter_mut().zip(data) {
+                *slot = value;
+            }
+            Ok(buffer)
+        }
+    }
+}
+
+impl<T: ?Sized> PooledBuffer<T> {
+    /// # Safety
+    ///
+    /// `size` must be correct for `T`.
+    pub unsafe fn raw<'a, I>(allocator: &GpuAllocator, category: MemoryCategory, size: usize, usage: BufferUsage, queue_families: I) -> Result<Arc<PooledBuffer<T>>, DeviceMemoryAllocError>
+        where I: IntoIterator<Item = QueueFamily<'a>>
+    {
+        let device = allocator.inner.device.clone();
+        let queue_families: Vec<u32> = queue_families.into_iter().map(|family| family.id()).collect();
+        let sharing = if queue_families.len() >= 2 { Sharing::Concurrent(queue_families.iter().cloned()) } else { Sharing::Exclusive };
+
+        let (buffer, requirements) = match UnsafeBuffer::new(device, size, usage, sharing, None) {
+            Ok(buffer) => buffer,
+            Err(BufferCreationError::AllocError(e)) => return Err(e),
+            // We don't use sparse binding, so nothing else can go wrong.
+            Err(_) => unreachable!(),
+        };
+
+        let memory = allocate(allocator, category, &requirements, AllocLayout::Linear)?;
+        buffer.bind_memory(memory.memory(), memory.offset())?;
+
+        Ok(Arc::new(PooledBuffer {
+            inner: buffer,
+            memory,
+            gpu_lock: Mutex::new(GpuAccess::None),
+            marker: PhantomData,
+        }))
+    }
+
+    unsafe fn host(allocator: &GpuAllocator, category: MemoryCategory, size: usize, usage: BufferUsage) -> Result<Arc<PooledBuffer<T>>, DeviceMemoryAllocError> {
+        let (buffer, requirements) = match UnsafeBuffer::new(allocator.inner.device.clone(), size, usage, Sharing::Exclusive::<Empty<u32>>, None) {
+            Ok(buffer) => buffer,
+            Err(BufferCreationError::AllocError(e)) => return Err(e),
+            Err(_) => unreachable!(),
+        };
+
+        // Coherent memory only, so writes need no flushing.
+        let memory = allocator.pool(category, category.strategy()).alloc_from_requirements(&requirements, AllocLayout::Linear, MappingRequirement::Map, DedicatedAlloc::None, |ty| {
+                                                                          if !ty.is_host_coherent() {
+                                                                              AllocFromRequirementsFilter::Forbidden
+                                                                          } else if ty.is_device_local() {
+                                                                              AllocFromRequirementsFilter::Allowed
+                                                                          } else {
+                                                                              AllocFromRequirementsFilter::Preferred
+                                                                          }
+                                                                      })?;
+        buffer.bind_memory(memory.memory(), memory.offset())?;
+
+        Ok(Arc::new(PooledBuffer {
+            inner: buffer,
+            memory,
+            gpu_lock: Mutex::new(GpuAccess::None),
+            marker: PhantomData,
+        }))
+    }
+
+    /// The contents of a buffer made by `host`, which the GPU must not be using.
+    unsafe fn write<C: ?Sized + Content>(&self) -> CpuAccess<'_, C> {
+        let offset = self.memory.offset();
+        self.memory.mapped_memory().unwrap().read_write(offset..offset + self.inner.size())
+    }
+
+}
+
+unsafe impl<T: ?Sized> DeviceOwned for PooledBuffer<T> {
+    fn device(&self) -> &Arc<Device> {
+        self.inner.device()
+    }
+}
+
+unsafe impl<T: ?Sized> BufferAccess for PooledBuffer<T> where T: 'static + Send + Sync
+{
+    fn inner(&self) -> BufferInner<'_> {
+        BufferInner { buffer: &self.inner, offset: 0 }
+    }
+
+    fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn conflicts_buffer(&self, other: &dyn BufferAccess) -> bool {
+        self.conflict_key() == other.conflict_key()
+    }
+
+    fn conflicts_image(&self, _: &dyn ImageAccess) -> bool {
+        false
+    }
+
+    fn conflict_key(&self) -> (u64, usize) {
+        (self.inner.key(), 0)
+    }
+
+    fn try_gpu_lock(&self, exclusive: bool, _: &Queue) -> Result<(), AccessError> {
+        let mut lock = self.gpu_lock.lock().unwrap();
+        match *lock {
+            GpuAccess::None => {
+                *lock = if exclusive { GpuAccess::Exclusive { num: 1 } } else { GpuAccess::NonExclusive { num: 1 } };
+                Ok(())
+            }
+            GpuAccess::NonExclusive { ref mut num } if !exclusive => {
+                *num += 1;
+                Ok(())
+            }
+            _ => Err(AccessError::AlreadyInUse),
+        }
+    }
+
+    unsafe fn increase_gpu_lock(&self) {
+        match *self.gpu_lock.lock().unwrap() {
+            GpuAccess::None => panic!("tried to increase the lock of a buffer that isn't locked"),
+            GpuAccess::NonExclusive { ref mut num } | GpuAccess::Exclusive { ref mut num } => *num += 1,
+        }
+    }
+
+    unsafe fn unlock(&self) {
+        let mut lock = self.gpu_lock.lock().unwrap();
+        let remaining = match *lock {
+            GpuAccess::None => panic!("tried to unlock a buffer that isn't locked"),
+            GpuAccess::NonExclusive { ref mut num } | GpuAccess::Exclusive { ref mut num } => {
+                *num -= 1;
+                *num
+            }
+        };
+
+        if remaining == 0 {
+            *lock = GpuAccess::None;
+        }
+    }
+}
+
+unsafe impl<T: ?Sized> TypedBufferAccess for PooledBuffer<T> where T: 'static + Send + Sync
+{
+    type Content = T;
+}
+
+/// Image whose memory comes from a `GpuAllocator`.
+///
+/// Like vulkano's `AttachmentImage` it has one steady layout that it is transitioned back to after every command
+/// buffer, but it supports mipmaps, array layers and any usage, so it covers both render targets and textures.
+pub struct PooledImage {
+    image: UnsafeImage,
+    // Returned to its block when the image is dropped.
+    _memory: PotentialDedicatedAllocation<Allocation>,
+    layout: ImageLayout,
+    // False until the first command buffer that uses the image moved it out of `Undefined`.
+    initialized: AtomicBool,
+    gpu_lock: AtomicUsize,
+}
+
+impl PooledImage {
+    /// A sampled image, filled with transfers and read in `ShaderReadOnlyOptimal`.
+    pub fn texture<'a, I>(allocator: &GpuAllocator,
+                          dimensions: ImageDimensions,
+                          format: Format,
+                          mip_levels: u32,
+                          flags: ImageCreateFlags,
+                          queue_families: I)
+                          -> Result<Arc<PooledImage>, ImageCreationError>
+        where I: IntoIterator<Item = QueueFamily<'a>>
+    {
+        let usage = ImageUsage {
+            transfer_source: true,
+            transfer_destination: true,
+            sampled: true,
+            ..ImageUsage::none()
+        };
+
+        let queue_families: Vec<u32> = queue_families.into_iter().map(|family| family.id()).collect();
+        let sharing = if queue_families.len() >= 2 { Sharing::Concurrent(queue_families.iter().cloned()) } else { Sharing::Exclusive };
+
+        let image = unsafe { UnsafeImage::new(allocator.inner.device.clone(), usage, format, flags, dimensions, 1, mip_levels, sharing, false, false)? };
+        unsafe { PooledImage::bind(allocator, MemoryCategory::Texture, image, ImageLayout::ShaderReadOnlyOptimal) }
+    }
+
+    /// A sampled image written by compute shaders, read in `ShaderReadOnlyOptimal`. Only used on the graphics queue.
+    pub fn storage(allocator: &GpuAllocator, dimensions: ImageDimensions, format: Format, mip_levels: u32, flags: ImageCreateFlags) -> Result<Arc<PooledImage>, ImageCreationError> {
+        let usage = ImageUsage {
+            storage: true,
+            sampled: true,
+            ..ImageUsage::none()
+        };
+
+        let image = unsafe { UnsafeImage::new(allocator.inner.device.clone(), usage, format, flags, dimensions, 1, mip_levels, Sharing::Exclusive::<Empty<u32>>, false, false)? };
+        unsafe { PooledImage::bind(allocator, MemoryCategory::Texture, image, ImageLayout::ShaderReadOnlyOptimal) }
+    }
+
+    /// A color or depth attachment with `layers` array layers, depending on `format`, that is also usable with `usage`.
+    pub fn render_target(allocator: &GpuAllocator, dimensions: [u32; 2], layers: u32, format: Format, samples: u32, usage: ImageUsage) -> Result<Arc<PooledImage>, ImageCreationError> {
+        let is_depth = match format.ty() {
+            FormatTy::Depth | FormatTy::DepthStencil | FormatTy::Stencil => true,
+            FormatTy::Compressed => panic!("compressed formats can't be rendered to"),
+            _ => false,
+        };
+
+        let usage = ImageUsage {
+            color_attachment: !is_depth,
+            depth_stencil_attachment: is_depth,
+            ..usage
+        };
+        let dimensions = ImageDimensions::Dim2d {
+            width: dimensions[0],
+            height: dimensions[1],
+            array_layers: layers,
+        };
+        let layout = if is_depth { ImageLayout::DepthStencilAttachmentOptimal } else { ImageLayout::ColorAttachmentOptimal };
+
+        let image = unsafe {
+            UnsafeImage::new(allocator.inner.device.clone(),
+                             usage,
+                             format,
+                             ImageCreateFlags::none(),
+                             dimensions,
+                             samples,
+                             1,
+                             Sharing::Exclusive::<Empty<u32>>,
+                             false,
+                             false)?
+        };
+        unsafe { PooledImage::bind(allocator, MemoryCategory::RenderTarget, image, layout) }
+    }
+
+    unsafe fn bind(allocator: &GpuAllocator, category: MemoryCategory, (image, requirements): (UnsafeImage, MemoryRequirements), layout: ImageLayout) -> Result<Arc<PooledImage>, ImageCreationError> {
+        let memory = allocate(allocator, category, &requirements, AllocLayout::Optimal)?;
+        image.bind_memory(memory.memory(), memory.offset())?;
+
+        Ok(Arc::new(PooledImage {
+            image,
+            _memory: memory,
+            layout,
+            initialized: AtomicBool::new(false),
+            gpu_lock: AtomicUsize::new(0),
+        }))
+    }
+}
+
+unsafe impl DeviceOwned for PooledImage {
+    fn device(&self) -> &Arc<Device> {
+        self.image.device()
+    }
+}
+
+unsafe impl ImageAccess for PooledImage {
+    fn inner(&self) -> ImageInner<'_> {
+        ImageInner {
+            image: &self.image,
+            first_layer: 0,
+            num_layers: self.image.dimensions().array_layers() as usize,
+            first_mipmap_level: 0,
+            num_mipmap_levels: self.image.mipmap_levels() as usize,
+        }
+    }
+
+    fn initial_layout_requirement(&self) -> ImageLayout {
+        self.layout
+    }
+
+    fn final_layout_requirement(&self) -> ImageLayout {
+        self.layout
+    }
+
+    fn descriptor_layouts(&self) -> Option<ImageDescriptorLayouts> {
+        Some(ImageDescriptorLayouts {
+            storage_image: ImageLayout::General,
+            combined_image_sampler: ImageLayout::ShaderReadOnlyOptimal,
+            sampled_image: ImageLayout::ShaderReadOnlyOptimal,
+            input_attachment: ImageLayout::ShaderReadOnlyOptimal,
+        })
+    }
+
+    fn conflicts_buffer(&self, _: &dyn BufferAccess) -> bool {
+        false
+    }
+
+    fn conflicts_image(&self, other: &dyn ImageAccess) -> bool {
+        self.conflict_key() == other.conflict_key()
+    }
+
+    fn conflict_key(&self) -> u64 {
+        self.image.key()
+    }
+
+    fn try_gpu_lock(&self, _: bool, expected_layout: ImageLayout) -> Result<(), AccessError> {
+        let initialized = self.initialized.load(Ordering::SeqCst);
+
+        if expected_layout != self.layout && expected_layout != ImageLayout::Undefined {
+            return Err(AccessError::UnexpectedImageLayout {
+                requested: expected_layout,
+                allowed: if initialized { self.layout } else { ImageLayout::Undefined },
+            });
+        }
+
+        if expected_layout != ImageLayout::Undefined && !initialized {
+            return Err(AccessError::ImageNotInitialized { requested: expected_layout });
+        }
+
+        match self.gpu_lock.compare_exchange(0, 1, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(AccessError::AlreadyInUse),
+        }
+    }
+
+    unsafe fn increase_gpu_lock(&self) {
+        let previous = self.gpu_lock.fetch_add(1, Ordering::SeqCst);
+        debug_assert!(previous >= 1);
+    }
+
+    unsafe fn unlock(&self, new_layout: Option<ImageLayout>) {
+        if let Some(new_layout) = new_layout {
+            debug_assert_eq!(new_layout, self.layout);
+            self.initialized.store(true, Ordering::SeqCst);
+        }
+
+        let previous = self.gpu_lock.fetch_sub(1, Ordering::SeqCst);
+        debug_assert!(previous >= 1);
+    }
+
+    unsafe fn layout_initialized(&self) {
+        self.initialized.store(true, Ordering::SeqCst);
+    }
+
+    fn is_layout_initialized(&self) -> bool {
+        self.initialized.load(Ordering::SeqCst)
+    }
+
+    fn current_miplevels_access(&self) -> std::ops::Range<u32> {
+        0..self.mipmap_levels()
+    }
+
+    fn current_layer_levels_access(&self) -> std::ops::Range<u32> {
+        0..self.image.dimensions().array_layers()
+    }
+}
+
+impl PartialEq for PooledImage {
+    fn eq(&self, other: &Self) -> bool {
+        ImageAccess::inner(self) == ImageAccess::inner(other)
+    }
+}
+
+impl Eq for PooledImage {}
+
+impl Hash for PooledImage {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        ImageAccess::inner(self).hash(state);
+    }
+}
+
+/// Memory for a resource in the allocator's pools, preferring device-local memory types.
+fn allocate(allocator: &GpuAllocator, category: MemoryCategory, requirements: &MemoryRequirements, layout: AllocLayout) -> Result<PotentialDedicatedAllocation<Allocation>, DeviceMemoryAllocError> {
+    // Never dedicated: a dedicated allocation would bypass the pools and the statistics.
+    let memory = allocator.pool(category, category.strategy()).alloc_from_requirements(requirements, layout, MappingRequirement::DoNotMap, DedicatedAlloc::None, |ty| {
+                                                                    if ty.is_device_local() {
+                                                                        AllocFromRequirementsFilter::Preferred
+                                                                    } else {
+                                                                        AllocFromRequirementsFilter::Allowed
+                                                                    }
+                                                                })?;
+    debug_assert_eq!(memory.offset() % requirements.alignment, 0);
+    Ok(memory)
+}
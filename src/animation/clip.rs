@@ -0,0 +1,161 @@
+use std::ops::{Add, Mul};
+
+use cgmath::{InnerSpace, Quaternion, Vector3, VectorSpace};
+
+use crate::animation::skeleton::Pose;
+
+/// How values between two keyframes are found, as in glTF.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Interpolation {
+    /// The earlier keyframe's value holds until the next one.
+    Step,
+    Linear,
+    /// Hermite spline through the values, with an in and an out tangent stored around every value.
+    CubicSpline,
+}
+
+/// The values of a channel, one per keyframe, or three (in tangent, value, out tangent) for cubic splines.
+#[derive(Debug, Clone)]
+pub enum Keyframes {
+    Translation(Vec<Vector3<f32>>),
+    Rotation(Vec<Quaternion<f32>>),
+    Scale(Vec<Vector3<f32>>),
+}
+
+/// Keyframes animating one property of one joint.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub joint: usize,
+    pub interpolation: Interpolation,
+    /// Seconds from the start of the clip, increasing.
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    pub name: String,
+    /// Seconds, the time of the last keyframe.
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    pub fn new(name: &str, channels: Vec<Channel>) -> AnimationClip {
+        let duration = channels.iter().filter_map(|channel| channel.times.last().cloned()).fold(0.0, f32::max);
+        AnimationClip { name: name.to_string(), duration, channels }
+    }
+
+    /// Writes the animated properties at `time` into `pose`, leaving the joints the clip doesn't animate as they are.
+    pub fn sample(&self, time: f32, pose: &mut Pose) {
+        for channel in self.channels.iter() {
+            let joint = &mut pose.local[channel.joint];
+            match &channel.keyframes {
+                Keyframes::Translation(values) => joint.translation = sample(channel, values, time),
+                Keyframes::Rotation(values) => joint.rotation = sample(channel, values, time).normalize(),
+                Keyframes::Scale(values) => joint.scale = sample(channel, values, time),
+            }
+        }
+    }
+}
+
+trait Value: Copy + Add<Output = Self> + Mul<f32, Output = Self> {
+    fn interpolate(self, other: Self, weight: f32) -> Self;
+}
+
+impl Value for Vector3<f32> {
+    fn interpolate(self, other: Vector3<f32>, weight: f32) -> Vector3<f32> {
+        self.lerp(other, weight)
+    }
+}
+
+impl Value for Quaternion<f32> {
+    fn interpolate(self, other: Quaternion<f32>, weight: f32) -> Quaternion<f32> {
+        self.slerp(other, weight)
+    }
+}
+
+fn sample<T: Value>(channel: &Channel, values: &[T], time: f32) -> T {
+    let times = &channel.times;
+    let cubic = channel.interpolation == Interpolation::CubicSpline;
+    let value = |key: usize| if cubic { values[key * 3 + 1] } else { values[key] };
+
+    // Clamped to the first and last keyframes.
+    let next = times.iter().position(|&t| t > time).unwrap_or(times.len());
+    if next == 0 {
+        return value(0);
+    }
+    if next == times.len() {
+        return value(times.len() - 1);
+    }
+    let previous = next - 1;
+    let span = times[next] - times[previous];
+    let s = (time - times[previous]) / span;
+
+    match channel.interpolation {
+        Interpolation::Step => value(previous),
+        Interpolation::Linear => value(previous).interpolate(value(next), s),
+        Interpolation::CubicSpline => {
+            let out_tangent = values[previous * 3 + 2] * span;
+            let in_tangent = values[next * 3] * span;
+            let (s2, s3) = (s * s, s * s * s);
+            value(previous) * (2.0 * s3 - 3.0 * s2 + 1.0) + out_tangent * (s3 - 2.0 * s2 + s) + value(next) * (-2.0 * s3 + 3.0 * s2) + in_tangent * (s3 - s2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, Rotation3};
+
+    use super::*;
+    use crate::animation::skeleton::Transform;
+
+    fn translation(interpolation: Interpolation, times: &[f32], values: &[[f32; 3]], time: f32) -> [f32; 3] {
+        let keyframes = Keyframes::Translation(values.iter().map(|&value| value.into()).collect());
+        let clip = AnimationClip::new("test", vec![Channel { joint: 0, interpolation, times: times.to_vec(), keyframes }]);
+        let mut pose = Pose { local: vec![Transform::default()] };
+        clip.sample(time, &mut pose);
+        pose.local[0].translation.into()
+    }
+
+    #[test]
+    fn step() {
+        let values = [[1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [3.0, 0.0, 0.0]];
+        let sample = |time| translation(Interpolation::Step, &[1.0, 2.0, 3.0], &values, time)[0];
+        assert_eq!([sample(0.0), sample(1.0), sample(1.9), sample(2.0), sample(2.5), sample(4.0)], [1.0, 1.0, 1.0, 2.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn linear() {
+        let values = [[0.0, 0.0, 0.0], [4.0, 2.0, 0.0]];
+        let sample = |time| translation(Interpolation::Linear, &[1.0, 3.0], &values, time);
+        assert_eq!(sample(0.0), [0.0, 0.0, 0.0]);
+        assert_eq!(sample(1.5), [1.0, 0.5, 0.0]);
+        assert_eq!(sample(2.0), [2.0, 1.0, 0.0]);
+        assert_eq!(sample(5.0), [4.0, 2.0, 0.0]);
+    }
+
+    #[test]
+    fn linear_rotation_slerps() {
+        let rotations = vec![Quaternion::from_angle_z(Deg(0.0)), Quaternion::from_angle_z(Deg(90.0))];
+        let clip = AnimationClip::new("test", vec![Channel { joint: 0, interpolation: Interpolation::Linear, times: vec![0.0, 1.0], keyframes: Keyframes::Rotation(rotations) }]);
+        let mut pose = Pose { local: vec![Transform::default()] };
+        clip.sample(0.5, &mut pose);
+        let expected = Quaternion::from_angle_z(Deg(45.0));
+        assert!((pose.local[0].rotation - expected).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn cubic_spline() {
+        // In tangent, value and out tangent of each keyframe.
+        let values = [[9.0, 0.0, 0.0], [0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [9.0, 0.0, 0.0]];
+        let sample = |time| translation(Interpolation::CubicSpline, &[1.0, 3.0], &values, time)[0];
+        // Outside the keyframes the tangents don't matter.
+        assert_eq!(sample(0.0), 0.0);
+        assert_eq!(sample(1.0), 0.0);
+        assert_eq!(sample(4.0), 1.0);
+        // Halfway the values weigh a half each and the out tangent, scaled by the 2 second span, an eighth.
+        assert_eq!(sample(2.0), 0.5 + 2.0 * 2.0 / 8.0);
+    }
+}
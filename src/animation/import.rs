@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use cgmath::{Matrix4, Quaternion, SquareMatrix, Vector3};
+use gltf::animation::util::ReadOutputs;
+use gltf::animation::Property;
+use tracing::warn;
+
+use crate::animation::clip::{AnimationClip, Channel, Interpolation, Keyframes};
+use crate::animation::skeleton::{Joint, Skeleton, Transform};
+use crate::animation::skin::{SkinnedMeshData, SkinnedVertex};
+use crate::animation::AnimationError;
+use crate::logging;
+use crate::scene::{AlphaMode, BlendMode, Material};
+
+/// The first skin of a glTF file, the primitives of the meshes it skins and every animation moving its joints.
+pub struct SkinnedModel {
+    pub skeleton: Arc<Skeleton>,
+    /// Materials only have their factors, textures aren't loaded.
+    pub parts: Vec<(SkinnedMeshData, Material)>,
+    pub clips: Vec<Arc<AnimationClip>>,
+}
+
+pub fn load(path: &Path) -> Result<SkinnedModel, AnimationError> {
+    let (document, buffers, _) = gltf::import(path)?;
+    let skin = document.skins().next().ok_or(AnimationError::NoSkin)?;
+    let buffer = |buffer: gltf::Buffer| Some(&*buffers[buffer.index()]);
+
+    let mut parents = HashMap::new();
+    for node in document.nodes() {
+        for child in node.children() {
+            parents.insert(child.index(), node.clone());
+        }
+    }
+
+    // Joint index of every joint node.
+    let joint_nodes: HashMap<usize, usize> = skin.joints().enumerate().map(|(joint, node)| (node.index(), joint)).collect();
+    let inverse_binds: Vec<[[f32; 4]; 4]> = skin.reader(buffer).read_inverse_bind_matrices().map(|matrices| matrices.collect()).unwrap_or_default();
+    let mut root = None;
+    let mut joints = vec![];
+    for (index, node) in skin.joints().enumerate() {
+        // The nearest ancestor that is a joint, and the transform of the nodes in between.
+        let mut parent = None;
+        let mut between = Matrix4::identity();
+        let mut ancestor = parents.get(&node.index());
+        while let Some(node) = ancestor {
+            if let Some(&joint) = joint_nodes.get(&node.index()) {
+                parent = Some(joint);
+                break;
+            }
+            between = Matrix4::from(node.transform().matrix()) * between;
+            ancestor = parents.get(&node.index());
+        }
+
+        let (translation, rotation, scale) = node.transform().decomposed();
+        let rest = Transform {
+            translation: translation.into(),
+            rotation: Quaternion::new(rotation[3], rotation[0], rotation[1], rotation[2]),
+            scale: scale.into(),
+        };
+        if parent.is_none() {
+            root.get_or_insert(between);
+        } else if between != Matrix4::identity() {
+            warn!(target: logging::ASSETS, "ignoring the transforms of the nodes between joint {} and its parent", index);
+        }
+
+        joints.push(Joint {
+            parent,
+            rest,
+            inverse_bind: inverse_binds.get(index).map_or_else(Matrix4::identity, |&matrix| matrix.into()),
+        });
+    }
+    let skeleton = Arc::new(Skeleton::new(joints, root.unwrap_or_else(Matrix4::identity))?);
+
+    let mut parts = vec![];
+    for node in document.nodes().filter(|node| matches!(node.skin(), Some(other) if other.index() == skin.index())) {
+        let mesh = match node.mesh() {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+        for primitive in mesh.primitives() {
+            let reader = primitive.reader(buffer);
+            let positions: Vec<[f32; 3]> = reader.read_positions().ok_or(AnimationError::MissingAttribute("POSITION"))?.collect();
+            let mut normals = reader.read_normals().map(|normals| normals.collect::<Vec<_>>()).unwrap_or_default();
+            let mut tangents = reader.read_tangents().map(|tangents| tangents.collect::<Vec<_>>()).unwrap_or_default();
+            let mut uvs = reader.read_tex_coords(0).map(|uvs| uvs.into_f32().collect::<Vec<_>>()).unwrap_or_default();
+            let joints: Vec<[u16; 4]> = reader.read_joints(0).ok_or(AnimationError::MissingAttribute("JOINTS_0"))?.into_u16().collect();
+            let weights: Vec<[f32; 4]> = reader.read_weights(0).ok_or(AnimationError::MissingAttribute("WEIGHTS_0"))?.into_f32().collect();
+            normals.resize(positions.len(), [0.0, 1.0, 0.0]);
+            tangents.resize(positions.len(), [1.0, 0.0, 0.0, 1.0]);
+            uvs.resize(positions.len(), [0.0, 0.0]);
+
+            let mut data = SkinnedMeshData::default();
+            for (index, &position) in positions.iter().enumerate() {
+                let weights = weights[index];
+                let total: f32 = weights.iter().sum();
+                data.vertices.push(SkinnedVertex {
+                    position,
+                    normal: normals[index],
+                    tangent: tangents[index],
+                    uv: uvs[index],
+                    joints: joints[index].map(u32::from),
+                    weights: if total > 0.0 { weights.map(|weight| weight / total) } else { [1.0, 0.0, 0.0, 0.0] },
+                });
+            }
+            data.indices = reader.read_indices().map_or_else(|| (0..positions.len() as u32).collect(), |indices| indices.into_u32().collect());
+
+            let material = primitive.material();
+            let pbr = material.pbr_metallic_roughness();
+            parts.push((data, Material {
+                base_color_factor: pbr.base_color_factor(),
+                metallic_factor: pbr.metallic_factor(),
+                roughness_factor: pbr.roughness_factor(),
+                emissive_factor: material.emissive_factor(),
+                alpha_mode: match material.alpha_mode() {
+                    gltf::material::AlphaMode::Mask => AlphaMode::Mask(material.alpha_cutoff().unwrap_or(0.5)),
+                    gltf::material::AlphaMode::Blend => AlphaMode::Blend(BlendMode::Alpha),
+                    gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+                },
+                double_sided: material.double_sided(),
+                ..Material::default()
+            }));
+        }
+    }
+
+    let mut clips = vec![];
+    for (index, animation) in document.animations().enumerate() {
+        let mut channels = vec![];
+        for channel in animation.channels() {
+            let joint = match joint_nodes.get(&channel.target().node().index()) {
+                Some(&joint) => joint,
+                None => continue,
+            };
+            let reader = channel.reader(buffer);
+            let times = match reader.read_inputs() {
+                Some(times) => times.collect(),
+                None => continue,
+            };
+            let keyframes = match (channel.target().property(), reader.read_outputs()) {
+                (Property::Translation, Some(ReadOutputs::Translations(values))) => Keyframes::Translation(values.map(Vector3::from).collect()),
+                (Property::Rotation, Some(ReadOutputs::Rotations(values))) => Keyframes::Rotation(values.into_f32().map(|[x, y, z, w]| Quaternion::new(w, x, y, z)).collect()),
+                (Property::Scale, Some(ReadOutputs::Scales(values))) => Keyframes::Scale(values.map(Vector3::from).collect()),
+                _ => continue,
+            };
+            let interpolation = match channel.sampler().interpolation() {
+                gltf::animation::Interpolation::Step => Interpolation::Step,
+                gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+            };
+            channels.push(Channel { joint, interpolation, times, keyframes });
+        }
+        let name = animation.name().map_or_else(|| format!("animation {}", index), str::to_string);
+        clips.push(Arc::new(AnimationClip::new(&name, channels)));
+    }
+
+    Ok(SkinnedModel { skeleton, parts, clips })
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cgmath::Matrix4;
+
+pub use crate::animation::clip::{AnimationClip, Channel, Interpolation, Keyframes};
+pub use crate::animation::import::load;
+pub use crate::animation::skeleton::{Joint, Pose, Skeleton, Transform};
+pub use crate::animation::skin::{SkinnedMesh, SkinnedMeshData, SkinnedVertex};
+pub use crate::animation::states::{AnimationStateMachine, Condition, Motion};
+
+use crate::scene::Material;
+
+mod clip;
+mod import;
+mod skeleton;
+mod skin;
+mod states;
+
+#[derive(Debug)]
+pub enum AnimationError {
+    Gltf(gltf::Error),
+    /// The file has no skin to animate.
+    NoSkin,
+    /// A skinned primitive lacks an attribute skinning needs.
+    MissingAttribute(&'static str),
+    /// The joints' parents form a cycle.
+    SkeletonCycle,
+}
+
+impl fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnimationError::Gltf(e) => write!(f, "couldn't import glTF: {}", e),
+            AnimationError::NoSkin => write!(f, "there is no skin"),
+            AnimationError::MissingAttribute(name) => write!(f, "a skinned primitive has no {} attribute", name),
+            AnimationError::SkeletonCycle => write!(f, "the skeleton's joints are their own ancestors"),
+        }
+    }
+}
+
+impl From<gltf::Error> for AnimationError {
+    fn from(e: gltf::Error) -> AnimationError {
+        AnimationError::Gltf(e)
+    }
+}
+
+/// A skinned mesh drawn with a material.
+pub struct SkinnedPart {
+    pub mesh: Arc<SkinnedMesh>,
+    pub material: Arc<Material>,
+}
+
+/// Skinned meshes sharing a skeleton, posed by a state machine.
+pub struct SkinnedObject {
+    pub parts: Vec<SkinnedPart>,
+    pub transform: Matrix4<f32>,
+    pub skeleton: Arc<Skeleton>,
+    pub animator: AnimationStateMachine,
+    /// Written by `update`.
+    pub pose: Pose,
+}
+
+impl SkinnedObject {
+    pub fn new(parts: Vec<SkinnedPart>, transform: Matrix4<f32>, skeleton: Arc<Skeleton>, animator: AnimationStateMachine) -> SkinnedObject {
+        let pose = skeleton.rest_pose();
+        SkinnedObject { parts, transform, skeleton, animator, pose }
+    }
+
+    /// Advances the state machine and poses the skeleton.
+    pub fn update(&mut self, frame_time: Duration) {
+        self.animator.update(frame_time.as_secs_f32());
+        self.animator.pose(&self.skeleton, &mut self.pose);
+    }
+
+    /// The joint matrices of the current pose, as the skinning vertex shader wants them.
+    pub fn joint_matrices(&self) -> Vec<Matrix4<f32>> {
+        self.skeleton.joint_matrices(&self.pose)
+    }
+}
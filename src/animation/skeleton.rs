@@ -0,0 +1,118 @@
+use cgmath::{InnerSpace, Matrix4, One, Quaternion, SquareMatrix, Vector3, VectorSpace};
+
+use crate::animation::AnimationError;
+
+/// Translation, rotation and scale of a joint relative to its parent, applied scale first.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform {
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Transform {
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation) * Matrix4::from(self.rotation) * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    /// Moves `weight` of the way to `other`, rotating the shorter way around.
+    pub fn lerp(&self, other: &Transform, weight: f32) -> Transform {
+        Transform {
+            translation: self.translation.lerp(other.translation, weight),
+            rotation: nlerp(self.rotation, other.rotation, weight),
+            scale: self.scale.lerp(other.scale, weight),
+        }
+    }
+}
+
+/// Normalized linear interpolation, cheaper than slerp and close enough for blending poses. Keyframes are sampled with
+/// slerp, as glTF specifies.
+fn nlerp(a: Quaternion<f32>, b: Quaternion<f32>, weight: f32) -> Quaternion<f32> {
+    let b = if a.dot(b) < 0.0 { -b } else { b };
+    (a * (1.0 - weight) + b * weight).normalize()
+}
+
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub parent: Option<usize>,
+    /// Where the joint is when no animation moves it.
+    pub rest: Transform,
+    /// Takes the skinned mesh's vertices from model space into the joint's space at bind time.
+    pub inverse_bind: Matrix4<f32>,
+}
+
+/// A hierarchy of joints, indexed the way the joints of skinned vertices are.
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+    /// Transform above the root joints, from the nodes of the file they came from that aren't joints themselves.
+    pub root: Matrix4<f32>,
+    // Joint indices with every parent before its children.
+    order: Vec<usize>,
+}
+
+impl Skeleton {
+    /// Fails if the joints' parents form a cycle.
+    pub fn new(joints: Vec<Joint>, root: Matrix4<f32>) -> Result<Skeleton, AnimationError> {
+        let mut order = Vec::with_capacity(joints.len());
+        let mut placed = vec![false; joints.len()];
+        while order.len() < joints.len() {
+            let before = order.len();
+            for (index, joint) in joints.iter().enumerate() {
+                if !placed[index] && joint.parent.iter().all(|&parent| placed[parent]) {
+                    placed[index] = true;
+                    order.push(index);
+                }
+            }
+            if order.len() == before {
+                return Err(AnimationError::SkeletonCycle);
+            }
+        }
+
+        Ok(Skeleton { joints, root, order })
+    }
+
+    pub fn rest_pose(&self) -> Pose {
+        Pose { local: self.joints.iter().map(|joint| joint.rest).collect() }
+    }
+
+    /// Model space transform of every joint in `pose`.
+    pub fn global_transforms(&self, pose: &Pose) -> Vec<Matrix4<f32>> {
+        let mut globals = vec![Matrix4::identity(); self.joints.len()];
+        for &index in self.order.iter() {
+            let parent = self.joints[index].parent.map_or(self.root, |parent| globals[parent]);
+            globals[index] = parent * pose.local[index].matrix();
+        }
+        globals
+    }
+
+    /// What the vertex shader skins with: how far every joint moved from where it was at bind time.
+    pub fn joint_matrices(&self, pose: &Pose) -> Vec<Matrix4<f32>> {
+        self.global_transforms(pose).iter().zip(self.joints.iter()).map(|(global, joint)| global * joint.inverse_bind).collect()
+    }
+}
+
+/// The local transform of every joint of a skeleton.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pose {
+    pub local: Vec<Transform>,
+}
+
+impl Pose {
+    /// Moves `weight` of the way to `other`, joint by joint.
+    pub fn blend(&mut self, other: &Pose, weight: f32) {
+        for (joint, other) in self.local.iter_mut().zip(other.local.iter()) {
+            *joint = joint.lerp(other, weight);
+        }
+    }
+}
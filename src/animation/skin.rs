@@ -0,0 +1,42 @@
+use std::sync::Arc;
+
+use vulkano::buffer::BufferUsage;
+
+use crate::allocator::{MemoryCategory, PooledBuffer};
+use crate::debug::ObjectNamer;
+use crate::upload::UploadManager;
+
+/// `MeshVertex` moved by up to four joints of a skeleton. The weights add up to one.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct SkinnedVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub tangent: [f32; 4],
+    pub uv: [f32; 2],
+    pub joints: [u32; 4],
+    pub weights: [f32; 4],
+}
+vulkano::impl_vertex!(SkinnedVertex, position, normal, tangent, uv, joints, weights);
+
+/// Indexed triangle list on the CPU, in the skeleton's bind pose.
+#[derive(Default, Debug, Clone)]
+pub struct SkinnedMeshData {
+    pub vertices: Vec<SkinnedVertex>,
+    pub indices: Vec<u32>,
+}
+
+/// Device-local vertex and index buffers of a `SkinnedMeshData`.
+pub struct SkinnedMesh {
+    pub vertices: Arc<PooledBuffer<[SkinnedVertex]>>,
+    pub indices: Arc<PooledBuffer<[u32]>>,
+}
+
+impl SkinnedMesh {
+    pub fn upload(uploads: &mut UploadManager, namer: &ObjectNamer, name: &str, data: &SkinnedMeshData) -> Arc<SkinnedMesh> {
+        let vertices = uploads.buffer(&data.vertices, BufferUsage::vertex_buffer(), MemoryCategory::Mesh).expect("failed to create vertex buffer");
+        let indices = uploads.buffer(&data.indices, BufferUsage::index_buffer(), MemoryCategory::Mesh).expect("failed to create index buffer");
+        namer.name_buffer(&*vertices, &format!("{} vertices", name));
+        namer.name_buffer(&*indices, &format!("{} indices", name));
+        Arc::new(SkinnedMesh { vertices, indices })
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::animation::clip::AnimationClip;
+use crate::animation::skeleton::{Pose, Skeleton};
+
+/// What a state plays.
+#[derive(Debug, Clone)]
+pub enum Motion {
+    Clip(Arc<AnimationClip>),
+    /// Blends between the two clips whose thresholds are around the float `parameter`, keeping them in step by
+    /// playing both at the same fraction of their duration. Thresholds increase.
+    Blend { parameter: String, clips: Vec<(f32, Arc<AnimationClip>)> },
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationState {
+    pub name: String,
+    pub motion: Motion,
+    /// Multiplies the time passing.
+    pub speed: f32,
+    /// Whether the state starts over at its end or holds the last pose.
+    pub looping: bool,
+}
+
+/// When a transition is taken.
+#[derive(Debug, Clone)]
+pub enum Condition {
+    /// The trigger was set since the last update. Taking the transition clears it.
+    Trigger(String),
+    /// A state that doesn't loop reached its end.
+    Finished,
+}
+
+#[derive(Debug, Clone)]
+pub struct Transition {
+    /// State the transition leaves, or any other state.
+    pub from: Option<usize>,
+    pub to: usize,
+    pub condition: Condition,
+    /// Seconds of crossfade.
+    pub duration: f32,
+}
+
+/// Plays one state at a time, moving between them when their transitions' conditions hold and crossfading from the
+/// state it left. Blends read parameters set by the game.
+#[derive(Debug, Clone, Default)]
+pub struct AnimationStateMachine {
+    pub states: Vec<AnimationState>,
+    pub transitions: Vec<Transition>,
+    floats: HashMap<String, f32>,
+    triggers: HashSet<String>,
+    current: usize,
+    // Fraction of the current state played.
+    phase: f32,
+    // State being faded out and its phase.
+    previous: Option<(usize, f32)>,
+    fade: f32,
+    fade_duration: f32,
+}
+
+impl AnimationStateMachine {
+    pub fn new() -> AnimationStateMachine {
+        AnimationStateMachine::default()
+    }
+
+    /// Adds a state and returns its index. The first state added is the one the machine starts in.
+    pub fn state(&mut self, name: &str, motion: Motion, looping: bool) -> usize {
+        self.states.push(AnimationState { name: name.to_string(), motion, speed: 1.0, looping });
+        self.states.len() - 1
+    }
+
+    /// Plays `state` `speed` times as fast.
+    pub fn set_speed(&mut self, state: usize, speed: f32) {
+        self.states[state].speed = speed;
+    }
+
+    pub fn transition(&mut self, from: Option<usize>, to: usize, condition: Condition, duration: f32) {
+        self.transitions.push(Transition { from, to, condition, duration });
+    }
+
+    pub fn set_float(&mut self, name: &str, value: f32) {
+        self.floats.insert(name.to_string(), value);
+    }
+
+    pub fn trigger(&mut self, name: &str) {
+        self.triggers.insert(name.to_string());
+    }
+
+    pub fn current_state(&self) -> &AnimationState {
+        &self.states[self.current]
+    }
+
+    /// Moves to `state` right away, fading the current one out over `duration` seconds.
+    pub fn crossfade(&mut self, state: usize, duration: f32) {
+        self.previous = if duration > 0.0 { Some((self.current, self.phase)) } else { None };
+        self.current = state;
+        self.phase = 0.0;
+        self.fade = 0.0;
+        self.fade_duration = duration;
+    }
+
+    /// Advances the states by `dt` seconds and takes the first transition whose condition holds. Triggers no
+    /// transition took are dropped.
+    pub fn update(&mut self, dt: f32) {
+        if self.states.is_empty() {
+            return;
+        }
+        self.phase = self.advance(self.current, self.phase, dt);
+        if let Some((state, phase)) = self.previous {
+            self.fade += dt;
+            self.previous = if self.fade < self.fade_duration { Some((state, self.advance(state, phase, dt))) } else { None };
+        }
+
+        let taken = self.transitions
+                        .iter()
+                        .find(|transition| transition.from.unwrap_or(self.current) == self.current && transition.to != self.current && self.holds(&transition.condition))
+                        .cloned();
+        if let Some(transition) = taken {
+            if let Condition::Trigger(name) = &transition.condition {
+                self.triggers.remove(name);
+            }
+            self.crossfade(transition.to, transition.duration);
+        }
+        self.triggers.clear();
+    }
+
+    /// Writes the pose of the current state, blended with the one being faded out, into `pose`.
+    pub fn pose(&self, skeleton: &Skeleton, pose: &mut Pose) {
+        if self.states.is_empty() {
+            *pose = skeleton.rest_pose();
+            return;
+        }
+        self.sample(self.current, self.phase, skeleton, pose);
+        if let Some((state, phase)) = self.previous {
+            let mut fading = skeleton.rest_pose();
+            self.sample(state, phase, skeleton, &mut fading);
+            fading.blend(pose, self.fade / self.fade_duration);
+            *pose = fading;
+        }
+    }
+
+    fn holds(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Trigger(name) => self.triggers.contains(name),
+            Condition::Finished => !self.states[self.current].looping && self.phase >= 1.0,
+        }
+    }
+
+    fn advance(&self, state: usize, phase: f32, dt: f32) -> f32 {
+        let state = &self.states[state];
+        let duration = self.duration(&state.motion);
+        if duration <= 0.0 {
+            return 1.0;
+        }
+        let phase = phase + dt * state.speed / duration;
+        if state.looping {
+            phase.rem_euclid(1.0)
+        } else {
+            phase.min(1.0)
+        }
+    }
+
+    /// The two clips of a blend around its parameter and how far the parameter is from the first to the second.
+    fn blend_clips<'a>(&self, parameter: &str, clips: &'a [(f32, Arc<AnimationClip>)]) -> (&'a AnimationClip, &'a AnimationClip, f32) {
+        let value = self.floats.get(parameter).cloned().unwrap_or(0.0);
+        let next = clips.iter().position(|(threshold, _)| *threshold > value).unwrap_or(clips.len());
+        if next == 0 {
+            return (&clips[0].1, &clips[0].1, 0.0);
+        }
+        if next == clips.len() {
+            return (&clips[next - 1].1, &clips[next - 1].1, 0.0);
+        }
+        let (low, high) = (&clips[next - 1], &clips[next]);
+        (&low.1, &high.1, (value - low.0) / (high.0 - low.0))
+    }
+
+    fn duration(&self, motion: &Motion) -> f32 {
+        match motion {
+            Motion::Clip(clip) => clip.duration,
+            Motion::Blend { parameter, clips } => {
+                let (first, second, weight) = self.blend_clips(parameter, clips);
+                first.duration + (second.duration - first.duration) * weight
+            }
+        }
+    }
+
+    fn sample(&self, state: usize, phase: f32, skeleton: &Skeleton, pose: &mut Pose) {
+        *pose = skeleton.rest_pose();
+        match &self.states[state].motion {
+            Motion::Clip(clip) => clip.sample(phase * clip.duration, pose),
+            Motion::Blend { parameter, clips } => {
+                let (first, second, weight) = self.blend_clips(parameter, clips);
+                first.sample(phase * first.duration, pose);
+                if weight > 0.0 {
+                    let mut other = skeleton.rest_pose();
+                    second.sample(phase * second.duration, &mut other);
+                    pose.blend(&other, weight);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use super::*;
+    use crate::animation::clip::{Channel, Interpolation, Keyframes};
+
+    /// A clip lasting `duration` seconds.
+    fn clip(duration: f32) -> Motion {
+        let keyframes = Keyframes::Scale(vec![Vector3::new(1.0, 1.0, 1.0); 2]);
+        Motion::Clip(Arc::new(AnimationClip::new("test", vec![Channel { joint: 0, interpolation: Interpolation::Linear, times: vec![0.0, duration], keyframes }])))
+    }
+
+    #[test]
+    fn triggers_are_consumed() {
+        let mut machine = AnimationStateMachine::new();
+        let idle = machine.state("idle", clip(1.0), true);
+        let jump = machine.state("jump", clip(1.0), true);
+        machine.transition(Some(idle), jump, Condition::Trigger("jump".to_string()), 0.0);
+        machine.transition(Some(jump), idle, Condition::Trigger("jump".to_string()), 0.0);
+
+        machine.update(0.1);
+        assert_eq!(machine.current_state().name, "idle");
+        machine.trigger("jump");
+        machine.update(0.1);
+        assert_eq!(machine.current_state().name, "jump");
+        // Taking the transition used the trigger up, so it doesn't lead straight back.
+        machine.update(0.1);
+        assert_eq!(machine.current_state().name, "jump");
+
+        // Triggers nothing takes are dropped too.
+        machine.trigger("land");
+        machine.update(0.1);
+        machine.transition(Some(jump), idle, Condition::Trigger("land".to_string()), 0.0);
+        machine.update(0.1);
+        assert_eq!(machine.current_state().name, "jump");
+    }
+
+    #[test]
+    fn finished_states() {
+        let mut machine = AnimationStateMachine::new();
+        let wave = machine.state("wave", clip(1.0), false);
+        let idle = machine.state("idle", clip(1.0), true);
+        machine.transition(Some(wave), idle, Condition::Finished, 0.5);
+        machine.transition(Some(idle), wave, Condition::Finished, 0.0);
+        machine.set_speed(wave, 2.0);
+
+        machine.update(0.4);
+        assert_eq!(machine.current_state().name, "wave");
+        machine.update(0.1);
+        assert_eq!(machine.current_state().name, "idle");
+        // The finished state fades out, holding its last pose.
+        assert_eq!(machine.previous, Some((wave, 1.0)));
+        machine.update(0.5);
+        assert_eq!(machine.previous, None);
+
+        // Looping states never finish.
+        machine.update(1.5);
+        assert_eq!(machine.current_state().name, "idle");
+    }
+}
@@ -0,0 +1,54 @@
+use std::path::Path;
+
+use image::ColorType;
+use tracing::info;
+use vulkano::format::Format;
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+use crate::compute::{Compute, ComputeError};
+use crate::logging;
+
+const SIZE: [u32; 2] = [1024, 1024];
+const PALETTE_SIZE: u32 = 256;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct View {
+    center: [f32; 2],
+    scale: f32,
+    iterations: u32,
+}
+
+/// Renders the Mandelbrot set on `compute`'s device and saves it to `path`, the smallest end to end compute job. A
+/// first job writes the palette the second one samples, and the second one counts the pixels inside the set.
+pub fn run(compute: &Compute, path: &Path) -> Result<(), ComputeError> {
+    let palette_shader = compute.shader("palette", include_str!("palette.comp"))?;
+    let palette = compute.image([PALETTE_SIZE, 1], Format::R8G8B8A8Unorm);
+    compute.run(palette_shader.job().image("palette", palette.clone()), [PALETTE_SIZE, 1, 1])?;
+
+    let shader = compute.shader("mandelbrot", include_str!("mandelbrot.comp"))?;
+    let image = compute.image(SIZE, Format::R8G8B8A8Unorm);
+    let sampler = Sampler::new(compute.device.clone(),
+                               Filter::Linear,
+                               Filter::Linear,
+                               MipmapMode::Nearest,
+                               SamplerAddressMode::ClampToEdge,
+                               SamplerAddressMode::ClampToEdge,
+                               SamplerAddressMode::ClampToEdge,
+                               0.0,
+                               1.0,
+                               0.0,
+                               0.0).unwrap();
+    let stats = compute.buffer(&[0u32]);
+
+    let view = View { center: [-0.5, 0.0], scale: 2.5, iterations: 200 };
+    let job = shader.job().image("img", image.clone()).sampled_image("palette", palette, sampler).buffer("stats", stats.clone()).push_constants(view);
+    compute.run(job, [SIZE[0], SIZE[1], 1])?;
+
+    image::save_buffer(path, &compute.read_image(&image), SIZE[0], SIZE[1], ColorType::Rgba8)?;
+    // The view is square, so each pixel covers the same share of `scale` squared.
+    let inside = compute.read(&stats)[0];
+    let area = inside as f32 / (SIZE[0] * SIZE[1]) as f32 * view.scale * view.scale;
+    info!(target: logging::RENDER, path = %path.display(), area, "wrote mandelbrot");
+    Ok(())
+}
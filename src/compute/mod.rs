@@ -0,0 +1,323 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::sync::Arc;
+use std::{fmt, io, mem};
+
+use tracing::{debug, info};
+use vulkano::buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, DispatchError, PrimaryAutoCommandBuffer};
+use vulkano::descriptor::descriptor::ShaderStages;
+use vulkano::descriptor::pipeline_layout::{PipelineLayout, PipelineLayoutAbstract, PipelineLayoutDesc, PipelineLayoutDescPcRange, RuntimePipelineDesc};
+use vulkano::device::{Device, DeviceExtensions, Features, Queue};
+use vulkano::format::Format;
+use vulkano::image::view::{ImageView, ImageViewAbstract};
+use vulkano::image::{ImageAccess, ImageDimensions, StorageImage};
+use vulkano::instance::{Instance, PhysicalDevice};
+use vulkano::memory::Content;
+use vulkano::pipeline::shader::ShaderModule;
+use vulkano::pipeline::{ComputePipeline, ComputePipelineCreationError};
+use vulkano::sampler::Sampler;
+use vulkano::sync;
+use vulkano::sync::GpuFuture;
+
+pub use crate::compute::reflect::{BindingKind, Reflection};
+pub use crate::compute::set::Resource;
+
+use crate::compute::set::BoundSet;
+use crate::debug::{Debug, ObjectNamer};
+use crate::logging;
+
+pub mod mandelbrot;
+mod reflect;
+mod set;
+
+/// The most push constant bytes every device takes.
+const MAX_PUSH_CONSTANTS: usize = 128;
+
+#[derive(Debug)]
+pub enum ComputeError {
+    Io(io::Error),
+    Image(image::ImageError),
+    Compile(String),
+    Reflect(String),
+    Pipeline(ComputePipelineCreationError),
+    /// The shader has no binding of that name.
+    UnknownBinding(String),
+    /// The resource bound to a name isn't the kind of resource the shader declares there.
+    WrongResource { name: String, kind: BindingKind },
+    /// The shader declares a binding, or push constants, that the job left unbound.
+    Unbound(String),
+    /// Push constants of a size that doesn't cover the shader's.
+    PushConstants { expected: usize, found: usize },
+    Dispatch(DispatchError),
+}
+
+impl fmt::Display for ComputeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ComputeError::Io(e) => write!(f, "{}", e),
+            ComputeError::Image(e) => write!(f, "couldn't write image: {}", e),
+            ComputeError::Compile(e) => write!(f, "couldn't compile compute shader: {}", e),
+            ComputeError::Reflect(e) => write!(f, "couldn't reflect compute shader: {}", e),
+            ComputeError::Pipeline(e) => write!(f, "couldn't create compute pipeline: {}", e),
+            ComputeError::UnknownBinding(name) => write!(f, "the shader has no binding named {}", name),
+            ComputeError::WrongResource { name, kind } => write!(f, "{} is bound to the wrong kind of resource, the shader wants a {:?}", name, kind),
+            ComputeError::Unbound(name) => write!(f, "nothing is bound to {}", name),
+            ComputeError::PushConstants { expected, found } => write!(f, "the shader takes {} bytes of push constants, not {}", expected, found),
+            ComputeError::Dispatch(e) => write!(f, "couldn't dispatch: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for ComputeError {
+    fn from(e: io::Error) -> ComputeError {
+        ComputeError::Io(e)
+    }
+}
+
+impl From<image::ImageError> for ComputeError {
+    fn from(e: image::ImageError) -> ComputeError {
+        ComputeError::Image(e)
+    }
+}
+
+impl From<DispatchError> for ComputeError {
+    fn from(e: DispatchError) -> ComputeError {
+        ComputeError::Dispatch(e)
+    }
+}
+
+/// A compute pipeline built at runtime from GLSL or SPIR-V, whose bindings, push constants and workgroup size come
+/// from reflecting the shader rather than from `vulkano_shaders` at compile time.
+pub struct ComputeShader {
+    name: String,
+    reflection: Reflection,
+    pipeline: Arc<ComputePipeline<PipelineLayout<RuntimePipelineDesc>>>,
+}
+
+impl ComputeShader {
+    pub fn from_glsl(device: Arc<Device>, namer: &ObjectNamer, name: &str, source: &str) -> Result<ComputeShader, ComputeError> {
+        let mut compiler = shaderc::Compiler::new().expect("failed to create shader compiler");
+        let spirv = compiler.compile_into_spirv(source, shaderc::ShaderKind::Compute, name, "main", None)
+                            .map_err(|e| ComputeError::Compile(e.to_string()))?;
+        ComputeShader::from_spirv(device, namer, name, spirv.as_binary())
+    }
+
+    pub fn from_spirv(device: Arc<Device>, namer: &ObjectNamer, name: &str, words: &[u32]) -> Result<ComputeShader, ComputeError> {
+        let reflection = reflect::reflect(words).map_err(ComputeError::Reflect)?;
+        if reflection.push_constants_size > MAX_PUSH_CONSTANTS {
+            return Err(ComputeError::Reflect(format!("{} bytes of push constants is more than devices take", reflection.push_constants_size)));
+        }
+
+        let push_constants = Some(PipelineLayoutDescPcRange {
+            offset: 0,
+            size: reflection.push_constants_size,
+            stages: ShaderStages { compute: true, ..ShaderStages::none() },
+        }).filter(|range| range.size > 0);
+        let layout = RuntimePipelineDesc::new(reflection.sets(), push_constants).map_err(|e| ComputeError::Reflect(e.to_string()))?;
+
+        // Safe because the layout and entry point come from the module itself.
+        let module = unsafe { ShaderModule::from_words(device.clone(), words) }.unwrap();
+        let entry_name = CString::new(reflection.entry_point.clone()).unwrap();
+        let entry_point = unsafe { module.compute_entry_point::<(), _>(&entry_name, layout) };
+        let pipeline = Arc::new(ComputePipeline::new(device, &entry_point, &(), None).map_err(ComputeError::Pipeline)?);
+        namer.name(&*pipeline, name);
+
+        debug!(target: logging::RENDER,
+               shader = name,
+               local_size = ?reflection.local_size,
+               bindings = reflection.bindings.len(),
+               push_constants = reflection.push_constants_size,
+               "created compute shader");
+
+        Ok(ComputeShader { name: name.to_string(), reflection, pipeline })
+    }
+
+    /// Workgroups covering `size` invocations, rounded up, so shaders check their bounds.
+    pub fn group_counts(&self, size: [u32; 3]) -> [u32; 3] {
+        let local = self.reflection.local_size;
+        [size[0].div_ceil(local[0]), size[1].div_ceil(local[1]), size[2].div_ceil(local[2])]
+    }
+
+    pub fn job(&self) -> ComputeJob<'_> {
+        ComputeJob {
+            shader: self,
+            resources: HashMap::new(),
+            push_constants: None,
+            error: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct PushConstants([u32; MAX_PUSH_CONSTANTS / 4]);
+
+/// One dispatch of a `ComputeShader`, with its resources bound by the names the shader gives them. Binding
+/// mistakes surface when the job is recorded.
+pub struct ComputeJob<'a> {
+    shader: &'a ComputeShader,
+    resources: HashMap<(u32, u32), Resource>,
+    push_constants: Option<PushConstants>,
+    error: Option<ComputeError>,
+}
+
+impl<'a> ComputeJob<'a> {
+    pub fn bind(mut self, name: &str, resource: Resource) -> ComputeJob<'a> {
+        if self.error.is_some() {
+            return self;
+        }
+        match self.shader.reflection.bindings.get(name) {
+            Some(binding) if resource.fits(binding.kind) => {
+                self.resources.insert((binding.set, binding.binding), resource);
+            }
+            Some(binding) => self.error = Some(ComputeError::WrongResource { name: name.to_string(), kind: binding.kind }),
+            None => self.error = Some(ComputeError::UnknownBinding(name.to_string())),
+        }
+        self
+    }
+
+    pub fn buffer<B: BufferAccess + Send + Sync + 'static>(self, name: &str, buffer: Arc<B>) -> ComputeJob<'a> {
+        self.bind(name, Resource::Buffer(buffer))
+    }
+
+    /// A storage image, or a sampled one for `texelFetch` without a sampler, whichever the shader declares.
+    pub fn image<I: ImageViewAbstract + Send + Sync + 'static>(self, name: &str, image: Arc<I>) -> ComputeJob<'a> {
+        self.bind(name, Resource::Image(image))
+    }
+
+    pub fn sampled_image<I: ImageViewAbstract + Send + Sync + 'static>(self, name: &str, image: Arc<I>, sampler: Arc<Sampler>) -> ComputeJob<'a> {
+        self.bind(name, Resource::SampledImage(image, sampler))
+    }
+
+    /// `constants` must be laid out like the shader's push constant block.
+    pub fn push_constants<T: Copy>(mut self, constants: T) -> ComputeJob<'a> {
+        let expected = self.shader.reflection.push_constants_size;
+        let found = mem::size_of::<T>();
+        if found < expected || found > MAX_PUSH_CONSTANTS {
+            self.error = self.error.or(Some(ComputeError::PushConstants { expected, found }));
+            return self;
+        }
+        let mut words = PushConstants([0; MAX_PUSH_CONSTANTS / 4]);
+        // Safe because `T` fits and is plain data.
+        unsafe { (words.0.as_mut_ptr() as *mut T).write_unaligned(constants) };
+        self.push_constants = Some(words);
+        self
+    }
+
+    /// Records a dispatch of enough workgroups to cover `size` invocations.
+    pub fn record(self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, size: [u32; 3]) -> Result<(), ComputeError> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let shader = self.shader;
+        let reflection = &shader.reflection;
+        if reflection.push_constants_size > 0 && self.push_constants.is_none() {
+            return Err(ComputeError::Unbound("push constants".to_string()));
+        }
+
+        // Blocks can be bound under two names, so go by slot.
+        let mut resources = vec![vec![]; shader.pipeline.num_sets()];
+        let mut slots = HashSet::new();
+        for (name, binding) in reflection.bindings.iter() {
+            if !slots.insert((binding.set, binding.binding)) {
+                continue;
+            }
+            let resource = self.resources.get(&(binding.set, binding.binding)).ok_or_else(|| ComputeError::Unbound(name.clone()))?;
+            resources[binding.set as usize].push((binding.binding, binding.kind, resource.clone()));
+        }
+
+        let device = shader.pipeline.device().clone();
+        let sets: Vec<BoundSet> = resources.iter()
+                                           .enumerate()
+                                           .map(|(set, resources)| BoundSet::new(&device, shader.pipeline.descriptor_set_layout(set).unwrap().clone(), resources))
+                                           .collect();
+        let push_constants = self.push_constants.unwrap_or(PushConstants([0; MAX_PUSH_CONSTANTS / 4]));
+        builder.dispatch(shader.group_counts(size), shader.pipeline.clone(), sets, push_constants, vec![])?;
+        Ok(())
+    }
+
+    /// Dispatches on `queue` and waits for the results.
+    pub fn run(self, queue: &Arc<Queue>, size: [u32; 3]) -> Result<(), ComputeError> {
+        let _span = tracing::debug_span!(target: logging::RENDER, "compute", shader = self.shader.name.as_str(), ?size).entered();
+        let mut builder = AutoCommandBufferBuilder::primary(queue.device().clone(), queue.family(), CommandBufferUsage::OneTimeSubmit).unwrap();
+        self.record(&mut builder, size)?;
+        submit(queue, builder);
+        Ok(())
+    }
+}
+
+fn submit(queue: &Arc<Queue>, builder: AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+    let command_buffer = builder.build().unwrap();
+    sync::now(queue.device().clone()).then_execute(queue.clone(), command_buffer)
+                                     .expect("failed to submit compute work")
+                                     .then_signal_fence_and_flush()
+                                     .unwrap()
+                                     .wait(None)
+                                     .unwrap();
+}
+
+/// A device with a single compute queue and no surface, for GPGPU jobs run outside of the renderer.
+pub struct Compute {
+    pub device: Arc<Device>,
+    pub queue: Arc<Queue>,
+    pub namer: ObjectNamer,
+}
+
+impl Compute {
+    pub fn headless(instance: &Arc<Instance>, debug: &Debug) -> Compute {
+        let physical = PhysicalDevice::enumerate(instance).next().expect("no device available");
+        let family = physical.queue_families().find(|&q| q.supports_compute()).expect("couldn't find a compute queue family");
+        let extensions = DeviceExtensions { khr_storage_buffer_storage_class: true, ..DeviceExtensions::none() }.intersection(&DeviceExtensions::supported_by_device(physical));
+
+        let (device, mut queues) = Device::new(physical, &Features::none(), &extensions, [(family, 1.0)].iter().cloned()).expect("failed device creation");
+        info!(target: logging::DEVICE, device = physical.name(), family = family.id(), "created headless compute device");
+
+        Compute {
+            namer: ObjectNamer::new(device.clone(), debug),
+            device,
+            queue: queues.next().unwrap(),
+        }
+    }
+
+    pub fn shader(&self, name: &str, source: &str) -> Result<ComputeShader, ComputeError> {
+        ComputeShader::from_glsl(self.device.clone(), &self.namer, name, source)
+    }
+
+    /// A host visible buffer holding `data`, for jobs to read or write and the CPU to read back.
+    pub fn buffer<T: Content + Copy + Send + Sync + 'static>(&self, data: &[T]) -> Arc<CpuAccessibleBuffer<[T]>> {
+        CpuAccessibleBuffer::from_iter(self.device.clone(), BufferUsage::all(), true, data.iter().cloned()).unwrap()
+    }
+
+    pub fn read<T: Content + Copy + Send + Sync + 'static>(&self, buffer: &CpuAccessibleBuffer<[T]>) -> Vec<T> {
+        buffer.read().expect("buffer is still in use by the GPU").to_vec()
+    }
+
+    /// A 2D image jobs can write to and sample.
+    pub fn image(&self, dimensions: [u32; 2], format: Format) -> Arc<ImageView<Arc<StorageImage>>> {
+        let dimensions = ImageDimensions::Dim2d { width: dimensions[0], height: dimensions[1], array_layers: 1 };
+        let image = StorageImage::new(self.device.clone(), dimensions, format, Some(self.queue.family())).unwrap();
+        ImageView::new(image).unwrap()
+    }
+
+    /// The texels of an image made by `image`, tightly packed row after row.
+    pub fn read_image(&self, image: &Arc<ImageView<Arc<StorageImage>>>) -> Vec<u8> {
+        let image = ImageView::image(image).clone();
+        let [width, height] = image.dimensions().width_height();
+        let texel_size = image.format().size().expect("image format has no texel size");
+        let buffer = CpuAccessibleBuffer::from_iter(self.device.clone(),
+                                                    BufferUsage::transfer_destination(),
+                                                    true,
+                                                    (0..width as usize * height as usize * texel_size).map(|_| 0u8)).unwrap();
+
+        let mut builder = AutoCommandBufferBuilder::primary(self.device.clone(), self.queue.family(), CommandBufferUsage::OneTimeSubmit).unwrap();
+        builder.copy_image_to_buffer(image, buffer.clone()).unwrap();
+        submit(&self.queue, builder);
+
+        self.read(&buffer)
+    }
+
+    pub fn run(&self, job: ComputeJob, size: [u32; 3]) -> Result<(), ComputeError> {
+        job.run(&self.queue, size)
+    }
+}
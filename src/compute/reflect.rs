@@ -0,0 +1,480 @@
+use std::collections::HashMap;
+
+use vulkano::descriptor::descriptor::{DescriptorBufferDesc, DescriptorDesc, DescriptorDescTy, DescriptorImageDesc, DescriptorImageDescArray, DescriptorImageDescDimensions, ShaderStages};
+
+const MAGIC: u32 = 0x0723_0203;
+
+// Opcodes.
+const OP_NAME: u32 = 5;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+// Operands.
+const EXECUTION_MODEL_GL_COMPUTE: u32 = 5;
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+const DIM_1D: u32 = 0;
+const DIM_2D: u32 = 1;
+const DIM_3D: u32 = 2;
+const DIM_CUBE: u32 = 3;
+
+/// What a shader expects to find at a binding.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BindingKind {
+    UniformBuffer,
+    StorageBuffer,
+    StorageImage,
+    SampledImage,
+    Sampler,
+    CombinedImageSampler,
+}
+
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub set: u32,
+    pub binding: u32,
+    pub kind: BindingKind,
+    pub(crate) desc: DescriptorDesc,
+}
+
+/// The interface of a compute shader, read back from its SPIR-V.
+#[derive(Debug, Clone)]
+pub struct Reflection {
+    pub entry_point: String,
+    pub local_size: [u32; 3],
+    /// Bindings by the name of their variable, and of their block for buffers.
+    pub bindings: HashMap<String, Binding>,
+    pub push_constants_size: usize,
+}
+
+impl Reflection {
+    /// Every set up to the highest one used, each with a slot per binding up to its highest.
+    pub(crate) fn sets(&self) -> Vec<Vec<Option<DescriptorDesc>>> {
+        let mut sets: Vec<Vec<Option<DescriptorDesc>>> = vec![];
+        for binding in self.bindings.values() {
+            let (set, index) = (binding.set as usize, binding.binding as usize);
+            if sets.len() <= set {
+                sets.resize(set + 1, vec![]);
+            }
+            if sets[set].len() <= index {
+                sets[set].resize(index + 1, None);
+            }
+            sets[set][index] = Some(binding.desc.clone());
+        }
+        sets
+    }
+}
+
+enum Type {
+    Scalar { bytes: usize },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, arrayed: bool, multisampled: bool, storage: bool },
+    Sampler,
+    SampledImage { image: u32 },
+    Array { element: u32, length: u32 },
+    RuntimeArray,
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+}
+
+#[derive(Default)]
+struct Decorations {
+    set: Option<u32>,
+    binding: Option<u32>,
+    buffer_block: bool,
+    array_stride: Option<u32>,
+}
+
+struct Module {
+    names: HashMap<u32, String>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    decorations: HashMap<u32, Decorations>,
+    // Offset and matrix stride of struct members.
+    members: HashMap<(u32, u32), (u32, Option<u32>)>,
+}
+
+impl Module {
+    fn name(&self, id: u32) -> Option<&str> {
+        self.names.get(&id).map(String::as_str).filter(|name| !name.is_empty())
+    }
+
+    fn decorations(&self, id: u32) -> Option<&Decorations> {
+        self.decorations.get(&id)
+    }
+
+    /// Size in bytes of a type laid out with its explicit offsets and strides.
+    fn size_of(&self, id: u32) -> usize {
+        match self.types.get(&id) {
+            Some(Type::Scalar { bytes }) => *bytes,
+            Some(Type::Vector { component, count }) => self.size_of(*component) * *count as usize,
+            Some(Type::Matrix { column, count }) => self.size_of(*column) * *count as usize,
+            Some(Type::Array { element, length }) => {
+                let stride = self.decorations(id).and_then(|d| d.array_stride).map(|s| s as usize).unwrap_or_else(|| self.size_of(*element));
+                stride * self.constants.get(length).cloned().unwrap_or(0) as usize
+            }
+            Some(Type::Struct { members }) => {
+                members.iter()
+                       .enumerate()
+                       .map(|(i, &member)| {
+                           let (offset, matrix_stride) = self.members.get(&(id, i as u32)).cloned().unwrap_or((0, None));
+                           let size = match (self.types.get(&member), matrix_stride) {
+                               (Some(Type::Matrix { count, .. }), Some(stride)) => (stride * count) as usize,
+                               _ => self.size_of(member),
+                           };
+                           offset as usize + size
+                       })
+                       .max()
+                       .unwrap_or(0)
+            }
+            _ => 0,
+        }
+    }
+}
+
+/// Reads the entry point, workgroup size, descriptor bindings and push constants of a compute shader.
+pub fn reflect(words: &[u32]) -> Result<Reflection, String> {
+    if words.len() < 5 || words[0] != MAGIC {
+        return Err("not SPIR-V".to_string());
+    }
+
+    let mut module = Module {
+        names: HashMap::new(),
+        types: HashMap::new(),
+        constants: HashMap::new(),
+        decorations: HashMap::new(),
+        members: HashMap::new(),
+    };
+    let mut entry_point = None;
+    let mut local_size = None;
+    // Id, pointer type and storage class of every global variable.
+    let mut variables = vec![];
+
+    let mut i = 5;
+    while i < words.len() {
+        let count = (words[i] >> 16) as usize;
+        let opcode = words[i] & 0xffff;
+        if count == 0 || i + count > words.len() {
+            return Err(format!("truncated instruction at word {}", i));
+        }
+        let operands = &words[i + 1..i + count];
+        let at = i;
+        i += count;
+
+        // The operands read below, or at least as many as every instruction of the opcode has.
+        let needed = match opcode {
+            OP_TYPE_SAMPLER | OP_TYPE_STRUCT => 1,
+            OP_NAME | OP_EXECUTION_MODE | OP_TYPE_INT | OP_TYPE_FLOAT | OP_TYPE_SAMPLED_IMAGE | OP_TYPE_RUNTIME_ARRAY | OP_DECORATE => 2,
+            OP_ENTRY_POINT | OP_TYPE_VECTOR | OP_TYPE_MATRIX | OP_TYPE_ARRAY | OP_TYPE_POINTER | OP_CONSTANT | OP_VARIABLE | OP_MEMBER_DECORATE => 3,
+            OP_TYPE_IMAGE => 8,
+            _ => 0,
+        };
+        operand_count(operands, needed, opcode, at)?;
+
+        match opcode {
+            OP_NAME => {
+                module.names.insert(operands[0], string(&operands[1..]));
+            }
+            OP_ENTRY_POINT if operands[0] == EXECUTION_MODEL_GL_COMPUTE && entry_point.is_none() => {
+                entry_point = Some((operands[1], string(&operands[2..])));
+            }
+            OP_EXECUTION_MODE if operands[1] == EXECUTION_MODE_LOCAL_SIZE => {
+                operand_count(operands, 5, opcode, at)?;
+                local_size = Some((operands[0], [operands[2], operands[3], operands[4]]));
+            }
+            OP_TYPE_INT | OP_TYPE_FLOAT => {
+                module.types.insert(operands[0], Type::Scalar { bytes: operands[1] as usize / 8 });
+            }
+            OP_TYPE_VECTOR => {
+                module.types.insert(operands[0], Type::Vector { component: operands[1], count: operands[2] });
+            }
+            OP_TYPE_MATRIX => {
+                module.types.insert(operands[0], Type::Matrix { column: operands[1], count: operands[2] });
+            }
+            OP_TYPE_IMAGE => {
+                module.types.insert(operands[0],
+                                    Type::Image {
+                                        dim: operands[2],
+                                        arrayed: operands[4] != 0,
+                                        multisampled: operands[5] != 0,
+                                        storage: operands[6] == 2,
+                                    });
+            }
+            OP_TYPE_SAMPLER => {
+                module.types.insert(operands[0], Type::Sampler);
+            }
+            OP_TYPE_SAMPLED_IMAGE => {
+                module.types.insert(operands[0], Type::SampledImage { image: operands[1] });
+            }
+            OP_TYPE_ARRAY => {
+                module.types.insert(operands[0], Type::Array { element: operands[1], length: operands[2] });
+            }
+            OP_TYPE_RUNTIME_ARRAY => {
+                module.types.insert(operands[0], Type::RuntimeArray);
+            }
+            OP_TYPE_STRUCT => {
+                module.types.insert(operands[0], Type::Struct { members: operands[1..].to_vec() });
+            }
+            OP_TYPE_POINTER => {
+                module.types.insert(operands[0], Type::Pointer { pointee: operands[2] });
+            }
+            OP_CONSTANT => {
+                module.constants.insert(operands[1], operands[2]);
+            }
+            OP_VARIABLE => {
+                variables.push((operands[1], operands[0], operands[2]));
+            }
+            OP_DECORATE => {
+                if matches!(operands[1], DECORATION_ARRAY_STRIDE | DECORATION_BINDING | DECORATION_DESCRIPTOR_SET) {
+                    operand_count(operands, 3, opcode, at)?;
+                }
+                let decorations = module.decorations.entry(operands[0]).or_default();
+                match operands[1] {
+                    DECORATION_BUFFER_BLOCK => decorations.buffer_block = true,
+                    DECORATION_ARRAY_STRIDE => decorations.array_stride = Some(operands[2]),
+                    DECORATION_BINDING => decorations.binding = Some(operands[2]),
+                    DECORATION_DESCRIPTOR_SET => decorations.set = Some(operands[2]),
+                    _ => {}
+                }
+            }
+            OP_MEMBER_DECORATE => {
+                if matches!(operands[2], DECORATION_OFFSET | DECORATION_MATRIX_STRIDE) {
+                    operand_count(operands, 4, opcode, at)?;
+                }
+                let member = module.members.entry((operands[0], operands[1])).or_insert((0, None));
+                match operands[2] {
+                    DECORATION_OFFSET => member.0 = operands[3],
+                    DECORATION_MATRIX_STRIDE => member.1 = Some(operands[3]),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let (entry_id, entry_point) = entry_point.ok_or_else(|| "no compute entry point".to_string())?;
+    let local_size = match local_size {
+        Some((id, size)) if id == entry_id => size,
+        _ => return Err(format!("no workgroup size for entry point {}", entry_point)),
+    };
+
+    let mut bindings = HashMap::new();
+    let mut push_constants_size = 0;
+    for (id, pointer, storage) in variables {
+        let pointee = match module.types.get(&pointer) {
+            Some(Type::Pointer { pointee }) => *pointee,
+            _ => continue,
+        };
+        if storage == STORAGE_PUSH_CONSTANT {
+            // Push constant ranges are whole words.
+            push_constants_size = (module.size_of(pointee) + 3) & !3;
+            continue;
+        }
+
+        let kind = match (storage, module.types.get(&pointee)) {
+            (STORAGE_UNIFORM_CONSTANT, Some(Type::Image { storage: true, .. })) => BindingKind::StorageImage,
+            (STORAGE_UNIFORM_CONSTANT, Some(Type::Image { .. })) => BindingKind::SampledImage,
+            (STORAGE_UNIFORM_CONSTANT, Some(Type::Sampler)) => BindingKind::Sampler,
+            (STORAGE_UNIFORM_CONSTANT, Some(Type::SampledImage { .. })) => BindingKind::CombinedImageSampler,
+            (STORAGE_UNIFORM_CONSTANT, Some(Type::Array { .. })) => {
+                return Err(format!("{} is an array of descriptors, which isn't supported", module.name(id).unwrap_or("a binding")));
+            }
+            (STORAGE_UNIFORM, Some(Type::Struct { .. })) if matches!(module.decorations(pointee), Some(Decorations { buffer_block: true, .. })) => BindingKind::StorageBuffer,
+            (STORAGE_UNIFORM, Some(Type::Struct { .. })) => BindingKind::UniformBuffer,
+            (STORAGE_STORAGE_BUFFER, Some(Type::Struct { .. })) => BindingKind::StorageBuffer,
+            _ => continue,
+        };
+
+        let decorations = module.decorations(id);
+        let (set, binding) = match decorations.map(|d| (d.set, d.binding)) {
+            Some((Some(set), Some(binding))) => (set, binding),
+            _ => return Err(format!("{} has no set or binding", module.name(id).unwrap_or("a binding"))),
+        };
+        let image = match kind {
+            BindingKind::CombinedImageSampler => match module.types.get(&pointee) {
+                Some(Type::SampledImage { image }) => module.types.get(image),
+                _ => None,
+            },
+            _ => module.types.get(&pointee),
+        };
+        let desc = DescriptorDesc {
+            ty: descriptor_ty(kind, image)?,
+            array_count: 1,
+            stages: ShaderStages { compute: true, ..ShaderStages::none() },
+            readonly: !matches!(kind, BindingKind::StorageBuffer | BindingKind::StorageImage),
+        };
+        let entry = Binding { set, binding, kind, desc };
+
+        // Blocks answer to their type name too, which is all an anonymous GLSL block has.
+        if let Some(name) = module.name(pointee).filter(|_| kind == BindingKind::UniformBuffer || kind == BindingKind::StorageBuffer) {
+            bindings.entry(name.to_string()).or_insert_with(|| entry.clone());
+        }
+        if let Some(name) = module.name(id) {
+            bindings.insert(name.to_string(), entry);
+        }
+    }
+
+    Ok(Reflection { entry_point, local_size, bindings, push_constants_size })
+}
+
+fn descriptor_ty(kind: BindingKind, image: Option<&Type>) -> Result<DescriptorDescTy, String> {
+    let image = |sampled| match image {
+        Some(&Type::Image { dim, arrayed, multisampled, .. }) => {
+            let dimensions = match dim {
+                DIM_1D => DescriptorImageDescDimensions::OneDimensional,
+                DIM_2D => DescriptorImageDescDimensions::TwoDimensional,
+                DIM_3D => DescriptorImageDescDimensions::ThreeDimensional,
+                DIM_CUBE => DescriptorImageDescDimensions::Cube,
+                _ => return Err(format!("unsupported image dimensionality {}", dim)),
+            };
+            Ok(DescriptorImageDesc {
+                sampled,
+                dimensions,
+                format: None,
+                multisampled,
+                array_layers: if arrayed { DescriptorImageDescArray::Arrayed { max_layers: None } } else { DescriptorImageDescArray::NonArrayed },
+            })
+        }
+        _ => Err("binding isn't an image".to_string()),
+    };
+
+    Ok(match kind {
+        BindingKind::UniformBuffer => DescriptorDescTy::Buffer(DescriptorBufferDesc { dynamic: Some(false), storage: false }),
+        BindingKind::StorageBuffer => DescriptorDescTy::Buffer(DescriptorBufferDesc { dynamic: Some(false), storage: true }),
+        BindingKind::StorageImage => DescriptorDescTy::Image(image(false)?),
+        BindingKind::SampledImage => DescriptorDescTy::Image(image(true)?),
+        BindingKind::Sampler => DescriptorDescTy::Sampler,
+        BindingKind::CombinedImageSampler => DescriptorDescTy::CombinedImageSampler(image(true)?),
+    })
+}
+
+fn operand_count(operands: &[u32], needed: usize, opcode: u32, at: usize) -> Result<(), String> {
+    if operands.len() < needed {
+        return Err(format!("instruction {} at word {} has {} operands, expected at least {}", opcode, at, operands.len(), needed));
+    }
+    Ok(())
+}
+
+/// A nul terminated UTF-8 literal, packed little endian into words.
+fn string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes().to_vec()).take_while(|&byte| byte != 0).collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADER: &str = "
+        #version 450
+
+        layout(local_size_x = 8, local_size_y = 4, local_size_z = 2) in;
+
+        layout(set = 0, binding = 0) uniform Params {
+            vec4 scale;
+        } params;
+
+        layout(set = 0, binding = 1) buffer Data {
+            float values[];
+        } data;
+
+        layout(set = 1, binding = 0, rgba8) uniform image2D target;
+        layout(set = 1, binding = 1) uniform sampler2D source;
+        layout(set = 1, binding = 2) uniform texture2DArray layers;
+        layout(set = 1, binding = 3) uniform sampler point;
+
+        layout(push_constant) uniform Push {
+            mat4 transform;
+            vec2 offset;
+        } push;
+
+        void main() {
+            ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+            vec4 color = textureLod(source, vec2(pixel), 0.0) + textureLod(sampler2DArray(layers, point), vec3(push.offset, 0.0), 0.0);
+            imageStore(target, pixel, push.transform * color * params.scale);
+            data.values[gl_GlobalInvocationID.x] = color.r;
+        }
+    ";
+
+    fn reflect_shader() -> Reflection {
+        let mut compiler = shaderc::Compiler::new().unwrap();
+        let spirv = compiler.compile_into_spirv(SHADER, shaderc::ShaderKind::Compute, "test.comp", "main", None).unwrap();
+        reflect(spirv.as_binary()).unwrap()
+    }
+
+    fn binding(reflection: &Reflection, name: &str) -> (u32, u32, BindingKind) {
+        let binding = &reflection.bindings[name];
+        (binding.set, binding.binding, binding.kind)
+    }
+
+    #[test]
+    fn entry_point_and_local_size() {
+        let reflection = reflect_shader();
+        assert_eq!(reflection.entry_point, "main");
+        assert_eq!(reflection.local_size, [8, 4, 2]);
+    }
+
+    #[test]
+    fn buffers() {
+        let reflection = reflect_shader();
+        assert_eq!(binding(&reflection, "params"), (0, 0, BindingKind::UniformBuffer));
+        assert_eq!(binding(&reflection, "Params"), (0, 0, BindingKind::UniformBuffer));
+        assert_eq!(binding(&reflection, "data"), (0, 1, BindingKind::StorageBuffer));
+        assert!(!reflection.bindings["data"].desc.readonly);
+    }
+
+    #[test]
+    fn images_and_samplers() {
+        let reflection = reflect_shader();
+        assert_eq!(binding(&reflection, "target"), (1, 0, BindingKind::StorageImage));
+        assert_eq!(binding(&reflection, "source"), (1, 1, BindingKind::CombinedImageSampler));
+        assert_eq!(binding(&reflection, "layers"), (1, 2, BindingKind::SampledImage));
+        assert_eq!(binding(&reflection, "point"), (1, 3, BindingKind::Sampler));
+        match reflection.bindings["layers"].desc.ty {
+            DescriptorDescTy::Image(ref image) => {
+                assert!(image.sampled);
+                assert_eq!(image.dimensions, DescriptorImageDescDimensions::TwoDimensional);
+                assert_eq!(image.array_layers, DescriptorImageDescArray::Arrayed { max_layers: None });
+            }
+            ref ty => panic!("layers is a {:?}", ty),
+        }
+    }
+
+    #[test]
+    fn push_constants() {
+        assert_eq!(reflect_shader().push_constants_size, 72);
+    }
+
+    #[test]
+    fn missing_operands() {
+        let header = [MAGIC, 0x0001_0000, 0, 100, 0];
+        // An OpName with only its target, and an OpTypeImage without its format.
+        assert!(reflect(&[&header[..], &[2 << 16 | OP_NAME, 1]].concat()).is_err());
+        assert!(reflect(&[&header[..], &[8 << 16 | OP_TYPE_IMAGE, 1, 2, DIM_2D, 0, 0, 0, 1]].concat()).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use std::sync::Arc;
+
+use vulkano::buffer::BufferAccess;
+use vulkano::descriptor::descriptor::DescriptorDesc;
+use vulkano::descriptor::descriptor_set::{DescriptorPool, DescriptorPoolAlloc, DescriptorSet, DescriptorSetDesc, DescriptorWrite, StdDescriptorPoolAlloc, UnsafeDescriptorSet, UnsafeDescriptorSetLayout};
+use vulkano::device::{Device, DeviceOwned};
+use vulkano::image::view::ImageViewAbstract;
+use vulkano::sampler::Sampler;
+
+use crate::compute::reflect::BindingKind;
+
+/// A resource bound to a compute job by name. Samplers only come combined with an image.
+#[derive(Clone)]
+pub enum Resource {
+    Buffer(Arc<dyn BufferAccess + Send + Sync>),
+    Image(Arc<dyn ImageViewAbstract + Send + Sync>),
+    SampledImage(Arc<dyn ImageViewAbstract + Send + Sync>, Arc<Sampler>),
+}
+
+impl Resource {
+    pub(crate) fn fits(&self, kind: BindingKind) -> bool {
+        match self {
+            Resource::Buffer(_) => kind == BindingKind::UniformBuffer || kind == BindingKind::StorageBuffer,
+            Resource::Image(_) => kind == BindingKind::StorageImage || kind == BindingKind::SampledImage,
+            Resource::SampledImage(..) => kind == BindingKind::CombinedImageSampler,
+        }
+    }
+}
+
+/// Descriptor set written from resources picked at runtime, which `PersistentDescriptorSet`'s typed builder can't
+/// take. Keeps the resources alive and hands them to the command buffer for synchronization.
+pub(crate) struct BoundSet {
+    inner: StdDescriptorPoolAlloc,
+    layout: Arc<UnsafeDescriptorSetLayout>,
+    buffers: Vec<(Arc<dyn BufferAccess + Send + Sync>, u32)>,
+    images: Vec<(Arc<dyn ImageViewAbstract + Send + Sync>, u32)>,
+    // Samplers only need to outlive the set.
+    _samplers: Vec<Arc<Sampler>>,
+}
+
+impl BoundSet {
+    /// Writes the binding, kind and resource of every descriptor of `layout` given in `resources`.
+    pub fn new(device: &Arc<Device>, layout: Arc<UnsafeDescriptorSetLayout>, resources: &[(u32, BindingKind, Resource)]) -> BoundSet {
+        let mut inner = Device::standard_descriptor_pool(device).alloc(&layout).unwrap();
+        let mut buffers = vec![];
+        let mut images = vec![];
+        let mut samplers = vec![];
+
+        let writes: Vec<DescriptorWrite> = resources.iter()
+                                                    .map(|(binding, kind, resource)| {
+                                                        let binding = *binding;
+                                                        match resource {
+                                                            Resource::Buffer(buffer) => {
+                                                                buffers.push((buffer.clone(), binding));
+                                                                // Safe as long as the buffer's range is valid for the shader, which we
+                                                                // can't know any better than the caller.
+                                                                unsafe {
+                                                                    if *kind == BindingKind::StorageBuffer {
+                                                                        DescriptorWrite::storage_buffer(binding, 0, buffer)
+                                                                    } else {
+                                                                        DescriptorWrite::uniform_buffer(binding, 0, buffer)
+                                                                    }
+                                                                }
+                                                            }
+                                                            Resource::Image(image) => {
+                                                                images.push((image.clone(), binding));
+                                                                if *kind == BindingKind::StorageImage {
+                                                                    DescriptorWrite::storage_image(binding, 0, image)
+                                                                } else {
+                                                                    DescriptorWrite::sampled_image(binding, 0, image)
+                                                                }
+                                                            }
+                                                            Resource::SampledImage(image, sampler) => {
+                                                                images.push((image.clone(), binding));
+                                                                samplers.push(sampler.clone());
+                                                                DescriptorWrite::combined_image_sampler(binding, 0, sampler, image)
+                                                            }
+                                                        }
+                                                    })
+                                                    .collect();
+        unsafe {
+            inner.inner_mut().write(device, writes.into_iter());
+        }
+
+        BoundSet { inner, layout, buffers, images, _samplers: samplers }
+    }
+}
+
+unsafe impl DescriptorSet for BoundSet {
+    fn inner(&self) -> &UnsafeDescriptorSet {
+        self.inner.inner()
+    }
+
+    fn num_buffers(&self) -> usize {
+        self.buffers.len()
+    }
+
+    fn buffer(&self, index: usize) -> Option<(&dyn BufferAccess, u32)> {
+        self.buffers.get(index).map(|(buffer, binding)| (&**buffer as &dyn BufferAccess, *binding))
+    }
+
+    fn num_images(&self) -> usize {
+        self.images.len()
+    }
+
+    fn image(&self, index: usize) -> Option<(&dyn ImageViewAbstract, u32)> {
+        self.images.get(index).map(|(image, binding)| (&**image as &dyn ImageViewAbstract, *binding))
+    }
+}
+
+unsafe impl DescriptorSetDesc for BoundSet {
+    fn num_bindings(&self) -> usize {
+        self.layout.num_bindings()
+    }
+
+    fn descriptor(&self, binding: usize) -> Option<DescriptorDesc> {
+        self.layout.descriptor(binding)
+    }
+}
+
+unsafe impl DeviceOwned for BoundSet {
+    fn device(&self) -> &Arc<Device> {
+        self.layout.device()
+    }
+}
@@ -0,0 +1,97 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix, Matrix4, Point3, Transform, Vector3, Vector4};
+
+/// Axis-aligned bounding box. Empty when `min` is greater than `max`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_points<I: IntoIterator<Item = Point3<f32>>>(points: I) -> Aabb {
+        points.into_iter().fold(Aabb::empty(), |bounds, point| bounds.union(&Aabb { min: point, max: point }))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Point3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            max: Point3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        }
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    pub fn half_extents(&self) -> Vector3<f32> {
+        (self.max - self.min) / 2.0
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let size = self.max - self.min;
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    /// The box around this one after `transform`, which is looser than the transformed box when it rotates.
+    pub fn transform(&self, transform: &Matrix4<f32>) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+        let center = transform.transform_point(self.center());
+        let half_extents = self.half_extents();
+        let extent = |row: usize| abs(transform.row(row).truncate()).dot(half_extents);
+        let half_extents = Vector3::new(extent(0), extent(1), extent(2));
+        Aabb { min: center - half_extents, max: center + half_extents }
+    }
+}
+
+/// The six planes bounding what a view projection matrix sees, pointing inwards.
+#[derive(Debug, Copy, Clone)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// The frustum of a Vulkan view projection matrix, with depth from 0 to 1, like `Camera::projection` times
+    /// `Camera::view`.
+    pub fn from_matrix(view_projection: Matrix4<f32>) -> Frustum {
+        let (x, y, z, w) = (view_projection.row(0), view_projection.row(1), view_projection.row(2), view_projection.row(3));
+        let normalize = |plane: Vector4<f32>| plane / plane.truncate().magnitude();
+        Frustum { planes: [normalize(w + x), normalize(w - x), normalize(w + y), normalize(w - y), normalize(z), normalize(w - z)] }
+    }
+
+    /// Whether any of `bounds` may be inside. Boxes near the corners can be taken as inside when they aren't.
+    pub fn intersects(&self, bounds: &Aabb) -> bool {
+        let (center, half_extents) = (bounds.center().to_vec(), bounds.half_extents());
+        !bounds.is_empty() && self.planes.iter().all(|plane| plane.truncate().dot(center) + plane.w + abs(plane.truncate()).dot(half_extents) >= 0.0)
+    }
+
+    /// Whether all of `bounds` is inside.
+    pub fn contains(&self, bounds: &Aabb) -> bool {
+        let (center, half_extents) = (bounds.center().to_vec(), bounds.half_extents());
+        !bounds.is_empty() && self.planes.iter().all(|plane| plane.truncate().dot(center) + plane.w - abs(plane.truncate()).dot(half_extents) >= 0.0)
+    }
+
+    /// Normals in `xyz` and distances from the origin in `w`, as `cull.comp` takes them.
+    pub fn planes(&self) -> [[f32; 4]; 6] {
+        self.planes.map(Into::into)
+    }
+}
+
+fn abs(vector: Vector3<f32>) -> Vector3<f32> {
+    Vector3::new(vector.x.abs(), vector.y.abs(), vector.z.abs())
+}
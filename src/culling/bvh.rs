@@ -0,0 +1,235 @@
+use std::cmp::Ordering;
+
+use cgmath::Matrix4;
+
+use crate::culling::bounds::{Aabb, Frustum};
+use crate::scene::Object;
+
+/// How much looser refitting may make the tree, in the summed area of its nodes, before it is built again.
+const REBUILD_RATIO: f32 = 2.0;
+
+/// Bounding volume hierarchy over the world bounds of a scene's objects, for finding the ones a frustum sees.
+///
+/// Moving objects or changing their meshes only refits the boxes around them, adding or removing objects builds the
+/// tree again. `update` has to be called once every frame after the objects have moved.
+#[derive(Default)]
+pub struct Bvh {
+    // Every parent before its children.
+    nodes: Vec<Node>,
+    objects: Vec<Tracked>,
+    // Summed area of the nodes right after the last build.
+    built_area: f32,
+}
+
+struct Node {
+    bounds: Aabb,
+    content: Content,
+}
+
+enum Content {
+    Object(usize),
+    Children(usize, usize),
+}
+
+/// What the tree was built or refit from, to tell what changed.
+struct Tracked {
+    mesh_bounds: Aabb,
+    transform: Matrix4<f32>,
+    bounds: Aabb,
+    node: usize,
+}
+
+impl Bvh {
+    pub fn update(&mut self, objects: &[Object]) {
+        self.update_bounds(objects.iter().map(|object| (object.mesh.bounds, object.transform)));
+    }
+
+    /// `update` from the mesh bounds and transform of every object.
+    fn update_bounds<I: ExactSizeIterator<Item = (Aabb, Matrix4<f32>)> + Clone>(&mut self, objects: I) {
+        if objects.len() != self.objects.len() {
+            self.build(objects);
+            return;
+        }
+
+        let mut moved = false;
+        for ((mesh_bounds, transform), tracked) in objects.clone().zip(self.objects.iter_mut()) {
+            if transform != tracked.transform || mesh_bounds != tracked.mesh_bounds {
+                tracked.transform = transform;
+                tracked.mesh_bounds = mesh_bounds;
+                tracked.bounds = mesh_bounds.transform(&transform);
+                self.nodes[tracked.node].bounds = tracked.bounds;
+                moved = true;
+            }
+        }
+        if moved {
+            self.refit();
+            if self.area() > self.built_area * REBUILD_RATIO {
+                self.build(objects);
+            }
+        }
+    }
+
+    /// World bounds of an object as of the last update.
+    pub fn bounds(&self, object: usize) -> Aabb {
+        self.objects[object].bounds
+    }
+
+    /// Replaces `visible` with the indices of the objects `frustum` may see, in increasing order.
+    pub fn cull(&self, frustum: &Frustum, visible: &mut Vec<usize>) {
+        visible.clear();
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        // Nodes and whether their parent was entirely inside, when they don't have to be tested.
+        let mut stack = vec![(0, false)];
+        while let Some((index, inside)) = stack.pop() {
+            let node = &self.nodes[index];
+            if !inside && !frustum.intersects(&node.bounds) {
+                continue;
+            }
+            match node.content {
+                Content::Object(object) => visible.push(object),
+                Content::Children(left, right) => {
+                    let inside = inside || frustum.contains(&node.bounds);
+                    stack.push((right, inside));
+                    stack.push((left, inside));
+                }
+            }
+        }
+        visible.sort_unstable();
+    }
+
+    fn build<I: Iterator<Item = (Aabb, Matrix4<f32>)>>(&mut self, objects: I) {
+        self.objects = objects.map(|(mesh_bounds, transform)| Tracked { mesh_bounds, transform, bounds: mesh_bounds.transform(&transform), node: 0 })
+                              .collect();
+        self.nodes.clear();
+        if !self.objects.is_empty() {
+            let mut indices: Vec<usize> = (0..self.objects.len()).collect();
+            self.build_node(&mut indices);
+        }
+        self.built_area = self.area();
+    }
+
+    /// Adds the node over `objects` and the ones below it, splitting the objects in half along the axis their
+    /// centers are most spread out on.
+    fn build_node(&mut self, objects: &mut [usize]) -> usize {
+        let index = self.nodes.len();
+        let bounds = objects.iter().fold(Aabb::empty(), |bounds, &object| bounds.union(&self.objects[object].bounds));
+        if let [object] = *objects {
+            self.nodes.push(Node { bounds, content: Content::Object(object) });
+            self.objects[object].node = index;
+            return index;
+        }
+        self.nodes.push(Node { bounds, content: Content::Children(0, 0) });
+
+        let centers = Aabb::from_points(objects.iter().map(|&object| self.objects[object].bounds.center()));
+        let spread = centers.max - centers.min;
+        let axis = if spread.x >= spread.y && spread.x >= spread.z {
+            0
+        } else if spread.y >= spread.z {
+            1
+        } else {
+            2
+        };
+        let middle = objects.len() / 2;
+        let tracked = &self.objects;
+        objects.select_nth_unstable_by(middle, |&a, &b| tracked[a].bounds.center()[axis].partial_cmp(&tracked[b].bounds.center()[axis]).unwrap_or(Ordering::Equal));
+
+        let (left, right) = objects.split_at_mut(middle);
+        let left = self.build_node(left);
+        let right = self.build_node(right);
+        self.nodes[index].content = Content::Children(left, right);
+        index
+    }
+
+    /// Grows or shrinks every parent's bounds to its children's, children first.
+    fn refit(&mut self) {
+        for index in (0..self.nodes.len()).rev() {
+            if let Content::Children(left, right) = self.nodes[index].content {
+                self.nodes[index].bounds = self.nodes[left].bounds.union(&self.nodes[right].bounds);
+            }
+        }
+    }
+
+    fn area(&self) -> f32 {
+        self.nodes.iter().map(|node| node.bounds.surface_area()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, Point3, Vector3};
+
+    use super::*;
+    use crate::scene::{Camera, RenderPath};
+
+    /// A unit cube around every point of a 20 by 4 by 20 grid, 2 apart.
+    fn grid() -> Vec<(Aabb, Matrix4<f32>)> {
+        let cube = Aabb { min: Point3::new(-0.5, -0.5, -0.5), max: Point3::new(0.5, 0.5, 0.5) };
+        (0..20 * 4 * 20).map(|i| (cube, Matrix4::from_translation(Vector3::new((i % 20) as f32 * 2.0 - 19.0, (i / 20 % 4) as f32 * 2.0, (i / 80) as f32 * 2.0 - 19.0))))
+                        .collect()
+    }
+
+    /// Looking along -z from `position`, seeing 50 units far.
+    fn frustum(position: Point3<f32>) -> Frustum {
+        let camera = Camera {
+            position,
+            target: position - Vector3::unit_z(),
+            up: Vector3::unit_y(),
+            fov_y: Deg(60.0).into(),
+            near: 0.1,
+            far: 50.0,
+            path: RenderPath::Forward,
+        };
+        Frustum::from_matrix(camera.projection(16.0 / 9.0) * camera.view())
+    }
+
+    fn brute_force(objects: &[(Aabb, Matrix4<f32>)], frustum: &Frustum) -> Vec<usize> {
+        (0..objects.len()).filter(|&object| frustum.intersects(&objects[object].0.transform(&objects[object].1))).collect()
+    }
+
+    #[test]
+    fn known_boxes() {
+        let frustum = frustum(Point3::new(0.0, 0.0, 0.0));
+        let cube = |x: f32, y: f32, z: f32| Aabb { min: Point3::new(x - 0.5, y - 0.5, z - 0.5), max: Point3::new(x + 0.5, y + 0.5, z + 0.5) };
+
+        assert!(frustum.contains(&cube(0.0, 0.0, -5.0)));
+        assert!(!frustum.intersects(&cube(0.0, 0.0, 5.0)));
+        assert!(!frustum.intersects(&cube(0.0, 0.0, -60.0)));
+        assert!(!frustum.intersects(&cube(20.0, 0.0, -5.0)));
+        // Across the far plane, seen but not entirely.
+        assert!(frustum.intersects(&cube(0.0, 0.0, -50.0)));
+        assert!(!frustum.contains(&cube(0.0, 0.0, -50.0)));
+        assert!(!frustum.intersects(&Aabb::empty()));
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mut objects = grid();
+        let mut bvh = Bvh::default();
+        bvh.update_bounds(objects.iter().cloned());
+        let mut visible = vec![];
+        for position in [Point3::new(0.0, 2.0, 25.0), Point3::new(-10.0, 4.0, 0.0), Point3::new(5.0, 2.0, -10.0)] {
+            let frustum = frustum(position);
+            bvh.cull(&frustum, &mut visible);
+            let expected = brute_force(&objects, &frustum);
+            assert!(!expected.is_empty() && expected.len() < objects.len());
+            assert_eq!(visible, expected);
+        }
+
+        // A few cubes moving a little only refits the tree, and far enough builds it again.
+        for (offset, rebuilt) in [(3.0, false), (100.0, true)] {
+            let built_area = bvh.built_area;
+            for object in (0..objects.len()).step_by(7) {
+                objects[object].1 = Matrix4::from_translation(Vector3::new(0.0, offset, -offset)) * objects[object].1;
+            }
+            bvh.update_bounds(objects.iter().cloned());
+            assert_eq!(bvh.built_area != built_area, rebuilt);
+            let frustum = frustum(Point3::new(0.0, 2.0, 25.0));
+            bvh.cull(&frustum, &mut visible);
+            assert_eq!(visible, brute_force(&objects, &frustum));
+            assert_eq!(bvh.bounds(7), objects[7].0.transform(&objects[7].1));
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use vulkano::buffer::{BufferUsage, CpuBufferPool, TypedBufferAccess};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::device::Device;
+use vulkano::pipeline::{ComputePipeline, ComputePipelineAbstract};
+use vulkano::render_pass::Subpass;
+
+use crate::culling::bounds::Frustum;
+use crate::culling::{Culling, CullingMode};
+use crate::debug::ObjectNamer;
+use crate::render_graph::{Pass, PassBuilder, PassContext};
+use crate::scene::Scene;
+
+mod cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/culling/cull.comp"
+    }
+}
+
+// Workgroup size of `cull.comp`.
+const GROUP_SIZE: u32 = 64;
+
+/// An object as `cull.comp` takes it.
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct GpuObject {
+    model: [[f32; 4]; 4],
+    min: [f32; 4],
+    max: [f32; 4],
+    index_count: u32,
+    padding: [u32; 3],
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct CullPush {
+    planes: [[f32; 4]; 6],
+    object_count: u32,
+}
+
+/// Culls the scene's objects against the camera on the GPU, writing the indirect draws the forward pass draws them
+/// with. Does nothing unless culling is `CullingMode::Gpu`.
+pub struct GpuCullPass {
+    culling: Culling,
+    namer: ObjectNamer,
+    objects: Option<CpuBufferPool<GpuObject>>,
+    pipeline: Option<Arc<dyn ComputePipelineAbstract + Send + Sync>>,
+}
+
+impl GpuCullPass {
+    pub fn new(culling: Culling, namer: &ObjectNamer) -> GpuCullPass {
+        GpuCullPass {
+            culling,
+            namer: namer.clone(),
+            objects: None,
+            pipeline: None,
+        }
+    }
+}
+
+impl Pass<Scene> for GpuCullPass {
+    fn declare(&mut self, pass: &mut PassBuilder) {
+        pass.write(self.culling.commands_id);
+    }
+
+    fn prepare(&mut self, device: &Arc<Device>, _subpass: Option<Subpass>) {
+        let cs = cs::Shader::load(device.clone()).unwrap();
+        let pipeline = Arc::new(ComputePipeline::new(device.clone(), &cs.main_entry_point(), &(), None).unwrap());
+        self.namer.name(&*pipeline, "culling pipeline");
+        self.pipeline = Some(pipeline);
+        self.objects = Some(CpuBufferPool::new(device.clone(), BufferUsage { storage_buffer: true, ..BufferUsage::none() }));
+    }
+
+    fn record(&mut self, context: &mut PassContext, scene: &Scene) {
+        let count = scene.objects.len().min(self.culling.capacity);
+        if *self.culling.mode.lock().unwrap() != CullingMode::Gpu || count == 0 {
+            return;
+        }
+
+        let camera = &scene.camera;
+        let aspect = context.backbuffer_dimensions[0] as f32 / context.backbuffer_dimensions[1] as f32;
+        let frustum = Frustum::from_matrix(camera.projection(aspect) * camera.view());
+
+        let objects = scene.objects[..count].iter().map(|object| GpuObject {
+                                                    model: object.transform.into(),
+                                                    min: object.mesh.bounds.min.to_homogeneous().into(),
+                                                    max: object.mesh.bounds.max.to_homogeneous().into(),
+                                                    index_count: object.mesh.indices.len() as u32,
+                                                    padding: [0; 3],
+                                                });
+        let objects = self.objects.as_ref().unwrap().chunk(objects).unwrap();
+        let pipeline = self.pipeline.clone().unwrap();
+        let layout = pipeline.descriptor_set_layout(0).unwrap().clone();
+        let set = Arc::new(PersistentDescriptorSet::start(layout).add_buffer(objects)
+                                                                 .unwrap()
+                                                                 .add_buffer(self.culling.commands.clone())
+                                                                 .unwrap()
+                                                                 .add_buffer(self.culling.visible.clone())
+                                                                 .unwrap()
+                                                                 .build()
+                                                                 .unwrap());
+
+        context.builder.fill_buffer(self.culling.visible.clone(), 0).unwrap();
+        context.builder
+               .dispatch([(count as u32).div_ceil(GROUP_SIZE), 1, 1],
+                         pipeline,
+                         set,
+                         CullPush { planes: frustum.planes(), object_count: count as u32 },
+                         vec![])
+               .unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::DrawIndexedIndirectCommand;
+use vulkano::device::Device;
+
+pub use crate::culling::bounds::{Aabb, Frustum};
+pub use crate::culling::bvh::Bvh;
+pub use crate::culling::gpu::GpuCullPass;
+
+use crate::allocator::{GpuAllocator, MemoryCategory, PooledBuffer};
+use crate::debug::ObjectNamer;
+use crate::render_graph::{BufferId, RenderGraphBuilder};
+use crate::scene::Scene;
+
+mod bounds;
+mod bvh;
+mod gpu;
+
+/// Where objects outside a view are skipped.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CullingMode {
+    /// Every object is drawn in every view.
+    Off,
+    /// The scene's `Bvh` is culled against every view on the CPU.
+    Cpu,
+    /// The camera's view is culled by a compute shader and drawn with indirect draws. Shadows are culled on the CPU.
+    Gpu,
+}
+
+impl CullingMode {
+    pub fn next(self) -> CullingMode {
+        match self {
+            CullingMode::Off => CullingMode::Cpu,
+            CullingMode::Cpu => CullingMode::Gpu,
+            CullingMode::Gpu => CullingMode::Off,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct CullingSettings {
+    pub mode: CullingMode,
+    /// Most objects culled on the GPU, the ones after them are culled on the CPU.
+    pub gpu_objects: usize,
+}
+
+impl Default for CullingSettings {
+    fn default() -> CullingSettings {
+        CullingSettings { mode: CullingMode::Cpu, gpu_objects: 4096 }
+    }
+}
+
+impl CullingSettings {
+    pub fn from_env() -> CullingSettings {
+        let mut settings = CullingSettings::default();
+        match env::var("TONIC_CULLING").as_ref().map(|s| s.to_ascii_lowercase()) {
+            Ok(ref s) if s == "off" => settings.mode = CullingMode::Off,
+            Ok(ref s) if s == "cpu" => settings.mode = CullingMode::Cpu,
+            Ok(ref s) if s == "gpu" => settings.mode = CullingMode::Gpu,
+            _ => {}
+        }
+        settings
+    }
+}
+
+/// How many objects a view drew and skipped in the last frame, summed over its layers.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ViewStats {
+    pub drawn: usize,
+    pub culled: usize,
+}
+
+/// Culling results by view. GPU culled counts are from the last frame the GPU finished.
+#[derive(Debug, Clone, Default)]
+pub struct CullingStats {
+    pub views: BTreeMap<String, ViewStats>,
+}
+
+/// Shared by the passes that cull and whatever shows or changes how they do.
+#[derive(Clone)]
+pub struct Culling {
+    pub mode: Arc<Mutex<CullingMode>>,
+    pub stats: Arc<Mutex<CullingStats>>,
+    /// Objects the GPU culls, from the first on.
+    pub(crate) capacity: usize,
+    /// An indirect draw per GPU culled object, without instances when it's culled.
+    pub(crate) commands_id: BufferId,
+    pub(crate) commands: Arc<PooledBuffer<[DrawIndexedIndirectCommand]>>,
+    /// How many objects the GPU drew.
+    pub(crate) visible: Arc<CpuAccessibleBuffer<u32>>,
+}
+
+/// Adds the pass culling the camera's view on the GPU, which the forward pass draws after. Other passes cull on
+/// their own.
+pub fn add_passes(graph: &mut RenderGraphBuilder<Scene>, settings: CullingSettings, device: &Arc<Device>, allocator: &GpuAllocator, namer: &ObjectNamer) -> Culling {
+    let capacity = settings.gpu_objects.max(1);
+    let usage = BufferUsage { storage_buffer: true, indirect_buffer: true, ..BufferUsage::none() };
+    let commands = PooledBuffer::<[DrawIndexedIndirectCommand]>::array(allocator, MemoryCategory::Other, capacity, usage, None).expect("failed to create indirect draw buffer");
+    let usage = BufferUsage { storage_buffer: true, transfer_destination: true, ..BufferUsage::none() };
+    let visible = CpuAccessibleBuffer::from_data(device.clone(), usage, true, 0).expect("failed to create visible object count buffer");
+    namer.name_buffer(&*commands, "culled indirect draws");
+    namer.name_buffer(&*visible, "visible object count");
+
+    let culling = Culling {
+        mode: Arc::new(Mutex::new(settings.mode)),
+        stats: Arc::new(Mutex::new(CullingStats::default())),
+        capacity,
+        commands_id: graph.buffer("culled indirect draws"),
+        commands,
+        visible,
+    };
+    graph.add_pass("gpu culling", GpuCullPass::new(culling.clone(), namer));
+    culling
+}
@@ -0,0 +1,159 @@
+use std::env;
+use std::ffi::CString;
+use std::sync::Arc;
+
+use tracing::{error, info, trace, warn};
+use vulkano::buffer::BufferAccess;
+use vulkano::device::{Device, DeviceOwned};
+use vulkano::image::ImageAccess;
+use vulkano::image::sys::UnsafeImage;
+use vulkano::instance::debug::{DebugCallback, Message, MessageSeverity, MessageType};
+use vulkano::instance::{layers_list, Instance, InstanceExtensions};
+use vulkano::{VulkanHandle, VulkanObject};
+
+use crate::logging;
+
+pub const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+/// Whether the validation layer and debug messenger are installed, and which severities get through.
+///
+/// On by default in debug builds. `TONIC_VALIDATION=0|1` forces it off or on and
+/// `TONIC_VALIDATION_SEVERITY=error|warning|info|verbose` sets the lowest severity that is reported.
+#[derive(Debug, Copy, Clone)]
+pub struct DebugSettings {
+    pub validation: bool,
+    pub severity: MessageSeverity,
+}
+
+impl DebugSettings {
+    pub fn from_env() -> DebugSettings {
+        let validation = match env::var("TONIC_VALIDATION") {
+            Ok(value) => value != "0" && !value.eq_ignore_ascii_case("false"),
+            Err(_) => cfg!(debug_assertions),
+        };
+
+        let severity = match env::var("TONIC_VALIDATION_SEVERITY").as_ref().map(|s| s.to_ascii_lowercase()) {
+            Ok(ref s) if s == "error" => MessageSeverity::errors(),
+            Ok(ref s) if s == "info" => MessageSeverity { information: true, ..MessageSeverity::errors_and_warnings() },
+            Ok(ref s) if s == "verbose" => MessageSeverity { information: true, verbose: true, ..MessageSeverity::errors_and_warnings() },
+            _ => MessageSeverity::errors_and_warnings(),
+        };
+
+        DebugSettings { validation, severity }
+    }
+}
+
+/// Everything the instance needs to be created with, and the messenger that must outlive it.
+pub struct Debug {
+    pub settings: DebugSettings,
+    pub layers: Vec<&'static str>,
+    callback: Option<DebugCallback>,
+}
+
+impl Debug {
+    pub fn new(settings: DebugSettings) -> Debug {
+        let mut layers = vec![];
+
+        if settings.validation {
+            let available = layers_list().map(|mut list| list.any(|layer| layer.name() == VALIDATION_LAYER)).unwrap_or(false);
+            if available {
+                layers.push(VALIDATION_LAYER);
+            } else {
+                warn!(target: logging::VULKAN, "{} requested but not installed, continuing without validation", VALIDATION_LAYER);
+            }
+        }
+
+        Debug { settings, layers, callback: None }
+    }
+
+    /// Adds `VK_EXT_debug_utils` to `extensions` when validation is on.
+    pub fn extensions(&self, extensions: &InstanceExtensions) -> InstanceExtensions {
+        if !self.settings.validation {
+            return *extensions;
+        }
+
+        let supported = InstanceExtensions::supported_by_core().map(|ext| ext.ext_debug_utils).unwrap_or(false);
+        InstanceExtensions { ext_debug_utils: supported, ..*extensions }
+    }
+
+    /// Installs the debug messenger. Must be called after the instance was created with `extensions()` and `layers`.
+    pub fn install(&mut self, instance: &Arc<Instance>) {
+        if !self.settings.validation || !instance.loaded_extensions().ext_debug_utils {
+            return;
+        }
+
+        match DebugCallback::new(instance, self.settings.severity, MessageType::all(), report_message) {
+            Ok(callback) => self.callback = Some(callback),
+            Err(e) => warn!(target: logging::VULKAN, "failed to install debug messenger: {:?}", e),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.callback.is_some()
+    }
+}
+
+fn report_message(message: &Message) {
+    let ty = if message.ty.validation {
+        "validation"
+    } else if message.ty.performance {
+        "performance"
+    } else {
+        "general"
+    };
+
+    let id = message.layer_prefix.unwrap_or("unknown");
+
+    if message.severity.error {
+        error!(target: logging::VULKAN, ty, id, "{}", message.description);
+    } else if message.severity.warning {
+        warn!(target: logging::VULKAN, ty, id, "{}", message.description);
+    } else if message.severity.information {
+        info!(target: logging::VULKAN, ty, id, "{}", message.description);
+    } else {
+        trace!(target: logging::VULKAN, ty, id, "{}", message.description);
+    }
+}
+
+/// Gives Vulkan objects readable names in validation messages and capture tools.
+///
+/// Every method is a no-op when the debug messenger isn't installed, since `vkSetDebugUtilsObjectNameEXT`
+/// is only loaded together with `VK_EXT_debug_utils`.
+#[derive(Clone)]
+pub struct ObjectNamer {
+    device: Arc<Device>,
+    enabled: bool,
+}
+
+impl ObjectNamer {
+    pub fn new(device: Arc<Device>, debug: &Debug) -> ObjectNamer {
+        ObjectNamer { device, enabled: debug.is_enabled() }
+    }
+
+    pub fn name_buffer<B: BufferAccess + ?Sized>(&self, buffer: &B, name: &str) {
+        if !self.enabled {
+            return;
+        }
+        let _ = self.device.set_object_name(buffer.inner().buffer, &object_name(name));
+    }
+
+    pub fn name_image<I: ImageAccess + ?Sized>(&self, image: &I, name: &str) {
+        if !self.enabled {
+            return;
+        }
+        let handle = image.inner().image.internal_object().value();
+        let _ = unsafe { self.device.set_object_name_raw(UnsafeImage::TYPE, handle, &object_name(name)) };
+    }
+
+    /// Names anything that knows its own device: pipelines and samplers.
+    pub fn name<T: VulkanObject + DeviceOwned>(&self, object: &T, name: &str) {
+        if !self.enabled {
+            return;
+        }
+        let _ = self.device.set_object_name(object, &object_name(name));
+    }
+}
+
+fn object_name(name: &str) -> CString {
+    CString::new(name.replace('\0', "")).unwrap()
+}
@@ -0,0 +1,15 @@
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec4 color;
+
+layout(location = 0) out vec4 v_color;
+
+layout(push_constant) uniform Camera {
+    mat4 view_projection;
+} camera;
+
+void main() {
+    gl_Position = camera.view_projection * vec4(position, 1.0);
+    v_color = color;
+}
@@ -0,0 +1,298 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use tracing::trace;
+use vulkano::buffer::{BufferSlice, CpuBufferPool};
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::Device;
+use vulkano::pipeline::depth_stencil::{Compare, DepthStencil};
+use vulkano::pipeline::vertex::SingleBufferDefinition;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::render_pass::Subpass;
+
+use crate::debug::ObjectNamer;
+use crate::logging;
+use crate::render_graph::{ImageId, Load, Pass, PassBuilder, PassContext};
+use crate::scene::Scene;
+use crate::text::{Font, Text, TextSpace};
+
+/// Pixel size of labels.
+pub const LABEL_SIZE: f32 = 16.0;
+// Segments of every circle of a sphere.
+const CIRCLE_SEGMENTS: usize = 32;
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/debug_draw/line.vert"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/debug_draw/line.frag"
+    }
+}
+
+/// How something is debug drawn.
+#[derive(Debug, Copy, Clone)]
+pub struct DebugOptions {
+    /// Linear RGBA.
+    pub color: [f32; 4],
+    /// How long it stays after the next frame, zero to draw it once.
+    pub duration: Duration,
+    /// Whether the scene hides it, otherwise it's drawn over everything. Labels are always on top.
+    pub depth_test: bool,
+}
+
+impl DebugOptions {
+    /// Drawn once and hidden by the scene.
+    pub fn color(color: [f32; 4]) -> DebugOptions {
+        DebugOptions { color, duration: Duration::from_secs(0), depth_test: true }
+    }
+
+    pub fn lasting(self, duration: Duration) -> DebugOptions {
+        DebugOptions { duration, ..self }
+    }
+
+    pub fn on_top(self) -> DebugOptions {
+        DebugOptions { depth_test: false, ..self }
+    }
+}
+
+impl Default for DebugOptions {
+    fn default() -> DebugOptions {
+        DebugOptions::color([1.0, 1.0, 1.0, 1.0])
+    }
+}
+
+struct DebugLine {
+    from: Point3<f32>,
+    to: Point3<f32>,
+    options: DebugOptions,
+}
+
+struct DebugLabel {
+    position: Point3<f32>,
+    text: String,
+    options: DebugOptions,
+}
+
+/// Lines, boxes, spheres, arrows, grids and labels queued from anywhere in a frame, for debugging.
+///
+/// Shapes are drawn as lines by the debug draw pass after the 3D scene, labels by the text pass. Everything is
+/// drawn the frame after it's queued and stays until its duration has passed, so gameplay code can queue what it
+/// wants to see every frame without keeping track of it.
+#[derive(Default)]
+pub struct DebugDraw {
+    lines: Vec<DebugLine>,
+    labels: Vec<DebugLabel>,
+    /// Labels are only drawn with a font.
+    pub font: Option<Font>,
+}
+
+impl DebugDraw {
+    /// Drops what has been drawn and has no time left, then takes `elapsed` off the rest. Call it once a frame,
+    /// before queueing anything.
+    pub fn update(&mut self, elapsed: Duration) {
+        let zero = Duration::from_secs(0);
+        self.lines.retain(|line| line.options.duration > zero);
+        self.labels.retain(|label| label.options.duration > zero);
+        for options in self.lines.iter_mut().map(|line| &mut line.options).chain(self.labels.iter_mut().map(|label| &mut label.options)) {
+            options.duration = options.duration.saturating_sub(elapsed);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.labels.clear();
+    }
+
+    pub fn line(&mut self, from: Point3<f32>, to: Point3<f32>, options: DebugOptions) {
+        self.lines.push(DebugLine { from, to, options });
+    }
+
+    /// The edges of the axis-aligned box from `min` to `max`.
+    pub fn aabb(&mut self, min: Point3<f32>, max: Point3<f32>, options: DebugOptions) {
+        let corner = |index: usize| {
+            Point3::new(if index & 1 == 0 { min.x } else { max.x },
+                        if index & 2 == 0 { min.y } else { max.y },
+                        if index & 4 == 0 { min.z } else { max.z })
+        };
+        for index in 0..8 {
+            for &axis in [1, 2, 4].iter() {
+                if index & axis == 0 {
+                    self.line(corner(index), corner(index | axis), options);
+                }
+            }
+        }
+    }
+
+    /// Circles around the x, y and z axes.
+    pub fn sphere(&mut self, center: Point3<f32>, radius: f32, options: DebugOptions) {
+        let (x, y, z) = (Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z());
+        self.circle(center, x, y, radius, options);
+        self.circle(center, y, z, radius, options);
+        self.circle(center, z, x, radius, options);
+    }
+
+    /// A line from `from` with a head at `to`, a fifth as long as the arrow.
+    pub fn arrow(&mut self, from: Point3<f32>, to: Point3<f32>, options: DebugOptions) {
+        self.line(from, to, options);
+
+        let direction = to - from;
+        let length = direction.magnitude();
+        if length == 0.0 {
+            return;
+        }
+        let direction = direction / length;
+        let side = if direction.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+        let u = direction.cross(side).normalize();
+        let v = direction.cross(u);
+        let head = length * 0.2;
+        let base = to - direction * head;
+        for &spoke in [u, -u, v, -v].iter() {
+            self.line(to, base + spoke * head * 0.4, options);
+        }
+    }
+
+    /// Lines `spacing` apart on the horizontal plane through `center`, up to `half_size` from it along x and z.
+    pub fn grid(&mut self, center: Point3<f32>, half_size: f32, spacing: f32, options: DebugOptions) {
+        let count = (half_size / spacing).floor() as i32;
+        for step in -count..=count {
+            let offset = step as f32 * spacing;
+            self.line(center + Vector3::new(offset, 0.0, -half_size), center + Vector3::new(offset, 0.0, half_size), options);
+            self.line(center + Vector3::new(-half_size, 0.0, offset), center + Vector3::new(half_size, 0.0, offset), options);
+        }
+    }
+
+    /// `text` centered above where `position` is on screen, `LABEL_SIZE` pixels high whatever the distance.
+    pub fn label(&mut self, position: Point3<f32>, text: &str, options: DebugOptions) {
+        self.labels.push(DebugLabel { position, text: text.to_string(), options });
+    }
+
+    /// The labels as text for the text pass, none without a font.
+    pub fn label_texts(&self) -> Vec<Text> {
+        let font = match &self.font {
+            Some(font) => font,
+            None => return vec![],
+        };
+        self.labels
+            .iter()
+            .map(|label| Text {
+                space: TextSpace::Label { position: label.position },
+                origin: [0.5, 1.0],
+                color: label.options.color,
+                ..Text::screen(&label.text, font.clone(), LABEL_SIZE, [0.0, 0.0])
+            })
+            .collect()
+    }
+
+    // A circle in the plane of `a` and `b`, which are perpendicular unit vectors.
+    fn circle(&mut self, center: Point3<f32>, a: Vector3<f32>, b: Vector3<f32>, radius: f32, options: DebugOptions) {
+        let point = |segment: usize| {
+            let angle = segment as f32 / CIRCLE_SEGMENTS as f32 * 2.0 * PI;
+            center + (a * angle.cos() + b * angle.sin()) * radius
+        };
+        for segment in 0..CIRCLE_SEGMENTS {
+            self.line(point(segment), point(segment + 1), options);
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+struct DebugVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+vulkano::impl_vertex!(DebugVertex, position, color);
+
+type LinePipeline = GraphicsPipeline<SingleBufferDefinition<DebugVertex>, Box<dyn PipelineLayoutAbstract + Send + Sync>>;
+
+/// Draws the lines of the scene's `DebugDraw` over `target` through the scene's camera, the depth tested ones
+/// against `depth` as the 3D scene left it.
+pub struct DebugDrawPass {
+    target: ImageId,
+    depth: ImageId,
+    vertices: CpuBufferPool<DebugVertex>,
+    namer: ObjectNamer,
+    depth_tested: Option<Arc<LinePipeline>>,
+    on_top: Option<Arc<LinePipeline>>,
+}
+
+impl DebugDrawPass {
+    pub fn new(device: Arc<Device>, target: ImageId, depth: ImageId, namer: &ObjectNamer) -> DebugDrawPass {
+        DebugDrawPass {
+            target,
+            depth,
+            vertices: CpuBufferPool::vertex_buffer(device),
+            namer: namer.clone(),
+            depth_tested: None,
+            on_top: None,
+        }
+    }
+}
+
+impl Pass<Scene> for DebugDrawPass {
+    fn declare(&mut self, pass: &mut PassBuilder) {
+        pass.color(self.target, Load::Keep).depth(self.depth, Load::Keep);
+    }
+
+    fn prepare(&mut self, device: &Arc<Device>, subpass: Option<Subpass>) {
+        let subpass = subpass.expect("the debug draw pass renders to attachments");
+        let vs = vs::Shader::load(device.clone()).unwrap();
+        let fs = fs::Shader::load(device.clone()).unwrap();
+        let create = |depth_stencil: DepthStencil, name: &str| {
+            let pipeline = Arc::new(GraphicsPipeline::start().vertex_input_single_buffer::<DebugVertex>()
+                                                             .vertex_shader(vs.main_entry_point(), ())
+                                                             .line_list()
+                                                             .viewports_dynamic_scissors_irrelevant(1)
+                                                             .fragment_shader(fs.main_entry_point(), ())
+                                                             .blend_alpha_blending()
+                                                             .depth_stencil(depth_stencil)
+                                                             .render_pass(subpass.clone())
+                                                             .build(device.clone())
+                                                             .unwrap());
+            self.namer.name(&*pipeline, name);
+            pipeline
+        };
+
+        // Lines exactly on a surface pass, and don't hide each other.
+        let depth_tested = create(DepthStencil { depth_compare: Compare::LessOrEqual, depth_write: false, ..DepthStencil::simple_depth_test() }, "debug line pipeline");
+        let on_top = create(DepthStencil::disabled(), "debug line on top pipeline");
+        self.depth_tested = Some(depth_tested);
+        self.on_top = Some(on_top);
+    }
+
+    fn record(&mut self, context: &mut PassContext, scene: &Scene) {
+        let lines = &scene.debug.lines;
+        if lines.is_empty() {
+            return;
+        }
+
+        // The depth tested lines first, then those on top.
+        let tested = lines.iter().filter(|line| line.options.depth_test).count();
+        let ordered = lines.iter().filter(|line| line.options.depth_test).chain(lines.iter().filter(|line| !line.options.depth_test));
+        let vertices: Vec<DebugVertex> = ordered.flat_map(|line| {
+                                                    let color = line.options.color;
+                                                    vec![DebugVertex { position: line.from.to_vec().into(), color }, DebugVertex { position: line.to.to_vec().into(), color }]
+                                                })
+                                                .collect();
+        let vertices = Arc::new(self.vertices.chunk(vertices).unwrap());
+
+        let aspect = context.dimensions[0] as f32 / context.dimensions[1] as f32;
+        let view_projection: [[f32; 4]; 4] = (scene.camera.projection(aspect) * scene.camera.view()).into();
+
+        for (pipeline, range) in [(self.depth_tested.clone().unwrap(), 0..tested * 2), (self.on_top.clone().unwrap(), tested * 2..lines.len() * 2)] {
+            if range.is_empty() {
+                continue;
+            }
+            let batch = BufferSlice::from_typed_buffer_access(vertices.clone()).slice(range).unwrap();
+            context.builder.draw(pipeline, context.dynamic_state, batch, (), view_projection, vec![]).unwrap();
+        }
+        trace!(target: logging::RENDER, lines = lines.len(), on_top = lines.len() - tested, "drew debug lines");
+    }
+}
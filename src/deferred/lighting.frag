@@ -0,0 +1,55 @@
+#version 450
+
+// Lights the G-buffer with `shading.glsl`, one light or the environment per draw, blended additively. The position
+// is reconstructed from depth.
+
+#include <shading.glsl>
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 1, binding = 0) uniform sampler gbuffer_sampler;
+layout(set = 1, binding = 1) uniform texture2D albedo_buffer;
+layout(set = 1, binding = 2) uniform texture2D normal_buffer;
+layout(set = 1, binding = 3) uniform texture2D material_buffer;
+layout(set = 1, binding = 4) uniform texture2D depth_buffer;
+
+layout(push_constant) uniform Volume {
+    mat4 inverse_view_projection;
+    vec4 rect;
+    int light;
+} volume;
+
+void main() {
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    float depth = texelFetch(sampler2D(depth_buffer, gbuffer_sampler), pixel, 0).r;
+    // Nothing was drawn here, the sky is drawn here later.
+    if (depth >= 1.0) {
+        discard;
+    }
+
+    vec2 size = vec2(textureSize(sampler2D(albedo_buffer, gbuffer_sampler), 0));
+    vec4 world = volume.inverse_view_projection * vec4(gl_FragCoord.xy / size * 2.0 - 1.0, depth, 1.0);
+    vec3 position = world.xyz / world.w;
+
+    vec3 base_color = texelFetch(sampler2D(albedo_buffer, gbuffer_sampler), pixel, 0).rgb;
+    vec3 n = texelFetch(sampler2D(normal_buffer, gbuffer_sampler), pixel, 0).xyz;
+    vec3 material = texelFetch(sampler2D(material_buffer, gbuffer_sampler), pixel, 0).rgb;
+    float metallic = material.r;
+    float roughness = material.g;
+    float occlusion = material.b;
+    float alpha = roughness * roughness;
+
+    vec3 v = normalize(globals.camera_position - position);
+    vec3 f0 = mix(vec3(0.04), base_color, metallic);
+    vec3 diffuse_color = base_color * (1.0 - metallic);
+
+    vec3 color;
+    if (volume.light < 0) {
+        color = shade_environment(n, v, diffuse_color, f0, roughness, occlusion);
+    } else {
+        // The G-buffer only keeps the shading normal, which stands in for the surface normal in the shadow bias.
+        color = shade_light(lights[volume.light], position, n, n, v, diffuse_color, f0, alpha);
+    }
+
+    f_color = vec4(color, 0.0);
+}
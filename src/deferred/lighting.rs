@@ -0,0 +1,202 @@
+use std::iter;
+use std::sync::Arc;
+
+use cgmath::{Matrix4, Point3, SquareMatrix, Vector3};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::pipeline::blend::{AttachmentBlend, BlendFactor};
+use vulkano::pipeline::vertex::{BufferlessDefinition, BufferlessVertices};
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::render_pass::Subpass;
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+use crate::culling::{Aabb, Frustum};
+use crate::debug::ObjectNamer;
+use crate::deferred::GBuffer;
+use crate::pbr::FrameSet;
+use crate::render_graph::{ImageId, Load, Pass, PassBuilder, PassContext};
+use crate::scene::{Light, LightKind, RenderPath, Scene};
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/deferred/lighting.vert"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/deferred/lighting.frag",
+        include: ["src/pbr"]
+    }
+}
+
+// The whole screen in normalized device coordinates.
+const FULL_SCREEN: [f32; 4] = [-1.0, -1.0, 1.0, 1.0];
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Volume {
+    inverse_view_projection: [[f32; 4]; 4],
+    rect: [f32; 4],
+    light: i32,
+}
+
+type LightingPipeline = GraphicsPipeline<BufferlessDefinition, Box<dyn PipelineLayoutAbstract + Send + Sync>>;
+
+/// Lights the G-buffer into `color`, over the emission the G-buffer pass left there, when the camera is on the
+/// deferred path.
+///
+/// The environment, or the ambient light, is drawn over the whole screen, then every light over the rectangle its
+/// range covers on screen, so a light only costs the pixels it can reach. Lights the camera can't see are skipped.
+pub struct LightingPass {
+    color: ImageId,
+    depth: ImageId,
+    gbuffer: GBuffer,
+    frame: FrameSet,
+    namer: ObjectNamer,
+    sampler: Arc<Sampler>,
+    pipeline: Option<Arc<LightingPipeline>>,
+    // G-buffer descriptor set and the graph generation it was made for.
+    set: Option<(u64, Arc<dyn DescriptorSet + Send + Sync>)>,
+}
+
+impl LightingPass {
+    pub fn new(device: &Arc<Device>, color: ImageId, depth: ImageId, gbuffer: GBuffer, frame: FrameSet, namer: &ObjectNamer) -> LightingPass {
+        let sampler = Sampler::new(device.clone(),
+                                   Filter::Nearest,
+                                   Filter::Nearest,
+                                   MipmapMode::Nearest,
+                                   SamplerAddressMode::ClampToEdge,
+                                   SamplerAddressMode::ClampToEdge,
+                                   SamplerAddressMode::ClampToEdge,
+                                   0.0,
+                                   1.0,
+                                   0.0,
+                                   0.0).unwrap();
+        namer.name(&*sampler, "g-buffer sampler");
+
+        LightingPass {
+            color,
+            depth,
+            gbuffer,
+            frame,
+            namer: namer.clone(),
+            sampler,
+            pipeline: None,
+            set: None,
+        }
+    }
+}
+
+impl Pass<Scene> for LightingPass {
+    fn declare(&mut self, pass: &mut PassBuilder) {
+        pass.color(self.color, Load::Keep).sample(self.gbuffer.albedo).sample(self.gbuffer.normal).sample(self.gbuffer.material).sample(self.depth);
+        self.frame.declare(pass);
+        pass.conditional();
+    }
+
+    fn prepare(&mut self, device: &Arc<Device>, subpass: Option<Subpass>) {
+        let vs = vs::Shader::load(device.clone()).unwrap();
+        let fs = fs::Shader::load(device.clone()).unwrap();
+        let additive = AttachmentBlend {
+            color_source: BlendFactor::One,
+            color_destination: BlendFactor::One,
+            alpha_source: BlendFactor::Zero,
+            alpha_destination: BlendFactor::One,
+            ..AttachmentBlend::alpha_blending()
+        };
+        let pipeline = Arc::new(GraphicsPipeline::start().vertex_input(BufferlessDefinition)
+                                                         .vertex_shader(vs.main_entry_point(), ())
+                                                         .triangle_strip()
+                                                         .viewports_dynamic_scissors_irrelevant(1)
+                                                         .fragment_shader(fs.main_entry_point(), ())
+                                                         .blend_collective(additive)
+                                                         .render_pass(subpass.expect("the lighting pass renders to an attachment"))
+                                                         .build(device.clone())
+                                                         .unwrap());
+        self.namer.name(&*pipeline, "deferred lighting pipeline");
+        self.pipeline = Some(pipeline);
+        self.set = None;
+    }
+
+    fn enabled(&self, scene: &Scene) -> bool {
+        scene.camera.path == RenderPath::Deferred
+    }
+
+    fn record(&mut self, context: &mut PassContext, scene: &Scene) {
+        let camera = &scene.camera;
+        let pipeline = self.pipeline.clone().unwrap();
+
+        if self.set.as_ref().map(|(generation, _)| *generation) != Some(context.generation()) {
+            let layout = pipeline.descriptor_set_layout(1).unwrap().clone();
+            let set = Arc::new(PersistentDescriptorSet::start(layout).add_sampler(self.sampler.clone())
+                                                                     .unwrap()
+                                                                     .add_image(context.image(self.gbuffer.albedo))
+                                                                     .unwrap()
+                                                                     .add_image(context.image(self.gbuffer.normal))
+                                                                     .unwrap()
+                                                                     .add_image(context.image(self.gbuffer.material))
+                                                                     .unwrap()
+                                                                     .add_image(context.image(self.depth))
+                                                                     .unwrap()
+                                                                     .build()
+                                                                     .unwrap());
+            self.set = Some((context.generation(), set));
+        }
+        let gbuffer_set = self.set.as_ref().unwrap().1.clone();
+        let frame_set = self.frame.lit(pipeline.descriptor_set_layout(0).unwrap().clone(), context, scene);
+
+        let aspect = context.dimensions[0] as f32 / context.dimensions[1] as f32;
+        let view_projection = camera.projection(aspect) * camera.view();
+        let inverse_view_projection = view_projection.invert().expect("camera can't be inverted").into();
+        let frustum = Frustum::from_matrix(view_projection);
+
+        // The environment or ambient light, then every light.
+        let ambient = Volume { inverse_view_projection, rect: FULL_SCREEN, light: -1 };
+        let lights = scene.lights.iter().enumerate().filter_map(|(index, light)| {
+                                                        let rect = screen_rect(light, &frustum, &view_projection)?;
+                                                        Some(Volume { inverse_view_projection, rect, light: index as i32 })
+                                                    });
+        for volume in iter::once(ambient).chain(lights) {
+            context.builder
+                   .draw(pipeline.clone(),
+                         context.dynamic_state,
+                         BufferlessVertices { vertices: 4, instances: 1 },
+                         (frame_set.clone(), gbuffer_set.clone()),
+                         volume,
+                         vec![])
+                   .unwrap();
+        }
+    }
+}
+
+/// The part of the screen `light` can reach as a rectangle in normalized device coordinates, all of it for lights
+/// without a range, or `None` when none of its range is in `frustum`.
+fn screen_rect(light: &Light, frustum: &Frustum, view_projection: &Matrix4<f32>) -> Option<[f32; 4]> {
+    let (position, range) = match (&light.kind, light.range) {
+        (LightKind::Point { position }, Some(range)) | (LightKind::Spot { position, .. }, Some(range)) => (*position, range),
+        _ => return Some(FULL_SCREEN),
+    };
+    let bounds = Aabb { min: position - Vector3::new(range, range, range), max: position + Vector3::new(range, range, range) };
+    if !frustum.intersects(&bounds) {
+        return None;
+    }
+
+    let mut rect = [f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY];
+    for corner in 0..8 {
+        let point = Point3::new(if corner & 1 == 0 { bounds.min.x } else { bounds.max.x },
+                                if corner & 2 == 0 { bounds.min.y } else { bounds.max.y },
+                                if corner & 4 == 0 { bounds.min.z } else { bounds.max.z });
+        let clip = view_projection * point.to_homogeneous();
+        // Corners behind the near plane don't project onto the screen, so the light may cover any of it.
+        if clip.w <= 0.0 || clip.z < 0.0 {
+            return Some(FULL_SCREEN);
+        }
+        let (x, y) = (clip.x / clip.w, clip.y / clip.w);
+        rect = [rect[0].min(x), rect[1].min(y), rect[2].max(x), rect[3].max(y)];
+    }
+    Some([rect[0].max(-1.0), rect[1].max(-1.0), rect[2].min(1.0), rect[3].min(1.0)])
+}
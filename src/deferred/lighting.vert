@@ -0,0 +1,15 @@
+#version 450
+
+layout(push_constant) uniform Volume {
+    mat4 inverse_view_projection;
+    // Part of the screen the light reaches in normalized device coordinates, the minimum in xy and the maximum in zw.
+    vec4 rect;
+    // Index of the light, or -1 for the environment or ambient light.
+    int light;
+} volume;
+
+// A quad over the rectangle, drawn as a 4 vertex triangle strip.
+void main() {
+    vec2 corner = vec2(float(gl_VertexIndex & 1), float((gl_VertexIndex >> 1) & 1));
+    gl_Position = vec4(mix(volume.rect.xy, volume.rect.zw, corner), 0.0, 1.0);
+}
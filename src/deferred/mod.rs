@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use vulkano::device::Device;
+use vulkano::format::{ClearValue, Format};
+
+use crate::culling::Culling;
+use crate::debug::ObjectNamer;
+use crate::deferred::lighting::LightingPass;
+use crate::environment::EnvironmentMaps;
+use crate::pbr::{ForwardPass, ForwardTarget, FrameSet};
+use crate::render_graph::{ImageDesc, ImageId, ImageSize, Load, RenderGraphBuilder};
+use crate::scene::Scene;
+use crate::shadow::ShadowMaps;
+use crate::upload::UploadManager;
+
+mod lighting;
+
+/// The surface attributes the deferred path lights, next to the depth and emission the G-buffer pass also writes.
+#[derive(Debug, Copy, Clone)]
+pub struct GBuffer {
+    /// Base color, in sRGB.
+    pub albedo: ImageId,
+    /// World space shading normal.
+    pub normal: ImageId,
+    /// Metallic, roughness and occlusion.
+    pub material: ImageId,
+}
+
+/// Adds the passes of the deferred path, which draw the objects into a G-buffer and light it into `color`, for
+/// cameras on `RenderPath::Deferred`. They clear `color` and `depth` and run instead of the forward pass, which clears
+/// them on the forward path.
+#[allow(clippy::too_many_arguments)]
+pub fn add_passes(graph: &mut RenderGraphBuilder<Scene>,
+                  color: ImageId,
+                  depth: ImageId,
+                  shadows: ShadowMaps,
+                  environment: EnvironmentMaps,
+                  culling: Culling,
+                  device: &Arc<Device>,
+                  uploads: &mut UploadManager,
+                  namer: &ObjectNamer) {
+    let gbuffer = GBuffer {
+        albedo: graph.image("g-buffer albedo", ImageDesc::new(Format::R8G8B8A8Srgb, ImageSize::Backbuffer)),
+        normal: graph.image("g-buffer normal", ImageDesc::new(Format::R16G16B16A16Sfloat, ImageSize::Backbuffer)),
+        material: graph.image("g-buffer material", ImageDesc::new(Format::R8G8B8A8Unorm, ImageSize::Backbuffer)),
+    };
+    graph.add_pass("g-buffer",
+                   ForwardPass::new(device.clone(),
+                                    ForwardTarget::GBuffer { emissive: color, gbuffer },
+                                    depth,
+                                    Load::Clear(ClearValue::Depth(1.0)),
+                                    shadows,
+                                    environment.clone(),
+                                    culling,
+                                    uploads,
+                                    namer));
+    graph.add_pass("deferred lighting", LightingPass::new(device, color, depth, gbuffer, FrameSet::new(device, shadows, environment, uploads.allocator(), namer), namer));
+}
@@ -0,0 +1,381 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use vulkano::device::{Features, RawDeviceExtensions};
+use vulkano::format::{Format, FormatFeatures};
+use vulkano::instance::{Instance, PhysicalDevice, PhysicalDeviceType};
+
+use crate::logging;
+
+/// Formats the engine renders to or samples from, and the ones worth knowing about when shipping compressed assets.
+const REPORTED_FORMATS: &[Format] = &[Format::R8G8B8A8Unorm,
+                                      Format::R8G8B8A8Srgb,
+                                      Format::B8G8R8A8Unorm,
+                                      Format::B8G8R8A8Srgb,
+                                      Format::A2B10G10R10UnormPack32,
+                                      Format::B10G11R11UfloatPack32,
+                                      Format::R16G16B16A16Sfloat,
+                                      Format::R32G32B32A32Sfloat,
+                                      Format::R32Sfloat,
+                                      Format::R8Unorm,
+                                      Format::D16Unorm,
+                                      Format::D32Sfloat,
+                                      Format::D24Unorm_S8Uint,
+                                      Format::D32Sfloat_S8Uint,
+                                      Format::BC1_RGBAUnormBlock,
+                                      Format::BC3UnormBlock,
+                                      Format::BC5UnormBlock,
+                                      Format::BC7UnormBlock,
+                                      Format::BC7SrgbBlock,
+                                      Format::ETC2_R8G8B8A8UnormBlock,
+                                      Format::ASTC_4x4UnormBlock];
+
+/// Everything we know about a physical device, in a form support can ask players to send us.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceReport {
+    pub index: usize,
+    pub name: String,
+    pub device_type: String,
+    pub api_version: String,
+    pub driver_version: u32,
+    pub pci_vendor_id: u32,
+    pub pci_device_id: u32,
+    pub features: BTreeMap<String, bool>,
+    pub extensions: Vec<String>,
+    pub limits: BTreeMap<&'static str, Value>,
+    pub memory_heaps: Vec<MemoryHeapReport>,
+    pub memory_types: Vec<MemoryTypeReport>,
+    pub queue_families: Vec<QueueFamilyReport>,
+    pub formats: BTreeMap<String, FormatReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryHeapReport {
+    pub id: u32,
+    pub size: usize,
+    pub device_local: bool,
+    pub multi_instance: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryTypeReport {
+    pub id: u32,
+    pub heap: u32,
+    pub device_local: bool,
+    pub host_visible: bool,
+    pub host_coherent: bool,
+    pub host_cached: bool,
+    pub lazily_allocated: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueFamilyReport {
+    pub id: u32,
+    pub queues: usize,
+    pub graphics: bool,
+    pub compute: bool,
+    pub transfer: bool,
+    pub sparse_binding: bool,
+    pub timestamp_valid_bits: Option<u32>,
+    pub min_image_transfer_granularity: [u32; 3],
+}
+
+/// Names of the format features that are set, per tiling.
+#[derive(Debug, Clone, Serialize)]
+pub struct FormatReport {
+    pub linear_tiling: Vec<String>,
+    pub optimal_tiling: Vec<String>,
+    pub buffer: Vec<String>,
+}
+
+// vulkano's `Features` and `FormatFeatures` are plain structs of `bool`s with no way to iterate them.
+macro_rules! flags {
+    ($flags:expr, $($name:ident),* $(,)?) => {{
+        let mut map = BTreeMap::new();
+        $(map.insert(stringify!($name).to_string(), $flags.$name);)*
+        map
+    }};
+}
+
+macro_rules! limits {
+    ($limits:expr, $($name:ident),* $(,)?) => {{
+        let mut map = BTreeMap::new();
+        $(map.insert(stringify!($name), json!($limits.$name()));)*
+        map
+    }};
+}
+
+impl DeviceReport {
+    pub fn new(physical: PhysicalDevice) -> DeviceReport {
+        let limits = physical.limits();
+
+        DeviceReport {
+            index: physical.index(),
+            name: physical.name().to_string(),
+            device_type: device_type_name(physical.ty()).to_string(),
+            api_version: physical.api_version().to_string(),
+            driver_version: physical.driver_version(),
+            pci_vendor_id: physical.pci_vendor_id(),
+            pci_device_id: physical.pci_device_id(),
+            features: features(physical.supported_features()),
+            extensions: supported_extensions(physical),
+            limits: limits!(limits,
+                            max_image_dimension_1d,
+                            max_image_dimension_2d,
+                            max_image_dimension_3d,
+                            max_image_dimension_cube,
+                            max_image_array_layers,
+                            max_texel_buffer_elements,
+                            max_uniform_buffer_range,
+                            max_storage_buffer_range,
+                            max_push_constants_size,
+                            max_memory_allocation_count,
+                            max_sampler_allocation_count,
+                            buffer_image_granularity,
+                            max_bound_descriptor_sets,
+                            max_per_stage_descriptor_samplers,
+                            max_per_stage_descriptor_uniform_buffers,
+                            max_per_stage_descriptor_storage_buffers,
+                            max_per_stage_descriptor_sampled_images,
+                            max_per_stage_descriptor_storage_images,
+                            max_per_stage_resources,
+                            max_descriptor_set_samplers,
+                            max_descriptor_set_uniform_buffers,
+                            max_descriptor_set_storage_buffers,
+                            max_descriptor_set_sampled_images,
+                            max_descriptor_set_storage_images,
+                            max_vertex_input_attributes,
+                            max_vertex_input_bindings,
+                            max_vertex_output_components,
+                            max_fragment_output_attachments,
+                            max_compute_shared_memory_size,
+                            max_compute_work_group_count,
+                            max_compute_work_group_invocations,
+                            max_compute_work_group_size,
+                            max_draw_indexed_index_value,
+                            max_draw_indirect_count,
+                            max_sampler_anisotropy,
+                            max_viewports,
+                            max_viewport_dimensions,
+                            min_memory_map_alignment,
+                            min_uniform_buffer_offset_alignment,
+                            min_storage_buffer_offset_alignment,
+                            max_framebuffer_width,
+                            max_framebuffer_height,
+                            max_framebuffer_layers,
+                            framebuffer_color_sample_counts,
+                            framebuffer_depth_sample_counts,
+                            max_color_attachments,
+                            timestamp_compute_and_graphics,
+                            timestamp_period,
+                            line_width_range,
+                            optimal_buffer_copy_offset_alignment,
+                            optimal_buffer_copy_row_pitch_alignment,
+                            non_coherent_atom_size),
+            memory_heaps: physical.memory_heaps()
+                                  .map(|heap| {
+                                      MemoryHeapReport {
+                                          id: heap.id(),
+                                          size: heap.size(),
+                                          device_local: heap.is_device_local(),
+                                          multi_instance: heap.is_multi_instance(),
+                                      }
+                                  })
+                                  .collect(),
+            memory_types: physical.memory_types()
+                                  .map(|ty| {
+                                      MemoryTypeReport {
+                                          id: ty.id(),
+                                          heap: ty.heap().id(),
+                                          device_local: ty.is_device_local(),
+                                          host_visible: ty.is_host_visible(),
+                                          host_coherent: ty.is_host_coherent(),
+                                          host_cached: ty.is_host_cached(),
+                                          lazily_allocated: ty.is_lazily_allocated(),
+                                      }
+                                  })
+                                  .collect(),
+            queue_families: physical.queue_families()
+                                    .map(|family| {
+                                        QueueFamilyReport {
+                                            id: family.id(),
+                                            queues: family.queues_count(),
+                                            graphics: family.supports_graphics(),
+                                            compute: family.supports_compute(),
+                                            transfer: family.explicitly_supports_transfers(),
+                                            sparse_binding: family.supports_sparse_binding(),
+                                            timestamp_valid_bits: family.timestamp_valid_bits(),
+                                            min_image_transfer_granularity: family.min_image_transfer_granularity(),
+                                        }
+                                    })
+                                    .collect(),
+            formats: REPORTED_FORMATS.iter()
+                                     .map(|format| {
+                                         let properties = format.properties(physical);
+                                         (format!("{:?}", format),
+                                          FormatReport {
+                                              linear_tiling: format_features(&properties.linear_tiling_features),
+                                              optimal_tiling: format_features(&properties.optimal_tiling_features),
+                                              buffer: format_features(&properties.buffer_features),
+                                          })
+                                     })
+                                     .collect(),
+        }
+    }
+}
+
+pub fn collect(instance: &Arc<Instance>) -> Vec<DeviceReport> {
+    PhysicalDevice::enumerate(instance).map(DeviceReport::new).collect()
+}
+
+/// `tonicengine report [path]`: writes the reports of every physical device as JSON to `path`, or stdout.
+pub fn run(instance: &Arc<Instance>, args: &[String]) -> io::Result<()> {
+    let reports = collect(instance);
+    let json = serde_json::to_string_pretty(&reports)?;
+
+    match args.first() {
+        Some(path) => {
+            File::create(path)?.write_all(json.as_bytes())?;
+            tracing::info!(target: logging::DEVICE, path = path.as_str(), devices = reports.len(), "wrote device report");
+        }
+        None => {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            stdout.write_all(json.as_bytes())?;
+            stdout.write_all(b"\n")?;
+        }
+    }
+
+    Ok(())
+}
+
+fn device_type_name(ty: PhysicalDeviceType) -> &'static str {
+    match ty {
+        PhysicalDeviceType::IntegratedGpu => "integrated",
+        PhysicalDeviceType::DiscreteGpu => "discrete",
+        PhysicalDeviceType::VirtualGpu => "virtual",
+        PhysicalDeviceType::Cpu => "cpu",
+        PhysicalDeviceType::Other => "other",
+    }
+}
+
+fn supported_extensions(physical: PhysicalDevice) -> Vec<String> {
+    let mut extensions: Vec<_> = RawDeviceExtensions::supported_by_device(physical).iter().map(|name| name.to_string_lossy().into_owned()).collect();
+    extensions.sort();
+    extensions
+}
+
+/// Every feature vulkano knows, by name.
+fn features(features: &Features) -> BTreeMap<String, bool> {
+    flags!(features,
+           robust_buffer_access,
+           full_draw_index_uint32,
+           image_cube_array,
+           independent_blend,
+           geometry_shader,
+           tessellation_shader,
+           sample_rate_shading,
+           dual_src_blend,
+           logic_op,
+           multi_draw_indirect,
+           draw_indirect_first_instance,
+           depth_clamp,
+           depth_bias_clamp,
+           fill_mode_non_solid,
+           depth_bounds,
+           wide_lines,
+           large_points,
+           alpha_to_one,
+           multi_viewport,
+           sampler_anisotropy,
+           texture_compression_etc2,
+           texture_compression_astc_ldr,
+           texture_compression_bc,
+           occlusion_query_precise,
+           pipeline_statistics_query,
+           vertex_pipeline_stores_and_atomics,
+           fragment_stores_and_atomics,
+           shader_tessellation_and_geometry_point_size,
+           shader_image_gather_extended,
+           shader_storage_image_extended_formats,
+           shader_storage_image_multisample,
+           shader_storage_image_read_without_format,
+           shader_storage_image_write_without_format,
+           shader_uniform_buffer_array_dynamic_indexing,
+           shader_sampled_image_array_dynamic_indexing,
+           shader_storage_buffer_array_dynamic_indexing,
+           shader_storage_image_array_dynamic_indexing,
+           shader_clip_distance,
+           shader_cull_distance,
+           shader_float64,
+           shader_int64,
+           shader_int16,
+           shader_resource_residency,
+           shader_resource_min_lod,
+           sparse_binding,
+           sparse_residency_buffer,
+           sparse_residency_image2d,
+           sparse_residency_image3d,
+           sparse_residency2_samples,
+           sparse_residency4_samples,
+           sparse_residency8_samples,
+           sparse_residency16_samples,
+           sparse_residency_aliased,
+           variable_multisample_rate,
+           inherited_queries,
+           buffer_device_address,
+           buffer_device_address_capture_replay,
+           buffer_device_address_multi_device,
+           variable_pointers_storage_buffer,
+           variable_pointers,
+           shader_buffer_int64_atomics,
+           shader_shared_int64_atomics,
+           storage_buffer_8bit,
+           storage_uniform_8bit,
+           storage_push_constant_8bit,
+           storage_buffer_16bit,
+           storage_uniform_16bit,
+           storage_push_constant_16bit,
+           storage_input_output_16bit,
+           shader_float16,
+           shader_int8)
+}
+
+/// Names of the features a format supports.
+fn format_features(features: &FormatFeatures) -> Vec<String> {
+    flags!(features,
+           sampled_image,
+           storage_image,
+           storage_image_atomic,
+           uniform_texel_buffer,
+           storage_texel_buffer,
+           storage_texel_buffer_atomic,
+           vertex_buffer,
+           color_attachment,
+           color_attachment_blend,
+           depth_stencil_attachment,
+           blit_src,
+           blit_dst,
+           sampled_image_filter_linear,
+           transfer_src,
+           transfer_dst,
+           midpoint_chroma_samples,
+           sampled_image_ycbcr_conversion_linear_filter,
+           sampled_image_ycbcr_conversion_separate_reconstruction_filter,
+           sampled_image_ycbcr_conversion_chroma_reconstruction_explicit,
+           sampled_image_ycbcr_conversion_chroma_reconstruction_explicit_forceable,
+           disjoint,
+           cosited_chroma_samples,
+           sampled_image_filter_minmax,
+           img_sampled_image_filter_cubic,
+           khr_acceleration_structure_vertex_buffer,
+           ext_fragment_density_map).into_iter()
+                     .filter(|(_, set)| *set)
+                     .map(|(name, _)| name)
+                     .collect()
+}
@@ -0,0 +1,165 @@
+use std::sync::Arc;
+
+use tracing::debug;
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::DescriptorSet;
+use vulkano::device::Device;
+use vulkano::image::view::ImageViewAbstract;
+use vulkano::pipeline::{ComputePipeline, ComputePipelineAbstract};
+use vulkano::render_pass::Subpass;
+
+use crate::debug::ObjectNamer;
+use crate::environment::{EnvironmentMap, EnvironmentMaps};
+use crate::logging;
+use crate::render_graph::{Pass, PassBuilder, PassContext};
+use crate::scene::Scene;
+
+mod to_cube {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/environment/to_cube.comp"
+    }
+}
+
+mod irradiance {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/environment/irradiance.comp"
+    }
+}
+
+mod prefilter {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/environment/prefilter.comp"
+    }
+}
+
+mod brdf {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/environment/brdf.comp"
+    }
+}
+
+// Workgroup size of every bake shader.
+const GROUP_SIZE: u32 = 8;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Level {
+    roughness: f32,
+    samples: u32,
+}
+
+struct Pipelines {
+    to_cube: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+    irradiance: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+    prefilter: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+    brdf: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+}
+
+/// Turns the scene's environment into a cube, then integrates the irradiance and prefiltered specular cubes from it,
+/// whenever the scene gets a different environment. Computes the BRDF table in the first frame.
+pub struct EnvironmentBakePass {
+    maps: EnvironmentMaps,
+    // Single level views of the cubes, to write them.
+    cube_faces: Arc<dyn ImageViewAbstract + Send + Sync>,
+    irradiance_faces: Arc<dyn ImageViewAbstract + Send + Sync>,
+    prefiltered_levels: Vec<Arc<dyn ImageViewAbstract + Send + Sync>>,
+    namer: ObjectNamer,
+    pipelines: Option<Pipelines>,
+    // What the cubes were baked from, and whether the BRDF table was.
+    baked: Option<Arc<EnvironmentMap>>,
+    brdf_baked: bool,
+}
+
+impl EnvironmentBakePass {
+    pub(crate) fn new(maps: EnvironmentMaps,
+                      cube_faces: Arc<dyn ImageViewAbstract + Send + Sync>,
+                      irradiance_faces: Arc<dyn ImageViewAbstract + Send + Sync>,
+                      prefiltered_levels: Vec<Arc<dyn ImageViewAbstract + Send + Sync>>,
+                      namer: &ObjectNamer)
+                      -> EnvironmentBakePass {
+        EnvironmentBakePass {
+            maps,
+            cube_faces,
+            irradiance_faces,
+            prefiltered_levels,
+            namer: namer.clone(),
+            pipelines: None,
+            baked: None,
+            brdf_baked: false,
+        }
+    }
+
+    /// Samples `input` and writes the six faces of `output`, whose sides are `size` texels long.
+    fn dispatch_faces<Pc>(&self, context: &mut PassContext, pipeline: &Arc<dyn ComputePipelineAbstract + Send + Sync>, input: Arc<dyn ImageViewAbstract + Send + Sync>, output: &Arc<dyn ImageViewAbstract + Send + Sync>, size: u32, push_constants: Pc) {
+        let layout = pipeline.descriptor_set_layout(0).unwrap().clone();
+        let set = Arc::new(PersistentDescriptorSet::start(layout).add_sampler(self.maps.sampler.clone())
+                                                                 .unwrap()
+                                                                 .add_image(input)
+                                                                 .unwrap()
+                                                                 .add_image(output.clone())
+                                                                 .unwrap()
+                                                                 .build()
+                                                                 .unwrap()) as Arc<dyn DescriptorSet + Send + Sync>;
+        let groups = size.div_ceil(GROUP_SIZE);
+        context.builder.dispatch([groups, groups, 6], pipeline.clone(), set, push_constants, vec![]).unwrap();
+    }
+}
+
+impl Pass<Scene> for EnvironmentBakePass {
+    fn declare(&mut self, pass: &mut PassBuilder) {
+        pass.write(self.maps.id);
+    }
+
+    fn prepare(&mut self, device: &Arc<Device>, _subpass: Option<Subpass>) {
+        let to_cube = to_cube::Shader::load(device.clone()).unwrap();
+        let irradiance = irradiance::Shader::load(device.clone()).unwrap();
+        let prefilter = prefilter::Shader::load(device.clone()).unwrap();
+        let brdf = brdf::Shader::load(device.clone()).unwrap();
+
+        let to_cube = Arc::new(ComputePipeline::new(device.clone(), &to_cube.main_entry_point(), &(), None).unwrap());
+        let irradiance = Arc::new(ComputePipeline::new(device.clone(), &irradiance.main_entry_point(), &(), None).unwrap());
+        let prefilter = Arc::new(ComputePipeline::new(device.clone(), &prefilter.main_entry_point(), &(), None).unwrap());
+        let brdf = Arc::new(ComputePipeline::new(device.clone(), &brdf.main_entry_point(), &(), None).unwrap());
+        self.namer.name(&*to_cube, "environment cube pipeline");
+        self.namer.name(&*irradiance, "irradiance pipeline");
+        self.namer.name(&*prefilter, "specular prefilter pipeline");
+        self.namer.name(&*brdf, "brdf table pipeline");
+
+        self.pipelines = Some(Pipelines { to_cube, irradiance, prefilter, brdf });
+    }
+
+    fn record(&mut self, context: &mut PassContext, scene: &Scene) {
+        let pipelines = self.pipelines.as_ref().unwrap();
+        let settings = self.maps.settings;
+
+        if !self.brdf_baked {
+            let layout = pipelines.brdf.descriptor_set_layout(0).unwrap().clone();
+            let set = Arc::new(PersistentDescriptorSet::start(layout).add_image(self.maps.brdf.clone()).unwrap().build().unwrap());
+            let groups = settings.brdf_resolution.div_ceil(GROUP_SIZE);
+            context.builder.dispatch([groups, groups, 1], pipelines.brdf.clone(), set, (), vec![]).unwrap();
+            self.brdf_baked = true;
+        }
+
+        let map = match &scene.environment {
+            Some(environment) => &environment.map,
+            None => return,
+        };
+        if matches!(&self.baked, Some(baked) if Arc::ptr_eq(baked, map)) {
+            return;
+        }
+
+        self.dispatch_faces(context, &pipelines.to_cube, map.source.clone(), &self.cube_faces, settings.cube_resolution, map.equirectangular as u32);
+        self.dispatch_faces(context, &pipelines.irradiance, self.maps.cube.clone(), &self.irradiance_faces, settings.irradiance_resolution, settings.irradiance_step);
+        for (level, faces) in self.prefiltered_levels.iter().enumerate() {
+            let roughness = if settings.specular_levels > 1 { level as f32 / (settings.specular_levels - 1) as f32 } else { 0.0 };
+            let size = (settings.specular_resolution >> level).max(1);
+            self.dispatch_faces(context, &pipelines.prefilter, self.maps.cube.clone(), faces, size, Level { roughness, samples: settings.specular_samples });
+        }
+        debug!(target: logging::RENDER, environment = %map.name, "baked environment maps");
+        self.baked = Some(map.clone());
+    }
+}
@@ -0,0 +1,101 @@
+use std::io::{BufRead, Read};
+
+/// Decodes a Radiance `.hdr` image with the standard `-Y height +X width` orientation into linear RGB, row by row
+/// from the top. Scanlines can be flat or run-length encoded the way every current writer does it.
+pub fn decode<R: BufRead>(mut reader: R) -> Result<([u32; 2], Vec<[f32; 3]>), String> {
+    let mut line = String::new();
+    let mut read_line = |reader: &mut R| -> Result<String, String> {
+        line.clear();
+        reader.read_line(&mut line).map_err(|e| e.to_string())?;
+        Ok(line.trim_end().to_string())
+    };
+
+    let magic = read_line(&mut reader)?;
+    if magic != "#?RADIANCE" && magic != "#?RGBE" {
+        return Err("not a Radiance HDR image".to_string());
+    }
+    loop {
+        let header = read_line(&mut reader)?;
+        if header.is_empty() {
+            break;
+        }
+        if let Some(format) = header.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(format!("unsupported pixel format {}", format));
+            }
+        }
+    }
+
+    let resolution = read_line(&mut reader)?;
+    let size = match resolution.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["-Y", height, "+X", width] => [width.parse::<u32>().map_err(|e| e.to_string())?, height.parse::<u32>().map_err(|e| e.to_string())?],
+        _ => return Err(format!("unsupported orientation {:?}", resolution)),
+    };
+
+    let width = size[0] as usize;
+    let mut pixels = Vec::with_capacity(width * size[1] as usize);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..size[1] {
+        read_scanline(&mut reader, &mut scanline)?;
+        pixels.extend(scanline.iter().map(|&rgbe| rgbe_to_linear(rgbe)));
+    }
+    Ok((size, pixels))
+}
+
+fn read_scanline<R: Read>(reader: &mut R, scanline: &mut [[u8; 4]]) -> Result<(), String> {
+    let mut read = |buffer: &mut [u8]| reader.read_exact(buffer).map_err(|e| e.to_string());
+
+    let mut first = [0u8; 4];
+    read(&mut first)?;
+    // Run-length encoded scanlines start with 2, 2 and their width, then hold each component separately.
+    let width = scanline.len();
+    if !(8..0x8000).contains(&width) || first[0] != 2 || first[1] != 2 || first[2] & 0x80 != 0 {
+        scanline[0] = first;
+        let mut rest = vec![0u8; (width - 1) * 4];
+        read(&mut rest)?;
+        for (pixel, bytes) in scanline[1..].iter_mut().zip(rest.chunks(4)) {
+            pixel.copy_from_slice(bytes);
+        }
+        return Ok(());
+    }
+    if ((first[2] as usize) << 8 | first[3] as usize) != width {
+        return Err("scanline width doesn't match the image".to_string());
+    }
+
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            read(&mut count)?;
+            // Above 128 a run of one value, otherwise that many literal values.
+            let (run, count) = if count[0] > 128 { (true, (count[0] - 128) as usize) } else { (false, count[0] as usize) };
+            if count == 0 || x + count > width {
+                return Err("invalid run length".to_string());
+            }
+            if run {
+                let mut value = [0u8; 1];
+                read(&mut value)?;
+                for pixel in scanline[x..x + count].iter_mut() {
+                    pixel[component] = value[0];
+                }
+            } else {
+                let mut values = vec![0u8; count];
+                read(&mut values)?;
+                for (pixel, &value) in scanline[x..x + count].iter_mut().zip(values.iter()) {
+                    pixel[component] = value;
+                }
+            }
+            x += count;
+        }
+    }
+    Ok(())
+}
+
+/// Mantissas share the exponent in the fourth byte.
+fn rgbe_to_linear([r, g, b, e]: [u8; 4]) -> [f32; 3] {
+    if e == 0 {
+        return [0.0, 0.0, 0.0];
+    }
+    let scale = 2f32.powi(e as i32 - 136);
+    [(r as f32 + 0.5) * scale, (g as f32 + 0.5) * scale, (b as f32 + 0.5) * scale]
+}
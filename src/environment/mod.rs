@@ -0,0 +1,275 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use half::f16;
+use tracing::info;
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::view::{ImageView, ImageViewAbstract, ImageViewType};
+use vulkano::image::{ImageCreateFlags, ImageDimensions};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+pub use crate::environment::sky::SkyPass;
+
+use crate::allocator::{GpuAllocator, PooledImage};
+use crate::debug::ObjectNamer;
+use crate::environment::bake::EnvironmentBakePass;
+use crate::logging;
+use crate::render_graph::{BufferId, RenderGraphBuilder};
+use crate::scene::Scene;
+use crate::upload::UploadManager;
+
+mod bake;
+mod hdr;
+mod sky;
+
+// Every environment image, sources included. Linearly filterable and writable by compute shaders everywhere.
+const FORMAT: Format = Format::R16G16B16A16Sfloat;
+
+// File names of the faces of a cube map directory, without their extension, in cube face order.
+const FACES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+#[derive(Debug)]
+pub enum EnvironmentError {
+    Io(io::Error),
+    Image(image::ImageError),
+    Hdr(String),
+    /// A cube map directory without one of its faces, or with faces of different sizes.
+    Faces(String),
+}
+
+impl fmt::Display for EnvironmentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnvironmentError::Io(e) => write!(f, "{}", e),
+            EnvironmentError::Image(e) => write!(f, "invalid environment image: {}", e),
+            EnvironmentError::Hdr(e) => write!(f, "invalid HDR image: {}", e),
+            EnvironmentError::Faces(e) => write!(f, "invalid cube map: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for EnvironmentError {
+    fn from(e: io::Error) -> EnvironmentError {
+        EnvironmentError::Io(e)
+    }
+}
+
+impl From<image::ImageError> for EnvironmentError {
+    fn from(e: image::ImageError) -> EnvironmentError {
+        EnvironmentError::Image(e)
+    }
+}
+
+/// Radiance from every direction, as loaded: an equirectangular image or six cube faces. The bake pass turns it
+/// into the maps the sky and image-based lighting use.
+pub struct EnvironmentMap {
+    /// Linear RGB, the equirectangular image or the faces in its layers.
+    source: Arc<dyn ImageViewAbstract + Send + Sync>,
+    equirectangular: bool,
+    name: String,
+}
+
+impl EnvironmentMap {
+    /// Loads an equirectangular image, or a directory of cube faces named `px`, `nx`, `py`, `ny`, `pz` and `nz`.
+    /// Radiance `.hdr` images are linear, anything else is taken to be sRGB.
+    pub fn load(uploads: &mut UploadManager, namer: &ObjectNamer, path: &Path) -> Result<Arc<EnvironmentMap>, EnvironmentError> {
+        let name = format!("environment {}", path.display());
+        if !path.is_dir() {
+            let (size, pixels) = load_image(path)?;
+            info!(target: logging::ASSETS, width = size[0], height = size[1], "loaded equirectangular environment {}", path.display());
+            return Ok(EnvironmentMap::equirectangular(uploads, namer, &name, &pixels, size));
+        }
+
+        let files = fs::read_dir(path)?.map(|entry| entry.map(|entry| entry.path())).collect::<Result<Vec<PathBuf>, _>>()?;
+        let mut faces = vec![];
+        let mut size = None;
+        for face in FACES.iter() {
+            let file = files.iter().find(|file| file.file_stem() == Some(OsStr::new(face))).ok_or_else(|| EnvironmentError::Faces(format!("missing face {}", face)))?;
+            let (face_size, pixels) = load_image(file)?;
+            if face_size[0] != face_size[1] || size.unwrap_or(face_size) != face_size {
+                return Err(EnvironmentError::Faces(format!("face {} isn't square or has a different size than the others", file.display())));
+            }
+            size = Some(face_size);
+            faces.extend(pixels);
+        }
+        info!(target: logging::ASSETS, size = size.unwrap()[0], "loaded cube map environment {}", path.display());
+        Ok(EnvironmentMap::cube(uploads, namer, &name, &faces, size.unwrap()[0]))
+    }
+
+    /// An environment from linear RGB pixels, row by row from the top, with the -z direction in the center.
+    pub fn equirectangular(uploads: &mut UploadManager, namer: &ObjectNamer, name: &str, pixels: &[[f32; 3]], size: [u32; 2]) -> Arc<EnvironmentMap> {
+        Arc::new(EnvironmentMap {
+                     source: upload(uploads, namer, name, pixels, size, 1),
+                     equirectangular: true,
+                     name: name.to_string(),
+                 })
+    }
+
+    /// An environment from the linear RGB pixels of six square faces, one after the other in the order +x, -x, +y,
+    /// -y, +z, -z.
+    pub fn cube(uploads: &mut UploadManager, namer: &ObjectNamer, name: &str, faces: &[[f32; 3]], size: u32) -> Arc<EnvironmentMap> {
+        Arc::new(EnvironmentMap {
+                     source: upload(uploads, namer, name, faces, [size, size], 6),
+                     equirectangular: false,
+                     name: name.to_string(),
+                 })
+    }
+}
+
+/// The environment of a scene, drawn behind it and lighting it.
+#[derive(Clone)]
+pub struct Environment {
+    pub map: Arc<EnvironmentMap>,
+    /// Multiplies the radiance of the map.
+    pub intensity: f32,
+}
+
+/// How detailed the maps baked from the environment are.
+#[derive(Debug, Copy, Clone)]
+pub struct EnvironmentSettings {
+    /// Size of the faces of the cube the sky is drawn from, and the other maps are baked from.
+    pub cube_resolution: u32,
+    pub irradiance_resolution: u32,
+    /// Radians between the samples of the irradiance integral, along both angles.
+    pub irradiance_step: f32,
+    /// Size of the sharpest level of the prefiltered specular cube.
+    pub specular_resolution: u32,
+    /// Levels of the prefiltered specular cube, from a roughness of 0 to 1.
+    pub specular_levels: u32,
+    /// GGX samples for every texel of the prefiltered specular cube. Small, very bright lights in the environment
+    /// need more to not leave speckles in rough reflections.
+    pub specular_samples: u32,
+    pub brdf_resolution: u32,
+}
+
+impl Default for EnvironmentSettings {
+    fn default() -> EnvironmentSettings {
+        EnvironmentSettings {
+            cube_resolution: 512,
+            irradiance_resolution: 32,
+            irradiance_step: 0.025,
+            specular_resolution: 128,
+            specular_levels: 6,
+            specular_samples: 1024,
+            brdf_resolution: 256,
+        }
+    }
+}
+
+/// The maps baked from the scene's environment, for passes that draw or light with it. They have to read `id`,
+/// so they run after the bake.
+#[derive(Clone)]
+pub struct EnvironmentMaps {
+    pub settings: EnvironmentSettings,
+    pub id: BufferId,
+    /// Trilinear, for every map.
+    pub sampler: Arc<Sampler>,
+    /// The environment as a cube.
+    pub cube: Arc<dyn ImageViewAbstract + Send + Sync>,
+    /// Irradiance around every direction divided by π, what a white Lambertian surface facing it reflects.
+    pub irradiance: Arc<dyn ImageViewAbstract + Send + Sync>,
+    /// Radiance through a GGX lobe around every direction, roughness increasing with the level.
+    pub prefiltered: Arc<dyn ImageViewAbstract + Send + Sync>,
+    /// Scale and bias of F0 for the specular lobe, by n·v and roughness.
+    pub brdf: Arc<dyn ImageViewAbstract + Send + Sync>,
+}
+
+/// Adds the pass baking the scene's environment into the maps it returns. It only does work when the environment
+/// changes, and once for the BRDF table.
+pub fn add_passes(graph: &mut RenderGraphBuilder<Scene>, settings: EnvironmentSettings, device: &Arc<Device>, allocator: &GpuAllocator, namer: &ObjectNamer) -> EnvironmentMaps {
+    let max_levels = 32 - settings.specular_resolution.leading_zeros();
+    assert!((1..=max_levels).contains(&settings.specular_levels), "the prefiltered specular cube has between 1 and {} levels", max_levels);
+
+    let sampler = Sampler::new(device.clone(),
+                               Filter::Linear,
+                               Filter::Linear,
+                               MipmapMode::Linear,
+                               SamplerAddressMode::ClampToEdge,
+                               SamplerAddressMode::ClampToEdge,
+                               SamplerAddressMode::ClampToEdge,
+                               0.0,
+                               1.0,
+                               0.0,
+                               1000.0).unwrap();
+    namer.name(&*sampler, "environment sampler");
+
+    let cube = cube_image(allocator, namer, "environment cube", settings.cube_resolution, 1);
+    let irradiance = cube_image(allocator, namer, "irradiance cube", settings.irradiance_resolution, 1);
+    let prefiltered = cube_image(allocator, namer, "prefiltered specular cube", settings.specular_resolution, settings.specular_levels);
+    let dimensions = ImageDimensions::Dim2d {
+        width: settings.brdf_resolution,
+        height: settings.brdf_resolution,
+        array_layers: 1,
+    };
+    let brdf = PooledImage::storage(allocator, dimensions, FORMAT, 1, ImageCreateFlags::none()).expect("failed to create brdf table");
+    namer.name_image(&*brdf, "brdf table");
+
+    let maps = EnvironmentMaps {
+        settings,
+        id: graph.buffer("environment maps"),
+        sampler,
+        cube: cube_view(&cube),
+        irradiance: cube_view(&irradiance),
+        prefiltered: cube_view(&prefiltered),
+        brdf: ImageView::new(brdf).unwrap(),
+    };
+    let prefiltered_levels = (0..settings.specular_levels).map(|level| face_view(&prefiltered, level)).collect();
+    graph.add_pass("environment bake", EnvironmentBakePass::new(maps.clone(), face_view(&cube, 0), face_view(&irradiance, 0), prefiltered_levels, namer));
+    maps
+}
+
+fn cube_image(allocator: &GpuAllocator, namer: &ObjectNamer, name: &str, size: u32, levels: u32) -> Arc<PooledImage> {
+    let dimensions = ImageDimensions::Dim2d { width: size, height: size, array_layers: 6 };
+    let flags = ImageCreateFlags { cube_compatible: true, ..ImageCreateFlags::none() };
+    let image = PooledImage::storage(allocator, dimensions, FORMAT, levels, flags).unwrap_or_else(|e| panic!("failed to create {}: {:?}", name, e));
+    namer.name_image(&*image, name);
+    image
+}
+
+fn cube_view(image: &Arc<PooledImage>) -> Arc<dyn ImageViewAbstract + Send + Sync> {
+    ImageView::start(image.clone()).with_type(ImageViewType::Cubemap).build().unwrap()
+}
+
+/// The six faces of one level, for compute shaders to write.
+fn face_view(image: &Arc<PooledImage>, level: u32) -> Arc<dyn ImageViewAbstract + Send + Sync> {
+    ImageView::start(image.clone()).with_type(ImageViewType::Dim2dArray).with_mipmap_levels(level..level + 1).build().unwrap()
+}
+
+/// Linear RGB pixels of the image at `path`, row by row from the top.
+fn load_image(path: &Path) -> Result<([u32; 2], Vec<[f32; 3]>), EnvironmentError> {
+    if matches!(path.extension().and_then(OsStr::to_str), Some(extension) if extension.eq_ignore_ascii_case("hdr")) {
+        return hdr::decode(BufReader::new(File::open(path)?)).map_err(EnvironmentError::Hdr);
+    }
+
+    let image = image::open(path)?.to_rgb8();
+    let decode = |value: u8| {
+        let value = value as f32 / 255.0;
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    Ok(([image.width(), image.height()], image.pixels().map(|pixel| [decode(pixel[0]), decode(pixel[1]), decode(pixel[2])]).collect()))
+}
+
+/// Uploads `layers` images of `size` as half floats, viewed as an array.
+fn upload(uploads: &mut UploadManager, namer: &ObjectNamer, name: &str, pixels: &[[f32; 3]], size: [u32; 2], layers: u32) -> Arc<dyn ImageViewAbstract + Send + Sync> {
+    let half = |value: f32| f16::from_f32(value).to_bits();
+    let pixels: Vec<[u16; 4]> = pixels.iter().map(|&[r, g, b]| [half(r), half(g), half(b), half(1.0)]).collect();
+    let dimensions = ImageDimensions::Dim2d {
+        width: size[0],
+        height: size[1],
+        array_layers: layers,
+    };
+    let image = uploads.image(&pixels, dimensions, FORMAT).expect("failed to create environment image");
+    namer.name_image(&*image, name);
+    ImageView::start(image).with_type(ImageViewType::Dim2dArray).build().unwrap()
+}
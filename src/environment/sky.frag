@@ -0,0 +1,20 @@
+#version 450
+
+layout(location = 0) in vec2 v_position;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler environment_sampler;
+layout(set = 0, binding = 1) uniform textureCube environment;
+
+layout(push_constant) uniform Sky {
+    // From clip space to world space directions around the camera.
+    mat4 inverse_view_projection;
+    float intensity;
+} sky;
+
+void main() {
+    vec4 world = sky.inverse_view_projection * vec4(v_position, 1.0, 1.0);
+    vec3 direction = normalize(world.xyz / world.w);
+    f_color = vec4(textureLod(samplerCube(environment, environment_sampler), direction, 0.0).rgb * sky.intensity, 1.0);
+}
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use cgmath::{SquareMatrix, Vector4};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::pipeline::depth_stencil::{Compare, DepthStencil};
+use vulkano::pipeline::vertex::{BufferlessDefinition, BufferlessVertices};
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::render_pass::Subpass;
+
+use crate::debug::ObjectNamer;
+use crate::environment::EnvironmentMaps;
+use crate::render_graph::{ImageId, Load, Pass, PassBuilder, PassContext};
+use crate::scene::Scene;
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/environment/sky.vert"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/environment/sky.frag"
+    }
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Sky {
+    inverse_view_projection: [[f32; 4]; 4],
+    intensity: f32,
+}
+
+type SkyPipeline = GraphicsPipeline<BufferlessDefinition, Box<dyn PipelineLayoutAbstract + Send + Sync>>;
+
+/// Draws the scene's environment wherever `depth` is still cleared to the far plane. Leaves `color` alone when
+/// there's no environment.
+pub struct SkyPass {
+    color: ImageId,
+    depth: ImageId,
+    maps: EnvironmentMaps,
+    namer: ObjectNamer,
+    pipeline: Option<Arc<SkyPipeline>>,
+    set: Option<Arc<dyn DescriptorSet + Send + Sync>>,
+}
+
+impl SkyPass {
+    pub fn new(color: ImageId, depth: ImageId, maps: EnvironmentMaps, namer: &ObjectNamer) -> SkyPass {
+        SkyPass {
+            color,
+            depth,
+            maps,
+            namer: namer.clone(),
+            pipeline: None,
+            set: None,
+        }
+    }
+}
+
+impl Pass<Scene> for SkyPass {
+    fn declare(&mut self, pass: &mut PassBuilder) {
+        pass.color(self.color, Load::Keep).depth(self.depth, Load::Keep).read(self.maps.id);
+    }
+
+    fn prepare(&mut self, device: &Arc<Device>, subpass: Option<Subpass>) {
+        let vs = vs::Shader::load(device.clone()).unwrap();
+        let fs = fs::Shader::load(device.clone()).unwrap();
+        let pipeline = Arc::new(GraphicsPipeline::start().vertex_input(BufferlessDefinition)
+                                                         .vertex_shader(vs.main_entry_point(), ())
+                                                         .triangle_list()
+                                                         .viewports_dynamic_scissors_irrelevant(1)
+                                                         .fragment_shader(fs.main_entry_point(), ())
+                                                         .depth_stencil(DepthStencil { depth_compare: Compare::LessOrEqual, depth_write: false, ..DepthStencil::simple_depth_test() })
+                                                         .render_pass(subpass.expect("the sky pass renders to attachments"))
+                                                         .build(device.clone())
+                                                         .unwrap());
+        self.namer.name(&*pipeline, "sky pipeline");
+
+        let layout = pipeline.descriptor_set_layout(0).unwrap().clone();
+        self.set = Some(Arc::new(PersistentDescriptorSet::start(layout).add_sampler(self.maps.sampler.clone())
+                                                                      .unwrap()
+                                                                      .add_image(self.maps.cube.clone())
+                                                                      .unwrap()
+                                                                      .build()
+                                                                      .unwrap()));
+        self.pipeline = Some(pipeline);
+    }
+
+    fn record(&mut self, context: &mut PassContext, scene: &Scene) {
+        let environment = match &scene.environment {
+            Some(environment) => environment,
+            None => return,
+        };
+
+        // Only the camera's rotation matters to directions.
+        let mut view = scene.camera.view();
+        view.w = Vector4::new(0.0, 0.0, 0.0, 1.0);
+        let aspect = context.dimensions[0] as f32 / context.dimensions[1] as f32;
+        let inverse_view_projection = (scene.camera.projection(aspect) * view).invert().expect("camera can't be inverted");
+
+        context.builder
+               .draw(self.pipeline.clone().unwrap(),
+                     context.dynamic_state,
+                     BufferlessVertices { vertices: 3, instances: 1 },
+                     self.set.clone().unwrap(),
+                     Sky { inverse_view_projection: inverse_view_projection.into(), intensity: environment.intensity },
+                     vec![])
+               .unwrap();
+    }
+}
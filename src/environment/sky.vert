@@ -0,0 +1,10 @@
+#version 450
+
+layout(location = 0) out vec2 v_position;
+
+// A single triangle covering the whole target on the far plane, behind everything drawn before.
+void main() {
+    vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    v_position = uv * 2.0 - 1.0;
+    gl_Position = vec4(v_position, 1.0, 1.0);
+}
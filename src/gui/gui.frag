@@ -0,0 +1,13 @@
+#version 450
+
+layout(location = 0) in vec2 v_uv;
+layout(location = 1) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler gui_sampler;
+layout(set = 0, binding = 1) uniform texture2D gui_texture;
+
+void main() {
+    f_color = texture(sampler2D(gui_texture, gui_sampler), v_uv) * v_color;
+}
@@ -0,0 +1,26 @@
+#version 450
+
+// In points from the top left, y down.
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec2 uv;
+// Premultiplied sRGB.
+layout(location = 2) in vec4 color;
+
+layout(location = 0) out vec2 v_uv;
+layout(location = 1) out vec4 v_color;
+
+layout(push_constant) uniform Screen {
+    // Size of the target in points.
+    vec2 size;
+} screen;
+
+vec3 linear_from_srgb(vec3 srgb) {
+    return mix(pow((srgb + 0.055) / 1.055, vec3(2.4)), srgb / 12.92, lessThan(srgb, vec3(0.04045)));
+}
+
+void main() {
+    gl_Position = vec4(position / screen.size * 2.0 - 1.0, 0.0, 1.0);
+    v_uv = uv;
+    // Blending happens in linear on the sRGB backbuffer.
+    v_color = vec4(linear_from_srgb(color.rgb), color.a);
+}
@@ -0,0 +1,193 @@
+use std::time::Instant;
+
+use egui::{Event, Key, Modifiers, PointerButton, Pos2, RawInput, Rect, Vec2};
+use winit::event::{ElementState, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+use winit::window::{CursorIcon, Window};
+
+// Points scrolled per line of a mouse wheel.
+const POINTS_PER_LINE: f32 = 50.0;
+
+/// egui's input for the next frame, gathered from winit's window events.
+pub struct Input {
+    raw: RawInput,
+    start: Instant,
+    // Window size in pixels.
+    size: [u32; 2],
+    pixels_per_point: f32,
+    pointer: Option<Pos2>,
+    modifiers: Modifiers,
+}
+
+impl Input {
+    pub fn new(window: &Window) -> Input {
+        Input {
+            raw: RawInput::default(),
+            start: Instant::now(),
+            size: window.inner_size().into(),
+            pixels_per_point: window.scale_factor() as f32,
+            pointer: None,
+            modifiers: Modifiers::default(),
+        }
+    }
+
+    pub fn event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::Resized(size) => self.size = [size.width, size.height],
+            WindowEvent::ScaleFactorChanged { scale_factor, new_inner_size } => {
+                self.pixels_per_point = *scale_factor as f32;
+                self.size = [new_inner_size.width, new_inner_size.height];
+            }
+            WindowEvent::ModifiersChanged(state) => self.modifiers = modifiers(*state),
+            WindowEvent::CursorMoved { position, .. } => {
+                let pointer = Pos2::new(position.x as f32 / self.pixels_per_point, position.y as f32 / self.pixels_per_point);
+                self.pointer = Some(pointer);
+                self.raw.events.push(Event::PointerMoved(pointer));
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.pointer = None;
+                self.raw.events.push(Event::PointerGone);
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left => PointerButton::Primary,
+                    MouseButton::Right => PointerButton::Secondary,
+                    MouseButton::Middle => PointerButton::Middle,
+                    MouseButton::Other(_) => return,
+                };
+                if let Some(pos) = self.pointer {
+                    self.raw.events.push(Event::PointerButton { pos, button, pressed: *state == ElementState::Pressed, modifiers: self.modifiers });
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let delta = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => Vec2::new(*x, *y) * POINTS_PER_LINE,
+                    MouseScrollDelta::PixelDelta(delta) => Vec2::new(delta.x as f32, delta.y as f32) / self.pixels_per_point,
+                };
+                self.raw.scroll_delta += delta;
+            }
+            WindowEvent::KeyboardInput { input, .. } => {
+                if let Some(key) = input.virtual_keycode.and_then(key) {
+                    self.raw.events.push(Event::Key { key, pressed: input.state == ElementState::Pressed, modifiers: self.modifiers });
+                }
+            }
+            // Shortcuts come as keys, not text.
+            WindowEvent::ReceivedCharacter(c) if !c.is_control() && !self.modifiers.ctrl && !self.modifiers.mac_cmd => {
+                self.raw.events.push(Event::Text(c.to_string()));
+            }
+            _ => (),
+        }
+    }
+
+    /// What happened since the last call.
+    pub fn take(&mut self) -> RawInput {
+        let size = Vec2::new(self.size[0] as f32, self.size[1] as f32) / self.pixels_per_point;
+        RawInput {
+            screen_rect: Some(Rect::from_min_size(Pos2::ZERO, size)),
+            pixels_per_point: Some(self.pixels_per_point),
+            time: Some(self.start.elapsed().as_secs_f64()),
+            modifiers: self.modifiers,
+            ..self.raw.take()
+        }
+    }
+}
+
+fn modifiers(state: ModifiersState) -> Modifiers {
+    Modifiers {
+        alt: state.alt(),
+        ctrl: state.ctrl(),
+        shift: state.shift(),
+        mac_cmd: cfg!(target_os = "macos") && state.logo(),
+        command: if cfg!(target_os = "macos") { state.logo() } else { state.ctrl() },
+    }
+}
+
+/// The egui key for keys egui knows about.
+fn key(key: VirtualKeyCode) -> Option<Key> {
+    use VirtualKeyCode as K;
+    Some(match key {
+        K::Down => Key::ArrowDown,
+        K::Left => Key::ArrowLeft,
+        K::Right => Key::ArrowRight,
+        K::Up => Key::ArrowUp,
+        K::Escape => Key::Escape,
+        K::Tab => Key::Tab,
+        K::Back => Key::Backspace,
+        K::Return | K::NumpadEnter => Key::Enter,
+        K::Space => Key::Space,
+        K::Insert => Key::Insert,
+        K::Delete => Key::Delete,
+        K::Home => Key::Home,
+        K::End => Key::End,
+        K::PageUp => Key::PageUp,
+        K::PageDown => Key::PageDown,
+        K::Key0 | K::Numpad0 => Key::Num0,
+        K::Key1 | K::Numpad1 => Key::Num1,
+        K::Key2 | K::Numpad2 => Key::Num2,
+        K::Key3 | K::Numpad3 => Key::Num3,
+        K::Key4 | K::Numpad4 => Key::Num4,
+        K::Key5 | K::Numpad5 => Key::Num5,
+        K::Key6 | K::Numpad6 => Key::Num6,
+        K::Key7 | K::Numpad7 => Key::Num7,
+        K::Key8 | K::Numpad8 => Key::Num8,
+        K::Key9 | K::Numpad9 => Key::Num9,
+        K::A => Key::A,
+        K::B => Key::B,
+        K::C => Key::C,
+        K::D => Key::D,
+        K::E => Key::E,
+        K::F => Key::F,
+        K::G => Key::G,
+        K::H => Key::H,
+        K::I => Key::I,
+        K::J => Key::J,
+        K::K => Key::K,
+        K::L => Key::L,
+        K::M => Key::M,
+        K::N => Key::N,
+        K::O => Key::O,
+        K::P => Key::P,
+        K::Q => Key::Q,
+        K::R => Key::R,
+        K::S => Key::S,
+        K::T => Key::T,
+        K::U => Key::U,
+        K::V => Key::V,
+        K::W => Key::W,
+        K::X => Key::X,
+        K::Y => Key::Y,
+        K::Z => Key::Z,
+        _ => return None,
+    })
+}
+
+/// The winit cursor for an egui one, `None` to hide it.
+pub fn cursor_icon(icon: egui::CursorIcon) -> Option<CursorIcon> {
+    use egui::CursorIcon as E;
+    Some(match icon {
+        E::None => return None,
+        E::Default => CursorIcon::Default,
+        E::ContextMenu => CursorIcon::ContextMenu,
+        E::Help => CursorIcon::Help,
+        E::PointingHand => CursorIcon::Hand,
+        E::Progress => CursorIcon::Progress,
+        E::Wait => CursorIcon::Wait,
+        E::Cell => CursorIcon::Cell,
+        E::Crosshair => CursorIcon::Crosshair,
+        E::Text => CursorIcon::Text,
+        E::VerticalText => CursorIcon::VerticalText,
+        E::Alias => CursorIcon::Alias,
+        E::Copy => CursorIcon::Copy,
+        E::Move => CursorIcon::Move,
+        E::NoDrop => CursorIcon::NoDrop,
+        E::NotAllowed => CursorIcon::NotAllowed,
+        E::Grab => CursorIcon::Grab,
+        E::Grabbing => CursorIcon::Grabbing,
+        E::AllScroll => CursorIcon::AllScroll,
+        E::ResizeHorizontal => CursorIcon::EwResize,
+        E::ResizeNeSw => CursorIcon::NeswResize,
+        E::ResizeNwSe => CursorIcon::NwseResize,
+        E::ResizeVertical => CursorIcon::NsResize,
+        E::ZoomIn => CursorIcon::ZoomIn,
+        E::ZoomOut => CursorIcon::ZoomOut,
+    })
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use egui::{ClippedMesh, CtxRef, TextureId};
+use vulkano::device::Device;
+use vulkano::image::view::ImageViewAbstract;
+use winit::event::WindowEvent;
+use winit::window::Window;
+
+use crate::debug::ObjectNamer;
+use crate::gui::input::Input;
+use crate::gui::pass::GuiPass;
+use crate::render_graph::{ImageId, RenderGraphBuilder};
+use crate::scene::{Material, Scene, Texture};
+use crate::upload::UploadManager;
+
+mod input;
+mod pass;
+
+/// An engine image egui can draw with `ui.image`.
+#[derive(Clone)]
+pub enum GuiTexture {
+    /// Any image view, like a material's texture.
+    View(Arc<dyn ImageViewAbstract + Send + Sync>),
+    /// A render graph image as the passes before the GUI left it. Has to be registered before the graph is
+    /// built, and can't be the image the GUI is drawn to.
+    Graph(ImageId),
+}
+
+/// What the GUI pass draws, as the last `Gui::run` left it.
+struct GuiFrame {
+    meshes: Vec<ClippedMesh>,
+    pixels_per_point: f32,
+    /// egui's font texture and its version.
+    font: Option<(u64, Texture)>,
+    textures: HashMap<u64, GuiTexture>,
+}
+
+/// An egui context fed by the window's events, drawn by the GUI pass over the rest of the frame.
+///
+/// Events go to `handle_event` before the game sees them, and the UI is built once a frame with `run`. There's no
+/// clipboard, so copying and pasting text doesn't leave the GUI.
+pub struct Gui {
+    context: CtxRef,
+    input: Input,
+    frame: Arc<Mutex<GuiFrame>>,
+    next_texture: u64,
+    namer: ObjectNamer,
+}
+
+impl Gui {
+    pub fn new(window: &Window, namer: &ObjectNamer) -> Gui {
+        let frame = GuiFrame {
+            meshes: vec![],
+            pixels_per_point: window.scale_factor() as f32,
+            font: None,
+            textures: HashMap::new(),
+        };
+        Gui {
+            context: CtxRef::default(),
+            input: Input::new(window),
+            frame: Arc::new(Mutex::new(frame)),
+            next_texture: 0,
+            namer: namer.clone(),
+        }
+    }
+
+    /// Passes `event` on to the GUI. True when the GUI is using it, in which case the game should ignore it: the
+    /// pointer is over a window or dragging something, or a text field has keyboard focus.
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        self.input.event(event);
+        match event {
+            WindowEvent::CursorMoved { .. } | WindowEvent::MouseInput { .. } | WindowEvent::MouseWheel { .. } => self.context.wants_pointer_input(),
+            WindowEvent::KeyboardInput { .. } | WindowEvent::ReceivedCharacter(_) => self.context.wants_keyboard_input(),
+            _ => false,
+        }
+    }
+
+    /// Builds this frame's UI with `build` and hands what it drew to the GUI pass. Call it once a frame, before
+    /// the frame is recorded, so a new font texture is uploaded in time.
+    pub fn run(&mut self, window: &Window, uploads: &mut UploadManager, build: impl FnOnce(&CtxRef)) {
+        self.context.begin_frame(self.input.take());
+        build(&self.context);
+        let (output, shapes) = self.context.end_frame();
+        let meshes = self.context.tessellate(shapes);
+
+        match input::cursor_icon(output.cursor_icon) {
+            Some(icon) => {
+                window.set_cursor_visible(true);
+                window.set_cursor_icon(icon);
+            }
+            None => window.set_cursor_visible(false),
+        }
+
+        let mut frame = self.frame.lock().unwrap();
+        let font = self.context.texture();
+        if frame.font.as_ref().map(|(version, _)| *version) != Some(font.version) {
+            // White with premultiplied coverage.
+            let pixels: Vec<[u8; 4]> = font.pixels.iter().map(|&alpha| [alpha; 4]).collect();
+            let texture = Material::texture(uploads, &self.namer, "gui font texture", &pixels, [font.width as u32, font.height as u32], false);
+            frame.font = Some((font.version, texture));
+        }
+        frame.meshes = meshes;
+        frame.pixels_per_point = self.context.pixels_per_point();
+    }
+
+    pub fn register_texture(&mut self, texture: GuiTexture) -> TextureId {
+        let id = self.next_texture;
+        self.next_texture += 1;
+        self.frame.lock().unwrap().textures.insert(id, texture);
+        TextureId::User(id)
+    }
+}
+
+/// Adds the pass drawing what `gui` built over `target`, sampling the graph images registered with it so far.
+pub fn add_pass(graph: &mut RenderGraphBuilder<Scene>, target: ImageId, gui: &Gui, device: &Arc<Device>, namer: &ObjectNamer) {
+    graph.add_pass("gui", GuiPass::new(device.clone(), target, gui.frame.clone(), namer));
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use egui::epaint::Vertex;
+use egui::{ClippedMesh, TextureId};
+use tracing::{trace, warn};
+use vulkano::buffer::{BufferUsage, CpuBufferPool};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::image::view::ImageViewAbstract;
+use vulkano::pipeline::blend::{AttachmentBlend, BlendFactor};
+use vulkano::pipeline::vertex::SingleBufferDefinition;
+use vulkano::pipeline::viewport::Scissor;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::render_pass::Subpass;
+use vulkano::sampler::Sampler;
+
+use crate::debug::ObjectNamer;
+use crate::gui::{GuiFrame, GuiTexture};
+use crate::logging;
+use crate::render_graph::{ImageId, Load, Pass, PassBuilder, PassContext};
+use crate::scene::Scene;
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/gui/gui.vert"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/gui/gui.frag"
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+struct GuiVertex {
+    position: [f32; 2],
+    uv: [f32; 2],
+    color: [f32; 4],
+}
+vulkano::impl_vertex!(GuiVertex, position, uv, color);
+
+impl GuiVertex {
+    fn new(vertex: &Vertex) -> GuiVertex {
+        let [r, g, b, a] = vertex.color.to_array();
+        GuiVertex {
+            position: [vertex.pos.x, vertex.pos.y],
+            uv: [vertex.uv.x, vertex.uv.y],
+            color: [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, a as f32 / 255.0],
+        }
+    }
+}
+
+type GuiPipeline = GraphicsPipeline<SingleBufferDefinition<GuiVertex>, Box<dyn PipelineLayoutAbstract + Send + Sync>>;
+
+/// Draws egui's meshes over `target` with premultiplied alpha, each clipped to its rectangle.
+pub struct GuiPass {
+    target: ImageId,
+    frame: Arc<Mutex<GuiFrame>>,
+    sampler: Arc<Sampler>,
+    vertices: CpuBufferPool<GuiVertex>,
+    indices: CpuBufferPool<u32>,
+    namer: ObjectNamer,
+    pipeline: Option<Arc<GuiPipeline>>,
+    // Descriptor set of every texture drawn, with the font version or graph generation it was made for.
+    sets: HashMap<TextureId, (u64, Arc<dyn DescriptorSet + Send + Sync>)>,
+}
+
+impl GuiPass {
+    pub(crate) fn new(device: Arc<Device>, target: ImageId, frame: Arc<Mutex<GuiFrame>>, namer: &ObjectNamer) -> GuiPass {
+        GuiPass {
+            target,
+            frame,
+            sampler: Sampler::simple_repeat_linear_no_mipmap(device.clone()),
+            vertices: CpuBufferPool::vertex_buffer(device.clone()),
+            indices: CpuBufferPool::new(device, BufferUsage::index_buffer()),
+            namer: namer.clone(),
+            pipeline: None,
+            sets: HashMap::new(),
+        }
+    }
+
+    fn descriptor_set(&mut self, context: &PassContext, frame: &GuiFrame, id: TextureId) -> Option<Arc<dyn DescriptorSet + Send + Sync>> {
+        let (stamp, view): (u64, Arc<dyn ImageViewAbstract + Send + Sync>) = match id {
+            TextureId::Egui => {
+                let (version, font) = frame.font.as_ref()?;
+                (*version, font.clone())
+            }
+            TextureId::User(id) => match frame.textures.get(&id)? {
+                GuiTexture::View(view) => (0, view.clone()),
+                GuiTexture::Graph(image) => (context.generation(), context.image(*image)),
+            },
+        };
+        if let Some((cached, set)) = self.sets.get(&id) {
+            if *cached == stamp {
+                return Some(set.clone());
+            }
+        }
+
+        let layout = self.pipeline.as_ref().unwrap().descriptor_set_layout(0).unwrap().clone();
+        let set = Arc::new(PersistentDescriptorSet::start(layout).add_sampler(self.sampler.clone()).unwrap().add_image(view).unwrap().build().unwrap()) as Arc<dyn DescriptorSet + Send + Sync>;
+        self.sets.insert(id, (stamp, set.clone()));
+        Some(set)
+    }
+}
+
+impl Pass<Scene> for GuiPass {
+    fn declare(&mut self, pass: &mut PassBuilder) {
+        pass.color(self.target, Load::Keep);
+        for texture in self.frame.lock().unwrap().textures.values() {
+            if let GuiTexture::Graph(image) = texture {
+                pass.sample(*image);
+            }
+        }
+    }
+
+    fn prepare(&mut self, device: &Arc<Device>, subpass: Option<Subpass>) {
+        let vs = vs::Shader::load(device.clone()).unwrap();
+        let fs = fs::Shader::load(device.clone()).unwrap();
+        let premultiplied = AttachmentBlend {
+            color_source: BlendFactor::One,
+            alpha_source: BlendFactor::OneMinusDstAlpha,
+            alpha_destination: BlendFactor::One,
+            ..AttachmentBlend::alpha_blending()
+        };
+        let pipeline = Arc::new(GraphicsPipeline::start().vertex_input_single_buffer::<GuiVertex>()
+                                                         .vertex_shader(vs.main_entry_point(), ())
+                                                         .triangle_list()
+                                                         .viewports_scissors_dynamic(1)
+                                                         .fragment_shader(fs.main_entry_point(), ())
+                                                         .blend_collective(premultiplied)
+                                                         .cull_mode_disabled()
+                                                         .render_pass(subpass.expect("the gui pass renders to an attachment"))
+                                                         .build(device.clone())
+                                                         .unwrap());
+        self.namer.name(&*pipeline, "gui pipeline");
+        self.pipeline = Some(pipeline);
+        self.sets.clear();
+    }
+
+    fn record(&mut self, context: &mut PassContext, _scene: &Scene) {
+        let frame = self.frame.clone();
+        let frame = frame.lock().unwrap();
+        // Forget textures that were unregistered.
+        self.sets.retain(|id, _| match id {
+                         TextureId::Egui => true,
+                         TextureId::User(id) => frame.textures.contains_key(id),
+                     });
+
+        let [width, height] = context.dimensions;
+        let pixels_per_point = frame.pixels_per_point;
+        let screen_size = [width as f32 / pixels_per_point, height as f32 / pixels_per_point];
+
+        let mut draws = 0;
+        for ClippedMesh(clip, mesh) in frame.meshes.iter() {
+            if mesh.indices.is_empty() {
+                continue;
+            }
+            let set = match self.descriptor_set(context, &frame, mesh.texture_id) {
+                Some(set) => set,
+                None => {
+                    warn!(target: logging::RENDER, texture = ?mesh.texture_id, "gui mesh with an unknown texture");
+                    continue;
+                }
+            };
+
+            // The clip rectangle in pixels, within the target.
+            let min_x = (clip.min.x * pixels_per_point).round().clamp(0.0, width as f32) as u32;
+            let min_y = (clip.min.y * pixels_per_point).round().clamp(0.0, height as f32) as u32;
+            let max_x = (clip.max.x * pixels_per_point).round().clamp(min_x as f32, width as f32) as u32;
+            let max_y = (clip.max.y * pixels_per_point).round().clamp(min_y as f32, height as f32) as u32;
+            if max_x == min_x || max_y == min_y {
+                continue;
+            }
+            let mut dynamic_state = context.dynamic_state.clone();
+            dynamic_state.scissors = Some(vec![Scissor { origin: [min_x as i32, min_y as i32], dimensions: [max_x - min_x, max_y - min_y] }]);
+
+            let vertices = self.vertices.chunk(mesh.vertices.iter().map(GuiVertex::new)).unwrap();
+            let indices = self.indices.chunk(mesh.indices.iter().copied()).unwrap();
+            context.builder
+                   .draw_indexed(self.pipeline.clone().unwrap(), &dynamic_state, vertices, indices, set, screen_size, vec![])
+                   .unwrap();
+            draws += 1;
+        }
+        trace!(target: logging::RENDER, meshes = frame.meshes.len(), draws, "drew gui");
+    }
+}
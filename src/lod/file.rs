@@ -0,0 +1,64 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::scene::{MeshData, MeshVertex};
+
+const MAGIC: &[u8; 4] = b"TMSH";
+
+// Floats in a vertex: position, normal, tangent and UV.
+const VERTEX_FLOATS: usize = 12;
+
+/// Writes `data` as little endian binary: the magic, vertex and index counts as `u32`s, the vertices as floats in
+/// field order and the indices.
+pub fn write(path: &Path, data: &MeshData) -> io::Result<()> {
+    let mut bytes = Vec::with_capacity(12 + data.vertices.len() * VERTEX_FLOATS * 4 + data.indices.len() * 4);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&(data.vertices.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&(data.indices.len() as u32).to_le_bytes());
+    for vertex in data.vertices.iter() {
+        for value in vertex.position.iter().chain(vertex.normal.iter()).chain(vertex.tangent.iter()).chain(vertex.uv.iter()) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    for index in data.indices.iter() {
+        bytes.extend_from_slice(&index.to_le_bytes());
+    }
+    fs::write(path, bytes)
+}
+
+/// Reads a mesh written by `write`.
+pub fn read(path: &Path) -> io::Result<MeshData> {
+    let bytes = fs::read(path)?;
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), message));
+    if bytes.len() < 12 || &bytes[..4] != MAGIC {
+        return Err(invalid("not a mesh"));
+    }
+    let word = |offset: usize| [bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]];
+    let (vertex_count, index_count) = (u32::from_le_bytes(word(4)) as usize, u32::from_le_bytes(word(8)) as usize);
+    if bytes.len() != 12 + (vertex_count * VERTEX_FLOATS + index_count) * 4 {
+        return Err(invalid("truncated mesh"));
+    }
+
+    let mut data = MeshData::default();
+    let mut offset = 12;
+    for _ in 0..vertex_count {
+        let values: Vec<f32> = (0..VERTEX_FLOATS).map(|i| f32::from_le_bytes(word(offset + i * 4))).collect();
+        data.vertices.push(MeshVertex {
+            position: [values[0], values[1], values[2]],
+            normal: [values[3], values[4], values[5]],
+            tangent: [values[6], values[7], values[8], values[9]],
+            uv: [values[10], values[11]],
+        });
+        offset += VERTEX_FLOATS * 4;
+    }
+    for _ in 0..index_count {
+        let index = u32::from_le_bytes(word(offset));
+        if index as usize >= vertex_count {
+            return Err(invalid("index out of range"));
+        }
+        data.indices.push(index);
+        offset += 4;
+    }
+    Ok(data)
+}
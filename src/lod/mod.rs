@@ -0,0 +1,229 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use cgmath::{InnerSpace, Matrix4, Transform};
+use serde::{Deserialize, Serialize};
+
+pub use crate::lod::simplify::simplify;
+
+use crate::debug::ObjectNamer;
+use crate::scene::{Camera, Mesh, MeshData, Object};
+use crate::upload::UploadManager;
+
+mod file;
+mod simplify;
+pub mod tool;
+
+#[derive(Debug)]
+pub enum LodError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Gltf(gltf::Error),
+    /// The source has no triangles to simplify.
+    NoTriangles,
+}
+
+impl fmt::Display for LodError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LodError::Io(e) => write!(f, "{}", e),
+            LodError::Json(e) => write!(f, "invalid lod group: {}", e),
+            LodError::Gltf(e) => write!(f, "couldn't import glTF: {}", e),
+            LodError::NoTriangles => write!(f, "there are no triangles"),
+        }
+    }
+}
+
+impl From<io::Error> for LodError {
+    fn from(e: io::Error) -> LodError {
+        LodError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for LodError {
+    fn from(e: serde_json::Error) -> LodError {
+        LodError::Json(e)
+    }
+}
+
+impl From<gltf::Error> for LodError {
+    fn from(e: gltf::Error) -> LodError {
+        LodError::Gltf(e)
+    }
+}
+
+/// A level of a LOD group file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LodLevelDesc {
+    /// A mesh written by the `lod` tool, relative to the group file.
+    pub mesh: PathBuf,
+    pub screen_size: f32,
+}
+
+/// A LOD group, usually written by the `lod` tool and read with `LodGroup::load`. See `LodGroup` for the fields.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LodGroupDesc {
+    pub levels: Vec<LodLevelDesc>,
+    pub hysteresis: f32,
+    pub crossfade: Option<f32>,
+}
+
+impl Default for LodGroupDesc {
+    fn default() -> LodGroupDesc {
+        LodGroupDesc { levels: vec![], hysteresis: 0.1, crossfade: Some(0.25) }
+    }
+}
+
+/// A mesh of a LOD group and how big objects have to be on screen to draw it.
+pub struct LodLevel {
+    pub mesh: Arc<Mesh>,
+    /// Smallest height on screen the level is drawn at, as a fraction of the screen's height. Objects smaller than
+    /// the last level's aren't drawn at all, so it's 0 for one that's never culled.
+    pub screen_size: f32,
+}
+
+/// Meshes of the same thing from the most to the least detailed, which objects switch between by how big they are
+/// on screen.
+pub struct LodGroup {
+    /// With decreasing screen sizes.
+    pub levels: Vec<LodLevel>,
+    /// Fraction of a level's screen size an object has to get past it by before switching, so it doesn't switch back
+    /// and forth when it's right at it.
+    pub hysteresis: f32,
+    /// Seconds the old level is dithered into the new one for. Levels pop without.
+    pub crossfade: Option<f32>,
+}
+
+impl LodGroup {
+    /// Reads a `LodGroupDesc` from a JSON file, with its meshes next to it.
+    pub fn load(uploads: &mut UploadManager, namer: &ObjectNamer, path: &Path) -> Result<LodGroup, LodError> {
+        let desc: LodGroupDesc = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut levels = vec![];
+        for level in desc.levels.iter() {
+            let path = directory.join(&level.mesh);
+            let mesh = Mesh::upload(uploads, namer, &path.display().to_string(), &file::read(&path)?);
+            levels.push(LodLevel { mesh, screen_size: level.screen_size });
+        }
+        Ok(LodGroup { levels, hysteresis: desc.hysteresis, crossfade: desc.crossfade })
+    }
+
+    /// Simplifies `data` into a level for every pair of the fraction of its triangles kept and the screen size, with
+    /// the defaults of `LodGroupDesc`.
+    pub fn generate(uploads: &mut UploadManager, namer: &ObjectNamer, name: &str, data: &MeshData, levels: &[(f32, f32)]) -> LodGroup {
+        let ratios: Vec<f32> = levels.iter().map(|&(ratio, _)| ratio).collect();
+        let levels = simplify_levels(data, &ratios).iter()
+                                                   .zip(levels.iter())
+                                                   .enumerate()
+                                                   .map(|(level, (data, &(_, screen_size)))| LodLevel {
+                                                       mesh: Mesh::upload(uploads, namer, &format!("{} lod {}", name, level), data),
+                                                       screen_size,
+                                                   })
+                                                   .collect();
+        let desc = LodGroupDesc::default();
+        LodGroup { levels, hysteresis: desc.hysteresis, crossfade: desc.crossfade }
+    }
+
+    /// Fraction of the screen's height a sphere around the first level's bounds covers, when it's `transform`ed and
+    /// seen by `camera`.
+    pub fn screen_size(&self, transform: &Matrix4<f32>, camera: &Camera) -> f32 {
+        let bounds = match self.levels.first() {
+            Some(level) => level.mesh.bounds,
+            None => return 0.0,
+        };
+        let scale = transform.x.truncate().magnitude().max(transform.y.truncate().magnitude()).max(transform.z.truncate().magnitude());
+        let radius = bounds.half_extents().magnitude() * scale;
+        let distance = (transform.transform_point(bounds.center()) - camera.position).magnitude();
+        if distance <= radius {
+            return f32::INFINITY;
+        }
+        radius / (distance * (camera.fov_y.0 / 2.0).tan())
+    }
+
+    /// The level an object on `level` switches to at `screen_size`, the number of levels when it's too small to
+    /// draw.
+    pub fn select(&self, level: usize, screen_size: f32) -> usize {
+        let mut level = level.min(self.levels.len());
+        while level < self.levels.len() && screen_size < self.levels[level].screen_size * (1.0 - self.hysteresis) {
+            level += 1;
+        }
+        while level > 0 && screen_size >= self.levels[level - 1].screen_size * (1.0 + self.hysteresis) {
+            level -= 1;
+        }
+        level
+    }
+}
+
+/// `data` simplified to every fraction of its triangles in `ratios`, each from the one before. Levels keeping all
+/// of them are copies.
+pub fn simplify_levels(data: &MeshData, ratios: &[f32]) -> Vec<MeshData> {
+    let triangles = data.indices.len() / 3;
+    let mut levels: Vec<MeshData> = vec![];
+    for &ratio in ratios.iter() {
+        let source = levels.last().unwrap_or(data);
+        let target = (triangles as f32 * ratio).round() as usize;
+        levels.push(if target >= source.indices.len() / 3 { source.clone() } else { simplify(source, target) });
+    }
+    levels
+}
+
+/// Which level of its group an object draws.
+#[derive(Clone)]
+pub struct Lod {
+    pub group: Arc<LodGroup>,
+    /// Index of the level, or the number of levels when the object is too small to draw.
+    pub level: usize,
+    /// The level being dithered out and how far along that is, from 0 to 1.
+    pub fading: Option<(usize, f32)>,
+}
+
+impl Lod {
+    /// Starts on the most detailed level.
+    pub fn new(group: Arc<LodGroup>) -> Lod {
+        Lod { group, level: 0, fading: None }
+    }
+
+    pub fn shown(&self) -> bool {
+        self.level < self.group.levels.len()
+    }
+
+    /// The mesh being dithered out and how far along that is, when it's drawn.
+    pub fn fading(&self) -> Option<(&Arc<Mesh>, f32)> {
+        let (level, progress) = self.fading?;
+        self.group.levels.get(level).map(|level| (&level.mesh, progress))
+    }
+}
+
+/// Picks the level of every object with a LOD group for how big it is seen by `camera`, points its mesh to it and
+/// advances crossfades by `frame_time`. Has to be called every frame before the scene's `Bvh` is updated.
+pub fn update(objects: &mut [Object], camera: &Camera, frame_time: Duration) {
+    for object in objects.iter_mut() {
+        let lod = match &mut object.lod {
+            Some(lod) => lod,
+            None => continue,
+        };
+        let group = lod.group.clone();
+
+        if let (Some((_, progress)), Some(crossfade)) = (&mut lod.fading, group.crossfade) {
+            *progress += frame_time.as_secs_f32() / crossfade;
+        }
+        if matches!(lod.fading, Some((_, progress)) if progress >= 1.0) {
+            lod.fading = None;
+        }
+
+        let level = group.select(lod.level, group.screen_size(&object.transform, camera));
+        if level != lod.level {
+            // A switch in the middle of a fade drops the level that was fading out.
+            lod.fading = group.crossfade.filter(|&crossfade| crossfade > 0.0).map(|_| (lod.level, 0.0));
+            lod.level = level;
+        }
+        if let Some(level) = group.levels.get(level) {
+            object.mesh = level.mesh.clone();
+        }
+    }
+}
@@ -0,0 +1,389 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use cgmath::{InnerSpace, Vector3};
+
+use crate::scene::MeshData;
+
+/// How much moving an open edge costs compared to moving a face of the same area.
+const BORDER_WEIGHT: f64 = 10.0;
+
+/// Sum of squared distances to planes as a symmetric 4x4 matrix, Garland and Heckbert's error quadric. Holds the
+/// upper triangle row by row.
+#[derive(Debug, Copy, Clone, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    /// Squared distance to the plane through `point` facing `normal`, which has to be normalized, times `weight`.
+    fn plane(normal: Vector3<f64>, point: Vector3<f64>, weight: f64) -> Quadric {
+        let (a, b, c, d) = (normal.x, normal.y, normal.z, -normal.dot(point));
+        Quadric([a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d].map(|value| value * weight))
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (value, other) in self.0.iter_mut().zip(other.0.iter()) {
+            *value += other;
+        }
+    }
+
+    fn error(&self, point: Vector3<f64>) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (point.x, point.y, point.z);
+        q[0] * x * x + q[4] * y * y + q[7] * z * z + 2.0 * (q[1] * x * y + q[2] * x * z + q[5] * y * z + q[3] * x + q[6] * y + q[8] * z) + q[9]
+    }
+}
+
+/// Moving point `from` onto point `to`, with the versions of both it was measured at.
+struct Collapse {
+    error: f64,
+    from: usize,
+    to: usize,
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Collapse) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Collapse) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    // Reversed, so the heap pops the cheapest collapse first.
+    fn cmp(&self, other: &Collapse) -> Ordering {
+        other.error.partial_cmp(&self.error).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// The surface being simplified. Vertices at the same position share a point, which is what moves, while triangles
+/// keep indexing vertices so normals and UVs stay as they were.
+struct Surface<'a> {
+    data: &'a MeshData,
+    point_of: Vec<usize>,
+    positions: Vec<Vector3<f64>>,
+    quadrics: Vec<Quadric>,
+    versions: Vec<u32>,
+    removed: Vec<bool>,
+    triangles: Vec<[u32; 3]>,
+    alive: Vec<bool>,
+    // Triangles around every point, including ones that have since collapsed.
+    point_triangles: Vec<Vec<usize>>,
+}
+
+/// Collapses edges of `data` until it has at most `triangles` triangles or no edge can collapse without folding the
+/// surface over, cheapest first by the error quadrics of the points.
+///
+/// Points only move onto their neighbours, so every vertex left has the attributes it had. Points on UV or normal
+/// seams only move along the seam, and open edges are kept in place by an extra plane along them.
+pub fn simplify(data: &MeshData, triangles: usize) -> MeshData {
+    let mut surface = Surface::new(data);
+    let mut remaining = surface.alive.iter().filter(|&&alive| alive).count();
+
+    let mut heap = BinaryHeap::new();
+    for point in 0..surface.positions.len() {
+        for neighbour in surface.neighbours(point) {
+            heap.push(surface.collapse(point, neighbour));
+        }
+    }
+
+    while remaining > triangles {
+        let collapse = match heap.pop() {
+            Some(collapse) => collapse,
+            None => break,
+        };
+        let (from, to) = (collapse.from, collapse.to);
+        if surface.removed[from] || surface.removed[to] || collapse.versions != (surface.versions[from], surface.versions[to]) {
+            continue;
+        }
+        let collapsed = match surface.apply(from, to) {
+            Some(collapsed) => collapsed,
+            None => continue,
+        };
+        remaining -= collapsed;
+        for neighbour in surface.neighbours(to) {
+            heap.push(surface.collapse(to, neighbour));
+            heap.push(surface.collapse(neighbour, to));
+        }
+    }
+
+    surface.finish()
+}
+
+impl<'a> Surface<'a> {
+    fn new(data: &'a MeshData) -> Surface<'a> {
+        let mut point_of = Vec::with_capacity(data.vertices.len());
+        let mut positions = vec![];
+        let mut points = HashMap::new();
+        for vertex in data.vertices.iter() {
+            let point = *points.entry(vertex.position.map(f32::to_bits)).or_insert_with(|| {
+                                                                        positions.push(Vector3::from(vertex.position.map(f64::from)));
+                                                                        positions.len() - 1
+                                                                    });
+            point_of.push(point);
+        }
+
+        let triangles: Vec<[u32; 3]> = data.indices.chunks_exact(3).map(|triangle| [triangle[0], triangle[1], triangle[2]]).collect();
+        let mut surface = Surface {
+            data,
+            quadrics: vec![Quadric::default(); positions.len()],
+            versions: vec![0; positions.len()],
+            removed: vec![false; positions.len()],
+            point_triangles: vec![vec![]; positions.len()],
+            alive: vec![true; triangles.len()],
+            point_of,
+            positions,
+            triangles,
+        };
+
+        // Triangles of every edge between points and one of them, for finding the open ones.
+        let mut edges: HashMap<(usize, usize), (u32, usize)> = HashMap::new();
+        for index in 0..surface.triangles.len() {
+            let points = surface.points(index);
+            let (normal, area) = surface.normal(points);
+            if area == 0.0 {
+                surface.alive[index] = false;
+                continue;
+            }
+            let quadric = Quadric::plane(normal, surface.positions[points[0]], area);
+            for corner in 0..3 {
+                let (a, b) = (points[corner], points[(corner + 1) % 3]);
+                surface.quadrics[a].add(&quadric);
+                surface.point_triangles[a].push(index);
+                edges.entry((a.min(b), a.max(b))).or_insert((0, index)).0 += 1;
+            }
+        }
+        for (&(a, b), &(count, triangle)) in edges.iter() {
+            if count != 1 {
+                continue;
+            }
+            // A plane through the edge standing on its triangle.
+            let edge = surface.positions[b] - surface.positions[a];
+            let (normal, _) = surface.normal(surface.points(triangle));
+            let quadric = Quadric::plane(edge.cross(normal).normalize(), surface.positions[a], BORDER_WEIGHT * edge.magnitude2());
+            surface.quadrics[a].add(&quadric);
+            surface.quadrics[b].add(&quadric);
+        }
+        surface
+    }
+
+    fn points(&self, triangle: usize) -> [usize; 3] {
+        self.triangles[triangle].map(|vertex| self.point_of[vertex as usize])
+    }
+
+    /// Normalized normal and area of the triangle between `points`.
+    fn normal(&self, points: [usize; 3]) -> (Vector3<f64>, f64) {
+        let [a, b, c] = points.map(|point| self.positions[point]);
+        let normal = (b - a).cross(c - a);
+        let length = normal.magnitude();
+        (if length > 0.0 { normal / length } else { normal }, length / 2.0)
+    }
+
+    fn neighbours(&self, point: usize) -> Vec<usize> {
+        let mut neighbours: Vec<usize> = self.point_triangles[point].iter()
+                                                                     .filter(|&&triangle| self.alive[triangle])
+                                                                     .flat_map(|&triangle| self.points(triangle))
+                                                                     .filter(|&other| other != point)
+                                                                     .collect();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        neighbours
+    }
+
+    fn collapse(&self, from: usize, to: usize) -> Collapse {
+        let mut quadric = self.quadrics[from];
+        quadric.add(&self.quadrics[to]);
+        Collapse {
+            error: quadric.error(self.positions[to]),
+            from,
+            to,
+            versions: (self.versions[from], self.versions[to]),
+        }
+    }
+
+    /// Moves `from` onto `to` and returns how many triangles collapsed, unless that would tear a seam, fold a
+    /// triangle over or join the surface to itself.
+    fn apply(&mut self, from: usize, to: usize) -> Option<usize> {
+        let triangles: Vec<usize> = self.point_triangles[from].iter().copied().filter(|&triangle| self.alive[triangle]).collect();
+        let (shared, moved): (Vec<usize>, Vec<usize>) = triangles.into_iter().partition(|&triangle| self.points(triangle).contains(&to));
+
+        // Every vertex of `from` becomes the vertex of `to` it shares a collapsing triangle with, so vertices on
+        // either side of a seam stay on their side. One without such a triangle would be torn from its side.
+        let mut vertices = HashMap::new();
+        for &triangle in shared.iter() {
+            let [a, b, c] = self.triangles[triangle];
+            for &(vertex, other) in [(a, b), (a, c), (b, a), (b, c), (c, a), (c, b)].iter() {
+                if self.point_of[vertex as usize] == from && self.point_of[other as usize] == to {
+                    vertices.entry(vertex).or_insert(other);
+                }
+            }
+        }
+        for &triangle in moved.iter() {
+            if self.triangles[triangle].iter().any(|&vertex| self.point_of[vertex as usize] == from && !vertices.contains_key(&vertex)) {
+                return None;
+            }
+            let points = self.points(triangle);
+            let (before, _) = self.normal(points);
+            let (after, _) = self.normal(points.map(|point| if point == from { to } else { point }));
+            if before.dot(after) <= 0.0 {
+                return None;
+            }
+        }
+        // Points next to both, other than across the collapsing triangles, would end up with an edge between them
+        // twice.
+        let neighbours = self.neighbours(to);
+        if self.neighbours(from).iter().filter(|&point| neighbours.binary_search(point).is_ok()).count() != shared.len() {
+            return None;
+        }
+
+        for &triangle in shared.iter() {
+            self.alive[triangle] = false;
+        }
+        for &triangle in moved.iter() {
+            for vertex in self.triangles[triangle].iter_mut() {
+                if let Some(&other) = vertices.get(vertex) {
+                    *vertex = other;
+                }
+            }
+        }
+        let quadric = self.quadrics[from];
+        self.quadrics[to].add(&quadric);
+        self.point_triangles[to].extend(moved);
+        let alive = &self.alive;
+        self.point_triangles[to].retain(|&triangle| alive[triangle]);
+        self.point_triangles[from].clear();
+        self.removed[from] = true;
+        self.versions[to] += 1;
+        Some(shared.len())
+    }
+
+    /// The triangles left, with only the vertices they use.
+    fn finish(self) -> MeshData {
+        let mut data = MeshData::default();
+        let mut remap = vec![u32::MAX; self.data.vertices.len()];
+        for (triangle, _) in self.triangles.iter().zip(self.alive.iter()).filter(|&(_, &alive)| alive) {
+            for &vertex in triangle.iter() {
+                if remap[vertex as usize] == u32::MAX {
+                    remap[vertex as usize] = data.vertices.len() as u32;
+                    data.vertices.push(self.data.vertices[vertex as usize]);
+                }
+                data.indices.push(remap[vertex as usize]);
+            }
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::scene::MeshVertex;
+
+    /// A square from -1 to 1 in the xz plane facing up, split into `cells` by `cells` quads.
+    fn grid(cells: u32) -> MeshData {
+        let mut data = MeshData::default();
+        for z in 0..=cells {
+            for x in 0..=cells {
+                let (u, v) = (x as f32 / cells as f32, z as f32 / cells as f32);
+                data.vertices.push(MeshVertex {
+                    position: [u * 2.0 - 1.0, 0.0, v * 2.0 - 1.0],
+                    normal: [0.0, 1.0, 0.0],
+                    tangent: [1.0, 0.0, 0.0, 1.0],
+                    uv: [u, v],
+                });
+            }
+        }
+        let stride = cells + 1;
+        for z in 0..cells {
+            for x in 0..cells {
+                let corner = z * stride + x;
+                data.indices.extend_from_slice(&[corner, corner + stride, corner + 1, corner + 1, corner + stride, corner + stride + 1]);
+            }
+        }
+        data
+    }
+
+    /// A UV sphere whose poles are single points. `MeshData::sphere` leaves them a rounding error apart, which the
+    /// simplifier takes for tiny triangles.
+    fn sphere() -> MeshData {
+        let mut data = MeshData::sphere(1.0, 32, 16);
+        for vertex in data.vertices.iter_mut().filter(|vertex| vertex.uv[1] == 0.0 || vertex.uv[1] == 1.0) {
+            vertex.position = [0.0, vertex.position[1].signum(), 0.0];
+        }
+        data
+    }
+
+    fn triangles(data: &MeshData) -> impl Iterator<Item = [MeshVertex; 3]> + '_ {
+        data.indices.chunks_exact(3).map(move |triangle| [0, 1, 2].map(|corner| data.vertices[triangle[corner] as usize]))
+    }
+
+    /// The unnormalized normal of a triangle, twice as long as its area.
+    fn normal(triangle: &[MeshVertex; 3]) -> Vector3<f32> {
+        let [a, b, c] = triangle.map(|vertex| Vector3::from(vertex.position));
+        (b - a).cross(c - a)
+    }
+
+    #[test]
+    fn reaches_target() {
+        let simplified = simplify(&grid(8), 16);
+        let count = simplified.indices.len() / 3;
+        // A collapse removes one or two triangles, so it may undershoot by one.
+        assert!(count == 16 || count == 15, "{} triangles left", count);
+    }
+
+    #[test]
+    fn no_flipped_triangles() {
+        let sphere = sphere();
+        let simplified = simplify(&sphere, 100);
+        assert!(simplified.indices.len() / 3 < sphere.indices.len() / 3);
+        for triangle in triangles(&simplified) {
+            let centroid = triangle.iter().map(|vertex| Vector3::from(vertex.position)).fold(Vector3::new(0.0, 0.0, 0.0), |sum, position| sum + position);
+            // Triangles left along a meridian stand edge on, so only ones clearly facing inwards count.
+            assert!(normal(&triangle).normalize().dot(centroid.normalize()) > -1e-4, "{:?} faces inwards", triangle);
+        }
+    }
+
+    #[test]
+    fn seams_keep_attributes() {
+        let sphere = sphere();
+        let simplified = simplify(&sphere, 100);
+        for triangle in triangles(&simplified) {
+            for vertex in triangle.iter() {
+                assert!(sphere.vertices.iter().any(|original| original.position == vertex.position && original.normal == vertex.normal && original.uv == vertex.uv),
+                        "{:?} isn't a vertex of the sphere",
+                        vertex);
+            }
+            // A vertex torn from its side of the seam would stretch the triangle over the whole texture. The poles have
+            // a vertex for every u, so they don't tell.
+            let us: Vec<f32> = triangle.iter().filter(|vertex| vertex.uv[1] > 0.0 && vertex.uv[1] < 1.0).map(|vertex| vertex.uv[0]).collect();
+            let width = us.iter().cloned().fold(f32::NEG_INFINITY, f32::max) - us.iter().cloned().fold(f32::INFINITY, f32::min);
+            assert!(width <= 0.5, "{:?} crosses the seam", triangle);
+        }
+    }
+
+    #[test]
+    fn border_stays() {
+        let simplified = simplify(&grid(8), 8);
+        for corner in [[-1.0, 0.0, -1.0], [1.0, 0.0, -1.0], [-1.0, 0.0, 1.0], [1.0, 0.0, 1.0]].iter() {
+            assert!(simplified.vertices.iter().any(|vertex| vertex.position == *corner), "corner {:?} moved", corner);
+        }
+        for vertex in simplified.vertices.iter() {
+            assert_eq!(vertex.position[1], 0.0);
+        }
+        // Still facing up and covering the whole square.
+        let area: f32 = triangles(&simplified).map(|triangle| {
+                                                   let normal = normal(&triangle);
+                                                   assert!(normal.y > 0.0, "{:?} faces down", triangle);
+                                                   normal.y / 2.0
+                                               })
+                                               .sum();
+        assert!((area - 4.0).abs() < 1e-4, "area is {}", area);
+    }
+}
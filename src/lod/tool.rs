@@ -0,0 +1,87 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, SquareMatrix, Transform, Vector3};
+use tracing::info;
+
+use crate::lod::{file, simplify_levels, LodError, LodGroupDesc, LodLevelDesc};
+use crate::logging;
+use crate::scene::{MeshData, MeshVertex};
+
+/// Fraction of the source's triangles and screen size of every level the tool writes.
+const LEVELS: [(f32, f32); 4] = [(1.0, 0.5), (0.5, 0.25), (0.2, 0.1), (0.05, 0.02)];
+
+/// Simplifies the meshes of the glTF file at `input` into a LOD group, written to `output` or next to the source as
+/// a `.mesh` file per level and a `.lod.json` file `LodGroup::load` reads.
+pub fn run(input: &Path, output: Option<&Path>) -> Result<(), LodError> {
+    let directory = output.map_or_else(|| input.parent().unwrap_or_else(|| Path::new("")).to_path_buf(), Path::to_path_buf);
+    let name = input.file_stem().map_or_else(|| "mesh".to_string(), |stem| stem.to_string_lossy().into_owned());
+    let source = import(input)?;
+    if source.indices.len() < 3 {
+        return Err(LodError::NoTriangles);
+    }
+    fs::create_dir_all(&directory)?;
+
+    let ratios: Vec<f32> = LEVELS.iter().map(|&(ratio, _)| ratio).collect();
+    let mut desc = LodGroupDesc::default();
+    for (level, (data, &(_, screen_size))) in simplify_levels(&source, &ratios).iter().zip(LEVELS.iter()).enumerate() {
+        let mesh = PathBuf::from(format!("{}_lod{}.mesh", name, level));
+        file::write(&directory.join(&mesh), data)?;
+        info!(target: logging::ASSETS, path = %directory.join(&mesh).display(), triangles = data.indices.len() / 3, "wrote lod level");
+        desc.levels.push(LodLevelDesc { mesh, screen_size });
+    }
+
+    let path = directory.join(format!("{}.lod.json", name));
+    fs::write(&path, serde_json::to_string_pretty(&desc)?)?;
+    info!(target: logging::ASSETS, path = %path.display(), "wrote lod group");
+    Ok(())
+}
+
+/// Every primitive in the default scene, or the first one, merged in world space. Materials are dropped.
+fn import(path: &Path) -> Result<MeshData, LodError> {
+    let (document, buffers, _) = gltf::import(path)?;
+    let mut data = MeshData::default();
+    if let Some(scene) = document.default_scene().or_else(|| document.scenes().next()) {
+        for node in scene.nodes() {
+            add_node(&mut data, &buffers, &node, Matrix4::identity());
+        }
+    }
+    Ok(data)
+}
+
+fn add_node(data: &mut MeshData, buffers: &[gltf::buffer::Data], node: &gltf::Node, parent: Matrix4<f32>) {
+    let transform = parent * Matrix4::from(node.transform().matrix());
+    let normal_matrix = Matrix3::from_cols(transform.x.truncate(), transform.y.truncate(), transform.z.truncate()).invert().map_or_else(Matrix3::identity, |matrix| matrix.transpose());
+
+    for primitive in node.mesh().iter().flat_map(|mesh| mesh.primitives()) {
+        let reader = primitive.reader(|buffer| Some(&*buffers[buffer.index()]));
+        let positions: Vec<[f32; 3]> = match reader.read_positions() {
+            Some(positions) => positions.collect(),
+            None => continue,
+        };
+        let mut normals = reader.read_normals().map(|normals| normals.collect::<Vec<_>>()).unwrap_or_default();
+        let mut tangents = reader.read_tangents().map(|tangents| tangents.collect::<Vec<_>>()).unwrap_or_default();
+        let mut uvs = reader.read_tex_coords(0).map(|uvs| uvs.into_f32().collect::<Vec<_>>()).unwrap_or_default();
+        normals.resize(positions.len(), [0.0, 1.0, 0.0]);
+        tangents.resize(positions.len(), [1.0, 0.0, 0.0, 1.0]);
+        uvs.resize(positions.len(), [0.0, 0.0]);
+
+        let first = data.vertices.len() as u32;
+        for (index, &position) in positions.iter().enumerate() {
+            let [x, y, z, w] = tangents[index];
+            let tangent = transform.transform_vector(Vector3::new(x, y, z)).normalize();
+            data.vertices.push(MeshVertex {
+                position: transform.transform_point(position.into()).into(),
+                normal: (normal_matrix * Vector3::from(normals[index])).normalize().into(),
+                tangent: [tangent.x, tangent.y, tangent.z, w],
+                uv: uvs[index],
+            });
+        }
+        let indices = reader.read_indices().map_or_else(|| (0..positions.len() as u32).collect::<Vec<_>>(), |indices| indices.into_u32().collect());
+        data.indices.extend(indices.into_iter().map(|index| first + index));
+    }
+
+    for child in node.children() {
+        add_node(data, buffers, &child, transform);
+    }
+}
@@ -0,0 +1,58 @@
+use std::env;
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, EnvFilter, Registry};
+
+// Targets the engine logs under, so `TONIC_LOG=swapchain=debug,render=trace` can pick subsystems apart.
+pub const DEVICE: &str = "device";
+pub const SWAPCHAIN: &str = "swapchain";
+pub const RENDER: &str = "render";
+pub const ASSETS: &str = "assets";
+pub const MEMORY: &str = "memory";
+pub const VULKAN: &str = "vulkan";
+
+/// Where log output goes.
+///
+/// `TONIC_LOG` is an `EnvFilter` directive string (default `info`). Frame phase spans are logged at `debug`
+/// on the `render` target with their busy/idle time when they close. `TONIC_LOG_FILE` additionally writes
+/// every event as one JSON object per line to that path, for attaching to bug reports.
+#[derive(Debug, Clone)]
+pub struct LogSettings {
+    pub filter: String,
+    pub json_file: Option<PathBuf>,
+}
+
+impl LogSettings {
+    pub fn from_env() -> LogSettings {
+        LogSettings {
+            filter: env::var("TONIC_LOG").unwrap_or_else(|_| "info".to_string()),
+            json_file: env::var_os("TONIC_LOG_FILE").map(PathBuf::from),
+        }
+    }
+}
+
+/// Installs the global subscriber. `log` records from dependencies (winit, vulkano) are forwarded into it.
+pub fn init(settings: &LogSettings) {
+    let filter = EnvFilter::try_new(&settings.filter).unwrap_or_else(|e| {
+        eprintln!("invalid TONIC_LOG filter {:?}: {}, falling back to info", settings.filter, e);
+        EnvFilter::new("info")
+    });
+
+    // stderr, so subcommands that print JSON to stdout stay machine-readable.
+    let console = fmt::layer().with_span_events(FmtSpan::CLOSE).with_writer(io::stderr);
+
+    let json = settings.json_file.as_ref().and_then(|path| match File::create(path) {
+        Ok(file) => Some(fmt::layer().json().with_span_events(FmtSpan::CLOSE).with_writer(Mutex::new(file))),
+        Err(e) => {
+            eprintln!("failed to create log file {}: {}", path.display(), e);
+            None
+        }
+    });
+
+    Registry::default().with(filter).with(console).with(json).init();
+}
@@ -1,34 +1,128 @@
+use std::env;
+use std::f32::consts::{FRAC_PI_2, PI};
 use std::ops::Deref;
+use std::path::Path;
+use std::process;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
-use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, DynamicState, SubpassContents};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage};
 use vulkano::descriptor::pipeline_layout::PipelineLayoutDesc;
 use vulkano::device::Device;
 use vulkano::device::DeviceExtensions;
 use vulkano::device::Features;
+use vulkano::format::{ClearValue, Format};
 use vulkano::image::{ImageUsage, SwapchainImage};
-use vulkano::image::view::ImageView;
+use vulkano::image::view::{ImageView, ImageViewAbstract};
 use vulkano::instance::Instance;
 use vulkano::instance::InstanceExtensions;
 use vulkano::instance::PhysicalDevice;
-use vulkano::pipeline::GraphicsPipeline;
-use vulkano::pipeline::viewport::Viewport;
-use vulkano::render_pass::{Framebuffer, FramebufferAbstract, RenderPass, Subpass};
+use vulkano::sampler::Filter;
 use vulkano::swapchain::{AcquireError, ColorSpace, FullscreenExclusive, PresentMode, SurfaceTransform, Swapchain, SwapchainCreationError};
 use vulkano::sync;
 use vulkano::sync::{FlushError, GpuFuture};
+use cgmath::{Deg, EuclideanSpace, Matrix4, Point3, Quaternion, Rotation3, Vector3, Zero};
+use image::{Rgba, RgbaImage};
+use tracing::{debug, debug_span, error, info, warn};
 use vulkano_win::VkSurfaceBuild;
-use winit::event::{Event, WindowEvent};
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::{Window, WindowBuilder};
 
+use crate::allocator::GpuAllocator;
+use crate::animation::{AnimationClip, AnimationStateMachine, Channel, Condition, Interpolation, Joint, Keyframes, Motion, Skeleton, SkinnedMesh, SkinnedMeshData, SkinnedObject, SkinnedPart, SkinnedVertex, Transform};
+use crate::compute::Compute;
+use crate::culling::{Bvh, Culling, CullingSettings};
+use crate::debug::{Debug, DebugSettings, ObjectNamer};
+use crate::debug_draw::{DebugDraw, DebugDrawPass, DebugOptions};
+use crate::environment::{Environment, EnvironmentMap, EnvironmentSettings, SkyPass};
+use crate::gui::{Gui, GuiTexture};
+use crate::lod::{Lod, LodGroup};
+use crate::logging::LogSettings;
+use crate::particles::{Emitter, ParticleEffect};
+use crate::queues::QueueFamilies;
+use crate::pbr::{ForwardPass, ForwardTarget};
+use crate::post::{PostSettings, Tonemapper};
+use crate::render_graph::{ImageDesc, ImageSize, Load, RenderGraphBuilder};
+use crate::scene::{AlphaMode, BlendMode, Camera, Light, LightKind, Material, Mesh, MeshData, Object, RenderPath, Scene};
+use crate::shadow::ShadowSettings;
+use crate::sprite::{AnimatedSprite, AtlasBuilder, AtlasSettings, Camera2d, Sprite, SpritePass, SpriteSheet};
+use crate::text::{Align, Font, Text, TextLayout};
+use crate::transparency::TransparencyMode;
+use crate::ui::{Edges, NodeId, StyleProps, StyleSheet, Ui, UiEvent, UiImage, Widget};
+use crate::upload::UploadManager;
+
+mod allocator;
+mod animation;
+mod compute;
+mod culling;
+mod debug;
+mod debug_draw;
+mod deferred;
+mod device_report;
+mod environment;
+mod gui;
+mod lod;
+mod logging;
+mod particles;
+mod pbr;
+mod post;
+mod queues;
+mod render_graph;
+mod scene;
+mod shadow;
+mod sprite;
+mod text;
+mod transparency;
+mod ui;
+mod upload;
+
 fn main() {
+    logging::init(&LogSettings::from_env());
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    // The LOD tool works offline, without a device.
+    if args.first().map(String::as_str) == Some("lod") {
+        let input = match args.get(1) {
+            Some(input) => Path::new(input),
+            None => {
+                error!(target: logging::ASSETS, "usage: lod <model.gltf> [output directory]");
+                process::exit(1);
+            }
+        };
+        if let Err(e) = lod::tool::run(input, args.get(2).map(Path::new)) {
+            error!(target: logging::ASSETS, "failed to generate lod group from {}: {}", input.display(), e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let mut debug = Debug::new(DebugSettings::from_env());
+
     let instance = {
-        let extensions = vulkano_win::required_extensions();
-        Instance::new(None, &extensions, None).expect("failed to create vulkan instance")
+        let extensions = allocator::instance_extensions(&debug.extensions(&vulkano_win::required_extensions()));
+        Instance::new(None, &extensions, debug.layers.iter().cloned()).expect("failed to create vulkan instance")
     };
 
+    debug.install(&instance);
+
+    if args.first().map(String::as_str) == Some("report") {
+        if let Err(e) = device_report::run(&instance, &args[1..]) {
+            error!(target: logging::DEVICE, "failed to write device report: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("mandelbrot") {
+        let compute = Compute::headless(&instance, &debug);
+        let path = args.get(1).map(String::as_str).unwrap_or("mandelbrot.png");
+        if let Err(e) = compute::mandelbrot::run(&compute, Path::new(path)) {
+            error!(target: logging::RENDER, "failed to render mandelbrot: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
     print_devices_info(&instance);
 
     let physical = PhysicalDevice::enumerate(&instance).next().expect("no device available");
@@ -38,23 +132,31 @@ fn main() {
         .build_vk_surface(&events_loop, instance.clone())
         .unwrap();
 
-    let queue_family = physical.queue_families()
-                               .find(|&q| {
-                                   q.supports_graphics() && surface.is_supported(q).unwrap_or(false)
-                               })
-                               .expect("couldn't find a graphical queue family");
+    let queue_families = QueueFamilies::select(physical, &surface);
 
     let device_ext = vulkano::device::DeviceExtensions {
         khr_swapchain: true,
         ..vulkano::device::DeviceExtensions::none()
     };
-
-    let (device, mut queues) = {
-        Device::new(physical, &Features::none(), &device_ext, [(queue_family, 0.5)].iter().cloned())
-            .expect("failed device creation")
+    let memory_budget = allocator::supports_memory_budget(physical);
+
+    let (device, queues) = {
+        // Weighted blended transparency needs it to blend its two targets differently. Without it transparency is sorted.
+        let features = Features { independent_blend: physical.supported_features().independent_blend, ..Features::none() };
+        let (device, queues) = Device::new(physical, &features, allocator::device_extensions(&device_ext, memory_budget), queue_families.requests())
+            .expect("failed device creation");
+        (device, queue_families.queues(queues))
     };
+    info!(target: logging::DEVICE,
+          async_compute = queues.has_async_compute(),
+          dedicated_transfer = queues.has_dedicated_transfer(),
+          "created queues");
 
-    let queue = queues.next().unwrap();
+    let queue = queues.graphics.clone();
+
+    let namer = ObjectNamer::new(device.clone(), &debug);
+
+    let gpu_allocator = GpuAllocator::new(device.clone(), memory_budget);
 
     // params missing from guide: 1 + true
     let (mut swapchain, image_views) = {
@@ -62,7 +164,12 @@ fn main() {
 
         let dimensions = capabilities.current_extent.unwrap_or([1280, 1024]);
         let alpha = capabilities.supported_composite_alpha.iter().next().unwrap();
-        let format = capabilities.supported_formats[0].0;
+        // Tone mapping writes linear color, so let the swapchain do the sRGB encoding when it can.
+        let format = capabilities.supported_formats
+                                 .iter()
+                                 .map(|&(format, _)| format)
+                                 .find(|&format| format == Format::B8G8R8A8Srgb || format == Format::R8G8B8A8Srgb)
+                                 .unwrap_or(capabilities.supported_formats[0].0);
 
         let (swapchain, images) = Swapchain::start(device.clone(), surface.clone())
             .num_images(capabilities.min_image_count)
@@ -77,192 +184,183 @@ fn main() {
             .color_space(ColorSpace::SrgbNonLinear)
             .build()
             .unwrap();
-        let images: Vec<_> = images.into_iter().map(|img| ImageView::new(img).unwrap()).collect();
-        (swapchain, images)
-    };
-
-    let vertex_buffer = {
-        #[derive(Default, Debug, Clone)]
-        struct Vertex {
-            position: [f32; 2],
+        for (i, image) in images.iter().enumerate() {
+            namer.name_image(image, &format!("swapchain image {}", i));
         }
-        vulkano::impl_vertex!(Vertex, position);
-
-        CpuAccessibleBuffer::from_iter(
-            device.clone(),
-            BufferUsage::all(),
-            false,
-            [
-                Vertex {
-                    position: [-0.5, -0.25],
-                },
-                Vertex {
-                    position: [0.0, 0.5],
-                },
-                Vertex {
-                    position: [0.25, -0.1],
-                },
-            ]
-                .iter()
-                .cloned(),
-        )
-            .unwrap()
+        (swapchain, backbuffer_views(images))
     };
 
-    mod vs {
-        vulkano_shaders::shader! {
-            ty: "vertex",
-            src: "
-				#version 450
-				layout(location = 0) in vec2 position;
-				void main() {
-					gl_Position = vec4(position, 0.0, 1.0);
-				}
-			"
-        }
-    }
-
-    mod fs {
-        vulkano_shaders::shader! {
-            ty: "fragment",
-            src: "
-				#version 450
-				layout(location = 0) out vec4 f_color;
-				void main() {
-					f_color = vec4(1.0, 0.0, 0.0, 1.0);
-				}
-			"
-        }
-    }
-
-    let vs = vs::Shader::load(device.clone()).unwrap();
-    let fs = fs::Shader::load(device.clone()).unwrap();
-
-    let render_pass = Arc::new(
-        vulkano::single_pass_renderpass!(
-            device.clone(),
-            attachments: {
-                // `color` is a custom name we give to the first and only attachment.
-                color: {
-                    // `load: Clear` means that we ask the GPU to clear the content of this
-                    // attachment at the start of the drawing.
-                    load: Clear,
-                    // `store: Store` means that we ask the GPU to store the output of the draw
-                    // in the actual image. We could also ask it to discard the result.
-                    store: Store,
-                    // `format: <ty>` indicates the type of the format of the image. This has to
-                    // be one of the types of the `vulkano::format` module (or alternatively one
-                    // of your structs that implements the `FormatDesc` trait). Here we use the
-                    // same format as the swapchain.
-                    format: swapchain.format(),
-                    // TODO:
-                    samples: 1,
-                }
-            },
-            pass: {
-                // We use the attachment named `color` as the one and only color attachment.
-                color: [color],
-                // No depth-stencil attachment is indicated with empty brackets.
-                depth_stencil: {}
-            }
-        )
-            .unwrap(),
-    );
-
-    let pipeline = Arc::new(
-        GraphicsPipeline::start()
-            // We need to indicate the layout of the vertices.
-            // The type `SingleBufferDefinition` actually contains a template parameter corresponding
-            // to the type of each vertex. But in this code it is automatically inferred.
-            .vertex_input_single_buffer()
-            // A Vulkan shader can in theory contain multiple entry points, so we have to specify
-            // which one. The `main` word of `main_entry_point` actually corresponds to the name of
-            // the entry point.
-            .vertex_shader(vs.main_entry_point(), ())
-            // The content of the vertex buffer describes a list of triangles.
-            .triangle_list()
-            // Use a resizable viewport set to draw over the entire window
-            .viewports_dynamic_scissors_irrelevant(1)
-            // See `vertex_shader`.
-            .fragment_shader(fs.main_entry_point(), ())
-            // We have to indicate which subpass of which render pass this pipeline is going to be used
-            // in. The pipeline will only be usable from this particular subpass.
-            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
-            // Now that our builder is filled, we call `build()` to obtain an actual pipeline.
-            .build(device.clone())
-            .unwrap(),
-    );
-
-    let mut dynamic_state = DynamicState {
-        line_width: None,
-        viewports: None,
-        scissors: None,
-        compare_mask: None,
-        write_mask: None,
-        reference: None,
+    let mut uploads = UploadManager::new(device.clone(), &gpu_allocator, &queues);
+
+    let mut scene = demo_scene(&mut uploads, &namer);
+
+    let mut gui = Gui::new(surface.window(), &namer);
+    let crate_texture = scene.objects[1].material.base_color_texture.clone().unwrap();
+    let crate_preview = gui.register_texture(GuiTexture::View(crate_texture));
+
+    let post_settings = PostSettings::from_env();
+    let (mut graph, post_settings, culling, transparency, hdr_preview) = {
+        let mut graph = RenderGraphBuilder::new(swapchain.format());
+        let backbuffer = graph.backbuffer();
+        let hdr = graph.image("hdr color", ImageDesc::new(Format::R16G16B16A16Sfloat, ImageSize::Backbuffer));
+        let depth = graph.image("depth", ImageDesc::new(Format::D32Sfloat, ImageSize::Backbuffer));
+        let hdr_preview = gui.register_texture(GuiTexture::Graph(hdr));
+        let culling = culling::add_passes(&mut graph, CullingSettings::from_env(), &device, &gpu_allocator, &namer);
+        let shadows = shadow::add_passes(&mut graph, ShadowSettings::default(), &culling, &namer);
+        let environment_maps = environment::add_passes(&mut graph, EnvironmentSettings::default(), &device, &gpu_allocator, &namer);
+        deferred::add_passes(&mut graph, hdr, depth, shadows, environment_maps.clone(), culling.clone(), &device, &mut uploads, &namer);
+        // Instead of the deferred passes, on the forward path.
+        graph.add_pass("forward",
+                       ForwardPass::new(device.clone(),
+                                        ForwardTarget::Shaded { color: hdr, load: Load::Clear([0.0, 0.0, 0.0, 1.0].into()) },
+                                        depth,
+                                        Load::Clear(ClearValue::Depth(1.0)),
+                                        shadows,
+                                        environment_maps.clone(),
+                                        culling.clone(),
+                                        &mut uploads,
+                                        &namer));
+        graph.add_pass("sky", SkyPass::new(hdr, depth, environment_maps.clone(), &namer));
+        let transparency = transparency::add_passes(&mut graph, hdr, depth, TransparencyMode::from_env(), shadows, environment_maps, culling.clone(), &device, &mut uploads, &namer);
+        particles::add_passes(&mut graph, hdr, depth, &device, &mut uploads, &namer);
+        let post_settings = post::add_passes(&mut graph, hdr, backbuffer, swapchain.format(), post_settings, &device, &gpu_allocator, &mut uploads, &namer);
+        // Untouched by post-processing, against the depth the scene left.
+        graph.add_pass("debug draw", DebugDrawPass::new(device.clone(), backbuffer, depth, &namer));
+        // Over the post-processed image, so pixel art stays crisp.
+        graph.add_pass("sprites", SpritePass::new(device.clone(), backbuffer, Load::Keep, Filter::Nearest, &mut uploads, &namer));
+        let glyphs = text::add_passes(&mut graph, backbuffer, &device, &gpu_allocator, &mut uploads, &namer);
+        ui::add_passes(&mut graph, backbuffer, &glyphs, &device, &mut uploads, &namer);
+        gui::add_pass(&mut graph, backbuffer, &gui, &device, &namer);
+        (graph.build(device.clone(), &gpu_allocator, &namer, image_views), post_settings, culling, transparency, hdr_preview)
     };
 
-    let mut framebuffers =
-        window_size_dependent_setup(&image_views, render_pass.clone(), &mut dynamic_state);
+    let mut menu = scene.debug.font.clone().map(|font| PauseMenu::new(font, &mut uploads, &namer));
 
     let mut recreate_swapchain = false;
+    let mut show_debug = false;
+    let mut last_frame = Instant::now();
 
-    let mut previous_frame_end = Some(sync::now(device.clone()).boxed());
+    let mut previous_frame_end = Some(uploads.flush().unwrap_or_else(|| sync::now(device.clone()).boxed()));
 
     events_loop.run(move |event, _, control_flow| {
         match event {
+            // What the GUI uses doesn't reach the game.
+            Event::WindowEvent { ref event, .. } if gui.handle_event(event) || menu.as_mut().map(|menu| menu.ui.handle_event(event)) == Some(true) => {}
             Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => {
+                debug!(target: logging::MEMORY, stats = ?gpu_allocator.stats(), "gpu memory at exit");
                 *control_flow = ControlFlow::Exit;
             }
             Event::WindowEvent { event: WindowEvent::Resized(_), .. } => {
                 recreate_swapchain = true;
             }
+            Event::WindowEvent { event: WindowEvent::KeyboardInput { input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(key), .. }, .. }, .. } => {
+                if key == VirtualKeyCode::Escape {
+                    if let Some(menu) = &mut menu {
+                        menu.show(true);
+                    }
+                } else if key == VirtualKeyCode::F7 {
+                    show_debug = !show_debug;
+                    if !show_debug {
+                        scene.debug.clear();
+                    }
+                    info!(target: logging::RENDER, show_debug, "debug drawing toggled");
+                } else if key == VirtualKeyCode::F8 {
+                    for object in scene.skinned.iter_mut() {
+                        object.animator.trigger("next");
+                        let position = Point3::from_vec(object.transform.w.truncate()) + Vector3::unit_y() * 2.0;
+                        scene.debug.label(position, "next", DebugOptions::color([1.0, 1.0, 1.0, 1.0]).lasting(Duration::from_secs(1)).on_top());
+                    }
+                } else if key == VirtualKeyCode::F10 {
+                    for emitter in scene.emitters.iter_mut() {
+                        emitter.restart();
+                    }
+                } else {
+                    post_hotkey(&mut post_settings.lock().unwrap(), key);
+                }
+            }
             Event::RedrawEventsCleared => {
                 previous_frame_end.as_mut().unwrap().cleanup_finished();
 
                 // Whenever the window resizes we need to recreate everything dependent on the window size.
-                // In this example that includes the swapchain, the framebuffers and the dynamic state viewport.
+                // That is the swapchain, and through it the render graph's framebuffers and images.
                 if recreate_swapchain {
+                    let _span = debug_span!(target: logging::SWAPCHAIN, "recreate").entered();
                     // Get the new dimensions of the window.
                     let dimensions: [u32; 2] = surface.window().inner_size().into();
+                    debug!(target: logging::SWAPCHAIN, ?dimensions, "recreating swapchain");
                     let (new_swapchain, new_image_views) =
                         match swapchain.recreate().dimensions(dimensions).build() {
-                            Ok((new_swapchain, new_images)) => {
-                                let new_image_views: Vec<_> = new_images.into_iter().map(|img| ImageView::new(img).unwrap()).collect();
-                                (new_swapchain, new_image_views)
-                            }
+                            Ok((new_swapchain, new_images)) => (new_swapchain, backbuffer_views(new_images)),
                             // This error tends to happen when the user is manually resizing the window.
                             // Simply restarting the loop is the easiest way to fix this issue.
                             Err(SwapchainCreationError::UnsupportedDimensions) => return,
-                            Err(e) => panic!("Failed to recreate swapchain: {:?}", e),
+                            Err(e) => {
+                                error!(target: logging::SWAPCHAIN, "failed to recreate swapchain: {:?}", e);
+                                panic!("Failed to recreate swapchain: {:?}", e)
+                            }
                         };
 
                     swapchain = new_swapchain;
-                    // Because framebuffers contains an Arc on the old swapchain, we need to
-                    // recreate framebuffers as well.
-                    framebuffers = window_size_dependent_setup(
-                        &new_image_views,
-                        render_pass.clone(),
-                        &mut dynamic_state,
-                    );
+                    // The graph's framebuffers hold the old swapchain images, and its own images may have to
+                    // follow the new size.
+                    graph.set_backbuffer(new_image_views);
                     recreate_swapchain = false;
                 }
 
+                let _frame = debug_span!(target: logging::RENDER, "frame").entered();
+
+                let now = Instant::now();
+                let frame_time = now - last_frame;
+                scene.debug.update(frame_time);
+                for emitter in scene.emitters.iter_mut() {
+                    emitter.update(frame_time);
+                }
+                for object in scene.skinned.iter_mut() {
+                    object.update(frame_time);
+                }
+                for sprite in scene.animated_sprites.iter_mut() {
+                    sprite.update(frame_time);
+                }
+                lod::update(&mut scene.objects, &scene.camera, frame_time);
+                scene.bvh.update(&scene.objects);
+                last_frame = now;
+
+                let previews = [("Crate texture", crate_preview), ("HDR color", hdr_preview)];
+                gui.run(surface.window(), &mut uploads, |ctx| debug_panel(ctx, &mut post_settings.lock().unwrap(), &culling, &mut transparency.lock().unwrap(), device.enabled_features().independent_blend, &mut scene.camera.path, &mut scene.environment, &mut show_debug, frame_time, &previews));
+                if show_debug {
+                    debug_draw_lights(&mut scene.debug, &scene.lights);
+                    for object in scene.skinned.iter() {
+                        debug_draw_skeleton(&mut scene.debug, object);
+                    }
+                    for object in 0..scene.objects.len() {
+                        let bounds = scene.bvh.bounds(object);
+                        scene.debug.aabb(bounds.min, bounds.max, DebugOptions::color([0.3, 1.0, 0.3, 0.5]));
+                    }
+                }
+                if let Some(menu) = &mut menu {
+                    menu.update(&mut post_settings.lock().unwrap());
+                    menu.ui.render(swapchain.dimensions(), &mut scene);
+                }
+
+                let acquire = debug_span!(target: logging::RENDER, "acquire").entered();
                 let (image_num, suboptimal, acquire_future) =
                     match vulkano::swapchain::acquire_next_image(swapchain.clone(), None) {
                         Ok(r) => r,
                         Err(AcquireError::OutOfDate) => {
+                            debug!(target: logging::SWAPCHAIN, "swapchain out of date on acquire");
                             recreate_swapchain = true;
                             return;
                         }
                         Err(e) => panic!("Failed to acquire next image: {:?}", e),
                     };
+                acquire.exit();
 
                 if suboptimal {
+                    debug!(target: logging::SWAPCHAIN, "swapchain suboptimal");
                     recreate_swapchain = true;
                 }
 
-                let clear_values = vec![[0.0, 0.0, 1.0, 1.0].into()];
+                let record = debug_span!(target: logging::RENDER, "record").entered();
 
                 let mut builder = vulkano::command_buffer::AutoCommandBufferBuilder::primary(
                     device.clone(),
@@ -270,20 +368,18 @@ fn main() {
                     CommandBufferUsage::OneTimeSubmit
                 ).unwrap();
 
-
-                let x = builder
-                    .begin_render_pass(framebuffers[image_num].clone(), SubpassContents::Inline, clear_values)
-                    .unwrap()
-                    .draw(pipeline.clone(), &dynamic_state, vertex_buffer.clone(), (), (), vec![])
-                    .unwrap()
-                    .end_render_pass()
-                    .unwrap();
+                graph.execute(&mut builder, image_num, &scene);
 
                 let command_buffer = builder.build().unwrap();
+                record.exit();
 
-                let future = previous_frame_end
-                    .take()
-                    .unwrap()
+                let _submit = debug_span!(target: logging::RENDER, "submit").entered();
+                let mut wait = previous_frame_end.take().unwrap();
+                if let Some(upload_future) = uploads.flush() {
+                    wait = wait.join(upload_future).boxed();
+                }
+
+                let future = wait
                     .join(acquire_future)
                     .then_execute(queue.clone(), command_buffer)
                     .unwrap()
@@ -295,11 +391,12 @@ fn main() {
                         previous_frame_end = Some(future.boxed());
                     }
                     Err(FlushError::OutOfDate) => {
+                        debug!(target: logging::SWAPCHAIN, "swapchain out of date on present");
                         recreate_swapchain = true;
                         previous_frame_end = Some(sync::now(device.clone()).boxed())
                     }
                     Err(e) => {
-                        println!("Failed to flush future: {:?}", e);
+                        warn!(target: logging::RENDER, "failed to flush future: {:?}", e);
                         previous_frame_end = Some(sync::now(device.clone()).boxed());
                     }
                 }
@@ -309,51 +406,551 @@ fn main() {
     });
 }
 
-fn window_size_dependent_setup(
-    image_views: &Vec<Arc<ImageView<Arc<SwapchainImage<Window>>>>>,
-    render_pass: Arc<RenderPass>,
-    dynamic_state: &mut DynamicState,
-) -> Vec<Arc<dyn FramebufferAbstract + Send + Sync>> {
-    let dimensions = image_views[0].image().dimensions();
-
-    let viewport = Viewport {
-        origin: [0.0, 0.0],
-        dimensions: [dimensions[0] as f32, dimensions[1] as f32],
-        depth_range: 0.0..1.0,
+fn backbuffer_views(images: Vec<Arc<SwapchainImage<Window>>>) -> Vec<Arc<dyn ImageViewAbstract + Send + Sync>> {
+    images.into_iter().map(|image| ImageView::new(image).unwrap() as Arc<dyn ImageViewAbstract + Send + Sync>).collect()
+}
+
+/// F1 to F5 toggle bloom, FXAA, chromatic aberration, the vignette and color grading, F6 cycles through the tone
+/// mappers and F9 reloads the post-processing settings file.
+fn post_hotkey(settings: &mut PostSettings, key: VirtualKeyCode) {
+    match key {
+        VirtualKeyCode::F1 => settings.bloom.enabled = !settings.bloom.enabled,
+        VirtualKeyCode::F2 => settings.fxaa.enabled = !settings.fxaa.enabled,
+        VirtualKeyCode::F3 => settings.chromatic_aberration.enabled = !settings.chromatic_aberration.enabled,
+        VirtualKeyCode::F4 => settings.vignette.enabled = !settings.vignette.enabled,
+        VirtualKeyCode::F5 => settings.color_grading.enabled = !settings.color_grading.enabled,
+        VirtualKeyCode::F6 => settings.tonemap.tonemapper = settings.tonemap.tonemapper.next(),
+        VirtualKeyCode::F9 => settings.reload(),
+        _ => return,
+    }
+    info!(target: logging::RENDER,
+          bloom = settings.bloom.enabled,
+          fxaa = settings.fxaa.enabled,
+          chromatic_aberration = settings.chromatic_aberration.enabled,
+          vignette = settings.vignette.enabled,
+          color_grading = settings.color_grading.enabled,
+          tonemapper = ?settings.tonemap.tonemapper,
+          "post-processing settings changed");
+}
+
+/// A window with the frame time, toggles for post-processing and debug drawing, the environment's intensity and
+/// previews of engine images.
+#[allow(clippy::too_many_arguments)]
+fn debug_panel(ctx: &egui::CtxRef, settings: &mut PostSettings, culling: &Culling, transparency: &mut TransparencyMode, weighted_blended: bool, path: &mut RenderPath, environment: &mut Option<Environment>, show_debug: &mut bool, frame_time: Duration, previews: &[(&str, egui::TextureId)]) {
+    egui::Window::new("Debug").default_pos([16.0, 160.0]).show(ctx, |ui| {
+        ui.label(format!("{:.2} ms", frame_time.as_secs_f64() * 1000.0));
+        ui.checkbox(show_debug, "Debug drawing");
+        if ui.button(format!("Render path: {:?}", *path)).clicked() {
+            *path = path.next();
+        }
+        // Stays sorted without weighted blended transparency.
+        if ui.button(format!("Transparency: {:?}", *transparency)).clicked() && weighted_blended {
+            *transparency = transparency.next();
+        }
+        if let Some(environment) = environment {
+            ui.add(egui::Slider::new(&mut environment.intensity, 0.0..=4.0).text("Environment intensity"));
+        }
+        ui.collapsing("Post-processing", |ui| {
+            ui.checkbox(&mut settings.bloom.enabled, "Bloom");
+            ui.checkbox(&mut settings.fxaa.enabled, "FXAA");
+            ui.checkbox(&mut settings.chromatic_aberration.enabled, "Chromatic aberration");
+            ui.checkbox(&mut settings.vignette.enabled, "Vignette");
+            ui.checkbox(&mut settings.color_grading.enabled, "Color grading");
+            if ui.button(format!("Tone mapper: {:?}", settings.tonemap.tonemapper)).clicked() {
+                settings.tonemap.tonemapper = settings.tonemap.tonemapper.next();
+            }
+        });
+        ui.collapsing("Culling", |ui| {
+            let mut mode = culling.mode.lock().unwrap();
+            if ui.button(format!("Culling: {:?}", *mode)).clicked() {
+                *mode = mode.next();
+            }
+            for (view, stats) in culling.stats.lock().unwrap().views.iter() {
+                ui.label(format!("{}: {} drawn, {} culled", view, stats.drawn, stats.culled));
+            }
+        });
+        for &(name, texture) in previews {
+            ui.collapsing(name, |ui| ui.image(texture, [256.0, 144.0]));
+        }
+    });
+}
+
+/// A menu over the paused game, shown with Escape and left with Escape or Resume, tweaking post-processing.
+struct PauseMenu {
+    ui: Ui,
+    window: NodeId,
+    resume: NodeId,
+    bloom: NodeId,
+    intensity: NodeId,
+    tonemappers: NodeId,
+}
+
+impl PauseMenu {
+    /// Styled by the style sheet at `TONIC_UI_STYLE` on top of the default one, if there is one, with the title in the
+    /// font at `TONIC_UI_TITLE_FONT`.
+    fn new(font: Font, uploads: &mut UploadManager, namer: &ObjectNamer) -> PauseMenu {
+        // A dark window with a light border, nine-sliced so the border stays 4 pixels wide.
+        let frame: Vec<[u8; 4]> = (0..16 * 16).map(|i| if (4..12).contains(&(i % 16)) && (4..12).contains(&(i / 16)) { [10, 10, 14, 220] } else { [120, 130, 160, 255] }).collect();
+        let frame = Material::texture(uploads, namer, "ui frame texture", &frame, [16, 16], true);
+        let mut style = StyleSheet::default();
+        style.add_image("frame", UiImage::new(frame.clone(), [16.0, 16.0]).sliced(Edges::all(4.0)))
+             .rule("panel.window", StyleProps { background_image: Some("frame".to_string()), background_color: Some([1.0, 1.0, 1.0, 1.0]), ..StyleProps::default() })
+             .unwrap();
+        if let Some(path) = env::var_os("TONIC_UI_TITLE_FONT") {
+            let path = Path::new(&path);
+            match Font::load(path) {
+                Ok(font) => {
+                    style.add_font("title", font).rule("label.title", StyleProps { font: Some("title".to_string()), ..StyleProps::default() }).unwrap();
+                }
+                Err(e) => warn!(target: logging::ASSETS, "failed to load ui title font {}: {}", path.display(), e),
+            }
+        }
+        if let Some(path) = env::var_os("TONIC_UI_STYLE") {
+            let path = Path::new(&path);
+            match StyleSheet::load(path) {
+                Ok(sheet) => {
+                    style.extend(sheet);
+                }
+                Err(e) => warn!(target: logging::ASSETS, "failed to load ui style sheet {}: {}", path.display(), e),
+            }
+        }
+
+        let mut ui = Ui::new(style, font, uploads, namer);
+        let center = ui.add(ui.root(), Widget::Panel, &["center"]);
+        let window = ui.add(center, Widget::Panel, &["window"]);
+        ui.add(window, Widget::label("Paused"), &["title"]);
+        // A line of the frame's border color under the title.
+        ui.add(window, Widget::image(UiImage { region: [0.0, 0.0, 0.25, 0.25], ..UiImage::new(frame, [1.0, 2.0]) }), &[]);
+        let resume = ui.add(window, Widget::button("Resume"), &[]);
+        let bloom = ui.add(window, Widget::button(""), &[]);
+        ui.add(window, Widget::label("Bloom intensity"), &[]);
+        let intensity = ui.add(window, Widget::slider(0.0, 0.0, 0.5, 0.02), &[]);
+        ui.add(window, Widget::label("Tone mapper"), &[]);
+        let tonemappers = ui.add(window, Widget::list(3), &[]);
+        for name in ["ACES", "Reinhard", "AgX"] {
+            ui.add(tonemappers, Widget::label(name), &[]);
+        }
+
+        let mut menu = PauseMenu { ui, window, resume, bloom, intensity, tonemappers };
+        menu.show(false);
+        menu
+    }
+
+    fn show(&mut self, shown: bool) {
+        self.ui.set_hidden(self.window, !shown);
+        self.ui.focus(if shown { Some(self.resume) } else { None });
+    }
+
+    /// Applies what was done in the menu and shows the current settings.
+    fn update(&mut self, settings: &mut PostSettings) {
+        for event in self.ui.take_events() {
+            match event {
+                UiEvent::Clicked(id) if id == self.resume => self.show(false),
+                UiEvent::Clicked(id) if id == self.bloom => settings.bloom.enabled = !settings.bloom.enabled,
+                UiEvent::Changed(id, value) if id == self.intensity => settings.bloom.intensity = value,
+                UiEvent::Selected(id, row) if id == self.tonemappers => {
+                    settings.tonemap.tonemapper = [Tonemapper::Aces, Tonemapper::Reinhard, Tonemapper::Agx][row];
+                    if let Widget::Label { text } = self.ui.widget(self.ui.children(self.tonemappers)[row]) {
+                        info!(target: logging::RENDER, tonemapper = %text, "tone mapper picked in the menu");
+                    }
+                }
+                UiEvent::Back => self.show(false),
+                _ => {}
+            }
+        }
+
+        *self.ui.widget_mut(self.bloom) = Widget::button(if settings.bloom.enabled { "Bloom: on" } else { "Bloom: off" });
+        self.ui.set_disabled(self.intensity, !settings.bloom.enabled);
+        if let Widget::Slider { value, .. } = self.ui.widget_mut(self.intensity) {
+            *value = settings.bloom.intensity;
+        }
+        if let Widget::List { selected, .. } = self.ui.widget_mut(self.tonemappers) {
+            *selected = settings.tonemap.tonemapper as usize;
+        }
+    }
+}
+
+/// A grid on the ground and where every light is and points, with F7.
+fn debug_draw_lights(debug: &mut DebugDraw, lights: &[Light]) {
+    debug.grid(Point3::new(0.0, 0.0, 0.0), 6.0, 1.0, DebugOptions::color([0.5, 0.5, 0.5, 0.5]));
+    for light in lights {
+        let options = DebugOptions::color([light.color[0], light.color[1], light.color[2], 1.0]).on_top();
+        match light.kind {
+            LightKind::Directional { direction } => {
+                let from = Point3::new(0.0, 4.0, 0.0);
+                debug.arrow(from, from + direction, options);
+                debug.label(from, "directional", options);
+            }
+            LightKind::Point { position } => {
+                debug.sphere(position, light.range.unwrap_or(0.25), options);
+                debug.label(position, "point", options);
+            }
+            LightKind::Spot { position, direction, .. } => {
+                debug.arrow(position, position + direction, options);
+                debug.label(position, "spot", options);
+            }
+        }
+    }
+}
+
+/// The bones of a skinned object and the state it plays, on top of everything else.
+fn debug_draw_skeleton(debug: &mut DebugDraw, object: &SkinnedObject) {
+    let options = DebugOptions::color([1.0, 1.0, 0.3, 1.0]).on_top();
+    let positions: Vec<Point3<f32>> = object.skeleton.global_transforms(&object.pose).iter().map(|global| Point3::from_vec((object.transform * global).w.truncate())).collect();
+    for (joint, &position) in object.skeleton.joints.iter().zip(positions.iter()) {
+        if let Some(parent) = joint.parent {
+            debug.line(positions[parent], position, options);
+        }
+        debug.sphere(position, 0.03, options);
+    }
+    let above = Point3::from_vec(object.transform.w.truncate()) + Vector3::unit_y() * 2.4;
+    debug.label(above, &object.animator.current_state().name, options);
+}
+
+/// Directory of the demo's particle effects.
+const PARTICLES_PATH: &str = "particles";
+
+/// A few primitives showing off the range of materials and every kind of light.
+fn demo_scene(uploads: &mut UploadManager, namer: &ObjectNamer) -> Scene {
+    let sphere = Mesh::upload(uploads, namer, "sphere", &MeshData::sphere(0.5, 48, 24));
+    let cube = Mesh::upload(uploads, namer, "cube", &MeshData::cube(1.0));
+    let plane = Mesh::upload(uploads, namer, "plane", &MeshData::plane(12.0));
+
+    let checker: Vec<[u8; 4]> = (0..64 * 64).map(|i| if (i % 64 / 8 + i / 64 / 8) % 2 == 0 { [230, 230, 230, 255] } else { [40, 40, 40, 255] }).collect();
+    let checker = Material::texture(uploads, namer, "checker texture", &checker, [64, 64], true);
+
+    let ground = Arc::new(Material {
+                              base_color_factor: [0.5, 0.5, 0.5, 1.0],
+                              metallic_factor: 0.0,
+                              roughness_factor: 0.9,
+                              ..Material::default()
+                          });
+    let crate_material = Arc::new(Material {
+                                      base_color_texture: Some(checker.clone()),
+                                      metallic_factor: 0.0,
+                                      roughness_factor: 0.5,
+                                      ..Material::default()
+                                  });
+
+    let mut objects = vec![Object {
+                               mesh: plane,
+                               material: ground,
+                               transform: Matrix4::from_scale(1.0),
+                               lod: None,
+                           },
+                           Object {
+                               mesh: cube,
+                               material: crate_material,
+                               transform: Matrix4::from_translation(Vector3::new(0.0, 0.5, -2.0)) * Matrix4::from_angle_y(Deg(30.0)),
+                               lod: None,
+                           }];
+    // Roughness increases to the right, the front row is metal.
+    for row in 0..2 {
+        for column in 0..5 {
+            let material = Arc::new(Material {
+                                        base_color_factor: if row == 0 { [0.8, 0.1, 0.1, 1.0] } else { [1.0, 0.78, 0.34, 1.0] },
+                                        metallic_factor: row as f32,
+                                        roughness_factor: 0.1 + 0.2 * column as f32,
+                                        ..Material::default()
+                                    });
+            objects.push(Object {
+                             mesh: sphere.clone(),
+                             material,
+                             transform: Matrix4::from_translation(Vector3::new(column as f32 * 1.25 - 2.5, 0.5, row as f32 * 1.25)),
+                             lod: None,
+                         });
+        }
+    }
+    // Overlapping panes in front of the crate, one for every blend mode.
+    let pane = Mesh::upload(uploads, namer, "pane", &MeshData::cube(1.0));
+    for (index, &(blend, color)) in [(BlendMode::Alpha, [0.2, 0.6, 1.0, 0.4]), (BlendMode::Premultiplied, [0.1, 0.1, 0.1, 0.2]), (BlendMode::Additive, [1.0, 0.4, 0.1, 0.8])].iter().enumerate() {
+        let material = Arc::new(Material {
+                                    base_color_factor: color,
+                                    metallic_factor: 0.0,
+                                    roughness_factor: 0.1,
+                                    alpha_mode: AlphaMode::Blend(blend),
+                                    ..Material::default()
+                                });
+        objects.push(Object {
+                         mesh: pane.clone(),
+                         material,
+                         transform: Matrix4::from_translation(Vector3::new(index as f32 * 0.6 - 0.6, 0.75, -0.8 - index as f32 * 0.3)) * Matrix4::from_nonuniform_scale(1.0, 1.5, 0.05),
+                         lod: None,
+                     });
+    }
+    objects.extend(demo_lod_row(uploads, namer));
+
+    // Overlapping tinted sprites below the center of the screen, the rotated one on top.
+    let mut sprites = vec![Sprite { color: [1.0, 0.3, 0.3, 1.0], ..Sprite::new(checker.clone(), [-48.0, -320.0], [64.0, 64.0]) },
+                           Sprite { color: [0.3, 1.0, 0.3, 0.8], layer: 1, rotation: Deg(30.0).into(), ..Sprite::new(checker.clone(), [0.0, -320.0], [64.0, 64.0]) },
+                           Sprite { color: [0.3, 0.3, 1.0, 1.0], ..Sprite::new(checker, [48.0, -320.0], [64.0, 64.0]) }];
+
+    // Atlas packed sprites to their left, every frame of the sprite sheet in TONIC_SPRITE_SHEET above them and each of
+    // its animations playing above those.
+    let mut atlas = AtlasBuilder::new(AtlasSettings::default());
+    atlas.add("tile".to_string(), RgbaImage::from_fn(32, 32, |x, y| if x % 31 == 0 || y % 31 == 0 { Rgba([90, 60, 30, 255]) } else { Rgba([200, 150, 80, 255]) }))
+         .add("dot".to_string(), RgbaImage::from_fn(16, 16, |x, y| Rgba([255, 255, 255, if (x as f32 - 7.5).hypot(y as f32 - 7.5) < 8.0 { 255 } else { 0 }])));
+    let atlas = atlas.build(uploads, namer, "demo atlas").unwrap();
+    sprites.push(atlas.region(&"tile".to_string()).unwrap().sprite([-128.0, -320.0], 1.0));
+    sprites.push(Sprite { color: [1.0, 0.8, 0.2, 1.0], layer: 1, ..atlas.region(&"dot".to_string()).unwrap().sprite([-128.0, -320.0], 1.0) });
+
+    let mut animated_sprites = vec![];
+    if let Some(path) = env::var_os("TONIC_SPRITE_SHEET") {
+        let path = Path::new(&path);
+        match SpriteSheet::load(path, AtlasSettings::default(), uploads, namer) {
+            Ok(sheet) => {
+                sprites.extend((0..sheet.frames.len()).map(|frame| sheet.sprite(frame, [frame as f32 * 64.0 - 256.0, -224.0], 1.0)));
+                let mut names: Vec<String> = sheet.animations.keys().cloned().collect();
+                names.sort();
+                let sheet = Arc::new(sheet);
+                animated_sprites.extend(names.iter().enumerate().filter_map(|(index, name)| AnimatedSprite::new(sheet.clone(), name, [index as f32 * 64.0 - 256.0, -128.0], 1.0)));
+            }
+            Err(e) => warn!(target: logging::RENDER, "failed to load sprite sheet {}: {}", path.display(), e),
+        }
+    }
+
+    // A wrapped paragraph in the top left and a label over the crate, in the font in TONIC_FONT if there is one. It's
+    // the debug labels' font too.
+    let mut texts = vec![];
+    let mut debug = DebugDraw::default();
+    if let Some(path) = env::var_os("TONIC_FONT") {
+        let path = Path::new(&path);
+        match Font::load(path) {
+            Ok(font) => {
+                debug.font = Some(font.clone());
+                texts.push(Text {
+                               layout: TextLayout { max_width: Some(360.0), align: Align::Left, ..TextLayout::default() },
+                               ..Text::screen("Tonic Engine\nMetallic spheres in front, dielectric behind, roughness increasing to the right.", font.clone(), 20.0, [16.0, 16.0])
+                           });
+                texts.push(Text { color: [1.0, 0.8, 0.3, 1.0], ..Text::world("Crate", font, 0.4, Matrix4::from_translation(Vector3::new(0.0, 1.4, -2.0))) });
+            }
+            Err(e) => warn!(target: logging::ASSETS, "failed to load font {}: {}", path.display(), e),
+        }
+    }
+
+    // The environment in TONIC_ENVIRONMENT, an equirectangular image or a directory of cube faces, or a plain sky.
+    let mut sky = None;
+    if let Some(path) = env::var_os("TONIC_ENVIRONMENT") {
+        let path = Path::new(&path);
+        match EnvironmentMap::load(uploads, namer, path) {
+            Ok(map) => sky = Some(map),
+            Err(e) => warn!(target: logging::ASSETS, "failed to load environment {}: {}", path.display(), e),
+        }
+    }
+    let sky = sky.unwrap_or_else(|| demo_sky(uploads, namer));
+
+    // Sparks bouncing off the ground to the right of the crate, smoke rising to its left and debris tumbling off it, all
+    // started over with F10.
+    let mut emitters = vec![];
+    for &(name, position) in [("sparks", [2.5, 0.0, -2.0]), ("smoke", [-2.5, 0.0, -2.0]), ("debris", [0.0, 1.1, -2.0])].iter() {
+        let path = Path::new(PARTICLES_PATH).join(name).with_extension("json");
+        match ParticleEffect::load(uploads, namer, &path) {
+            Ok(effect) => emitters.push(Emitter::new(effect, Matrix4::from_translation(position.into()), uploads, namer)),
+            Err(e) => warn!(target: logging::ASSETS, "failed to load particle effect {}: {}", path.display(), e),
+        }
+    }
+
+    Scene {
+        camera: Camera {
+            position: Point3::new(0.0, 3.0, 7.0),
+            target: Point3::new(0.0, 0.5, 0.0),
+            up: Vector3::unit_y(),
+            fov_y: Deg(50.0).into(),
+            near: 0.1,
+            far: 100.0,
+            path: RenderPath::from_env(),
+        },
+        ambient: [0.03, 0.03, 0.04],
+        environment: Some(Environment { map: sky, intensity: 1.0 }),
+        lights: vec![Light::directional(Vector3::new(-0.5, -1.0, -0.3), [1.0, 0.95, 0.9], 2.0),
+                     Light::point(Point3::new(2.0, 1.5, 2.0), [0.3, 0.5, 1.0], 3.0),
+                     Light::spot(Point3::new(-3.0, 4.0, 1.0), Vector3::new(0.6, -1.0, -0.3), Deg(15.0).into(), Deg(25.0).into(), [1.0, 0.6, 0.3], 20.0)].into_iter()
+                                                                                                                                                   .map(|light| Light { casts_shadows: true, ..light })
+                                                                                                                                                   .collect(),
+        objects,
+        bvh: Bvh::default(),
+        skinned: demo_characters(uploads, namer),
+        emitters,
+        sprite_camera: Camera2d { pixel_perfect: true, ..Camera2d::default() },
+        sprites,
+        animated_sprites,
+        texts,
+        ui_sprites: vec![],
+        ui_texts: vec![],
+        debug,
+    }
+}
+
+/// A row of objects going into the distance behind the crate, dropping to simpler levels of the LOD group in
+/// TONIC_LOD_GROUP, a file written by the `lod` tool, or of a simplified sphere.
+fn demo_lod_row(uploads: &mut UploadManager, namer: &ObjectNamer) -> Vec<Object> {
+    let mut group = None;
+    if let Some(path) = env::var_os("TONIC_LOD_GROUP") {
+        let path = Path::new(&path);
+        match LodGroup::load(uploads, namer, path) {
+            Ok(loaded) => group = Some(loaded),
+            Err(e) => warn!(target: logging::ASSETS, "failed to load lod group {}: {}", path.display(), e),
+        }
+    }
+    let group = Arc::new(group.unwrap_or_else(|| {
+                                                 let sphere = MeshData::sphere(0.5, 64, 32);
+                                                 LodGroup::generate(uploads, namer, "lod sphere", &sphere, &[(1.0, 0.25), (0.3, 0.1), (0.08, 0.04), (0.02, 0.01)])
+                                             }));
+    if group.levels.is_empty() {
+        return vec![];
+    }
+
+    let material = Arc::new(Material {
+                                base_color_factor: [0.2, 0.6, 0.3, 1.0],
+                                metallic_factor: 0.0,
+                                roughness_factor: 0.6,
+                                ..Material::default()
+                            });
+    (0..12).map(|i| Object {
+                   mesh: group.levels[0].mesh.clone(),
+                   material: material.clone(),
+                   transform: Matrix4::from_translation(Vector3::new(-4.0, 0.5, -4.0 - 6.0 * i as f32)),
+                   lod: Some(Lod::new(group.clone())),
+               })
+           .collect()
+}
+
+/// A tentacle swaying and curling left of the spheres, waving with F8, and the character in TONIC_CHARACTER to their
+/// right, a glTF file whose animations F8 steps through.
+fn demo_characters(uploads: &mut UploadManager, namer: &ObjectNamer) -> Vec<SkinnedObject> {
+    let mut characters = vec![demo_tentacle(uploads, namer)];
+
+    if let Some(path) = env::var_os("TONIC_CHARACTER") {
+        let path = Path::new(&path);
+        match animation::load(path) {
+            Ok(model) => {
+                let parts = model.parts
+                                 .iter()
+                                 .enumerate()
+                                 .map(|(index, (data, material))| SkinnedPart {
+                                     mesh: SkinnedMesh::upload(uploads, namer, &format!("{} part {}", path.display(), index), data),
+                                     material: Arc::new(material.clone()),
+                                 })
+                                 .collect();
+                let mut animator = AnimationStateMachine::new();
+                let states: Vec<usize> = model.clips.iter().map(|clip| animator.state(&clip.name, Motion::Clip(clip.clone()), true)).collect();
+                for (index, &state) in states.iter().enumerate() {
+                    animator.transition(Some(state), states[(index + 1) % states.len()], Condition::Trigger("next".to_string()), 0.3);
+                }
+                characters.push(SkinnedObject::new(parts, Matrix4::from_translation(Vector3::new(4.0, 0.0, 0.0)), model.skeleton, animator));
+            }
+            Err(e) => warn!(target: logging::ASSETS, "failed to load character {}: {}", path.display(), e),
+        }
+    }
+    characters
+}
+
+/// A cylinder skinned to a chain of joints, blending between swaying and curling and waving when triggered.
+fn demo_tentacle(uploads: &mut UploadManager, namer: &ObjectNamer) -> SkinnedObject {
+    const JOINTS: usize = 5;
+    const SPACING: f32 = 0.4;
+    const RADIUS: f32 = 0.12;
+
+    let joints = (0..JOINTS).map(|index| Joint {
+                                parent: index.checked_sub(1),
+                                rest: Transform { translation: Vector3::new(0.0, if index == 0 { 0.0 } else { SPACING }, 0.0), ..Transform::default() },
+                                inverse_bind: Matrix4::from_translation(Vector3::new(0.0, -SPACING * index as f32, 0.0)),
+                            })
+                            .collect();
+    let skeleton = Arc::new(Skeleton::new(joints, Matrix4::from_scale(1.0)).unwrap());
+
+    // Every ring weighted to the two joints around it, narrowing towards the tip.
+    let (segments, rings) = (16, 32);
+    let height = SPACING * (JOINTS - 1) as f32;
+    let mut data = SkinnedMeshData::default();
+    for ring in 0..=rings {
+        let y = height * ring as f32 / rings as f32;
+        let radius = RADIUS * (1.0 - 0.7 * y / height);
+        let joint = ((y / SPACING) as usize).min(JOINTS - 2);
+        let weight = (y / SPACING - joint as f32).min(1.0);
+        for segment in 0..=segments {
+            let angle = 2.0 * PI * segment as f32 / segments as f32;
+            let (sin, cos) = angle.sin_cos();
+            data.vertices.push(SkinnedVertex {
+                position: [sin * radius, y, cos * radius],
+                normal: [sin, 0.0, cos],
+                tangent: [cos, 0.0, -sin, 1.0],
+                uv: [segment as f32 / segments as f32, 1.0 - ring as f32 / rings as f32],
+                joints: [joint as u32, joint as u32 + 1, 0, 0],
+                weights: [1.0 - weight, weight, 0.0, 0.0],
+            });
+        }
+    }
+    let stride = segments + 1;
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let bottom = ring * stride + segment;
+            let top = bottom + stride;
+            data.indices.extend_from_slice(&[bottom, bottom + 1, top + 1, bottom, top + 1, top]);
+        }
+    }
+    let material = Arc::new(Material {
+                                base_color_factor: [0.2, 0.7, 0.5, 1.0],
+                                metallic_factor: 0.0,
+                                roughness_factor: 0.4,
+                                ..Material::default()
+                            });
+    let parts = vec![SkinnedPart { mesh: SkinnedMesh::upload(uploads, namer, "tentacle", &data), material }];
+
+    // Every joint but the root turning by the same angle at every keyframe.
+    let bend = |axis: Vector3<f32>, interpolation: Interpolation, times: &[f32], angles: &[f32]| -> Vec<Channel> {
+        let rotations: Vec<Quaternion<f32>> = angles.iter().map(|&angle| Quaternion::from_axis_angle(axis, Deg(angle))).collect();
+        let rotations = if interpolation == Interpolation::CubicSpline {
+            // Flat tangents, easing in and out of every keyframe.
+            rotations.iter().flat_map(|&rotation| vec![Quaternion::zero(), rotation, Quaternion::zero()]).collect()
+        } else {
+            rotations
+        };
+        (1..JOINTS).map(|joint| Channel { joint, interpolation, times: times.to_vec(), keyframes: Keyframes::Rotation(rotations.clone()) }).collect()
     };
-    dynamic_state.viewports = Some(vec![viewport]);
-
-    image_views
-        .iter()
-        .map(|image_view| {
-            let arc_swapchain_image = image_view.clone();
-            let builder = Framebuffer::start(render_pass.clone())
-                .add(arc_swapchain_image)
-                .unwrap();
-            let framebuffer = builder
-                .build()
-                .unwrap();
-            let framebuffer_abstract = Arc::new(
-                framebuffer,
-            ) as Arc<dyn FramebufferAbstract + Send + Sync>;
-            framebuffer_abstract
-        })
-        .collect::<Vec<_>>()
+    let sway = Arc::new(AnimationClip::new("sway", bend(Vector3::unit_z(), Interpolation::CubicSpline, &[0.0, 1.5, 3.0], &[-12.0, 12.0, -12.0])));
+    let curl = Arc::new(AnimationClip::new("curl", bend(Vector3::unit_x(), Interpolation::Linear, &[0.0, 1.0, 2.0], &[15.0, 30.0, 15.0])));
+    let wave = Arc::new(AnimationClip::new("wave", bend(Vector3::unit_z(), Interpolation::Step, &[0.0, 0.2, 0.4, 0.6, 0.8, 1.0], &[0.0, 20.0, -20.0, 20.0, -20.0, 0.0])));
+
+    let mut animator = AnimationStateMachine::new();
+    let idle = animator.state("idle", Motion::Blend { parameter: "curl".to_string(), clips: vec![(0.0, sway), (1.0, curl)] }, true);
+    let waving = animator.state("wave", Motion::Clip(wave), false);
+    animator.transition(Some(idle), waving, Condition::Trigger("next".to_string()), 0.2);
+    animator.transition(Some(waving), idle, Condition::Finished, 0.4);
+    animator.set_speed(waving, 1.5);
+    animator.set_float("curl", 0.3);
+
+    SkinnedObject::new(parts, Matrix4::from_translation(Vector3::new(-4.0, 0.0, 0.0)), skeleton, animator)
+}
+
+/// A sky fading from blue overhead to a pale horizon, over dark brown ground.
+fn demo_sky(uploads: &mut UploadManager, namer: &ObjectNamer) -> Arc<EnvironmentMap> {
+    let size = [256, 128];
+    let mix = |a: [f32; 3], b: [f32; 3], t: f32| [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t];
+    let pixels: Vec<[f32; 3]> = (0..size[0] * size[1]).map(|i| {
+                                                          let elevation = (0.5 - ((i / size[0]) as f32 + 0.5) / size[1] as f32) * 2.0 * FRAC_PI_2;
+                                                          if elevation >= 0.0 {
+                                                              mix([0.6, 0.7, 0.8], [0.1, 0.25, 0.6], (elevation / FRAC_PI_2).sqrt())
+                                                          } else {
+                                                              mix([0.3, 0.27, 0.24], [0.08, 0.06, 0.05], (-elevation / FRAC_PI_2).sqrt())
+                                                          }
+                                                      })
+                                                      .collect();
+    EnvironmentMap::equirectangular(uploads, namer, "demo sky", &pixels, size)
 }
 
 fn print_devices_info(instance: &Arc<Instance>) {
-    for physical_device in PhysicalDevice::enumerate(&instance) {
-        println!("found a physical device name: {}", physical_device.name());
-        println!("\tapi version: {}", physical_device.api_version());
-        println!("\tdriver_version: {}", physical_device.driver_version());
-        println!("\tpci_vendor_id: {}", physical_device.pci_vendor_id());
-        println!("\tpci_device_id: {}", physical_device.pci_device_id());
-//        println!("\tsupported features: {:#?}", physical_device.supported_features());
-        println!("\tqueue families:");
+    for physical_device in PhysicalDevice::enumerate(instance) {
+        info!(target: logging::DEVICE,
+              name = physical_device.name(),
+              api_version = %physical_device.api_version(),
+              driver_version = physical_device.driver_version(),
+              pci_vendor_id = physical_device.pci_vendor_id(),
+              pci_device_id = physical_device.pci_device_id(),
+              "found a physical device");
         for family in physical_device.queue_families() {
-            println!("\t\tFound a queue family with {:?} queue(s)", family.queues_count());
+            info!(target: logging::DEVICE, id = family.id(), queues = family.queues_count(), "found a queue family");
         }
-        println!();
     }
 }
 
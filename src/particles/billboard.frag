@@ -0,0 +1,30 @@
+#version 450
+
+// Particle color times the effect's texture, or a soft round dot without one.
+
+layout(set = 1, binding = 0) uniform Look {
+    vec4 start_color;
+    vec4 end_color;
+    vec2 size;
+    uint max_particles;
+    uint textured;
+} look;
+
+layout(set = 1, binding = 3) uniform sampler particle_sampler;
+layout(set = 1, binding = 4) uniform texture2D particle_texture;
+
+layout(location = 0) in vec2 v_uv;
+layout(location = 1) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    vec4 color = v_color;
+    if (look.textured != 0) {
+        color *= texture(sampler2D(particle_texture, particle_sampler), v_uv);
+    } else {
+        float r = length(v_uv * 2.0 - 1.0);
+        color.a *= 1.0 - smoothstep(0.0, 1.0, r);
+    }
+    f_color = color;
+}
@@ -0,0 +1,66 @@
+#version 450
+
+// Expands every living particle into a quad facing the camera, in the order of the keys.
+
+struct Particle {
+    vec3 position;
+    float age;
+    vec3 velocity;
+    float lifetime;
+    vec3 axis;
+    float angle;
+    float spin;
+    float padding0;
+    float padding1;
+    float padding2;
+};
+
+layout(set = 0, binding = 0) uniform Frame {
+    mat4 view_projection;
+    vec4 camera_right;
+    vec4 camera_up;
+    vec4 camera_position;
+} frame;
+
+layout(set = 1, binding = 0) uniform Look {
+    vec4 start_color;
+    vec4 end_color;
+    vec2 size;
+    uint max_particles;
+    uint textured;
+} look;
+
+layout(set = 1, binding = 1) readonly buffer Particles {
+    Particle particles[];
+};
+
+layout(set = 1, binding = 2) readonly buffer Keys {
+    uvec2 keys[];
+};
+
+layout(location = 0) out vec2 v_uv;
+layout(location = 1) out vec4 v_color;
+
+void main() {
+    uint index = keys[gl_InstanceIndex].y;
+    if (index >= look.max_particles || particles[index].age >= particles[index].lifetime) {
+        // Outside of the clip volume.
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        v_uv = vec2(0.0);
+        v_color = vec4(0.0);
+        return;
+    }
+
+    // Two triangles: (-1, -1) (1, -1) (1, 1) and (-1, -1) (1, 1) (-1, 1).
+    int vertex = int(gl_VertexIndex);
+    vec2 corner = vec2(vertex == 1 || vertex == 2 || vertex == 4 ? 1.0 : -1.0, vertex == 2 || vertex == 4 || vertex == 5 ? 1.0 : -1.0);
+    float angle = particles[index].angle;
+    vec2 turned = vec2(corner.x * cos(angle) - corner.y * sin(angle), corner.x * sin(angle) + corner.y * cos(angle));
+
+    float t = particles[index].age / particles[index].lifetime;
+    float size = mix(look.size.x, look.size.y, t);
+    vec3 position = particles[index].position + (frame.camera_right.xyz * turned.x + frame.camera_up.xyz * turned.y) * size * 0.5;
+    gl_Position = frame.view_projection * vec4(position, 1.0);
+    v_uv = vec2(corner.x, -corner.y) * 0.5 + 0.5;
+    v_color = mix(look.start_color, look.end_color, t);
+}
@@ -0,0 +1,259 @@
+use std::sync::Arc;
+
+use vulkano::buffer::{BufferAccess, CpuBufferPool};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::pipeline::blend::{AttachmentBlend, BlendFactor};
+use vulkano::pipeline::depth_stencil::{Compare, DepthStencil};
+use vulkano::pipeline::vertex::{BufferlessDefinition, BufferlessVertices, OneVertexOneInstanceDefinition};
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::render_pass::Subpass;
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+use crate::debug::ObjectNamer;
+use crate::particles::{ParticleBlend, SortKey};
+use crate::render_graph::{BufferId, ImageId, Load, Pass, PassBuilder, PassContext};
+use crate::scene::{Material, MeshVertex, Scene, Texture};
+use crate::upload::UploadManager;
+
+mod billboard_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/particles/billboard.vert"
+    }
+}
+
+mod billboard_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/particles/billboard.frag"
+    }
+}
+
+mod mesh_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/particles/mesh.vert"
+    }
+}
+
+mod mesh_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/particles/mesh.frag"
+    }
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Frame {
+    view_projection: [[f32; 4]; 4],
+    camera_right: [f32; 4],
+    camera_up: [f32; 4],
+    camera_position: [f32; 4],
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Look {
+    start_color: [f32; 4],
+    end_color: [f32; 4],
+    size: [f32; 2],
+    max_particles: u32,
+    textured: u32,
+}
+
+type BillboardPipeline = GraphicsPipeline<BufferlessDefinition, Box<dyn PipelineLayoutAbstract + Send + Sync>>;
+
+struct Pipelines {
+    billboard_alpha: Arc<BillboardPipeline>,
+    billboard_additive: Arc<BillboardPipeline>,
+    mesh_alpha: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    mesh_additive: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+}
+
+/// Draws the particles of the scene's emitters over `color`, hidden behind `depth` but not writing it.
+pub struct ParticleDrawPass {
+    color: ImageId,
+    depth: ImageId,
+    particles: BufferId,
+    namer: ObjectNamer,
+    sampler: Arc<Sampler>,
+    // Bound for effects without a texture.
+    white: Texture,
+    frames: CpuBufferPool<Frame>,
+    looks: CpuBufferPool<Look>,
+    pipelines: Option<Pipelines>,
+}
+
+impl ParticleDrawPass {
+    pub fn new(device: Arc<Device>, color: ImageId, depth: ImageId, particles: BufferId, uploads: &mut UploadManager, namer: &ObjectNamer) -> ParticleDrawPass {
+        let sampler = Sampler::new(device.clone(),
+                                   Filter::Linear,
+                                   Filter::Linear,
+                                   MipmapMode::Linear,
+                                   SamplerAddressMode::ClampToEdge,
+                                   SamplerAddressMode::ClampToEdge,
+                                   SamplerAddressMode::ClampToEdge,
+                                   0.0,
+                                   1.0,
+                                   0.0,
+                                   1000.0).unwrap();
+        namer.name(&*sampler, "particle sampler");
+
+        ParticleDrawPass {
+            color,
+            depth,
+            particles,
+            namer: namer.clone(),
+            sampler,
+            white: Material::texture(uploads, namer, "white particle texture", &[[255, 255, 255, 255]], [1, 1], false),
+            frames: CpuBufferPool::uniform_buffer(device.clone()),
+            looks: CpuBufferPool::uniform_buffer(device),
+            pipelines: None,
+        }
+    }
+}
+
+impl Pass<Scene> for ParticleDrawPass {
+    fn declare(&mut self, pass: &mut PassBuilder) {
+        pass.color(self.color, Load::Keep).depth(self.depth, Load::Keep).read(self.particles);
+    }
+
+    fn prepare(&mut self, device: &Arc<Device>, subpass: Option<Subpass>) {
+        let subpass = subpass.expect("the particle pass renders to attachments");
+        let billboard_vs = billboard_vs::Shader::load(device.clone()).unwrap();
+        let billboard_fs = billboard_fs::Shader::load(device.clone()).unwrap();
+        let mesh_vs = mesh_vs::Shader::load(device.clone()).unwrap();
+        let mesh_fs = mesh_fs::Shader::load(device.clone()).unwrap();
+        let depth_stencil = DepthStencil { depth_compare: Compare::Less, depth_write: false, ..DepthStencil::simple_depth_test() };
+        let additive = AttachmentBlend {
+            color_source: BlendFactor::SrcAlpha,
+            color_destination: BlendFactor::One,
+            alpha_source: BlendFactor::Zero,
+            alpha_destination: BlendFactor::One,
+            ..AttachmentBlend::alpha_blending()
+        };
+
+        let billboard = |blend: AttachmentBlend, name: &str| {
+            let pipeline = Arc::new(GraphicsPipeline::start().vertex_input(BufferlessDefinition)
+                                                             .vertex_shader(billboard_vs.main_entry_point(), ())
+                                                             .triangle_list()
+                                                             .viewports_dynamic_scissors_irrelevant(1)
+                                                             .fragment_shader(billboard_fs.main_entry_point(), ())
+                                                             .blend_collective(blend)
+                                                             .depth_stencil(depth_stencil.clone())
+                                                             .render_pass(subpass.clone())
+                                                             .build(device.clone())
+                                                             .unwrap());
+            self.namer.name(&*pipeline, name);
+            pipeline
+        };
+        let mesh = |blend: AttachmentBlend, name: &str| {
+            let pipeline = Arc::new(GraphicsPipeline::start().vertex_input(OneVertexOneInstanceDefinition::<MeshVertex, SortKey>::new())
+                                                             .vertex_shader(mesh_vs.main_entry_point(), ())
+                                                             .triangle_list()
+                                                             .viewports_dynamic_scissors_irrelevant(1)
+                                                             .fragment_shader(mesh_fs.main_entry_point(), ())
+                                                             .blend_collective(blend)
+                                                             .depth_stencil(depth_stencil.clone())
+                                                             .cull_mode_back()
+                                                             .render_pass(subpass.clone())
+                                                             .build(device.clone())
+                                                             .unwrap());
+            self.namer.name(&*pipeline, name);
+            pipeline as Arc<dyn GraphicsPipelineAbstract + Send + Sync>
+        };
+
+        self.pipelines = Some(Pipelines {
+            billboard_alpha: billboard(AttachmentBlend::alpha_blending(), "particle billboard pipeline"),
+            billboard_additive: billboard(additive.clone(), "additive particle billboard pipeline"),
+            mesh_alpha: mesh(AttachmentBlend::alpha_blending(), "particle mesh pipeline"),
+            mesh_additive: mesh(additive, "additive particle mesh pipeline"),
+        });
+    }
+
+    fn record(&mut self, context: &mut PassContext, scene: &Scene) {
+        if scene.emitters.is_empty() {
+            return;
+        }
+        let pipelines = self.pipelines.as_ref().unwrap();
+
+        let camera = &scene.camera;
+        let view = camera.view();
+        let aspect = context.dimensions[0] as f32 / context.dimensions[1] as f32;
+        // The rows of the view's rotation are the camera's axes in the world.
+        let frame = self.frames
+                        .next(Frame {
+                            view_projection: (camera.projection(aspect) * view).into(),
+                            camera_right: [view.x.x, view.y.x, view.z.x, 0.0],
+                            camera_up: [view.x.y, view.y.y, view.z.y, 0.0],
+                            camera_position: camera.position.to_homogeneous().into(),
+                        })
+                        .unwrap();
+        let layout = pipelines.billboard_alpha.descriptor_set_layout(0).unwrap().clone();
+        let frame_set = Arc::new(PersistentDescriptorSet::start(layout).add_buffer(frame).unwrap().build().unwrap());
+
+        for emitter in scene.emitters.iter() {
+            let effect = &emitter.effect;
+            let look = self.looks
+                           .next(Look {
+                               start_color: effect.desc.color[0],
+                               end_color: effect.desc.color[1],
+                               size: effect.desc.size,
+                               max_particles: emitter.max_particles,
+                               textured: effect.texture.is_some() as u32,
+                           })
+                           .unwrap();
+            let additive = effect.desc.blend == ParticleBlend::Additive;
+
+            match &effect.mesh {
+                None => {
+                    let pipeline = if additive { &pipelines.billboard_additive } else { &pipelines.billboard_alpha };
+                    let layout = pipeline.descriptor_set_layout(1).unwrap().clone();
+                    let set = Arc::new(PersistentDescriptorSet::start(layout).add_buffer(look)
+                                                                             .unwrap()
+                                                                             .add_buffer(emitter.particles.clone())
+                                                                             .unwrap()
+                                                                             .add_buffer(emitter.keys.clone())
+                                                                             .unwrap()
+                                                                             .add_sampler(self.sampler.clone())
+                                                                             .unwrap()
+                                                                             .add_image(effect.texture.as_ref().unwrap_or(&self.white).clone())
+                                                                             .unwrap()
+                                                                             .build()
+                                                                             .unwrap());
+                    // Living particles come first in the keys, or are spread over the first `max_particles` unsorted.
+                    context.builder
+                           .draw(pipeline.clone(),
+                                 context.dynamic_state,
+                                 BufferlessVertices { vertices: 6, instances: emitter.max_particles as usize },
+                                 (frame_set.clone(), set),
+                                 (),
+                                 vec![])
+                           .unwrap();
+                }
+                Some(mesh) => {
+                    let pipeline = if additive { &pipelines.mesh_additive } else { &pipelines.mesh_alpha };
+                    let layout = pipeline.descriptor_set_layout(1).unwrap().clone();
+                    let set = Arc::new(PersistentDescriptorSet::start(layout).add_buffer(look)
+                                                                             .unwrap()
+                                                                             .add_buffer(emitter.particles.clone())
+                                                                             .unwrap()
+                                                                             .build()
+                                                                             .unwrap()) as Arc<dyn DescriptorSet + Send + Sync>;
+                    context.builder
+                           .draw_indexed(pipeline.clone(),
+                                         context.dynamic_state,
+                                         vec![mesh.vertices.clone() as Arc<dyn BufferAccess + Send + Sync>, emitter.keys.clone()],
+                                         mesh.indices.clone(),
+                                         (frame_set.clone() as Arc<dyn DescriptorSet + Send + Sync>, set),
+                                         (),
+                                         vec![])
+                           .unwrap();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,14 @@
+#version 450
+
+// Particle color, darker where the mesh faces away from the camera so its shape shows.
+
+layout(location = 0) in vec3 v_normal;
+layout(location = 1) in vec3 v_to_camera;
+layout(location = 2) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+void main() {
+    float facing = max(dot(normalize(v_normal), normalize(v_to_camera)), 0.0);
+    f_color = vec4(v_color.rgb * (0.35 + 0.65 * facing), v_color.a);
+}
@@ -0,0 +1,73 @@
+#version 450
+
+// Places the mesh at every living particle, scaled to its size and turned around its axis. The particle comes from
+// the keys, bound as instance attributes.
+
+struct Particle {
+    vec3 position;
+    float age;
+    vec3 velocity;
+    float lifetime;
+    vec3 axis;
+    float angle;
+    float spin;
+    float padding0;
+    float padding1;
+    float padding2;
+};
+
+layout(set = 0, binding = 0) uniform Frame {
+    mat4 view_projection;
+    vec4 camera_right;
+    vec4 camera_up;
+    vec4 camera_position;
+} frame;
+
+layout(set = 1, binding = 0) uniform Look {
+    vec4 start_color;
+    vec4 end_color;
+    vec2 size;
+    uint max_particles;
+    uint textured;
+} look;
+
+layout(set = 1, binding = 1) readonly buffer Particles {
+    Particle particles[];
+};
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in uint index;
+
+layout(location = 0) out vec3 v_normal;
+layout(location = 1) out vec3 v_to_camera;
+layout(location = 2) out vec4 v_color;
+
+// Rotation by `angle` radians around the unit vector `axis`.
+mat3 rotation(vec3 axis, float angle) {
+    float c = cos(angle);
+    float s = sin(angle);
+    vec3 t = axis * (1.0 - c);
+    return mat3(t.x * axis.x + c, t.x * axis.y + s * axis.z, t.x * axis.z - s * axis.y,
+                t.x * axis.y - s * axis.z, t.y * axis.y + c, t.y * axis.z + s * axis.x,
+                t.x * axis.z + s * axis.y, t.y * axis.z - s * axis.x, t.z * axis.z + c);
+}
+
+void main() {
+    if (index >= look.max_particles || particles[index].age >= particles[index].lifetime) {
+        // Outside of the clip volume.
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        v_normal = vec3(0.0);
+        v_to_camera = vec3(0.0);
+        v_color = vec4(0.0);
+        return;
+    }
+
+    mat3 turn = rotation(particles[index].axis, particles[index].angle);
+    float t = particles[index].age / particles[index].lifetime;
+    vec3 world = particles[index].position + turn * position * mix(look.size.x, look.size.y, t);
+    gl_Position = frame.view_projection * vec4(world, 1.0);
+    v_normal = turn * normal;
+    v_to_camera = frame.camera_position.xyz - world;
+    v_color = mix(look.start_color, look.end_color, t);
+}
@@ -0,0 +1,340 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use cgmath::Matrix4;
+use serde::Deserialize;
+use vulkano::buffer::BufferUsage;
+use vulkano::device::Device;
+
+pub use crate::particles::draw::ParticleDrawPass;
+pub use crate::particles::simulate::ParticleSimulatePass;
+
+use crate::allocator::{MemoryCategory, PooledBuffer};
+use crate::debug::ObjectNamer;
+use crate::render_graph::{ImageId, RenderGraphBuilder};
+use crate::scene::{Material, Mesh, MeshData, Scene, Texture};
+use crate::upload::UploadManager;
+
+mod draw;
+mod simulate;
+
+// Workgroup size of the particle compute shaders. Emitters keep at least this many keys.
+const GROUP_SIZE: u32 = 64;
+
+#[derive(Debug)]
+pub enum ParticleError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Image(image::ImageError),
+}
+
+impl fmt::Display for ParticleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParticleError::Io(e) => write!(f, "{}", e),
+            ParticleError::Json(e) => write!(f, "invalid particle effect: {}", e),
+            ParticleError::Image(e) => write!(f, "invalid particle texture: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for ParticleError {
+    fn from(e: io::Error) -> ParticleError {
+        ParticleError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ParticleError {
+    fn from(e: serde_json::Error) -> ParticleError {
+        ParticleError::Json(e)
+    }
+}
+
+impl From<image::ImageError> for ParticleError {
+    fn from(e: image::ImageError) -> ParticleError {
+        ParticleError::Image(e)
+    }
+}
+
+/// Where particles spawn in the emitter's space, and which way they fly off.
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmitterShape {
+    /// At the origin, in every direction.
+    Point,
+    /// Inside the sphere, away from its center.
+    Sphere { radius: f32 },
+    /// Inside the box, along +y.
+    Box { half_extents: [f32; 3] },
+    /// On a disc facing +y, within `angle` radians of +y.
+    Cone { angle: f32, radius: f32 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParticleBlend {
+    /// Blended over what's behind, drawn farthest first.
+    Alpha,
+    /// Adding light, in any order.
+    Additive,
+}
+
+/// What a particle looks like. Meshes are `size` across and tumble around a random axis, billboards turn around
+/// the view direction.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParticleRender {
+    Billboard,
+    Cube,
+    Sphere,
+}
+
+/// How particles bounce off the depth buffer.
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(default)]
+pub struct ParticleCollision {
+    /// Fraction of the speed into the surface that's kept, out of it.
+    pub bounce: f32,
+    /// Fraction of the speed along the surface that's lost.
+    pub friction: f32,
+    /// How far behind the depth buffer a particle still collides, rather than being hidden behind something.
+    pub thickness: f32,
+}
+
+impl Default for ParticleCollision {
+    fn default() -> ParticleCollision {
+        ParticleCollision { bounce: 0.5, friction: 0.1, thickness: 0.5 }
+    }
+}
+
+/// A particle effect, usually read from a JSON file with `ParticleEffect::load`. Every field is optional. Pairs
+/// of values are ranges picked from at random for every particle, or values at its birth and death with everything
+/// in between over its life.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct EffectDesc {
+    /// Particles alive at once. When there are more the oldest are reused, so it should cover `rate` times the
+    /// longest lifetime.
+    pub max_particles: u32,
+    /// Particles per second.
+    pub rate: f32,
+    /// Particles spawned at once when the emitter starts.
+    pub burst: u32,
+    /// Seconds the emitter emits for, forever without.
+    pub duration: Option<f32>,
+    pub shape: EmitterShape,
+    /// Seconds, random.
+    pub lifetime: [f32; 2],
+    /// Initial speed, random.
+    pub speed: [f32; 2],
+    /// Radians per second, random.
+    pub spin: [f32; 2],
+    pub gravity: [f32; 3],
+    /// Slows particles down, their velocity shrinks by a factor of e every `1 / drag` seconds.
+    pub drag: f32,
+    /// At birth and death.
+    pub size: [f32; 2],
+    /// Linear RGBA at birth and death. Above 1 it feeds bloom.
+    pub color: [[f32; 4]; 2],
+    pub blend: ParticleBlend,
+    pub render: ParticleRender,
+    /// Multiplies the color of billboards, relative to the effect file. Without one they're soft dots.
+    pub texture: Option<PathBuf>,
+    /// Without it particles fly through everything.
+    pub collision: Option<ParticleCollision>,
+}
+
+impl Default for EffectDesc {
+    fn default() -> EffectDesc {
+        EffectDesc {
+            max_particles: 1024,
+            rate: 50.0,
+            burst: 0,
+            duration: None,
+            shape: EmitterShape::Point,
+            lifetime: [1.0, 2.0],
+            speed: [1.0, 2.0],
+            spin: [0.0, 0.0],
+            gravity: [0.0, -9.81, 0.0],
+            drag: 0.0,
+            size: [0.1, 0.1],
+            color: [[1.0, 1.0, 1.0, 1.0], [1.0, 1.0, 1.0, 0.0]],
+            blend: ParticleBlend::Alpha,
+            render: ParticleRender::Billboard,
+            texture: None,
+            collision: None,
+        }
+    }
+}
+
+/// An effect with its texture and mesh on the GPU, shared by every emitter playing it.
+pub struct ParticleEffect {
+    pub name: String,
+    pub desc: EffectDesc,
+    pub(crate) texture: Option<Texture>,
+    pub(crate) mesh: Option<Arc<Mesh>>,
+}
+
+impl ParticleEffect {
+    /// Reads an `EffectDesc` from a JSON file, named after the file.
+    pub fn load(uploads: &mut UploadManager, namer: &ObjectNamer, path: &Path) -> Result<Arc<ParticleEffect>, ParticleError> {
+        let mut desc: EffectDesc = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if let Some(texture) = &mut desc.texture {
+            *texture = path.parent().unwrap_or_else(|| Path::new("")).join(&texture);
+        }
+        let name = path.file_stem().map_or_else(|| path.display().to_string(), |stem| stem.to_string_lossy().into_owned());
+        ParticleEffect::new(uploads, namer, &name, desc)
+    }
+
+    /// Loads the effect's texture, if it has one, from `desc.texture` as it is.
+    pub fn new(uploads: &mut UploadManager, namer: &ObjectNamer, name: &str, desc: EffectDesc) -> Result<Arc<ParticleEffect>, ParticleError> {
+        let texture = match &desc.texture {
+            Some(path) => {
+                let image = image::open(path)?.to_rgba8();
+                let pixels: Vec<[u8; 4]> = image.pixels().map(|pixel| pixel.0).collect();
+                Some(Material::texture(uploads, namer, &format!("{} particle texture", name), &pixels, [image.width(), image.height()], true))
+            }
+            None => None,
+        };
+        let mesh = match desc.render {
+            ParticleRender::Billboard => None,
+            ParticleRender::Cube => Some(Mesh::upload(uploads, namer, &format!("{} particle cube", name), &MeshData::cube(1.0))),
+            ParticleRender::Sphere => Some(Mesh::upload(uploads, namer, &format!("{} particle sphere", name), &MeshData::sphere(0.5, 12, 6))),
+        };
+
+        Ok(Arc::new(ParticleEffect {
+            name: name.to_string(),
+            desc,
+            texture,
+            mesh,
+        }))
+    }
+}
+
+/// A particle as `simulate.comp` stores it. Dead once `age` reaches `lifetime`, which all of them start out as.
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+pub(crate) struct Particle {
+    position: [f32; 3],
+    age: f32,
+    velocity: [f32; 3],
+    lifetime: f32,
+    axis: [f32; 3],
+    angle: f32,
+    spin: f32,
+    padding: [f32; 3],
+}
+
+/// A slot in the draw order: the particle's distance to the camera as bits, and its index. Also the instance
+/// attributes of mesh particles.
+#[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
+pub(crate) struct SortKey {
+    distance: u32,
+    index: u32,
+}
+vulkano::impl_vertex!(SortKey, distance, index);
+
+// Gives every emitter different random numbers.
+static NEXT_SEED: AtomicU32 = AtomicU32::new(1);
+
+/// An effect playing in the scene. Its particles only exist on the GPU, where the `ParticleSimulatePass` spawns and
+/// moves them and the `ParticleDrawPass` draws them. `update` has to be called once every frame.
+pub struct Emitter {
+    pub effect: Arc<ParticleEffect>,
+    /// Places the emitter's shape in the world. Particles fly off turned with it, but not faster when it's scaled.
+    pub transform: Matrix4<f32>,
+    /// Whether new particles spawn. The living ones play out either way.
+    pub emitting: bool,
+    pub(crate) particles: Arc<PooledBuffer<[Particle]>>,
+    pub(crate) keys: Arc<PooledBuffer<[SortKey]>>,
+    pub(crate) max_particles: u32,
+    /// Keys, `max_particles` rounded up to a power of two for sorting.
+    pub(crate) sorted_count: u32,
+    // Slots to spawn into this frame, `count` of them from `first` on, wrapping around.
+    pub(crate) first: u32,
+    pub(crate) count: u32,
+    // Seconds since the last frame, and new random numbers for it.
+    pub(crate) elapsed: f32,
+    pub(crate) seed: u32,
+    // Seconds spent emitting, and the fraction of a particle left over from the last frame.
+    time: f32,
+    carry: f32,
+    burst_done: bool,
+}
+
+impl Emitter {
+    pub fn new(effect: Arc<ParticleEffect>, transform: Matrix4<f32>, uploads: &mut UploadManager, namer: &ObjectNamer) -> Emitter {
+        let max_particles = effect.desc.max_particles.max(1);
+        let sorted_count = max_particles.next_power_of_two().max(GROUP_SIZE);
+
+        let particles = uploads.buffer(&vec![Particle::default(); max_particles as usize], BufferUsage { storage_buffer: true, ..BufferUsage::none() }, MemoryCategory::Other)
+                               .expect("failed to create particle buffer");
+        let keys = uploads.buffer(&vec![SortKey::default(); sorted_count as usize], BufferUsage { storage_buffer: true, vertex_buffer: true, ..BufferUsage::none() }, MemoryCategory::Other)
+                          .expect("failed to create particle key buffer");
+        namer.name_buffer(&*particles, &format!("{} particles", effect.name));
+        namer.name_buffer(&*keys, &format!("{} particle keys", effect.name));
+
+        Emitter {
+            effect,
+            transform,
+            emitting: true,
+            particles,
+            keys,
+            max_particles,
+            sorted_count,
+            first: 0,
+            count: 0,
+            elapsed: 0.0,
+            seed: NEXT_SEED.fetch_add(1, Ordering::Relaxed).wrapping_mul(0x9e37_79b9),
+            time: 0.0,
+            carry: 0.0,
+            burst_done: false,
+        }
+    }
+
+    /// Decides which particles spawn this frame, `elapsed` after the last one.
+    pub fn update(&mut self, elapsed: Duration) {
+        let desc = &self.effect.desc;
+        let elapsed = elapsed.as_secs_f32();
+
+        let mut spawn = self.carry;
+        if self.emitting {
+            if !self.burst_done {
+                spawn += desc.burst as f32;
+                self.burst_done = true;
+            }
+            let end = desc.duration.unwrap_or(f32::INFINITY);
+            spawn += desc.rate * ((self.time + elapsed).min(end) - self.time.min(end));
+            self.time += elapsed;
+        }
+        let count = spawn.floor();
+        self.carry = spawn - count;
+
+        self.first = (self.first + self.count) % self.max_particles;
+        self.count = (count as u32).min(self.max_particles);
+        self.elapsed = elapsed;
+        self.seed = self.seed.wrapping_mul(747_796_405).wrapping_add(2_891_336_453);
+    }
+
+    /// Emits from the start again, burst included.
+    pub fn restart(&mut self) {
+        self.emitting = true;
+        self.time = 0.0;
+        self.carry = 0.0;
+        self.burst_done = false;
+    }
+}
+
+/// Adds the passes that simulate the scene's emitters, colliding with `depth`, and draw their particles over
+/// `color`. They have to be added after the passes drawing the opaque scene to both.
+pub fn add_passes(graph: &mut RenderGraphBuilder<Scene>, color: ImageId, depth: ImageId, device: &Arc<Device>, uploads: &mut UploadManager, namer: &ObjectNamer) {
+    let particles = graph.buffer("particles");
+    graph.add_pass("particle simulation", ParticleSimulatePass::new(device.clone(), depth, particles, namer));
+    graph.add_pass("particles", ParticleDrawPass::new(device.clone(), color, depth, particles, uploads, namer));
+}
@@ -0,0 +1,227 @@
+use std::sync::Arc;
+
+use cgmath::SquareMatrix;
+use vulkano::buffer::CpuBufferPool;
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::DescriptorSet;
+use vulkano::device::Device;
+use vulkano::pipeline::{ComputePipeline, ComputePipelineAbstract};
+use vulkano::render_pass::Subpass;
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+use crate::debug::ObjectNamer;
+use crate::particles::{EmitterShape, ParticleBlend, GROUP_SIZE};
+use crate::render_graph::{BufferId, ImageId, Pass, PassBuilder, PassContext};
+use crate::scene::Scene;
+
+mod simulation {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/particles/simulate.comp"
+    }
+}
+
+mod sort {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/particles/sort.comp"
+    }
+}
+
+// Emitter shapes of `simulate.comp`.
+const POINT: u32 = 0;
+const SPHERE: u32 = 1;
+const BOX: u32 = 2;
+const CONE: u32 = 3;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Frame {
+    view_projection: [[f32; 4]; 4],
+    inverse_view_projection: [[f32; 4]; 4],
+    camera_position: [f32; 4],
+    texel_size: [f32; 2],
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct GpuEmitter {
+    transform: [[f32; 4]; 4],
+    gravity: [f32; 3],
+    drag: f32,
+    extent: [f32; 3],
+    shape: u32,
+    lifetime: [f32; 2],
+    speed: [f32; 2],
+    spin: [f32; 2],
+    first: u32,
+    count: u32,
+    max_particles: u32,
+    sorted_count: u32,
+    seed: u32,
+    collide: u32,
+    bounce: f32,
+    friction: f32,
+    thickness: f32,
+    elapsed: f32,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct SortStep {
+    block: u32,
+    distance: u32,
+}
+
+struct Pipelines {
+    simulate: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+    sort: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+}
+
+/// Spawns and moves the particles of the scene's emitters, bouncing them off `depth` where their effect collides,
+/// and sorts the particles of alpha blended effects farthest first.
+pub struct ParticleSimulatePass {
+    depth: ImageId,
+    particles: BufferId,
+    namer: ObjectNamer,
+    depth_sampler: Arc<Sampler>,
+    frames: CpuBufferPool<Frame>,
+    emitters: CpuBufferPool<GpuEmitter>,
+    pipelines: Option<Pipelines>,
+}
+
+impl ParticleSimulatePass {
+    pub fn new(device: Arc<Device>, depth: ImageId, particles: BufferId, namer: &ObjectNamer) -> ParticleSimulatePass {
+        let depth_sampler = Sampler::new(device.clone(),
+                                         Filter::Nearest,
+                                         Filter::Nearest,
+                                         MipmapMode::Nearest,
+                                         SamplerAddressMode::ClampToEdge,
+                                         SamplerAddressMode::ClampToEdge,
+                                         SamplerAddressMode::ClampToEdge,
+                                         0.0,
+                                         1.0,
+                                         0.0,
+                                         0.0).unwrap();
+        namer.name(&*depth_sampler, "particle depth sampler");
+
+        ParticleSimulatePass {
+            depth,
+            particles,
+            namer: namer.clone(),
+            depth_sampler,
+            frames: CpuBufferPool::uniform_buffer(device.clone()),
+            emitters: CpuBufferPool::uniform_buffer(device),
+            pipelines: None,
+        }
+    }
+}
+
+impl Pass<Scene> for ParticleSimulatePass {
+    fn declare(&mut self, pass: &mut PassBuilder) {
+        pass.sample(self.depth).write(self.particles);
+    }
+
+    fn prepare(&mut self, device: &Arc<Device>, _subpass: Option<Subpass>) {
+        let simulate = simulation::Shader::load(device.clone()).unwrap();
+        let sort = sort::Shader::load(device.clone()).unwrap();
+        let simulate = Arc::new(ComputePipeline::new(device.clone(), &simulate.main_entry_point(), &(), None).unwrap());
+        let sort = Arc::new(ComputePipeline::new(device.clone(), &sort.main_entry_point(), &(), None).unwrap());
+        self.namer.name(&*simulate, "particle simulation pipeline");
+        self.namer.name(&*sort, "particle sort pipeline");
+
+        self.pipelines = Some(Pipelines { simulate, sort });
+    }
+
+    fn record(&mut self, context: &mut PassContext, scene: &Scene) {
+        if scene.emitters.is_empty() {
+            return;
+        }
+        let pipelines = self.pipelines.as_ref().unwrap();
+
+        let camera = &scene.camera;
+        let [width, height] = context.dimensions;
+        let view_projection = camera.projection(width as f32 / height as f32) * camera.view();
+        let frame = self.frames
+                        .next(Frame {
+                            view_projection: view_projection.into(),
+                            inverse_view_projection: view_projection.invert().expect("camera can't be inverted").into(),
+                            camera_position: camera.position.to_homogeneous().into(),
+                            texel_size: [1.0 / width as f32, 1.0 / height as f32],
+                        })
+                        .unwrap();
+        let layout = pipelines.simulate.descriptor_set_layout(0).unwrap().clone();
+        let frame_set = Arc::new(PersistentDescriptorSet::start(layout).add_buffer(frame)
+                                                                       .unwrap()
+                                                                       .add_sampler(self.depth_sampler.clone())
+                                                                       .unwrap()
+                                                                       .add_image(context.image(self.depth))
+                                                                       .unwrap()
+                                                                       .build()
+                                                                       .unwrap()) as Arc<dyn DescriptorSet + Send + Sync>;
+
+        for emitter in scene.emitters.iter() {
+            let desc = &emitter.effect.desc;
+            let (shape, extent) = match desc.shape {
+                EmitterShape::Point => (POINT, [0.0; 3]),
+                EmitterShape::Sphere { radius } => (SPHERE, [radius, 0.0, 0.0]),
+                EmitterShape::Box { half_extents } => (BOX, half_extents),
+                EmitterShape::Cone { angle, radius } => (CONE, [angle, radius, 0.0]),
+            };
+            let collision = desc.collision.unwrap_or_default();
+            let uniform = self.emitters
+                              .next(GpuEmitter {
+                                  transform: emitter.transform.into(),
+                                  gravity: desc.gravity,
+                                  drag: desc.drag,
+                                  extent,
+                                  shape,
+                                  lifetime: [desc.lifetime[0].max(0.001), desc.lifetime[1].max(0.001)],
+                                  speed: desc.speed,
+                                  spin: desc.spin,
+                                  first: emitter.first,
+                                  count: emitter.count,
+                                  max_particles: emitter.max_particles,
+                                  sorted_count: emitter.sorted_count,
+                                  seed: emitter.seed,
+                                  collide: desc.collision.is_some() as u32,
+                                  bounce: collision.bounce,
+                                  friction: collision.friction,
+                                  thickness: collision.thickness,
+                                  elapsed: emitter.elapsed,
+                              })
+                              .unwrap();
+
+            let layout = pipelines.simulate.descriptor_set_layout(1).unwrap().clone();
+            let emitter_set = Arc::new(PersistentDescriptorSet::start(layout).add_buffer(uniform)
+                                                                             .unwrap()
+                                                                             .add_buffer(emitter.particles.clone())
+                                                                             .unwrap()
+                                                                             .add_buffer(emitter.keys.clone())
+                                                                             .unwrap()
+                                                                             .build()
+                                                                             .unwrap());
+            let groups = emitter.sorted_count / GROUP_SIZE;
+            context.builder
+                   .dispatch([groups, 1, 1], pipelines.simulate.clone(), (frame_set.clone(), emitter_set), (), vec![])
+                   .unwrap();
+
+            if desc.blend != ParticleBlend::Alpha {
+                continue;
+            }
+            let layout = pipelines.sort.descriptor_set_layout(0).unwrap().clone();
+            let sort_set = Arc::new(PersistentDescriptorSet::start(layout).add_buffer(emitter.keys.clone()).unwrap().build().unwrap());
+            let mut block = 2;
+            while block <= emitter.sorted_count {
+                let mut distance = block / 2;
+                while distance > 0 {
+                    context.builder
+                           .dispatch([groups, 1, 1], pipelines.sort.clone(), sort_set.clone(), SortStep { block, distance }, vec![])
+                           .unwrap();
+                    distance /= 2;
+                }
+                block *= 2;
+            }
+        }
+    }
+}
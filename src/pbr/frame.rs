@@ -0,0 +1,195 @@
+use std::sync::Arc;
+
+use cgmath::Rad;
+use vulkano::buffer::BufferUsage;
+use vulkano::descriptor::descriptor_set::{PersistentDescriptorSet, UnsafeDescriptorSetLayout};
+use vulkano::descriptor::DescriptorSet;
+use vulkano::device::Device;
+use vulkano::pipeline::depth_stencil::Compare;
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+use crate::allocator::{GpuAllocator, MemoryCategory, PooledBuffer};
+use crate::debug::ObjectNamer;
+use crate::environment::EnvironmentMaps;
+use crate::render_graph::{PassBuilder, PassContext};
+use crate::scene::{Light, LightKind, Scene};
+use crate::shadow::{LightShadow, ShadowMaps, ShadowViews};
+
+// Light kinds of the lit shaders.
+const DIRECTIONAL: u32 = 0;
+const POINT: u32 = 1;
+const SPOT: u32 = 2;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Globals {
+    view_projection: [[f32; 4]; 4],
+    view: [[f32; 4]; 4],
+    camera_position: [f32; 3],
+    light_count: u32,
+    ambient: [f32; 3],
+    cascade_count: u32,
+    cascade_splits: [f32; 4],
+    depth_bias: f32,
+    slope_bias: f32,
+    normal_bias: f32,
+    pcf_radius: i32,
+    environment_intensity: f32,
+    specular_levels: f32,
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub(crate) struct GpuLight {
+    position: [f32; 3],
+    range: f32,
+    direction: [f32; 3],
+    kind: u32,
+    color: [f32; 3],
+    intensity: f32,
+    cone_scale: f32,
+    cone_offset: f32,
+    shadow_layer: i32,
+    shadow_matrix: i32,
+}
+
+impl GpuLight {
+    fn new(light: &Light, shadow: Option<LightShadow>) -> GpuLight {
+        let mut gpu = GpuLight {
+            range: light.range.unwrap_or(0.0),
+            color: light.color,
+            intensity: light.intensity,
+            shadow_layer: shadow.map_or(-1, |shadow| shadow.layer as i32),
+            shadow_matrix: shadow.map_or(-1, |shadow| shadow.matrix as i32),
+            ..GpuLight::default()
+        };
+        match light.kind {
+            LightKind::Directional { direction } => {
+                gpu.kind = DIRECTIONAL;
+                gpu.direction = direction.into();
+            }
+            LightKind::Point { position } => {
+                gpu.kind = POINT;
+                gpu.position = position.into();
+            }
+            LightKind::Spot { position, direction, inner_cone_angle, outer_cone_angle } => {
+                let (Rad(inner), Rad(outer)) = (inner_cone_angle, outer_cone_angle);
+                gpu.kind = SPOT;
+                gpu.position = position.into();
+                gpu.direction = direction.into();
+                gpu.cone_scale = 1.0 / (inner.cos() - outer.cos()).max(0.001);
+                gpu.cone_offset = -outer.cos() * gpu.cone_scale;
+            }
+        }
+        gpu
+    }
+}
+
+/// Descriptor set 0 of the lit shaders, as `shading.glsl` declares it: the camera, the lights with their shadow maps
+/// and the environment, written every frame into `MemoryCategory::Uniform` buffers. Vertex shaders only read the
+/// globals at binding 0.
+pub struct FrameSet {
+    pub shadows: ShadowMaps,
+    pub environment: EnvironmentMaps,
+    shadow_sampler: Arc<Sampler>,
+    allocator: GpuAllocator,
+}
+
+impl FrameSet {
+    pub fn new(device: &Arc<Device>, shadows: ShadowMaps, environment: EnvironmentMaps, allocator: &GpuAllocator, namer: &ObjectNamer) -> FrameSet {
+        let shadow_sampler = Sampler::compare(device.clone(),
+                                              Filter::Linear,
+                                              Filter::Linear,
+                                              MipmapMode::Nearest,
+                                              SamplerAddressMode::ClampToEdge,
+                                              SamplerAddressMode::ClampToEdge,
+                                              SamplerAddressMode::ClampToEdge,
+                                              0.0,
+                                              1.0,
+                                              0.0,
+                                              0.0,
+                                              Compare::LessOrEqual).unwrap();
+        namer.name(&*shadow_sampler, "shadow sampler");
+
+        FrameSet {
+            shadows,
+            environment,
+            shadow_sampler,
+            allocator: allocator.clone(),
+        }
+    }
+
+    /// Declares the shadow maps and environment maps the whole set reads.
+    pub fn declare(&self, pass: &mut PassBuilder) {
+        pass.sample(self.shadows.cascades).sample(self.shadows.spot).sample(self.shadows.point).read(self.environment.id);
+    }
+
+    /// The whole set, for lighting.
+    pub fn lit(&self, layout: Arc<UnsafeDescriptorSetLayout>, context: &PassContext, scene: &Scene) -> Arc<dyn DescriptorSet + Send + Sync> {
+        let aspect = context.dimensions[0] as f32 / context.dimensions[1] as f32;
+        let shadow_views = ShadowViews::new(scene, &self.shadows.settings, aspect);
+        let globals = self.globals(scene, aspect, &shadow_views);
+        // A descriptor can't point at an empty buffer.
+        let lights: Vec<GpuLight> = if scene.lights.is_empty() {
+            vec![GpuLight::default()]
+        } else {
+            scene.lights.iter().zip(shadow_views.lights.iter()).map(|(light, &shadow)| GpuLight::new(light, shadow)).collect()
+        };
+        let storage = BufferUsage { storage_buffer: true, ..BufferUsage::none() };
+        let lights = PooledBuffer::from_iter(&self.allocator, MemoryCategory::Uniform, storage, lights.into_iter()).unwrap();
+        let shadow_matrices = PooledBuffer::<[[[f32; 4]; 4]]>::from_iter(&self.allocator, MemoryCategory::Uniform, storage, shadow_views.matrices.iter().map(|&matrix| matrix.into())).unwrap();
+
+        Arc::new(PersistentDescriptorSet::start(layout).add_buffer(globals)
+                                                       .unwrap()
+                                                       .add_buffer(lights)
+                                                       .unwrap()
+                                                       .add_buffer(shadow_matrices)
+                                                       .unwrap()
+                                                       .add_sampler(self.shadow_sampler.clone())
+                                                       .unwrap()
+                                                       .add_image(context.image(self.shadows.cascades))
+                                                       .unwrap()
+                                                       .add_image(context.image(self.shadows.spot))
+                                                       .unwrap()
+                                                       .add_image(context.image(self.shadows.point))
+                                                       .unwrap()
+                                                       .add_sampler(self.environment.sampler.clone())
+                                                       .unwrap()
+                                                       .add_image(self.environment.irradiance.clone())
+                                                       .unwrap()
+                                                       .add_image(self.environment.prefiltered.clone())
+                                                       .unwrap()
+                                                       .add_image(self.environment.brdf.clone())
+                                                       .unwrap()
+                                                       .build()
+                                                       .unwrap())
+    }
+
+    /// Only the globals, for pipelines that don't light anything. Needs nothing declared.
+    pub fn unlit(&self, layout: Arc<UnsafeDescriptorSetLayout>, context: &PassContext, scene: &Scene) -> Arc<dyn DescriptorSet + Send + Sync> {
+        let aspect = context.dimensions[0] as f32 / context.dimensions[1] as f32;
+        let globals = self.globals(scene, aspect, &ShadowViews::new(scene, &self.shadows.settings, aspect));
+        Arc::new(PersistentDescriptorSet::start(layout).add_buffer(globals).unwrap().build().unwrap())
+    }
+
+    fn globals(&self, scene: &Scene, aspect: f32, shadow_views: &ShadowViews) -> Arc<PooledBuffer<Globals>> {
+        let camera = &scene.camera;
+        let settings = &self.shadows.settings;
+        let globals = Globals {
+            view_projection: (camera.projection(aspect) * camera.view()).into(),
+            view: camera.view().into(),
+            camera_position: camera.position.into(),
+            light_count: scene.lights.len() as u32,
+            ambient: scene.ambient,
+            cascade_count: settings.cascades,
+            cascade_splits: shadow_views.cascade_splits,
+            depth_bias: settings.depth_bias,
+            slope_bias: settings.slope_bias,
+            normal_bias: settings.normal_bias,
+            pcf_radius: settings.pcf_radius as i32,
+            environment_intensity: scene.environment.as_ref().map_or(0.0, |environment| environment.intensity),
+            specular_levels: self.environment.settings.specular_levels as f32,
+        };
+        PooledBuffer::from_data(&self.allocator, MemoryCategory::Uniform, BufferUsage::uniform_buffer(), globals).unwrap()
+    }
+}
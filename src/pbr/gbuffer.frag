@@ -0,0 +1,85 @@
+#version 450
+
+// The surface attributes `forward.frag` lights with, written for the deferred lighting pass to light instead.
+
+layout(location = 0) in vec3 v_position;
+layout(location = 1) in vec3 v_normal;
+layout(location = 2) in vec4 v_tangent;
+layout(location = 3) in vec2 v_uv;
+
+// Emission, the only radiance known before lighting.
+layout(location = 0) out vec4 f_emissive;
+// Base color.
+layout(location = 1) out vec4 f_albedo;
+// World space shading normal.
+layout(location = 2) out vec4 f_normal;
+// Metallic, roughness and occlusion.
+layout(location = 3) out vec4 f_material;
+
+layout(set = 1, binding = 0) uniform sampler material_sampler;
+layout(set = 1, binding = 1) uniform texture2D base_color_texture;
+layout(set = 1, binding = 2) uniform texture2D metallic_roughness_texture;
+layout(set = 1, binding = 3) uniform texture2D normal_texture;
+layout(set = 1, binding = 4) uniform texture2D occlusion_texture;
+layout(set = 1, binding = 5) uniform texture2D emissive_texture;
+
+layout(push_constant) uniform Draw {
+    mat4 model;
+    vec4 base_color_factor;
+    vec3 emissive_factor;
+    float normal_scale;
+    float metallic_factor;
+    float roughness_factor;
+    float occlusion_strength;
+    // Negative for opaque materials.
+    float alpha_cutoff;
+    // Fraction of the pixels drawn while LOD levels crossfade, the complement of that fraction when negative. 1
+    // otherwise.
+    float lod_fade;
+    uint blend_mode;
+} draw;
+
+// Ordered dither threshold in a 4x4 Bayer pattern, from bit interleaving rather than a table.
+float bayer(uvec2 pixel) {
+    uvec2 p = pixel & 3u;
+    uint x = p.x ^ p.y;
+    uint index = ((x & 1u) << 3) | ((p.y & 1u) << 2) | (x & 2u) | ((p.y & 2u) >> 1);
+    return (float(index) + 0.5) / 16.0;
+}
+
+void main() {
+    if (draw.lod_fade < 1.0) {
+        float threshold = bayer(uvec2(gl_FragCoord.xy));
+        if (draw.lod_fade >= 0.0 ? threshold >= draw.lod_fade : threshold < -draw.lod_fade) {
+            discard;
+        }
+    }
+
+    vec4 base_color = draw.base_color_factor * texture(sampler2D(base_color_texture, material_sampler), v_uv);
+    if (base_color.a < draw.alpha_cutoff) {
+        discard;
+    }
+
+    vec3 metallic_roughness = texture(sampler2D(metallic_roughness_texture, material_sampler), v_uv).rgb;
+    float metallic = clamp(draw.metallic_factor * metallic_roughness.b, 0.0, 1.0);
+    float roughness = clamp(draw.roughness_factor * metallic_roughness.g, 0.0, 1.0);
+    float occlusion = mix(1.0, texture(sampler2D(occlusion_texture, material_sampler), v_uv).r, draw.occlusion_strength);
+
+    vec3 n = normalize(v_normal);
+    vec3 t = normalize(v_tangent.xyz - n * dot(n, v_tangent.xyz));
+    vec3 b = cross(n, t) * v_tangent.w;
+    // Back faces are only drawn for double sided materials, which light them as if they faced the other way.
+    if (!gl_FrontFacing) {
+        t = -t;
+        b = -b;
+        n = -n;
+    }
+    vec3 tangent_normal = texture(sampler2D(normal_texture, material_sampler), v_uv).xyz * 2.0 - 1.0;
+    tangent_normal.xy *= draw.normal_scale;
+    n = normalize(mat3(t, b, n) * tangent_normal);
+
+    f_emissive = vec4(draw.emissive_factor * texture(sampler2D(emissive_texture, material_sampler), v_uv).rgb, 1.0);
+    f_albedo = vec4(base_color.rgb, 1.0);
+    f_normal = vec4(n, 0.0);
+    f_material = vec4(metallic, roughness, occlusion, 1.0);
+}
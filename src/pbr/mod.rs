@@ -0,0 +1,542 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use cgmath::{Matrix4, MetricSpace, Transform};
+use vulkano::buffer::{BufferAccess, BufferSlice, BufferUsage, CpuBufferPool};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::DescriptorSet;
+use vulkano::device::Device;
+use vulkano::pipeline::blend::{AttachmentBlend, BlendFactor};
+use vulkano::pipeline::depth_stencil::DepthStencil;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::render_pass::Subpass;
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+pub use crate::pbr::frame::FrameSet;
+
+use crate::animation::SkinnedVertex;
+use crate::culling::{Culling, CullingMode, Frustum, ViewStats};
+use crate::debug::ObjectNamer;
+use crate::deferred::GBuffer;
+use crate::environment::EnvironmentMaps;
+use crate::lod::Lod;
+use crate::render_graph::{ImageId, Load, Pass, PassBuilder, PassContext};
+use crate::scene::{AlphaMode, BlendMode, Material, MeshVertex, Object, RenderPath, Scene, Texture};
+use crate::shadow::ShadowMaps;
+use crate::transparency::TransparencyMode;
+use crate::upload::UploadManager;
+
+mod frame;
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/pbr/forward.vert"
+    }
+}
+
+mod skinned_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/pbr/skinned.vert"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/pbr/forward.frag",
+        include: ["src/pbr"]
+    }
+}
+
+mod gbuffer_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/pbr/gbuffer.frag"
+    }
+}
+
+mod oit_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/pbr/oit.frag",
+        include: ["src/pbr"]
+    }
+}
+
+/// Push constants of a draw: the object's transform and the material's factors.
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Draw {
+    model: [[f32; 4]; 4],
+    base_color_factor: [f32; 4],
+    emissive_factor: [f32; 3],
+    normal_scale: f32,
+    metallic_factor: f32,
+    roughness_factor: f32,
+    occlusion_strength: f32,
+    alpha_cutoff: f32,
+    lod_fade: f32,
+    blend_mode: u32,
+}
+
+impl Draw {
+    fn new(transform: Matrix4<f32>, material: &Material, lod_fade: f32) -> Draw {
+        Draw {
+            model: transform.into(),
+            base_color_factor: material.base_color_factor,
+            emissive_factor: material.emissive_factor,
+            normal_scale: material.normal_scale,
+            metallic_factor: material.metallic_factor,
+            roughness_factor: material.roughness_factor,
+            occlusion_strength: material.occlusion_strength,
+            alpha_cutoff: match material.alpha_mode {
+                AlphaMode::Opaque => -1.0,
+                AlphaMode::Mask(cutoff) => cutoff,
+                AlphaMode::Blend(_) => -1.0,
+            },
+            lod_fade,
+            blend_mode: match material.alpha_mode {
+                AlphaMode::Blend(BlendMode::Premultiplied) => 1,
+                AlphaMode::Blend(BlendMode::Additive) => 2,
+                _ => 0,
+            },
+        }
+    }
+}
+
+struct Pipelines {
+    single_sided: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    double_sided: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    skinned_single_sided: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    skinned_double_sided: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+}
+
+/// What a `ForwardPass` draws the objects into. The first two draw opaque and masked materials, the others blended
+/// ones.
+pub enum ForwardTarget {
+    /// Lit radiance, loaded as `load` says, for cameras on the forward path.
+    Shaded { color: ImageId, load: Load },
+    /// Surface attributes and emission for `deferred::LightingPass` to light, for cameras on the deferred path.
+    GBuffer { emissive: ImageId, gbuffer: GBuffer },
+    /// Lit radiance blended over `color` the farthest object first, for additive materials and, when `mode` is
+    /// `TransparencyMode::Sorted`, all the others.
+    Sorted { color: ImageId, mode: Arc<Mutex<TransparencyMode>> },
+    /// Weighted sums of lit radiance and coverage for `transparency::CompositePass` to resolve, when `mode` is
+    /// `TransparencyMode::WeightedBlended`.
+    WeightedBlended { accum: ImageId, revealage: ImageId, mode: Arc<Mutex<TransparencyMode>> },
+}
+
+impl ForwardTarget {
+    /// Whether objects with `material` are drawn into the target.
+    fn draws(&self, material: &Material, transparency: TransparencyMode) -> bool {
+        match (self, material.alpha_mode) {
+            (ForwardTarget::Shaded { .. }, blend) | (ForwardTarget::GBuffer { .. }, blend) => !matches!(blend, AlphaMode::Blend(_)),
+            (ForwardTarget::Sorted { .. }, AlphaMode::Blend(blend)) => transparency == TransparencyMode::Sorted || blend == BlendMode::Additive,
+            (ForwardTarget::WeightedBlended { .. }, AlphaMode::Blend(blend)) => transparency == TransparencyMode::WeightedBlended && blend != BlendMode::Additive,
+            _ => false,
+        }
+    }
+
+    fn transparent(&self) -> bool {
+        matches!(self, ForwardTarget::Sorted { .. } | ForwardTarget::WeightedBlended { .. })
+    }
+}
+
+/// How a sorted blended material's shaded color is blended over what's behind.
+fn attachment_blend(blend: BlendMode) -> AttachmentBlend {
+    match blend {
+        BlendMode::Alpha => AttachmentBlend::alpha_blending(),
+        BlendMode::Premultiplied => AttachmentBlend {
+            color_source: BlendFactor::One,
+            alpha_source: BlendFactor::One,
+            ..AttachmentBlend::alpha_blending()
+        },
+        BlendMode::Additive => AttachmentBlend {
+            color_source: BlendFactor::SrcAlpha,
+            color_destination: BlendFactor::One,
+            alpha_source: BlendFactor::Zero,
+            alpha_destination: BlendFactor::One,
+            ..AttachmentBlend::alpha_blending()
+        },
+    }
+}
+
+/// Draws the objects of the scene with metallic-roughness materials, lit by the scene's lights, into a G-buffer or
+/// blended, whichever `target` is. Opaque targets only draw when the camera's render path is the one they're for,
+/// blended ones draw on both. Sorted blended objects are drawn the farthest from the camera first, skinned objects
+/// after them, unsorted.
+///
+/// Lights are written to a storage buffer every frame, so there is no limit on their number beyond the cost of
+/// looping over all of them in every fragment. Lights with shadow maps are filtered with PCF. The scene's environment
+/// adds image-based lighting from the baked environment maps, or `ambient` does without one. Shading writes linear
+/// radiance. Skinned objects are posed in the vertex shader from a buffer of their joint matrices.
+///
+/// Objects outside the camera's view are skipped as `culling` says, after the GPU culled them when it does.
+pub struct ForwardPass {
+    target: ForwardTarget,
+    depth: ImageId,
+    depth_load: Load,
+    frame: FrameSet,
+    culling: Culling,
+    namer: ObjectNamer,
+    sampler: Arc<Sampler>,
+    // Stand-ins for the textures a material doesn't have.
+    white: Texture,
+    flat_normal: Texture,
+    joints: CpuBufferPool<[[f32; 4]; 4]>,
+    // By blend mode for sorted blending, under `None` for the other targets.
+    pipelines: HashMap<Option<BlendMode>, Pipelines>,
+    // Texture descriptor sets by material, holding on to the material so the address isn't reused.
+    materials: HashMap<*const Material, (Arc<Material>, Arc<dyn DescriptorSet + Send + Sync>)>,
+    // Objects culled on the CPU that the camera sees, and the ones drawn in the order they're drawn in, kept to reuse
+    // the allocations.
+    visible: Vec<usize>,
+    order: Vec<usize>,
+    // Objects the GPU drew in the last frame it finished.
+    gpu_drawn: usize,
+}
+
+impl ForwardPass {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(device: Arc<Device>,
+               target: ForwardTarget,
+               depth: ImageId,
+               depth_load: Load,
+               shadows: ShadowMaps,
+               environment: EnvironmentMaps,
+               culling: Culling,
+               uploads: &mut UploadManager,
+               namer: &ObjectNamer)
+               -> ForwardPass {
+        let sampler = Sampler::new(device.clone(),
+                                   Filter::Linear,
+                                   Filter::Linear,
+                                   MipmapMode::Linear,
+                                   SamplerAddressMode::Repeat,
+                                   SamplerAddressMode::Repeat,
+                                   SamplerAddressMode::Repeat,
+                                   0.0,
+                                   1.0,
+                                   0.0,
+                                   1000.0).unwrap();
+        namer.name(&*sampler, "material sampler");
+
+        ForwardPass {
+            target,
+            depth,
+            depth_load,
+            frame: FrameSet::new(&device, shadows, environment, uploads.allocator(), namer),
+            culling,
+            namer: namer.clone(),
+            sampler,
+            white: Material::texture(uploads, namer, "white texture", &[[255, 255, 255, 255]], [1, 1], false),
+            flat_normal: Material::texture(uploads, namer, "flat normal texture", &[[128, 128, 255, 255]], [1, 1], false),
+            joints: CpuBufferPool::new(device, BufferUsage { storage_buffer: true, ..BufferUsage::none() }),
+            pipelines: HashMap::new(),
+            materials: HashMap::new(),
+            visible: vec![],
+            order: vec![],
+            gpu_drawn: 0,
+        }
+    }
+
+    fn pipelines(&self, material: &Material) -> &Pipelines {
+        let blend = match (&self.target, material.alpha_mode) {
+            (ForwardTarget::Sorted { .. }, AlphaMode::Blend(blend)) => Some(blend),
+            _ => None,
+        };
+        &self.pipelines[&blend]
+    }
+
+    fn material_set(&mut self, material: &Arc<Material>) -> Arc<dyn DescriptorSet + Send + Sync> {
+        if let Some((_, set)) = self.materials.get(&Arc::as_ptr(material)) {
+            return set.clone();
+        }
+
+        let layout = self.pipelines.values().next().unwrap().single_sided.descriptor_set_layout(1).unwrap().clone();
+        let white = &self.white;
+        let texture = |texture: &Option<Texture>| texture.as_ref().unwrap_or(white).clone();
+
+        let set = Arc::new(PersistentDescriptorSet::start(layout).add_sampler(self.sampler.clone())
+                                                                 .unwrap()
+                                                                 .add_image(texture(&material.base_color_texture))
+                                                                 .unwrap()
+                                                                 .add_image(texture(&material.metallic_roughness_texture))
+                                                                 .unwrap()
+                                                                 .add_image(material.normal_texture.as_ref().unwrap_or(&self.flat_normal).clone())
+                                                                 .unwrap()
+                                                                 .add_image(texture(&material.occlusion_texture))
+                                                                 .unwrap()
+                                                                 .add_image(texture(&material.emissive_texture))
+                                                                 .unwrap()
+                                                                 .build()
+                                                                 .unwrap()) as Arc<dyn DescriptorSet + Send + Sync>;
+        self.materials.insert(Arc::as_ptr(material), (material.clone(), set.clone()));
+        set
+    }
+}
+
+impl Pass<Scene> for ForwardPass {
+    fn declare(&mut self, pass: &mut PassBuilder) {
+        match self.target {
+            ForwardTarget::Shaded { color, load } => {
+                pass.color(color, load);
+                self.frame.declare(pass);
+            }
+            ForwardTarget::GBuffer { emissive, gbuffer } => {
+                pass.color(emissive, Load::Clear([0.0, 0.0, 0.0, 1.0].into()))
+                    .color(gbuffer.albedo, Load::Clear([0.0, 0.0, 0.0, 0.0].into()))
+                    .color(gbuffer.normal, Load::Clear([0.0, 0.0, 0.0, 0.0].into()))
+                    .color(gbuffer.material, Load::Clear([0.0, 0.0, 0.0, 0.0].into()));
+            }
+            ForwardTarget::Sorted { color, .. } => {
+                pass.color(color, Load::Keep);
+                self.frame.declare(pass);
+            }
+            ForwardTarget::WeightedBlended { accum, revealage, .. } => {
+                pass.color(accum, Load::Clear([0.0, 0.0, 0.0, 0.0].into())).color(revealage, Load::Clear([1.0, 1.0, 1.0, 1.0].into()));
+                self.frame.declare(pass);
+            }
+        }
+        if !matches!(self.target, ForwardTarget::Sorted { .. }) {
+            pass.conditional();
+        }
+        pass.depth(self.depth, self.depth_load).read(self.culling.commands_id);
+    }
+
+    // The deferred path draws opaque and masked materials into the G-buffer, leaving only blended ones, which are
+    // drawn the same on both paths.
+    fn enabled(&self, scene: &Scene) -> bool {
+        match &self.target {
+            ForwardTarget::Shaded { .. } => scene.camera.path == RenderPath::Forward,
+            ForwardTarget::GBuffer { .. } => scene.camera.path == RenderPath::Deferred,
+            ForwardTarget::Sorted { .. } => true,
+            ForwardTarget::WeightedBlended { mode, .. } => *mode.lock().unwrap() == TransparencyMode::WeightedBlended,
+        }
+    }
+
+    fn prepare(&mut self, device: &Arc<Device>, subpass: Option<Subpass>) {
+        let vs = vs::Shader::load(device.clone()).unwrap();
+        let skinned_vs = skinned_vs::Shader::load(device.clone()).unwrap();
+        let subpass = subpass.expect("the forward pass renders to attachments");
+
+        let opaque = DepthStencil::simple_depth_test();
+        // Blended surfaces are hidden by the opaque scene without hiding each other.
+        let blended = DepthStencil { depth_write: false, ..DepthStencil::simple_depth_test() };
+
+        // Every combination of vertex layout and culling, with any of the fragment shaders, whose entry points have
+        // different types. Attachments only blend differently with the independent blend feature, which only weighted
+        // blended transparency needs.
+        macro_rules! pipeline {
+            ($vertex:ty, $vs:expr, $fs:expr, $depth:expr, $blend:expr, $cull:ident, $name:expr) => {{
+                let blend: &Vec<AttachmentBlend> = &$blend;
+                let builder = GraphicsPipeline::start().vertex_input_single_buffer::<$vertex>()
+                                                       .vertex_shader($vs.main_entry_point(), ())
+                                                       .triangle_list()
+                                                       .viewports_dynamic_scissors_irrelevant(1)
+                                                       .fragment_shader($fs.main_entry_point(), ())
+                                                       .depth_stencil($depth.clone());
+                let builder = if blend.iter().all(|attachment| *attachment == blend[0]) {
+                    builder.blend_collective(blend[0].clone())
+                } else {
+                    builder.blend_individual(blend.clone())
+                };
+                let pipeline = Arc::new(builder.$cull().render_pass(subpass.clone()).build(device.clone()).unwrap());
+                self.namer.name(&*pipeline, &$name);
+                pipeline as Arc<dyn GraphicsPipelineAbstract + Send + Sync>
+            }};
+        }
+        macro_rules! pipelines {
+            ($fs:expr, $depth:expr, $blend:expr, $name:expr) => {{
+                let (fs, blend): (_, Vec<AttachmentBlend>) = ($fs, $blend);
+                Pipelines {
+                    single_sided: pipeline!(MeshVertex, vs, fs, $depth, blend, cull_mode_back, format!("{} pipeline", $name)),
+                    double_sided: pipeline!(MeshVertex, vs, fs, $depth, blend, cull_mode_disabled, format!("{} double sided pipeline", $name)),
+                    skinned_single_sided: pipeline!(SkinnedVertex, skinned_vs, fs, $depth, blend, cull_mode_back, format!("{} skinned pipeline", $name)),
+                    skinned_double_sided: pipeline!(SkinnedVertex, skinned_vs, fs, $depth, blend, cull_mode_disabled, format!("{} skinned double sided pipeline", $name)),
+                }
+            }};
+        }
+        self.pipelines.clear();
+        match self.target {
+            ForwardTarget::Shaded { .. } => {
+                let fs = fs::Shader::load(device.clone()).unwrap();
+                self.pipelines.insert(None, pipelines!(fs, opaque, vec![AttachmentBlend::pass_through()], "forward"));
+            }
+            ForwardTarget::GBuffer { .. } => {
+                let fs = gbuffer_fs::Shader::load(device.clone()).unwrap();
+                self.pipelines.insert(None, pipelines!(fs, opaque, vec![AttachmentBlend::pass_through(); 4], "g-buffer"));
+            }
+            ForwardTarget::Sorted { .. } => {
+                let fs = fs::Shader::load(device.clone()).unwrap();
+                for &blend in [BlendMode::Alpha, BlendMode::Premultiplied, BlendMode::Additive].iter() {
+                    let name = format!("{} blended", format!("{:?}", blend).to_lowercase());
+                    self.pipelines.insert(Some(blend), pipelines!(&fs, blended, vec![attachment_blend(blend)], name));
+                }
+            }
+            ForwardTarget::WeightedBlended { .. } => {
+                let fs = oit_fs::Shader::load(device.clone()).unwrap();
+                // Coverage and weighted radiance add up, what's revealed behind multiplies by one minus coverage.
+                let accumulate = AttachmentBlend {
+                    color_source: BlendFactor::One,
+                    color_destination: BlendFactor::One,
+                    alpha_source: BlendFactor::One,
+                    alpha_destination: BlendFactor::One,
+                    ..AttachmentBlend::alpha_blending()
+                };
+                let reveal = AttachmentBlend {
+                    color_source: BlendFactor::Zero,
+                    color_destination: BlendFactor::OneMinusSrcColor,
+                    alpha_source: BlendFactor::Zero,
+                    alpha_destination: BlendFactor::OneMinusSrcAlpha,
+                    ..AttachmentBlend::alpha_blending()
+                };
+                self.pipelines.insert(None, pipelines!(fs, blended, vec![accumulate, reveal], "weighted blended"));
+            }
+        }
+        self.materials.clear();
+    }
+
+    fn record(&mut self, context: &mut PassContext, scene: &Scene) {
+        // Forget materials the scene no longer uses.
+        self.materials.retain(|_, (material, _)| Arc::strong_count(material) > 1);
+
+        let transparency = match &self.target {
+            ForwardTarget::Sorted { mode, .. } | ForwardTarget::WeightedBlended { mode, .. } => *mode.lock().unwrap(),
+            ForwardTarget::Shaded { .. } | ForwardTarget::GBuffer { .. } => TransparencyMode::Sorted,
+        };
+        let mut materials = scene.objects.iter().map(|object| &object.material).chain(scene.skinned.iter().flat_map(|object| object.parts.iter().map(|part| &part.material)));
+        if !materials.any(|material| self.target.draws(material, transparency)) {
+            return;
+        }
+
+        let camera = &scene.camera;
+        let aspect = context.dimensions[0] as f32 / context.dimensions[1] as f32;
+        let layout = self.pipelines.values().next().unwrap().single_sided.descriptor_set_layout(0).unwrap().clone();
+        let frame_set = match self.target {
+            ForwardTarget::GBuffer { .. } => self.frame.unlit(layout, context, scene),
+            _ => self.frame.lit(layout, context, scene),
+        };
+
+        // The first objects are drawn indirectly when the GPU culls them, the rest are culled here.
+        let mode = *self.culling.mode.lock().unwrap();
+        let gpu_objects = if mode == CullingMode::Gpu { scene.objects.len().min(self.culling.capacity) } else { 0 };
+        match mode {
+            CullingMode::Off => {
+                self.visible.clear();
+                self.visible.extend(0..scene.objects.len());
+            }
+            CullingMode::Cpu | CullingMode::Gpu => {
+                let frustum = Frustum::from_matrix(camera.projection(aspect) * camera.view());
+                scene.bvh.cull(&frustum, &mut self.visible);
+                self.visible.retain(|&object| object >= gpu_objects);
+            }
+        }
+        if gpu_objects > 0 {
+            // Only readable once the GPU is done with the last frame that wrote it.
+            if let Ok(visible) = self.culling.visible.read() {
+                self.gpu_drawn = *visible as usize;
+            }
+        }
+        if !self.target.transparent() {
+            let drawn = self.visible.len() + if gpu_objects > 0 { self.gpu_drawn.min(gpu_objects) } else { 0 };
+            self.culling.stats.lock().unwrap().views.insert("camera".to_string(), ViewStats { drawn, culled: scene.objects.len() - drawn });
+        }
+
+        // Blended objects are drawn the farthest from the camera first.
+        let mut order = mem::take(&mut self.order);
+        order.clear();
+        let visible = |index: usize| index < gpu_objects || self.visible.binary_search(&index).is_ok();
+        order.extend((0..scene.objects.len()).filter(|&index| visible(index) && self.target.draws(&scene.objects[index].material, transparency)));
+        if let ForwardTarget::Sorted { .. } = self.target {
+            let distance = |index: usize| {
+                let object = &scene.objects[index];
+                camera.position.distance2(object.transform.transform_point(object.mesh.bounds.center()))
+            };
+            order.sort_by(|&a, &b| distance(b).partial_cmp(&distance(a)).unwrap_or(Ordering::Equal));
+        }
+
+        for &index in order.iter() {
+            let Object { ref mesh, ref material, transform, ref lod } = scene.objects[index];
+            let indirect = index < gpu_objects;
+            let material_set = self.material_set(material);
+            let pipelines = self.pipelines(material);
+            let pipeline = if material.double_sided { &pipelines.double_sided } else { &pipelines.single_sided };
+
+            // While LOD levels crossfade the old one is drawn on the pixels the new one isn't.
+            let fading = lod.as_ref().and_then(Lod::fading);
+            if let Some((mesh, progress)) = fading {
+                context.builder
+                       .draw_indexed(pipeline.clone(),
+                                     context.dynamic_state,
+                                     vec![mesh.vertices.clone() as Arc<dyn BufferAccess + Send + Sync>],
+                                     mesh.indices.clone(),
+                                     (frame_set.clone(), material_set.clone()),
+                                     Draw::new(transform, material, -progress),
+                                     vec![])
+                       .unwrap();
+            }
+            if matches!(lod, Some(lod) if !lod.shown()) {
+                continue;
+            }
+            let draw = Draw::new(transform, material, fading.map_or(1.0, |(_, progress)| progress));
+
+            if indirect {
+                let command = BufferSlice::from_typed_buffer_access(self.culling.commands.clone()).slice(index..index + 1).unwrap();
+                context.builder
+                       .draw_indexed_indirect(pipeline.clone(),
+                                              context.dynamic_state,
+                                              vec![mesh.vertices.clone() as Arc<dyn BufferAccess + Send + Sync>],
+                                              mesh.indices.clone(),
+                                              command,
+                                              (frame_set.clone(), material_set),
+                                              draw,
+                                              vec![])
+                       .unwrap();
+            } else {
+                context.builder
+                       .draw_indexed(pipeline.clone(),
+                                     context.dynamic_state,
+                                     vec![mesh.vertices.clone() as Arc<dyn BufferAccess + Send + Sync>],
+                                     mesh.indices.clone(),
+                                     (frame_set.clone(), material_set),
+                                     draw,
+                                     vec![])
+                       .unwrap();
+            }
+        }
+        self.order = order;
+
+        for object in scene.skinned.iter() {
+            if !object.parts.iter().any(|part| self.target.draws(&part.material, transparency)) {
+                continue;
+            }
+            let joints = self.joints.chunk(object.joint_matrices().into_iter().map(Into::into).collect::<Vec<_>>()).unwrap();
+            let layout = self.pipelines.values().next().unwrap().skinned_single_sided.descriptor_set_layout(2).unwrap().clone();
+            let joint_set = Arc::new(PersistentDescriptorSet::start(layout).add_buffer(joints).unwrap().build().unwrap());
+
+            for part in object.parts.iter() {
+                if !self.target.draws(&part.material, transparency) {
+                    continue;
+                }
+                let material_set = self.material_set(&part.material);
+                let pipelines = self.pipelines(&part.material);
+                let pipeline = if part.material.double_sided { &pipelines.skinned_double_sided } else { &pipelines.skinned_single_sided };
+
+                context.builder
+                       .draw_indexed(pipeline.clone(),
+                                     context.dynamic_state,
+                                     vec![part.mesh.vertices.clone() as Arc<dyn BufferAccess + Send + Sync>],
+                                     part.mesh.indices.clone(),
+                                     (frame_set.clone(), material_set, joint_set.clone()),
+                                     Draw::new(object.transform, &part.material, 1.0),
+                                     vec![])
+                       .unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+#version 450
+
+// Shading from `shading.glsl`, accumulated for weighted blended order-independent transparency as described by
+// McGuire and Bavoil: premultiplied radiance weighted to favor surfaces near the camera, and the product of how much
+// of what's behind every surface lets through.
+
+#include <shading.glsl>
+
+const uint PREMULTIPLIED = 1;
+
+layout(location = 0) in vec3 v_position;
+layout(location = 1) in vec3 v_normal;
+layout(location = 2) in vec4 v_tangent;
+layout(location = 3) in vec2 v_uv;
+
+// Weighted sums of premultiplied radiance in rgb and coverage in a.
+layout(location = 0) out vec4 f_accum;
+// Coverage, which the blend state turns into the product of one minus it.
+layout(location = 1) out vec4 f_revealage;
+
+layout(set = 1, binding = 0) uniform sampler material_sampler;
+layout(set = 1, binding = 1) uniform texture2D base_color_texture;
+layout(set = 1, binding = 2) uniform texture2D metallic_roughness_texture;
+layout(set = 1, binding = 3) uniform texture2D normal_texture;
+layout(set = 1, binding = 4) uniform texture2D occlusion_texture;
+layout(set = 1, binding = 5) uniform texture2D emissive_texture;
+
+layout(push_constant) uniform Draw {
+    mat4 model;
+    vec4 base_color_factor;
+    vec3 emissive_factor;
+    float normal_scale;
+    float metallic_factor;
+    float roughness_factor;
+    float occlusion_strength;
+    // Negative for opaque materials.
+    float alpha_cutoff;
+    // Fraction of the pixels drawn while LOD levels crossfade, the complement of that fraction when negative. 1
+    // otherwise.
+    float lod_fade;
+    // 0 for `BlendMode::Alpha`, 1 for `BlendMode::Premultiplied`. Additive materials are drawn sorted instead.
+    uint blend_mode;
+} draw;
+
+// Ordered dither threshold in a 4x4 Bayer pattern, from bit interleaving rather than a table.
+float bayer(uvec2 pixel) {
+    uvec2 p = pixel & 3u;
+    uint x = p.x ^ p.y;
+    uint index = ((x & 1u) << 3) | ((p.y & 1u) << 2) | (x & 2u) | ((p.y & 2u) >> 1);
+    return (float(index) + 0.5) / 16.0;
+}
+
+void main() {
+    if (draw.lod_fade < 1.0) {
+        float threshold = bayer(uvec2(gl_FragCoord.xy));
+        if (draw.lod_fade >= 0.0 ? threshold >= draw.lod_fade : threshold < -draw.lod_fade) {
+            discard;
+        }
+    }
+
+    vec4 base_color = draw.base_color_factor * texture(sampler2D(base_color_texture, material_sampler), v_uv);
+    if (base_color.a < draw.alpha_cutoff) {
+        discard;
+    }
+
+    vec3 metallic_roughness = texture(sampler2D(metallic_roughness_texture, material_sampler), v_uv).rgb;
+    float metallic = clamp(draw.metallic_factor * metallic_roughness.b, 0.0, 1.0);
+    float roughness = clamp(draw.roughness_factor * metallic_roughness.g, 0.0, 1.0);
+    float alpha = roughness * roughness;
+
+    vec3 n = normalize(v_normal);
+    vec3 t = normalize(v_tangent.xyz - n * dot(n, v_tangent.xyz));
+    vec3 b = cross(n, t) * v_tangent.w;
+    // Back faces are only drawn for double sided materials, which light them as if they faced the other way.
+    if (!gl_FrontFacing) {
+        t = -t;
+        b = -b;
+        n = -n;
+    }
+    vec3 surface_normal = n;
+    vec3 tangent_normal = texture(sampler2D(normal_texture, material_sampler), v_uv).xyz * 2.0 - 1.0;
+    tangent_normal.xy *= draw.normal_scale;
+    n = normalize(mat3(t, b, n) * tangent_normal);
+
+    vec3 v = normalize(globals.camera_position - v_position);
+    vec3 f0 = mix(vec3(0.04), base_color.rgb, metallic);
+    vec3 diffuse_color = base_color.rgb * (1.0 - metallic);
+
+    vec3 color = vec3(0.0);
+    for (uint i = 0; i < globals.light_count; i++) {
+        color += shade_light(lights[i], v_position, n, surface_normal, v, diffuse_color, f0, alpha);
+    }
+
+    float occlusion = mix(1.0, texture(sampler2D(occlusion_texture, material_sampler), v_uv).r, draw.occlusion_strength);
+    color += shade_environment(n, v, diffuse_color, f0, roughness, occlusion);
+
+    color += draw.emissive_factor * texture(sampler2D(emissive_texture, material_sampler), v_uv).rgb;
+
+    float coverage = base_color.a;
+    vec3 premultiplied = draw.blend_mode == PREMULTIPLIED ? color : color * coverage;
+    // Surfaces that cover more and are closer to the camera count for more. Weights up to 3000 times HDR radiance
+    // would overflow 16 bit floats, which is why the accumulation target has 32 bit ones.
+    float weight = clamp(pow(min(1.0, coverage * 10.0) + 0.01, 3.0) * 1e8 * pow(1.0 - gl_FragCoord.z * 0.9, 3.0), 0.01, 3000.0);
+
+    f_accum = vec4(premultiplied, coverage) * weight;
+    f_revealage = vec4(coverage);
+}
@@ -0,0 +1,219 @@
+// Metallic-roughness shading as described in the glTF 2.0 specification's appendix B: a Lambertian diffuse lobe
+// and a GGX specular lobe with a height-correlated Smith visibility term, mixed by Schlick's Fresnel. Included by
+// every shader that lights surfaces with the frame's descriptor set, `pbr::FrameSet`, bound as set 0.
+
+const float PI = 3.14159265359;
+
+const uint DIRECTIONAL = 0;
+const uint POINT = 1;
+const uint SPOT = 2;
+
+struct Light {
+    vec3 position;
+    // Zero for lights without a range.
+    float range;
+    // The direction light travels in.
+    vec3 direction;
+    uint kind;
+    vec3 color;
+    float intensity;
+    // Spot light falloff is clamp(dot(direction, -l) * cone_scale + cone_offset, 0, 1) squared.
+    float cone_scale;
+    float cone_offset;
+    // First layer of the light's shadow map, or -1 without one.
+    int shadow_layer;
+    // Index of the matrix of that layer in `shadow_matrices`.
+    int shadow_matrix;
+};
+
+layout(set = 0, binding = 0) uniform Globals {
+    mat4 view_projection;
+    mat4 view;
+    vec3 camera_position;
+    uint light_count;
+    vec3 ambient;
+    uint cascade_count;
+    // View space depth at which each cascade ends.
+    vec4 cascade_splits;
+    float depth_bias;
+    float slope_bias;
+    float normal_bias;
+    int pcf_radius;
+    // Zero without an environment, when `ambient` lights the scene instead.
+    float environment_intensity;
+    // Levels of the prefiltered specular cube.
+    float specular_levels;
+} globals;
+
+layout(set = 0, binding = 1) readonly buffer Lights {
+    Light lights[];
+};
+
+layout(set = 0, binding = 2) readonly buffer ShadowMatrices {
+    mat4 shadow_matrices[];
+};
+
+layout(set = 0, binding = 3) uniform samplerShadow shadow_sampler;
+layout(set = 0, binding = 4) uniform texture2DArray cascade_shadow_map;
+layout(set = 0, binding = 5) uniform texture2DArray spot_shadow_map;
+layout(set = 0, binding = 6) uniform texture2DArray point_shadow_map;
+layout(set = 0, binding = 7) uniform sampler environment_sampler;
+layout(set = 0, binding = 8) uniform textureCube irradiance_map;
+layout(set = 0, binding = 9) uniform textureCube prefiltered_map;
+layout(set = 0, binding = 10) uniform texture2D brdf_lut;
+
+float distribution_ggx(float n_dot_h, float alpha) {
+    float alpha2 = alpha * alpha;
+    float d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * d * d);
+}
+
+float visibility_smith_ggx(float n_dot_l, float n_dot_v, float alpha) {
+    float alpha2 = alpha * alpha;
+    float ggx_v = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - alpha2) + alpha2);
+    float ggx_l = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - alpha2) + alpha2);
+    float ggx = ggx_v + ggx_l;
+    return ggx > 0.0 ? 0.5 / ggx : 0.0;
+}
+
+vec3 fresnel_schlick(vec3 f0, float v_dot_h) {
+    return f0 + (vec3(1.0) - f0) * pow(clamp(1.0 - v_dot_h, 0.0, 1.0), 5.0);
+}
+
+// Inverse square falloff, windowed to reach zero at the range as recommended by KHR_lights_punctual.
+float range_attenuation(float range, float distance) {
+    float inverse_square = 1.0 / max(distance * distance, 0.0001);
+    if (range <= 0.0) {
+        return inverse_square;
+    }
+    return clamp(1.0 - pow(distance / range, 4.0), 0.0, 1.0) * inverse_square;
+}
+
+// Fraction of the light that reaches `position` according to layer `layer` of the shadow map of `kind` lights,
+// averaged over a square of depth comparisons around it.
+float filter_shadow(uint kind, int layer, mat4 matrix, vec3 position, float bias) {
+    vec4 clip = matrix * vec4(position, 1.0);
+    vec3 ndc = clip.xyz / clip.w;
+    if (abs(ndc.x) > 1.0 || abs(ndc.y) > 1.0 || ndc.z > 1.0) {
+        return 1.0;
+    }
+    vec2 uv = ndc.xy * 0.5 + 0.5;
+    float depth = ndc.z - bias;
+
+    vec2 texel;
+    if (kind == DIRECTIONAL) {
+        texel = 1.0 / vec2(textureSize(sampler2DArrayShadow(cascade_shadow_map, shadow_sampler), 0).xy);
+    } else if (kind == SPOT) {
+        texel = 1.0 / vec2(textureSize(sampler2DArrayShadow(spot_shadow_map, shadow_sampler), 0).xy);
+    } else {
+        texel = 1.0 / vec2(textureSize(sampler2DArrayShadow(point_shadow_map, shadow_sampler), 0).xy);
+    }
+
+    float lit = 0.0;
+    for (int x = -globals.pcf_radius; x <= globals.pcf_radius; x++) {
+        for (int y = -globals.pcf_radius; y <= globals.pcf_radius; y++) {
+            vec4 coords = vec4(uv + vec2(x, y) * texel, float(layer), depth);
+            if (kind == DIRECTIONAL) {
+                lit += texture(sampler2DArrayShadow(cascade_shadow_map, shadow_sampler), coords);
+            } else if (kind == SPOT) {
+                lit += texture(sampler2DArrayShadow(spot_shadow_map, shadow_sampler), coords);
+            } else {
+                lit += texture(sampler2DArrayShadow(point_shadow_map, shadow_sampler), coords);
+            }
+        }
+    }
+    float width = float(globals.pcf_radius * 2 + 1);
+    return lit / (width * width);
+}
+
+// Fraction of `light` that isn't blocked on its way to `position`, on a surface facing `normal`.
+float shadow(Light light, vec3 position, vec3 normal, float n_dot_l) {
+    if (light.shadow_layer < 0) {
+        return 1.0;
+    }
+
+    // Surfaces at a grazing angle to the light cover more depth per texel, so they need more bias.
+    float cos_angle = max(n_dot_l, 0.05);
+    float tangent = sqrt(1.0 - cos_angle * cos_angle) / cos_angle;
+    float bias = globals.depth_bias + globals.slope_bias * min(tangent, 10.0);
+    vec3 offset_position = position + normal * globals.normal_bias;
+
+    // Cascade, or cube face, within the light's shadow map.
+    int offset = 0;
+    if (light.kind == DIRECTIONAL) {
+        float depth = -(globals.view * vec4(position, 1.0)).z;
+        for (uint cascade = 0; cascade < globals.cascade_count; cascade++) {
+            if (depth > globals.cascade_splits[cascade]) {
+                offset++;
+            }
+        }
+        if (offset >= int(globals.cascade_count)) {
+            return 1.0;
+        }
+    } else if (light.kind == POINT) {
+        vec3 d = position - light.position;
+        vec3 a = abs(d);
+        if (a.x >= a.y && a.x >= a.z) {
+            offset = d.x > 0.0 ? 0 : 1;
+        } else if (a.y >= a.z) {
+            offset = d.y > 0.0 ? 2 : 3;
+        } else {
+            offset = d.z > 0.0 ? 4 : 5;
+        }
+    }
+
+    return filter_shadow(light.kind, light.shadow_layer + offset, shadow_matrices[light.shadow_matrix + offset], offset_position, bias);
+}
+
+// Radiance `light` reflects towards `v` off the surface at `position` with shading normal `n`. The shadow is biased
+// along `surface_normal`, the interpolated normal before normal mapping.
+vec3 shade_light(Light light, vec3 position, vec3 n, vec3 surface_normal, vec3 v, vec3 diffuse_color, vec3 f0, float alpha) {
+    vec3 l;
+    float attenuation = 1.0;
+    if (light.kind == DIRECTIONAL) {
+        l = -light.direction;
+    } else {
+        vec3 to_light = light.position - position;
+        float distance = length(to_light);
+        l = to_light / distance;
+        attenuation = range_attenuation(light.range, distance);
+        if (light.kind == SPOT) {
+            float cone = clamp(dot(light.direction, -l) * light.cone_scale + light.cone_offset, 0.0, 1.0);
+            attenuation *= cone * cone;
+        }
+    }
+
+    float n_dot_l = clamp(dot(n, l), 0.0, 1.0);
+    if (n_dot_l <= 0.0 || attenuation <= 0.0) {
+        return vec3(0.0);
+    }
+
+    vec3 h = normalize(l + v);
+    float n_dot_v = max(dot(n, v), 0.0001);
+    float n_dot_h = clamp(dot(n, h), 0.0, 1.0);
+    float v_dot_h = clamp(dot(v, h), 0.0, 1.0);
+
+    vec3 f = fresnel_schlick(f0, v_dot_h);
+    vec3 diffuse = (vec3(1.0) - f) * diffuse_color / PI;
+    vec3 specular = f * distribution_ggx(n_dot_h, alpha) * visibility_smith_ggx(n_dot_l, n_dot_v, alpha);
+
+    float visibility = shadow(light, position, surface_normal, clamp(dot(surface_normal, l), 0.0, 1.0));
+
+    return light.color * light.intensity * attenuation * visibility * n_dot_l * (diffuse + specular);
+}
+
+// Radiance the environment, or `globals.ambient` without one, reflects towards `v` off a surface with normal `n`.
+vec3 shade_environment(vec3 n, vec3 v, vec3 diffuse_color, vec3 f0, float roughness, float occlusion) {
+    if (globals.environment_intensity <= 0.0) {
+        return globals.ambient * (diffuse_color + f0) * occlusion;
+    }
+
+    // Image-based lighting with the split-sum approximation: the prefiltered environment in the reflected direction
+    // times the integral of the specular lobe over it.
+    float n_dot_v = max(dot(n, v), 0.0001);
+    vec3 r = reflect(-v, n);
+    vec3 irradiance = textureLod(samplerCube(irradiance_map, environment_sampler), n, 0.0).rgb;
+    vec3 prefiltered = textureLod(samplerCube(prefiltered_map, environment_sampler), r, roughness * (globals.specular_levels - 1.0)).rgb;
+    vec2 brdf = textureLod(sampler2D(brdf_lut, environment_sampler), vec2(n_dot_v, roughness), 0.0).rg;
+    return (irradiance * diffuse_color + prefiltered * (f0 * brdf.x + brdf.y)) * globals.environment_intensity * occlusion;
+}
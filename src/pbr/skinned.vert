@@ -0,0 +1,63 @@
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec4 tangent;
+layout(location = 3) in vec2 uv;
+layout(location = 4) in uvec4 joints;
+layout(location = 5) in vec4 weights;
+
+layout(location = 0) out vec3 v_position;
+layout(location = 1) out vec3 v_normal;
+layout(location = 2) out vec4 v_tangent;
+layout(location = 3) out vec2 v_uv;
+
+layout(set = 0, binding = 0) uniform Globals {
+    mat4 view_projection;
+    mat4 view;
+    vec3 camera_position;
+    uint light_count;
+    vec3 ambient;
+    uint cascade_count;
+    // View space depth at which each cascade ends.
+    vec4 cascade_splits;
+    float depth_bias;
+    float slope_bias;
+    float normal_bias;
+    int pcf_radius;
+    // Zero without an environment, when `ambient` lights the scene instead.
+    float environment_intensity;
+    // Levels of the prefiltered specular cube.
+    float specular_levels;
+} globals;
+
+// Joint matrices of the object's skeleton, taking bind pose model space to posed model space.
+layout(set = 2, binding = 0) readonly buffer Joints {
+    mat4 joint_matrices[];
+};
+
+layout(push_constant) uniform Draw {
+    mat4 model;
+    vec4 base_color_factor;
+    vec3 emissive_factor;
+    float normal_scale;
+    float metallic_factor;
+    float roughness_factor;
+    float occlusion_strength;
+    float alpha_cutoff;
+    float lod_fade;
+    uint blend_mode;
+} draw;
+
+void main() {
+    mat4 skin = weights.x * joint_matrices[joints.x] + weights.y * joint_matrices[joints.y] + weights.z * joint_matrices[joints.z] + weights.w * joint_matrices[joints.w];
+    mat4 model = draw.model * skin;
+    vec4 world = model * vec4(position, 1.0);
+    mat3 normal_matrix = transpose(inverse(mat3(model)));
+
+    v_position = world.xyz;
+    v_normal = normal_matrix * normal;
+    v_tangent = vec4(mat3(model) * tangent.xyz, tangent.w);
+    v_uv = uv;
+    gl_Position = globals.view_projection * world;
+}
@@ -0,0 +1,167 @@
+use std::sync::{Arc, Mutex};
+
+use serde::Deserialize;
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::pipeline::vertex::BufferlessDefinition;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::render_pass::Subpass;
+use vulkano::sampler::Sampler;
+
+use crate::debug::ObjectNamer;
+use crate::post::fullscreen::{vs, FullscreenPass, FullscreenPipeline};
+use crate::post::PostSettings;
+use crate::render_graph::{ImageDesc, ImageId, ImageSize, RenderGraphBuilder};
+
+mod downsample {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/post/bloom_downsample.frag"
+    }
+}
+
+mod upsample {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/post/bloom_upsample.frag"
+    }
+}
+
+pub const MAX_LEVELS: u32 = 8;
+
+const FORMAT: Format = Format::R16G16B16A16Sfloat;
+
+// Graph images and passes are named statically.
+const DOWNSAMPLED: [&str; MAX_LEVELS as usize] = ["bloom 1/2", "bloom 1/4", "bloom 1/8", "bloom 1/16", "bloom 1/32", "bloom 1/64", "bloom 1/128", "bloom 1/256"];
+const UPSAMPLED: [&str; MAX_LEVELS as usize] = ["bloom up 1/2", "bloom up 1/4", "bloom up 1/8", "bloom up 1/16", "bloom up 1/32", "bloom up 1/64", "bloom up 1/128", "bloom up 1/256"];
+const DOWNSAMPLE_PASSES: [&str; MAX_LEVELS as usize] = ["bloom downsample 1/2",
+                                                        "bloom downsample 1/4",
+                                                        "bloom downsample 1/8",
+                                                        "bloom downsample 1/16",
+                                                        "bloom downsample 1/32",
+                                                        "bloom downsample 1/64",
+                                                        "bloom downsample 1/128",
+                                                        "bloom downsample 1/256"];
+const UPSAMPLE_PASSES: [&str; MAX_LEVELS as usize] = ["bloom upsample 1/2",
+                                                      "bloom upsample 1/4",
+                                                      "bloom upsample 1/8",
+                                                      "bloom upsample 1/16",
+                                                      "bloom upsample 1/32",
+                                                      "bloom upsample 1/64",
+                                                      "bloom upsample 1/128",
+                                                      "bloom upsample 1/256"];
+
+/// Light bleeding around bright parts of the image, from a chain of ever smaller blurred copies.
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(default)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    /// Fraction of the image replaced by the bloom.
+    pub intensity: f32,
+    /// Halvings of the image, from 1 to 8. More levels spread the light further.
+    pub levels: u32,
+    /// Luminance below which pixels don't bloom, zero to let everything bloom.
+    pub threshold: f32,
+    /// Range below the threshold over which bloom fades in.
+    pub knee: f32,
+    /// Spread of the blur between levels, in texels.
+    pub radius: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> BloomSettings {
+        BloomSettings {
+            enabled: true,
+            intensity: 0.04,
+            levels: 6,
+            threshold: 0.0,
+            knee: 0.5,
+            radius: 1.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DownsamplePush {
+    threshold: f32,
+    knee: f32,
+    first_level: u32,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct UpsamplePush {
+    radius: f32,
+}
+
+/// Adds the passes blurring `hdr` down to `levels` halvings and back up to half its size. Returns the half size
+/// image, which holds the sum of every level.
+pub fn add_passes<F: 'static>(graph: &mut RenderGraphBuilder<F>, hdr: ImageId, levels: u32, sampler: &Arc<Sampler>, settings: &Arc<Mutex<PostSettings>>, namer: &ObjectNamer) -> ImageId {
+    let levels = levels as usize;
+
+    let mut downsampled = vec![];
+    for level in 0..levels {
+        let scale = 0.5f32.powi(level as i32 + 1);
+        let image = graph.image(DOWNSAMPLED[level], ImageDesc::new(FORMAT, ImageSize::Scaled(scale)));
+        let source = if level == 0 { hdr } else { downsampled[level - 1] };
+        let push_constants = move |settings: &PostSettings| {
+            let bloom = &settings.bloom;
+            if !bloom.enabled {
+                return None;
+            }
+            Some(DownsamplePush {
+                threshold: bloom.threshold,
+                knee: bloom.knee.max(1e-5),
+                first_level: (level == 0) as u32,
+            })
+        };
+        graph.add_pass(DOWNSAMPLE_PASSES[level],
+                       FullscreenPass::new(DOWNSAMPLE_PASSES[level], image, sampler.clone(), settings.clone(), downsample_pipeline, Box::new(push_constants), namer).input(source));
+        downsampled.push(image);
+    }
+
+    // Every level but the smallest adds the one below it.
+    let mut smaller = downsampled[levels - 1];
+    for level in (0..levels - 1).rev() {
+        let image = graph.image(UPSAMPLED[level], ImageDesc::new(FORMAT, ImageSize::Scaled(0.5f32.powi(level as i32 + 1))));
+        let push_constants = |settings: &PostSettings| {
+            let bloom = &settings.bloom;
+            if !bloom.enabled {
+                return None;
+            }
+            Some(UpsamplePush { radius: bloom.radius })
+        };
+        graph.add_pass(UPSAMPLE_PASSES[level],
+                       FullscreenPass::new(UPSAMPLE_PASSES[level], image, sampler.clone(), settings.clone(), upsample_pipeline, Box::new(push_constants), namer).input(downsampled[level])
+                                                                                                                                                  .input(smaller));
+        smaller = image;
+    }
+    smaller
+}
+
+fn downsample_pipeline(device: &Arc<Device>, subpass: Subpass) -> FullscreenPipeline {
+    let vs = vs::Shader::load(device.clone()).unwrap();
+    let fs = downsample::Shader::load(device.clone()).unwrap();
+    GraphicsPipeline::start().vertex_input(BufferlessDefinition)
+                             .vertex_shader(vs.main_entry_point(), ())
+                             .triangle_list()
+                             .viewports_dynamic_scissors_irrelevant(1)
+                             .fragment_shader(fs.main_entry_point(), ())
+                             .render_pass(subpass)
+                             .build(device.clone())
+                             .unwrap()
+}
+
+fn upsample_pipeline(device: &Arc<Device>, subpass: Subpass) -> FullscreenPipeline {
+    let vs = vs::Shader::load(device.clone()).unwrap();
+    let fs = upsample::Shader::load(device.clone()).unwrap();
+    GraphicsPipeline::start().vertex_input(BufferlessDefinition)
+                             .vertex_shader(vs.main_entry_point(), ())
+                             .triangle_list()
+                             .viewports_dynamic_scissors_irrelevant(1)
+                             .fragment_shader(fs.main_entry_point(), ())
+                             .render_pass(subpass)
+                             .build(device.clone())
+                             .unwrap()
+}
@@ -0,0 +1,75 @@
+#version 450
+
+// Halves the source with the 13 tap filter from Jorge Jimenez's "Next Generation Post Processing in Call of Duty:
+// Advanced Warfare". The first level weights its 2x2 blocks by inverse luminance against fireflies and applies the
+// threshold.
+
+layout(location = 0) in vec2 v_uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler linear_sampler;
+layout(set = 0, binding = 1) uniform texture2D source;
+
+layout(push_constant) uniform Settings {
+    float threshold;
+    float knee;
+    uint first_level;
+} settings;
+
+vec3 fetch(vec2 uv) {
+    return texture(sampler2D(source, linear_sampler), uv).rgb;
+}
+
+float luminance(vec3 color) {
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// Average of four samples, weighted by how dark it is.
+vec3 karis_average(vec3 a, vec3 b, vec3 c, vec3 d, out float weight) {
+    vec3 average = (a + b + c + d) * 0.25;
+    weight = 1.0 / (1.0 + luminance(average));
+    return average * weight;
+}
+
+void main() {
+    vec2 texel = 1.0 / vec2(textureSize(sampler2D(source, linear_sampler), 0));
+
+    vec3 a = fetch(v_uv + texel * vec2(-2.0, -2.0));
+    vec3 b = fetch(v_uv + texel * vec2(0.0, -2.0));
+    vec3 c = fetch(v_uv + texel * vec2(2.0, -2.0));
+    vec3 d = fetch(v_uv + texel * vec2(-1.0, -1.0));
+    vec3 e = fetch(v_uv + texel * vec2(1.0, -1.0));
+    vec3 f = fetch(v_uv + texel * vec2(-2.0, 0.0));
+    vec3 g = fetch(v_uv);
+    vec3 h = fetch(v_uv + texel * vec2(2.0, 0.0));
+    vec3 i = fetch(v_uv + texel * vec2(-1.0, 1.0));
+    vec3 j = fetch(v_uv + texel * vec2(1.0, 1.0));
+    vec3 k = fetch(v_uv + texel * vec2(-2.0, 2.0));
+    vec3 l = fetch(v_uv + texel * vec2(0.0, 2.0));
+    vec3 m = fetch(v_uv + texel * vec2(2.0, 2.0));
+
+    vec3 color;
+    if (settings.first_level != 0) {
+        float w0, w1, w2, w3, w4;
+        color = karis_average(d, e, i, j, w0) * 0.5;
+        color += karis_average(a, b, f, g, w1) * 0.125;
+        color += karis_average(b, c, g, h, w2) * 0.125;
+        color += karis_average(f, g, k, l, w3) * 0.125;
+        color += karis_average(g, h, l, m, w4) * 0.125;
+        color /= w0 * 0.5 + (w1 + w2 + w3 + w4) * 0.125;
+
+        // Soft knee around the threshold.
+        float brightness = max(color.r, max(color.g, color.b));
+        float soft = clamp(brightness - settings.threshold + settings.knee, 0.0, 2.0 * settings.knee);
+        soft = soft * soft / (4.0 * settings.knee + 1e-5);
+        color *= max(soft, brightness - settings.threshold) / max(brightness, 1e-5);
+    } else {
+        color = (d + e + i + j) * 0.125;
+        color += (b + f + h + l) * 0.0625;
+        color += (a + c + k + m) * 0.03125;
+        color += g * 0.125;
+    }
+
+    f_color = vec4(color, 1.0);
+}
@@ -0,0 +1,32 @@
+#version 450
+
+// Blurs the next smaller level up with a 3x3 tent filter and adds it to this level's downsampled image.
+
+layout(location = 0) in vec2 v_uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler linear_sampler;
+layout(set = 0, binding = 1) uniform texture2D current;
+layout(set = 0, binding = 2) uniform texture2D smaller;
+
+layout(push_constant) uniform Settings {
+    // Distance between the taps, in texels of the smaller level.
+    float radius;
+} settings;
+
+vec3 fetch(vec2 uv) {
+    return texture(sampler2D(smaller, linear_sampler), uv).rgb;
+}
+
+void main() {
+    vec2 offset = settings.radius / vec2(textureSize(sampler2D(smaller, linear_sampler), 0));
+
+    vec3 color = fetch(v_uv) * 4.0;
+    color += (fetch(v_uv + vec2(-offset.x, 0.0)) + fetch(v_uv + vec2(offset.x, 0.0))) * 2.0;
+    color += (fetch(v_uv + vec2(0.0, -offset.y)) + fetch(v_uv + vec2(0.0, offset.y))) * 2.0;
+    color += fetch(v_uv - offset) + fetch(v_uv + offset);
+    color += fetch(v_uv + vec2(-offset.x, offset.y)) + fetch(v_uv + vec2(offset.x, -offset.y));
+
+    f_color = vec4(texture(sampler2D(current, linear_sampler), v_uv).rgb + color / 16.0, 1.0);
+}
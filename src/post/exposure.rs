@@ -0,0 +1,258 @@
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use serde::Deserialize;
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::DescriptorSet;
+use vulkano::device::Device;
+use vulkano::pipeline::{ComputePipeline, ComputePipelineAbstract};
+use vulkano::render_pass::Subpass;
+use vulkano::sampler::Sampler;
+
+use crate::allocator::PooledBuffer;
+use crate::debug::ObjectNamer;
+use crate::post::PostSettings;
+use crate::render_graph::{BufferId, ImageId, Pass, PassBuilder, PassContext};
+
+mod histogram {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/post/histogram.comp"
+    }
+}
+
+mod average {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/post/exposure.comp"
+    }
+}
+
+pub const HISTOGRAM_BINS: usize = 256;
+// Workgroup size of `histogram.comp`.
+const TILE_SIZE: u32 = 16;
+
+/// How bright the HDR image is made before tone mapping.
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Exposure {
+    /// A fixed exposure value at ISO 100. Higher values darken the image: about 15 for a sunny day, 5 for a lit
+    /// interior.
+    Manual(f32),
+    /// Adapts to the average luminance of the image, like a camera's auto exposure.
+    Auto(AutoExposure),
+}
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(default)]
+pub struct AutoExposure {
+    /// Range of EV100 the exposure is kept within. Luminance outside of it isn't measured.
+    pub min_ev100: f32,
+    pub max_ev100: f32,
+    /// Stops added to the measured exposure, positive values brighten the image.
+    pub compensation: f32,
+    /// Fractions of the darkest and brightest pixels left out of the average.
+    pub low_percentile: f32,
+    pub high_percentile: f32,
+    /// How fast the exposure follows changes in luminance, per second.
+    pub speed: f32,
+}
+
+impl Default for AutoExposure {
+    fn default() -> AutoExposure {
+        AutoExposure {
+            min_ev100: -4.0,
+            max_ev100: 16.0,
+            compensation: 0.0,
+            low_percentile: 0.5,
+            high_percentile: 0.95,
+            speed: 1.5,
+        }
+    }
+}
+
+impl AutoExposure {
+    // Luminance a light meter reads at EV100 is 2^EV100 * 12.5 / 100.
+    fn min_log_luminance(&self) -> f32 {
+        self.min_ev100 - 3.0
+    }
+
+    fn log_luminance_range(&self) -> f32 {
+        (self.max_ev100 - self.min_ev100).max(1.0)
+    }
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct HistogramRange {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+struct ExposureSettings {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    low_percentile: f32,
+    high_percentile: f32,
+    adaptation: f32,
+    compensation: f32,
+    manual_exposure: f32,
+}
+
+/// Counts the pixels of the HDR image into a histogram of log luminance. Does nothing with manual exposure.
+pub struct HistogramPass {
+    hdr: ImageId,
+    histogram_id: BufferId,
+    histogram: Arc<PooledBuffer<[u32]>>,
+    sampler: Arc<Sampler>,
+    settings: Arc<Mutex<PostSettings>>,
+    namer: ObjectNamer,
+    pipeline: Option<Arc<dyn ComputePipelineAbstract + Send + Sync>>,
+    // Descriptor set and the graph generation its image is from.
+    set: Option<(u64, Arc<dyn DescriptorSet + Send + Sync>)>,
+}
+
+impl HistogramPass {
+    pub fn new(hdr: ImageId, histogram_id: BufferId, histogram: Arc<PooledBuffer<[u32]>>, sampler: Arc<Sampler>, settings: Arc<Mutex<PostSettings>>, namer: &ObjectNamer) -> HistogramPass {
+        HistogramPass {
+            hdr,
+            histogram_id,
+            histogram,
+            sampler,
+            settings,
+            namer: namer.clone(),
+            pipeline: None,
+            set: None,
+        }
+    }
+}
+
+impl<F> Pass<F> for HistogramPass {
+    fn declare(&mut self, pass: &mut PassBuilder) {
+        pass.sample(self.hdr).write(self.histogram_id);
+    }
+
+    fn prepare(&mut self, device: &Arc<Device>, _subpass: Option<Subpass>) {
+        let cs = histogram::Shader::load(device.clone()).unwrap();
+        let pipeline = Arc::new(ComputePipeline::new(device.clone(), &cs.main_entry_point(), &(), None).unwrap());
+        self.namer.name(&*pipeline, "luminance histogram pipeline");
+        self.pipeline = Some(pipeline);
+        self.set = None;
+    }
+
+    fn record(&mut self, context: &mut PassContext, _frame: &F) {
+        let range = match self.settings.lock().unwrap().tonemap.exposure {
+            Exposure::Auto(auto) => HistogramRange {
+                min_log_luminance: auto.min_log_luminance(),
+                log_luminance_range: auto.log_luminance_range(),
+            },
+            Exposure::Manual(_) => return,
+        };
+        let pipeline = self.pipeline.clone().unwrap();
+        let hdr = context.image(self.hdr);
+        let [width, height] = hdr.image().dimensions().width_height();
+
+        if self.set.as_ref().map(|(generation, _)| *generation) != Some(context.generation()) {
+            let layout = pipeline.descriptor_set_layout(0).unwrap().clone();
+            let set = Arc::new(PersistentDescriptorSet::start(layout).add_sampler(self.sampler.clone())
+                                                                     .unwrap()
+                                                                     .add_image(hdr)
+                                                                     .unwrap()
+                                                                     .add_buffer(self.histogram.clone())
+                                                                     .unwrap()
+                                                                     .build()
+                                                                     .unwrap());
+            self.set = Some((context.generation(), set));
+        }
+        let set = self.set.as_ref().unwrap().1.clone();
+
+        context.builder.fill_buffer(self.histogram.clone(), 0).unwrap();
+        context.builder
+               .dispatch([width.div_ceil(TILE_SIZE), height.div_ceil(TILE_SIZE), 1], pipeline, set, range, vec![])
+               .unwrap();
+    }
+}
+
+/// Turns the histogram into the exposure the tone mapper uses, adapting to it over time. With manual exposure it
+/// only writes the fixed exposure.
+pub struct ExposurePass {
+    histogram_id: BufferId,
+    exposure_id: BufferId,
+    histogram: Arc<PooledBuffer<[u32]>>,
+    exposure: Arc<PooledBuffer<[f32]>>,
+    settings: Arc<Mutex<PostSettings>>,
+    namer: ObjectNamer,
+    pipeline: Option<Arc<dyn ComputePipelineAbstract + Send + Sync>>,
+    set: Option<Arc<dyn DescriptorSet + Send + Sync>>,
+    // When the previous frame was recorded, `None` before the first one.
+    last_frame: Option<Instant>,
+}
+
+impl ExposurePass {
+    pub fn new(histogram_id: BufferId, exposure_id: BufferId, histogram: Arc<PooledBuffer<[u32]>>, exposure: Arc<PooledBuffer<[f32]>>, settings: Arc<Mutex<PostSettings>>, namer: &ObjectNamer) -> ExposurePass {
+        ExposurePass {
+            histogram_id,
+            exposure_id,
+            histogram,
+            exposure,
+            settings,
+            namer: namer.clone(),
+            pipeline: None,
+            set: None,
+            last_frame: None,
+        }
+    }
+}
+
+impl<F> Pass<F> for ExposurePass {
+    fn declare(&mut self, pass: &mut PassBuilder) {
+        pass.read(self.histogram_id).write(self.exposure_id);
+    }
+
+    fn prepare(&mut self, device: &Arc<Device>, _subpass: Option<Subpass>) {
+        let cs = average::Shader::load(device.clone()).unwrap();
+        let pipeline = Arc::new(ComputePipeline::new(device.clone(), &cs.main_entry_point(), &(), None).unwrap());
+        self.namer.name(&*pipeline, "exposure pipeline");
+        let pipeline: Arc<dyn ComputePipelineAbstract + Send + Sync> = pipeline;
+
+        let layout = pipeline.descriptor_set_layout(0).unwrap().clone();
+        self.set = Some(Arc::new(PersistentDescriptorSet::start(layout).add_buffer(self.histogram.clone())
+                                                                      .unwrap()
+                                                                      .add_buffer(self.exposure.clone())
+                                                                      .unwrap()
+                                                                      .build()
+                                                                      .unwrap()));
+        self.pipeline = Some(pipeline);
+    }
+
+    fn record(&mut self, context: &mut PassContext, _frame: &F) {
+        let now = Instant::now();
+        let exposure = self.settings.lock().unwrap().tonemap.exposure;
+        let settings = match exposure {
+            Exposure::Manual(ev100) => ExposureSettings { manual_exposure: 1.0 / (1.2 * 2f32.powf(ev100)), ..ExposureSettings::default() },
+            Exposure::Auto(auto) => {
+                let elapsed = self.last_frame.map_or(0.0, |last| (now - last).as_secs_f32());
+                ExposureSettings {
+                    min_log_luminance: auto.min_log_luminance(),
+                    log_luminance_range: auto.log_luminance_range(),
+                    low_percentile: auto.low_percentile,
+                    high_percentile: auto.high_percentile,
+                    adaptation: 1.0 - (-elapsed * auto.speed).exp(),
+                    compensation: auto.compensation,
+                    manual_exposure: 0.0,
+                }
+            }
+        };
+        // Zero luminance makes the first measurement the adapted one.
+        if self.last_frame.is_none() {
+            context.builder.fill_buffer(self.exposure.clone(), 0).unwrap();
+        }
+        self.last_frame = Some(now);
+
+        context.builder
+               .dispatch([1, 1, 1], self.pipeline.clone().unwrap(), self.set.clone().unwrap(), settings, vec![])
+               .unwrap();
+    }
+}
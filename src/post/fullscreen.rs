@@ -0,0 +1,158 @@
+use std::sync::{Arc, Mutex};
+
+use vulkano::buffer::BufferAccess;
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::descriptor_set::UnsafeDescriptorSetLayout;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::image::view::ImageViewAbstract;
+use vulkano::pipeline::vertex::{BufferlessDefinition, BufferlessVertices};
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::render_pass::Subpass;
+use vulkano::sampler::Sampler;
+
+use crate::debug::ObjectNamer;
+use crate::post::PostSettings;
+use crate::render_graph::{BufferId, ImageId, Load, Pass, PassBuilder, PassContext};
+
+pub mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/post/fullscreen.vert"
+    }
+}
+
+pub type FullscreenPipeline = GraphicsPipeline<BufferlessDefinition, Box<dyn PipelineLayoutAbstract + Send + Sync>>;
+
+/// Push constants of a fullscreen pass for the current settings, `None` to leave the output undefined this frame.
+pub type PushConstants<P> = Box<dyn Fn(&PostSettings) -> Option<P>>;
+
+/// A pass drawing one triangle over its output, with `vs` and a fragment shader reading from images.
+///
+/// Set 0 of the fragment shader has a sampler at binding 0, followed by the graph images in `inputs`, the
+/// `textures` and the `buffers`, in that order.
+pub struct FullscreenPass<P> {
+    name: &'static str,
+    inputs: Vec<ImageId>,
+    textures: Vec<Arc<dyn ImageViewAbstract + Send + Sync>>,
+    buffers: Vec<(BufferId, Arc<dyn BufferAccess + Send + Sync>)>,
+    output: ImageId,
+    sampler: Arc<Sampler>,
+    settings: Arc<Mutex<PostSettings>>,
+    push_constants: PushConstants<P>,
+    create_pipeline: fn(&Arc<Device>, Subpass) -> FullscreenPipeline,
+    namer: ObjectNamer,
+    pipeline: Option<Arc<FullscreenPipeline>>,
+    // Descriptor set and the graph generation its images are from.
+    set: Option<(u64, Arc<dyn DescriptorSet + Send + Sync>)>,
+}
+
+impl<P> FullscreenPass<P> {
+    pub fn new(name: &'static str,
+               output: ImageId,
+               sampler: Arc<Sampler>,
+               settings: Arc<Mutex<PostSettings>>,
+               create_pipeline: fn(&Arc<Device>, Subpass) -> FullscreenPipeline,
+               push_constants: PushConstants<P>,
+               namer: &ObjectNamer)
+               -> FullscreenPass<P> {
+        FullscreenPass {
+            name,
+            inputs: vec![],
+            textures: vec![],
+            buffers: vec![],
+            output,
+            sampler,
+            settings,
+            push_constants,
+            create_pipeline,
+            namer: namer.clone(),
+            pipeline: None,
+            set: None,
+        }
+    }
+
+    pub fn input(mut self, image: ImageId) -> FullscreenPass<P> {
+        self.inputs.push(image);
+        self
+    }
+
+    /// An image from outside of the graph, like a lookup table.
+    pub fn texture(mut self, texture: Arc<dyn ImageViewAbstract + Send + Sync>) -> FullscreenPass<P> {
+        self.textures.push(texture);
+        self
+    }
+
+    pub fn buffer(mut self, id: BufferId, buffer: Arc<dyn BufferAccess + Send + Sync>) -> FullscreenPass<P> {
+        self.buffers.push((id, buffer));
+        self
+    }
+}
+
+impl<F, P> Pass<F> for FullscreenPass<P> where P: Copy + Send + Sync + 'static
+{
+    fn declare(&mut self, pass: &mut PassBuilder) {
+        for &image in self.inputs.iter() {
+            pass.sample(image);
+        }
+        for &(id, _) in self.buffers.iter() {
+            pass.read(id);
+        }
+        pass.color(self.output, Load::DontCare);
+    }
+
+    fn prepare(&mut self, device: &Arc<Device>, subpass: Option<Subpass>) {
+        let subpass = subpass.unwrap_or_else(|| panic!("the {} pass renders to an attachment", self.name));
+        let pipeline = Arc::new((self.create_pipeline)(device, subpass));
+        self.namer.name(&*pipeline, &format!("{} pipeline", self.name));
+        self.pipeline = Some(pipeline);
+        self.set = None;
+    }
+
+    fn record(&mut self, context: &mut PassContext, _frame: &F) {
+        let push_constants = match (self.push_constants)(&self.settings.lock().unwrap()) {
+            Some(push_constants) => push_constants,
+            None => return,
+        };
+        let pipeline = self.pipeline.clone().unwrap();
+
+        if self.set.as_ref().map(|(generation, _)| *generation) != Some(context.generation()) {
+            let images: Vec<_> = self.inputs.iter().map(|&image| context.image(image)).chain(self.textures.iter().cloned()).collect();
+            let buffers: Vec<_> = self.buffers.iter().map(|(_, buffer)| buffer.clone()).collect();
+            let layout = pipeline.descriptor_set_layout(0).unwrap().clone();
+            self.set = Some((context.generation(), descriptor_set(layout, self.sampler.clone(), &images, &buffers)));
+        }
+        let set = self.set.as_ref().unwrap().1.clone();
+
+        context.builder
+               .draw(pipeline, context.dynamic_state, BufferlessVertices { vertices: 3, instances: 1 }, set, push_constants, vec![])
+               .unwrap();
+    }
+}
+
+// The builder's type grows with every binding, so every combination fullscreen passes use is spelled out.
+fn descriptor_set(layout: Arc<UnsafeDescriptorSetLayout>,
+                  sampler: Arc<Sampler>,
+                  images: &[Arc<dyn ImageViewAbstract + Send + Sync>],
+                  buffers: &[Arc<dyn BufferAccess + Send + Sync>])
+                  -> Arc<dyn DescriptorSet + Send + Sync> {
+    let set = PersistentDescriptorSet::start(layout).add_sampler(sampler).unwrap();
+    match (images, buffers) {
+        ([a], []) => Arc::new(set.add_image(a.clone()).unwrap().build().unwrap()),
+        ([a, b], []) => Arc::new(set.add_image(a.clone()).unwrap().add_image(b.clone()).unwrap().build().unwrap()),
+        ([a, b, c], []) => Arc::new(set.add_image(a.clone()).unwrap().add_image(b.clone()).unwrap().add_image(c.clone()).unwrap().build().unwrap()),
+        ([a], [x]) => Arc::new(set.add_image(a.clone()).unwrap().add_buffer(x.clone()).unwrap().build().unwrap()),
+        ([a, b], [x]) => Arc::new(set.add_image(a.clone()).unwrap().add_image(b.clone()).unwrap().add_buffer(x.clone()).unwrap().build().unwrap()),
+        ([a, b, c], [x]) => Arc::new(set.add_image(a.clone())
+                                         .unwrap()
+                                         .add_image(b.clone())
+                                         .unwrap()
+                                         .add_image(c.clone())
+                                         .unwrap()
+                                         .add_buffer(x.clone())
+                                         .unwrap()
+                                         .build()
+                                         .unwrap()),
+        _ => panic!("fullscreen passes take one to three images and up to one buffer"),
+    }
+}
@@ -0,0 +1,53 @@
+#version 450
+
+// FXAA in the style of Timothy Lottes' console version: blurs along the local edge direction, and falls back to a
+// shorter blur where the long one would pick up colors from across the edge.
+
+layout(location = 0) in vec2 v_uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler linear_sampler;
+layout(set = 0, binding = 1) uniform texture2D source;
+
+layout(push_constant) uniform Settings {
+    // Longest blur, in pixels.
+    float span_max;
+    // Part of the average luma the edge direction is shortened by.
+    float reduce_mul;
+    // Shortest the edge direction is shortened by.
+    float reduce_min;
+} settings;
+
+vec3 fetch(vec2 uv) {
+    return texture(sampler2D(source, linear_sampler), uv).rgb;
+}
+
+// Perceptual luma, the source is linear.
+float luma(vec3 color) {
+    return sqrt(dot(color, vec3(0.299, 0.587, 0.114)));
+}
+
+void main() {
+    vec2 texel = 1.0 / vec2(textureSize(sampler2D(source, linear_sampler), 0));
+
+    vec3 center = fetch(v_uv);
+    float luma_nw = luma(fetch(v_uv + vec2(-1.0, -1.0) * texel));
+    float luma_ne = luma(fetch(v_uv + vec2(1.0, -1.0) * texel));
+    float luma_sw = luma(fetch(v_uv + vec2(-1.0, 1.0) * texel));
+    float luma_se = luma(fetch(v_uv + vec2(1.0, 1.0) * texel));
+    float luma_m = luma(center);
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    vec2 direction = vec2(-((luma_nw + luma_ne) - (luma_sw + luma_se)), (luma_nw + luma_sw) - (luma_ne + luma_se));
+    float reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * settings.reduce_mul, settings.reduce_min);
+    float scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, vec2(-settings.span_max), vec2(settings.span_max)) * texel;
+
+    vec3 short_blur = 0.5 * (fetch(v_uv + direction * (1.0 / 3.0 - 0.5)) + fetch(v_uv + direction * (2.0 / 3.0 - 0.5)));
+    vec3 long_blur = short_blur * 0.5 + 0.25 * (fetch(v_uv - direction * 0.5) + fetch(v_uv + direction * 0.5));
+
+    float luma_long = luma(long_blur);
+    f_color = vec4(luma_long < luma_min || luma_long > luma_max ? short_blur : long_blur, 1.0);
+}
@@ -0,0 +1,78 @@
+use std::sync::{Arc, Mutex};
+
+use serde::Deserialize;
+use vulkano::device::Device;
+use vulkano::pipeline::vertex::BufferlessDefinition;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::render_pass::Subpass;
+use vulkano::sampler::Sampler;
+
+use crate::debug::ObjectNamer;
+use crate::post::fullscreen::{vs, FullscreenPass, FullscreenPipeline};
+use crate::post::PostSettings;
+use crate::render_graph::ImageId;
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/post/fxaa.frag"
+    }
+}
+
+/// Fast approximate anti-aliasing, blurring along the edges it finds in the tone mapped image.
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(default)]
+pub struct FxaaSettings {
+    pub enabled: bool,
+    /// Longest blur along an edge, in pixels.
+    pub span_max: f32,
+    /// Shorten the blur on bright edges by this part of their luma...
+    pub reduce_mul: f32,
+    /// ...but at least by this much.
+    pub reduce_min: f32,
+}
+
+impl Default for FxaaSettings {
+    fn default() -> FxaaSettings {
+        FxaaSettings {
+            enabled: true,
+            span_max: 8.0,
+            reduce_mul: 1.0 / 8.0,
+            reduce_min: 1.0 / 128.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct FxaaPush {
+    span_max: f32,
+    reduce_mul: f32,
+    reduce_min: f32,
+}
+
+pub fn pass(source: ImageId, output: ImageId, sampler: &Arc<Sampler>, settings: &Arc<Mutex<PostSettings>>, namer: &ObjectNamer) -> FullscreenPass<FxaaPush> {
+    let push_constants = |settings: &PostSettings| {
+        let fxaa = &settings.fxaa;
+        Some(FxaaPush {
+            // Without a span every tap lands on the pixel itself.
+            span_max: if fxaa.enabled { fxaa.span_max } else { 0.0 },
+            reduce_mul: fxaa.reduce_mul,
+            reduce_min: fxaa.reduce_min.max(1e-5),
+        })
+    };
+    FullscreenPass::new("fxaa", output, sampler.clone(), settings.clone(), pipeline, Box::new(push_constants), namer).input(source)
+}
+
+fn pipeline(device: &Arc<Device>, subpass: Subpass) -> FullscreenPipeline {
+    let vs = vs::Shader::load(device.clone()).unwrap();
+    let fs = fs::Shader::load(device.clone()).unwrap();
+    GraphicsPipeline::start().vertex_input(BufferlessDefinition)
+                             .vertex_shader(vs.main_entry_point(), ())
+                             .triangle_list()
+                             .viewports_dynamic_scissors_irrelevant(1)
+                             .fragment_shader(fs.main_entry_point(), ())
+                             .render_pass(subpass)
+                             .build(device.clone())
+                             .unwrap()
+}
@@ -0,0 +1,107 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use half::f16;
+use serde::Deserialize;
+use tracing::{info, warn};
+use vulkano::format::Format;
+use vulkano::image::view::{ImageView, ImageViewAbstract};
+use vulkano::image::ImageDimensions;
+
+use crate::debug::ObjectNamer;
+use crate::logging;
+use crate::upload::UploadManager;
+
+// Entries along each axis of the table used without a file.
+const IDENTITY_SIZE: u32 = 16;
+
+/// Color grading with a 3D lookup table, applied to tone mapped colors.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ColorGradingSettings {
+    pub enabled: bool,
+    /// A `.cube` file with a 3D table over sRGB encoded colors, as exported by Resolve or Photoshop. Without one
+    /// there is no grading.
+    pub lut: Option<PathBuf>,
+    /// How much of the graded color replaces the original, from 0 to 1.
+    pub contribution: f32,
+}
+
+impl Default for ColorGradingSettings {
+    fn default() -> ColorGradingSettings {
+        ColorGradingSettings {
+            enabled: true,
+            lut: None,
+            contribution: 1.0,
+        }
+    }
+}
+
+/// Loads the `.cube` file at `path`, or logs why it can't.
+pub fn load_lut(uploads: &mut UploadManager, namer: &ObjectNamer, path: &Path) -> Option<Arc<dyn ImageViewAbstract + Send + Sync>> {
+    let parsed = fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|text| parse_cube(&text));
+    match parsed {
+        Ok((size, entries)) => {
+            info!(target: logging::ASSETS, size, "loaded color grading table {}", path.display());
+            Some(upload(uploads, namer, &format!("color grading table {}", path.display()), size, &entries))
+        }
+        Err(e) => {
+            warn!(target: logging::ASSETS, "failed to load color grading table {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// A table that maps every color to itself.
+pub fn identity_lut(uploads: &mut UploadManager, namer: &ObjectNamer) -> Arc<dyn ImageViewAbstract + Send + Sync> {
+    let max = (IDENTITY_SIZE - 1) as f32;
+    let mut entries = Vec::with_capacity((IDENTITY_SIZE * IDENTITY_SIZE * IDENTITY_SIZE) as usize);
+    for b in 0..IDENTITY_SIZE {
+        for g in 0..IDENTITY_SIZE {
+            for r in 0..IDENTITY_SIZE {
+                entries.push([r as f32 / max, g as f32 / max, b as f32 / max]);
+            }
+        }
+    }
+    upload(uploads, namer, "identity color grading table", IDENTITY_SIZE, &entries)
+}
+
+/// Uploads a table of `size` cubed entries as half floats, so it keeps the precision of the file.
+fn upload(uploads: &mut UploadManager, namer: &ObjectNamer, name: &str, size: u32, entries: &[[f32; 3]]) -> Arc<dyn ImageViewAbstract + Send + Sync> {
+    let half = |value: f32| f16::from_f32(value).to_bits();
+    let entries: Vec<[u16; 4]> = entries.iter().map(|&[r, g, b]| [half(r), half(g), half(b), half(1.0)]).collect();
+    let dimensions = ImageDimensions::Dim3d { width: size, height: size, depth: size };
+    let image = uploads.image(&entries, dimensions, Format::R16G16B16A16Sfloat).expect("failed to create color grading table");
+    namer.name_image(&*image, name);
+    ImageView::new(image).unwrap()
+}
+
+/// Parses a 3D table in the `.cube` format: a `LUT_3D_SIZE` line and that many cubed lines of red, green and blue,
+/// red changing fastest. Only the default domain of 0 to 1 is supported.
+fn parse_cube(text: &str) -> Result<(u32, Vec<[f32; 3]>), String> {
+    let mut size = None;
+    let mut entries = vec![];
+
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let mut words = line.split_whitespace();
+        match words.next().unwrap() {
+            "LUT_3D_SIZE" => size = Some(words.next().and_then(|word| word.parse::<u32>().ok()).ok_or_else(|| format!("invalid size in {:?}", line))?),
+            "LUT_1D_SIZE" => return Err("1D tables aren't supported".to_string()),
+            "TITLE" | "DOMAIN_MIN" | "DOMAIN_MAX" => {}
+            _ => {
+                let values = line.split_whitespace().map(str::parse::<f32>).collect::<Result<Vec<_>, _>>().map_err(|_| format!("invalid entry {:?}", line))?;
+                if values.len() != 3 {
+                    return Err(format!("invalid entry {:?}", line));
+                }
+                entries.push([values[0], values[1], values[2]]);
+            }
+        }
+    }
+
+    let size = size.ok_or_else(|| "missing LUT_3D_SIZE".to_string())?;
+    if size < 2 || entries.len() != (size * size * size) as usize {
+        return Err(format!("expected {} entries for size {}, found {}", size * size * size, size, entries.len()));
+    }
+    Ok((size, entries))
+}
@@ -0,0 +1,4 @@
+// Binning of the luminance histogram, shared by `histogram.comp`, which fills it, and `exposure.comp`, which
+// averages it. Bin 0 holds the pixels darker than the range, bin `i` above it the positions within the range from
+// `(i - 1) / BIN_SCALE` up to `i / BIN_SCALE`.
+const float BIN_SCALE = 254.0;
@@ -0,0 +1,51 @@
+#version 450
+
+// Chromatic aberration and vignetting, the last pass before presenting.
+
+layout(location = 0) in vec2 v_uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler linear_sampler;
+layout(set = 0, binding = 1) uniform texture2D source;
+
+layout(push_constant) uniform Settings {
+    // How far red and blue are moved apart at the corners, in pixels.
+    float aberration;
+    // How much the corners are darkened, from 0 to 1.
+    float vignette_intensity;
+    // Part of the distance from the center to the corners the darkening fades in over.
+    float vignette_smoothness;
+    // Whether the target stores linear values, so the shader has to apply the sRGB transfer function itself.
+    uint encode_srgb;
+} settings;
+
+vec3 encode_srgb(vec3 color) {
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+void main() {
+    vec2 size = vec2(textureSize(sampler2D(source, linear_sampler), 0));
+    vec2 from_center = v_uv - 0.5;
+
+    vec3 color;
+    if (settings.aberration > 0.0) {
+        vec2 offset = from_center * 2.0 * settings.aberration / size;
+        color.r = texture(sampler2D(source, linear_sampler), v_uv + offset).r;
+        color.g = texture(sampler2D(source, linear_sampler), v_uv).g;
+        color.b = texture(sampler2D(source, linear_sampler), v_uv - offset).b;
+    } else {
+        color = texture(sampler2D(source, linear_sampler), v_uv).rgb;
+    }
+
+    // Distance from the center in pixels relative to the corners, so the vignette stays round.
+    float distance = length(from_center * size) / length(0.5 * size);
+    color *= 1.0 - settings.vignette_intensity * smoothstep(1.0 - settings.vignette_smoothness, 1.0, distance);
+
+    if (settings.encode_srgb != 0) {
+        color = encode_srgb(clamp(color, 0.0, 1.0));
+    }
+    f_color = vec4(color, 1.0);
+}
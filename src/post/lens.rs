@@ -0,0 +1,93 @@
+use std::sync::{Arc, Mutex};
+
+use serde::Deserialize;
+use vulkano::device::Device;
+use vulkano::pipeline::vertex::BufferlessDefinition;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::render_pass::Subpass;
+use vulkano::sampler::Sampler;
+
+use crate::debug::ObjectNamer;
+use crate::post::fullscreen::{vs, FullscreenPass, FullscreenPipeline};
+use crate::post::PostSettings;
+use crate::render_graph::ImageId;
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/post/lens.frag"
+    }
+}
+
+/// Red and blue drifting apart towards the edges of the image, like through a cheap lens.
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(default)]
+pub struct ChromaticAberrationSettings {
+    pub enabled: bool,
+    /// How far red and blue are moved apart at the corners, in pixels.
+    pub intensity: f32,
+}
+
+impl Default for ChromaticAberrationSettings {
+    fn default() -> ChromaticAberrationSettings {
+        ChromaticAberrationSettings { enabled: false, intensity: 3.0 }
+    }
+}
+
+/// Darkened corners.
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(default)]
+pub struct VignetteSettings {
+    pub enabled: bool,
+    /// How much the corners are darkened, from 0 to 1.
+    pub intensity: f32,
+    /// Part of the way from the center to the corners over which the darkening fades in.
+    pub smoothness: f32,
+}
+
+impl Default for VignetteSettings {
+    fn default() -> VignetteSettings {
+        VignetteSettings {
+            enabled: false,
+            intensity: 0.3,
+            smoothness: 0.6,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct LensPush {
+    aberration: f32,
+    vignette_intensity: f32,
+    vignette_smoothness: f32,
+    encode_srgb: u32,
+}
+
+/// Applies chromatic aberration and the vignette while writing to `output`, which stores linear values unless
+/// `output_srgb`.
+pub fn pass(source: ImageId, output: ImageId, output_srgb: bool, sampler: &Arc<Sampler>, settings: &Arc<Mutex<PostSettings>>, namer: &ObjectNamer) -> FullscreenPass<LensPush> {
+    let push_constants = move |settings: &PostSettings| {
+        let (aberration, vignette) = (&settings.chromatic_aberration, &settings.vignette);
+        Some(LensPush {
+            aberration: if aberration.enabled { aberration.intensity } else { 0.0 },
+            vignette_intensity: if vignette.enabled { vignette.intensity } else { 0.0 },
+            vignette_smoothness: vignette.smoothness.max(1e-3),
+            encode_srgb: !output_srgb as u32,
+        })
+    };
+    FullscreenPass::new("lens", output, sampler.clone(), settings.clone(), pipeline, Box::new(push_constants), namer).input(source)
+}
+
+fn pipeline(device: &Arc<Device>, subpass: Subpass) -> FullscreenPipeline {
+    let vs = vs::Shader::load(device.clone()).unwrap();
+    let fs = fs::Shader::load(device.clone()).unwrap();
+    GraphicsPipeline::start().vertex_input(BufferlessDefinition)
+                             .vertex_shader(vs.main_entry_point(), ())
+                             .triangle_list()
+                             .viewports_dynamic_scissors_irrelevant(1)
+                             .fragment_shader(fs.main_entry_point(), ())
+                             .render_pass(subpass)
+                             .build(device.clone())
+                             .unwrap()
+}
@@ -0,0 +1,168 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use serde::Deserialize;
+use tracing::{debug, warn};
+use vulkano::buffer::BufferUsage;
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+pub use crate::post::bloom::BloomSettings;
+pub use crate::post::exposure::{AutoExposure, Exposure};
+pub use crate::post::fxaa::FxaaSettings;
+pub use crate::post::grading::ColorGradingSettings;
+pub use crate::post::lens::{ChromaticAberrationSettings, VignetteSettings};
+pub use crate::post::tonemap::{TonemapSettings, Tonemapper};
+
+use crate::allocator::{GpuAllocator, MemoryCategory, PooledBuffer};
+use crate::debug::ObjectNamer;
+use crate::logging;
+use crate::post::exposure::{ExposurePass, HistogramPass, HISTOGRAM_BINS};
+use crate::render_graph::{ImageDesc, ImageId, ImageSize, RenderGraphBuilder};
+use crate::upload::UploadManager;
+
+mod bloom;
+mod exposure;
+mod fullscreen;
+mod fxaa;
+mod grading;
+mod lens;
+mod tonemap;
+
+/// Settings file read when `TONIC_POST_SETTINGS` isn't set.
+pub const DEFAULT_SETTINGS_PATH: &str = "post.json";
+
+// Tone mapped images between passes. The hardware does the sRGB encoding, shaders work with linear values.
+const LDR_FORMAT: Format = Format::R8G8B8A8Srgb;
+
+/// Everything the post-processing passes do, shared with them through a mutex so it can change while running.
+///
+/// Loaded from the JSON file at `TONIC_POST_SETTINGS` (default `post.json`), where every field is optional.
+/// `TONIC_TONEMAP=aces|reinhard|agx` and `TONIC_EXPOSURE=auto|<EV100>` override the file at startup. The number of
+/// bloom levels and the color grading table are only read when the passes are built.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PostSettings {
+    pub tonemap: TonemapSettings,
+    pub bloom: BloomSettings,
+    pub fxaa: FxaaSettings,
+    pub chromatic_aberration: ChromaticAberrationSettings,
+    pub vignette: VignetteSettings,
+    pub color_grading: ColorGradingSettings,
+    /// The file the settings came from, see `reload`.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+impl PostSettings {
+    pub fn from_env() -> PostSettings {
+        let path = env::var_os("TONIC_POST_SETTINGS").map_or_else(|| PathBuf::from(DEFAULT_SETTINGS_PATH), PathBuf::from);
+        let mut settings = PostSettings { path: Some(path), ..PostSettings::default() };
+        settings.reload();
+
+        match env::var("TONIC_TONEMAP").as_ref().map(|s| s.to_ascii_lowercase()) {
+            Ok(ref s) if s == "aces" => settings.tonemap.tonemapper = Tonemapper::Aces,
+            Ok(ref s) if s == "reinhard" => settings.tonemap.tonemapper = Tonemapper::Reinhard,
+            Ok(ref s) if s == "agx" => settings.tonemap.tonemapper = Tonemapper::Agx,
+            _ => {}
+        }
+
+        match env::var("TONIC_EXPOSURE").as_ref().map(|s| s.to_ascii_lowercase()) {
+            Ok(ref s) if s == "auto" => settings.tonemap.exposure = Exposure::Auto(AutoExposure::default()),
+            Ok(s) => {
+                if let Ok(ev100) = s.parse() {
+                    settings.tonemap.exposure = Exposure::Manual(ev100);
+                }
+            }
+            Err(_) => {}
+        }
+
+        settings
+    }
+
+    /// Reads the settings file again. Keeps the current settings when it's missing or invalid.
+    pub fn reload(&mut self) {
+        let path = match &self.path {
+            Some(path) => path.clone(),
+            None => return,
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(text) => match serde_json::from_str::<PostSettings>(&text) {
+                Ok(settings) => *self = PostSettings { path: Some(path), ..settings },
+                Err(e) => warn!(target: logging::RENDER, "invalid post-processing settings in {}: {}", path.display(), e),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => debug!(target: logging::RENDER, "no post-processing settings at {}, using defaults", path.display()),
+            Err(e) => warn!(target: logging::RENDER, "failed to read post-processing settings from {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Adds the passes that turn the linear HDR image `hdr` into display colors in `output`: bloom, a luminance
+/// histogram and the exposure computed from it, tone mapping with color grading, FXAA, and finally chromatic
+/// aberration and vignetting. Effects that are turned off pass their input through.
+///
+/// Returns the settings the passes read every frame.
+#[allow(clippy::too_many_arguments)]
+pub fn add_passes<F: 'static>(graph: &mut RenderGraphBuilder<F>,
+                              hdr: ImageId,
+                              output: ImageId,
+                              output_format: Format,
+                              settings: PostSettings,
+                              device: &Arc<Device>,
+                              allocator: &GpuAllocator,
+                              uploads: &mut UploadManager,
+                              namer: &ObjectNamer)
+                              -> Arc<Mutex<PostSettings>> {
+    let lut = settings.color_grading.lut.as_deref().and_then(|path| grading::load_lut(uploads, namer, path));
+    let has_lut = lut.is_some();
+    // Without a table the shader still needs one bound, grading is skipped anyway.
+    let lut = lut.unwrap_or_else(|| grading::identity_lut(uploads, namer));
+    let bloom_levels = settings.bloom.levels.clamp(1, bloom::MAX_LEVELS);
+    let settings = Arc::new(Mutex::new(settings));
+
+    let usage = BufferUsage { storage_buffer: true, transfer_destination: true, ..BufferUsage::none() };
+    let histogram = PooledBuffer::<[u32]>::array(allocator, MemoryCategory::Other, HISTOGRAM_BINS, usage, None).expect("failed to create luminance histogram");
+    // The exposure the tone mapper multiplies with, and the adapted luminance it was computed from.
+    let exposure = PooledBuffer::<[f32]>::array(allocator, MemoryCategory::Other, 2, usage, None).expect("failed to create exposure buffer");
+    namer.name_buffer(&*histogram, "luminance histogram");
+    namer.name_buffer(&*exposure, "exposure");
+
+    let sampler = Sampler::new(device.clone(),
+                               Filter::Linear,
+                               Filter::Linear,
+                               MipmapMode::Nearest,
+                               SamplerAddressMode::ClampToEdge,
+                               SamplerAddressMode::ClampToEdge,
+                               SamplerAddressMode::ClampToEdge,
+                               0.0,
+                               1.0,
+                               0.0,
+                               0.0).unwrap();
+
+    let bloom = bloom::add_passes(graph, hdr, bloom_levels, &sampler, &settings, namer);
+
+    let histogram_id = graph.buffer("luminance histogram");
+    let exposure_id = graph.buffer("exposure");
+    graph.add_pass("luminance histogram", HistogramPass::new(hdr, histogram_id, histogram.clone(), sampler.clone(), settings.clone(), namer));
+    graph.add_pass("exposure", ExposurePass::new(histogram_id, exposure_id, histogram, exposure.clone(), settings.clone(), namer));
+
+    let ldr = graph.image("tone mapped color", ImageDesc::new(LDR_FORMAT, ImageSize::Backbuffer));
+    graph.add_pass("tonemap", tonemap::pass(hdr, bloom, bloom_levels, lut, has_lut, exposure_id, exposure, ldr, &sampler, &settings, namer));
+
+    let antialiased = graph.image("antialiased color", ImageDesc::new(LDR_FORMAT, ImageSize::Backbuffer));
+    graph.add_pass("fxaa", fxaa::pass(ldr, antialiased, &sampler, &settings, namer));
+
+    graph.add_pass("lens", lens::pass(antialiased, output, is_srgb(output_format), &sampler, &settings, namer));
+
+    settings
+}
+
+/// Whether the hardware applies the sRGB transfer function when writing to `format`.
+fn is_srgb(format: Format) -> bool {
+    matches!(format, Format::B8G8R8A8Srgb | Format::R8G8B8A8Srgb | Format::A8B8G8R8SrgbPack32)
+}
@@ -0,0 +1,107 @@
+#version 450
+
+layout(location = 0) in vec2 v_uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler linear_sampler;
+layout(set = 0, binding = 1) uniform texture2D hdr;
+layout(set = 0, binding = 2) uniform texture2D bloom;
+// Color grading lookup table, indexed and filled with sRGB encoded colors.
+layout(set = 0, binding = 3) uniform texture3D lut;
+
+layout(set = 0, binding = 4) readonly buffer Exposure {
+    float exposure;
+    float luminance;
+} state;
+
+layout(push_constant) uniform Settings {
+    uint tonemapper;
+    // The image is blended with the bloom as hdr * hdr_weight + bloom * bloom_weight.
+    float hdr_weight;
+    float bloom_weight;
+    // How much of the graded color replaces the original, zero to skip grading.
+    float lut_contribution;
+} settings;
+
+const uint ACES = 0;
+const uint REINHARD = 1;
+const uint AGX = 2;
+
+// Stephen Hill's fit of the ACES reference rendering and output device transforms.
+vec3 aces(vec3 color) {
+    const mat3 input_matrix = mat3(0.59719, 0.07600, 0.02840, 0.35458, 0.90834, 0.13383, 0.04823, 0.01566, 0.83777);
+    const mat3 output_matrix = mat3(1.60475, -0.10208, -0.00327, -0.53108, 1.10813, -0.07276, -0.07367, -0.00605, 1.07602);
+
+    color = input_matrix * color;
+    vec3 a = color * (color + 0.0245786) - 0.000090537;
+    vec3 b = color * (0.983729 * color + 0.4329510) + 0.238081;
+    return clamp(output_matrix * (a / b), 0.0, 1.0);
+}
+
+vec3 reinhard(vec3 color) {
+    return color / (1.0 + color);
+}
+
+// Troy Sobotka's AgX with the default look, using Benjamin Wrensch's polynomial fit of the contrast curve.
+vec3 agx(vec3 color) {
+    const mat3 inset = mat3(0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+                            0.0784335999999992, 0.878468636469772, 0.0784336,
+                            0.0792237451477643, 0.0791661274605434, 0.879142973793104);
+    const mat3 outset = mat3(1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+                             -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+                             -0.0990297440797205, -0.0989611768448433, 1.15107367264116);
+    const float min_ev = -12.47393;
+    const float max_ev = 4.026069;
+
+    color = inset * color;
+    color = clamp(log2(max(color, vec3(1e-10))), min_ev, max_ev);
+    color = (color - min_ev) / (max_ev - min_ev);
+
+    vec3 x2 = color * color;
+    vec3 x4 = x2 * x2;
+    color = 15.5 * x4 * x2 - 40.14 * x4 * color + 31.96 * x4 - 6.868 * x2 * color + 0.4298 * x2 + 0.1191 * color - 0.00232;
+
+    // Back to linear, the curve's output is meant for a 2.2 gamma display.
+    return pow(max(outset * color, vec3(0.0)), vec3(2.2));
+}
+
+vec3 encode_srgb(vec3 color) {
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+vec3 decode_srgb(vec3 color) {
+    vec3 low = color / 12.92;
+    vec3 high = pow((color + 0.055) / 1.055, vec3(2.4));
+    return mix(high, low, lessThanEqual(color, vec3(0.04045)));
+}
+
+vec3 grade(vec3 color) {
+    float size = float(textureSize(sampler3D(lut, linear_sampler), 0).x);
+    // Sample texel centers, so the ends of the range hit the first and last entries exactly.
+    vec3 uv = encode_srgb(clamp(color, 0.0, 1.0)) * ((size - 1.0) / size) + 0.5 / size;
+    return decode_srgb(texture(sampler3D(lut, linear_sampler), uv).rgb);
+}
+
+void main() {
+    vec3 color = texture(sampler2D(hdr, linear_sampler), v_uv).rgb * settings.hdr_weight;
+    if (settings.bloom_weight > 0.0) {
+        color += texture(sampler2D(bloom, linear_sampler), v_uv).rgb * settings.bloom_weight;
+    }
+    color *= state.exposure;
+
+    if (settings.tonemapper == ACES) {
+        color = aces(color);
+    } else if (settings.tonemapper == REINHARD) {
+        color = reinhard(color);
+    } else {
+        color = agx(color);
+    }
+
+    if (settings.lut_contribution > 0.0) {
+        color = mix(color, grade(color), settings.lut_contribution);
+    }
+    f_color = vec4(color, 1.0);
+}
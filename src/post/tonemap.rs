@@ -0,0 +1,125 @@
+use std::sync::{Arc, Mutex};
+
+use serde::Deserialize;
+use vulkano::buffer::BufferAccess;
+use vulkano::device::Device;
+use vulkano::image::view::ImageViewAbstract;
+use vulkano::pipeline::vertex::BufferlessDefinition;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::render_pass::Subpass;
+use vulkano::sampler::Sampler;
+
+use crate::debug::ObjectNamer;
+use crate::post::exposure::{AutoExposure, Exposure};
+use crate::post::fullscreen::{vs, FullscreenPass, FullscreenPipeline};
+use crate::post::PostSettings;
+use crate::render_graph::{BufferId, ImageId};
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/post/tonemap.frag"
+    }
+}
+
+/// How the HDR image is exposed and mapped to the display.
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(default)]
+pub struct TonemapSettings {
+    pub tonemapper: Tonemapper,
+    pub exposure: Exposure,
+}
+
+impl Default for TonemapSettings {
+    fn default() -> TonemapSettings {
+        TonemapSettings {
+            tonemapper: Tonemapper::Aces,
+            exposure: Exposure::Auto(AutoExposure::default()),
+        }
+    }
+}
+
+/// Curve compressing HDR colors into the displayable range.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tonemapper {
+    /// Filmic, with saturated highlights. Stephen Hill's fit of the ACES reference transforms.
+    Aces,
+    /// `c / (1 + c)` per channel. Keeps midtones but looks flat.
+    Reinhard,
+    /// Desaturates highlights towards white instead of skewing their hue.
+    Agx,
+}
+
+impl Tonemapper {
+    pub fn next(self) -> Tonemapper {
+        match self {
+            Tonemapper::Aces => Tonemapper::Reinhard,
+            Tonemapper::Reinhard => Tonemapper::Agx,
+            Tonemapper::Agx => Tonemapper::Aces,
+        }
+    }
+
+    // Values of `tonemap.frag`'s `settings.tonemapper`.
+    fn index(self) -> u32 {
+        match self {
+            Tonemapper::Aces => 0,
+            Tonemapper::Reinhard => 1,
+            Tonemapper::Agx => 2,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct TonemapPush {
+    tonemapper: u32,
+    hdr_weight: f32,
+    bloom_weight: f32,
+    lut_contribution: f32,
+}
+
+/// Blends in the bloom, exposes and tone maps the HDR image, and grades it with `lut` unless `has_lut` is false.
+#[allow(clippy::too_many_arguments)]
+pub fn pass(hdr: ImageId,
+            bloom: ImageId,
+            bloom_levels: u32,
+            lut: Arc<dyn ImageViewAbstract + Send + Sync>,
+            has_lut: bool,
+            exposure_id: BufferId,
+            exposure: Arc<dyn BufferAccess + Send + Sync>,
+            output: ImageId,
+            sampler: &Arc<Sampler>,
+            settings: &Arc<Mutex<PostSettings>>,
+            namer: &ObjectNamer)
+            -> FullscreenPass<TonemapPush> {
+    let push_constants = move |settings: &PostSettings| {
+        let bloom = if settings.bloom.enabled { settings.bloom.intensity } else { 0.0 };
+        let grading = &settings.color_grading;
+        Some(TonemapPush {
+            tonemapper: settings.tonemap.tonemapper.index(),
+            hdr_weight: 1.0 - bloom,
+            // Every level adds about the brightness of the image, so the sum is averaged.
+            bloom_weight: bloom / bloom_levels as f32,
+            lut_contribution: if grading.enabled && has_lut { grading.contribution } else { 0.0 },
+        })
+    };
+
+    FullscreenPass::new("tonemap", output, sampler.clone(), settings.clone(), pipeline, Box::new(push_constants), namer).input(hdr)
+                                                                                                                         .input(bloom)
+                                                                                                                         .texture(lut)
+                                                                                                                         .buffer(exposure_id, exposure)
+}
+
+fn pipeline(device: &Arc<Device>, subpass: Subpass) -> FullscreenPipeline {
+    let vs = vs::Shader::load(device.clone()).unwrap();
+    let fs = fs::Shader::load(device.clone()).unwrap();
+    GraphicsPipeline::start().vertex_input(BufferlessDefinition)
+                             .vertex_shader(vs.main_entry_point(), ())
+                             .triangle_list()
+                             .viewports_dynamic_scissors_irrelevant(1)
+                             .fragment_shader(fs.main_entry_point(), ())
+                             .render_pass(subpass)
+                             .build(device.clone())
+                             .unwrap()
+}
@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use tracing::info;
+use vulkano::device::{Queue, QueuesIter};
+use vulkano::instance::{PhysicalDevice, QueueFamily};
+use vulkano::swapchain::Surface;
+use vulkano::sync::GpuFuture;
+
+use crate::logging;
+
+/// The queue families the device gets created with.
+///
+/// Async compute wants a family with compute but no graphics, and transfers want a family with neither, since those
+/// are the ones that actually run alongside the graphics queue. When the hardware has no such family the work
+/// goes to the graphics queue instead.
+pub struct QueueFamilies<'a> {
+    graphics: QueueFamily<'a>,
+    compute: Option<QueueFamily<'a>>,
+    transfer: Option<QueueFamily<'a>>,
+}
+
+impl<'a> QueueFamilies<'a> {
+    pub fn select<W>(physical: PhysicalDevice<'a>, surface: &Surface<W>) -> QueueFamilies<'a> {
+        let graphics = physical.queue_families()
+                               .find(|&q| q.supports_graphics() && surface.is_supported(q).unwrap_or(false))
+                               .expect("couldn't find a graphical queue family");
+
+        let compute = physical.queue_families().find(|&q| q.supports_compute() && !q.supports_graphics());
+
+        let transfer = physical.queue_families()
+                               .find(|&q| q.explicitly_supports_transfers() && !q.supports_compute() && !q.supports_graphics());
+
+        info!(target: logging::DEVICE,
+              graphics = graphics.id(),
+              compute = ?compute.map(|q| q.id()),
+              transfer = ?transfer.map(|q| q.id()),
+              "selected queue families");
+
+        QueueFamilies { graphics, compute, transfer }
+    }
+
+    /// What to pass to `Device::new`, one queue per distinct family, in the order `queues` expects them back.
+    pub fn requests(&self) -> Vec<(QueueFamily<'a>, f32)> {
+        let mut requests = vec![(self.graphics, 1.0)];
+        if let Some(compute) = self.compute {
+            requests.push((compute, 0.5));
+        }
+        if let Some(transfer) = self.transfer {
+            requests.push((transfer, 0.5));
+        }
+        requests
+    }
+
+    pub fn queues(&self, mut queues: QueuesIter) -> Queues {
+        let graphics = queues.next().unwrap();
+        let compute = self.compute.map(|_| queues.next().unwrap()).unwrap_or_else(|| graphics.clone());
+        let transfer = self.transfer.map(|_| queues.next().unwrap()).unwrap_or_else(|| graphics.clone());
+
+        Queues { graphics, compute, transfer }
+    }
+}
+
+/// The engine's queues. `compute` and `transfer` are the graphics queue itself when there is no dedicated family.
+#[derive(Clone)]
+pub struct Queues {
+    pub graphics: Arc<Queue>,
+    pub compute: Arc<Queue>,
+    pub transfer: Arc<Queue>,
+}
+
+impl Queues {
+    pub fn has_async_compute(&self) -> bool {
+        !self.compute.is_same(&self.graphics)
+    }
+
+    pub fn has_dedicated_transfer(&self) -> bool {
+        !self.transfer.is_same(&self.graphics)
+    }
+
+    /// Every distinct family in use. Resources touched from more than one queue are created concurrently shared
+    /// between these, which is how the engine avoids explicit queue family ownership transfers.
+    pub fn families(&self) -> Vec<QueueFamily<'_>> {
+        let mut families: Vec<QueueFamily> = vec![self.graphics.family()];
+        for queue in [&self.compute, &self.transfer].iter() {
+            if families.iter().all(|f| f.id() != queue.family().id()) {
+                families.push(queue.family());
+            }
+        }
+        families
+    }
+}
+
+/// Makes work submitted after `future` on `queue` wait for it.
+///
+/// Vulkano refuses to chain a future onto a different queue than the one it was submitted to, so crossing queues
+/// signals a semaphore that the next submission waits on. On the same queue the future is passed through untouched.
+pub fn handoff<F>(future: F, queue: &Arc<Queue>) -> Box<dyn GpuFuture>
+    where F: GpuFuture + 'static
+{
+    match future.queue() {
+        Some(ref from) if !from.is_same(queue) => future.then_signal_semaphore().boxed(),
+        _ => future.boxed(),
+    }
+}
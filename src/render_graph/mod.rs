@@ -0,0 +1,754 @@
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Arc;
+
+use tracing::debug;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState, PrimaryAutoCommandBuffer, SubpassContents};
+use vulkano::device::Device;
+use vulkano::format::{ClearValue, Format};
+use vulkano::image::view::{ImageView, ImageViewAbstract, ImageViewType};
+use vulkano::image::{ImageLayout, ImageUsage};
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::render_pass::{AttachmentDesc, Framebuffer, FramebufferAbstract, LoadOp, RenderPass, RenderPassDesc, StoreOp, Subpass, SubpassDesc};
+
+use crate::allocator::{GpuAllocator, PooledImage};
+use crate::debug::ObjectNamer;
+use crate::logging;
+use crate::render_graph::pass::Access;
+
+pub use crate::render_graph::pass::{Load, Pass, PassBuilder, PassContext};
+
+mod pass;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ImageId(usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct BufferId(usize);
+
+/// How big a graph image is. Everything but `Fixed` is recreated when the backbuffer changes size.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImageSize {
+    Backbuffer,
+    /// A fraction (or multiple) of the backbuffer, at least one pixel.
+    Scaled(f32),
+    Fixed([u32; 2]),
+}
+
+impl ImageSize {
+    pub fn resolve(self, backbuffer: [u32; 2]) -> [u32; 2] {
+        match self {
+            ImageSize::Backbuffer => backbuffer,
+            ImageSize::Scaled(scale) => [((backbuffer[0] as f32 * scale) as u32).max(1), ((backbuffer[1] as f32 * scale) as u32).max(1)],
+            ImageSize::Fixed(size) => size,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ImageDesc {
+    pub format: Format,
+    pub size: ImageSize,
+    pub samples: u32,
+    /// Array layers. Passes render to layered images one layer at a time, and sample them as arrays.
+    pub layers: u32,
+}
+
+impl ImageDesc {
+    pub fn new(format: Format, size: ImageSize) -> ImageDesc {
+        ImageDesc { format, size, samples: 1, layers: 1 }
+    }
+}
+
+struct ImageDecl {
+    name: &'static str,
+    desc: ImageDesc,
+}
+
+struct PassDecl<F> {
+    name: &'static str,
+    pass: Box<dyn Pass<F>>,
+    conditional: bool,
+}
+
+/// Declares the images, buffers and passes of a frame. See `RenderGraph`.
+pub struct RenderGraphBuilder<F = ()> {
+    images: Vec<ImageDecl>,
+    buffers: Vec<&'static str>,
+    passes: Vec<PassDecl<F>>,
+}
+
+impl<F: 'static> RenderGraphBuilder<F> {
+    pub fn new(backbuffer_format: Format) -> RenderGraphBuilder<F> {
+        RenderGraphBuilder {
+            images: vec![ImageDecl {
+                name: "backbuffer",
+                desc: ImageDesc::new(backbuffer_format, ImageSize::Backbuffer),
+            }],
+            buffers: vec![],
+            passes: vec![],
+        }
+    }
+
+    /// The swapchain image being rendered to this frame.
+    pub fn backbuffer(&self) -> ImageId {
+        ImageId(0)
+    }
+
+    /// An image owned by the graph. It only lives between its first and last use in a frame, so its contents are
+    /// undefined before the first pass that writes it.
+    pub fn image(&mut self, name: &'static str, desc: ImageDesc) -> ImageId {
+        self.images.push(ImageDecl { name, desc });
+        ImageId(self.images.len() - 1)
+    }
+
+    /// A buffer owned outside of the graph. Only used to order passes, and passes writing it are never culled.
+    pub fn buffer(&mut self, name: &'static str) -> BufferId {
+        self.buffers.push(name);
+        BufferId(self.buffers.len() - 1)
+    }
+
+    pub fn add_pass<P>(&mut self, name: &'static str, pass: P)
+        where P: Pass<F> + 'static
+    {
+        self.passes.push(PassDecl { name, pass: Box::new(pass), conditional: false });
+    }
+
+    pub fn build(self, device: Arc<Device>, allocator: &GpuAllocator, namer: &ObjectNamer, backbuffer: Vec<Arc<dyn ImageViewAbstract + Send + Sync>>) -> RenderGraph<F> {
+        let _span = tracing::debug_span!(target: logging::RENDER, "build_render_graph").entered();
+
+        let RenderGraphBuilder { images, buffers, passes } = self;
+
+        let mut declared: Vec<(PassDecl<F>, Vec<Access>)> = passes.into_iter()
+                                                                  .map(|mut decl| {
+                                                                      let mut builder = PassBuilder::default();
+                                                                      decl.pass.declare(&mut builder);
+                                                                      validate(&decl, &builder.accesses, &images);
+                                                                      decl.conditional = builder.conditional;
+                                                                      (decl, builder.accesses)
+                                                                  })
+                                                                  .collect();
+
+        let order = sort(&declared, &images, buffers.len());
+        let live = cull(&declared, &order);
+        for (index, (decl, _)) in declared.iter().enumerate() {
+            if !live.contains(&index) {
+                debug!(target: logging::RENDER, pass = decl.name, "culled pass, nothing reads what it writes");
+            }
+        }
+
+        let mut nodes = vec![];
+        let mut declared: Vec<Option<(PassDecl<F>, Vec<Access>)>> = declared.drain(..).map(Some).collect();
+        for index in order.into_iter().filter(|index| live.contains(index)) {
+            let (decl, accesses) = declared[index].take().unwrap();
+            nodes.push(Node {
+                name: decl.name,
+                pass: decl.pass,
+                accesses,
+                render_pass: None,
+                clear_values: vec![],
+                layers: 1,
+                framebuffers: vec![],
+            });
+        }
+        debug!(target: logging::RENDER, order = ?nodes.iter().map(|node| node.name).collect::<Vec<_>>(), "render graph order");
+
+        let (physical, assignment) = assign_images(&nodes, &images);
+        let lifetimes = lifetimes(&nodes, images.len());
+
+        for (index, node) in nodes.iter_mut().enumerate() {
+            if node.accesses.iter().any(Access::is_attachment) {
+                let (render_pass, clear_values) = create_render_pass(&device, &last_uses(index, &lifetimes), node, &images);
+                node.pass.prepare(&device, Subpass::from(render_pass.clone(), 0));
+                node.layers = attachments(&node.accesses).first().map_or(1, |(image, _)| images[image.0].desc.layers);
+                node.render_pass = Some(render_pass);
+                node.clear_values = clear_values;
+            } else {
+                node.pass.prepare(&device, None);
+            }
+        }
+
+        let dimensions = backbuffer[0].image().dimensions().width_height();
+        let mut graph = RenderGraph {
+            allocator: allocator.clone(),
+            namer: namer.clone(),
+            images,
+            physical,
+            assignment,
+            nodes,
+            backbuffer,
+            dimensions,
+            generation: 0,
+        };
+        graph.create_images(true);
+        graph.create_framebuffers();
+        graph
+    }
+}
+
+/// A frame described as passes over images and buffers.
+///
+/// Passes declare what they read and write. From that the graph orders them, drops the ones whose output is never
+/// used, gives every transient image a physical image (sharing one between images with the same description whose
+/// lifetimes don't overlap), and creates a render pass and framebuffers for each pass with attachments. Passes whose
+/// attachments are layered are recorded once per layer, and passes are skipped on frames `Pass::enabled` says they
+/// don't run. Attachments stay in their attachment layout between passes; the layout transitions and pipeline
+/// barriers between passes are inserted by vulkano's command buffer builder based on the accesses the graph records.
+pub struct RenderGraph<F = ()> {
+    allocator: GpuAllocator,
+    namer: ObjectNamer,
+    images: Vec<ImageDecl>,
+    physical: Vec<PhysicalImage>,
+    // Physical image of every declared image, `None` for the backbuffer and images no live pass uses.
+    assignment: Vec<Option<usize>>,
+    nodes: Vec<Node<F>>,
+    backbuffer: Vec<Arc<dyn ImageViewAbstract + Send + Sync>>,
+    dimensions: [u32; 2],
+    generation: u64,
+}
+
+struct Node<F> {
+    name: &'static str,
+    pass: Box<dyn Pass<F>>,
+    accesses: Vec<Access>,
+    render_pass: Option<Arc<RenderPass>>,
+    clear_values: Vec<ClearValue>,
+    // Layers of the attachments, the pass is recorded once for each.
+    layers: u32,
+    // One per backbuffer image if the pass renders to the backbuffer, otherwise one per layer.
+    framebuffers: Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
+}
+
+struct PhysicalImage {
+    desc: ImageDesc,
+    usage: ImageUsage,
+    view: Option<Arc<dyn ImageViewAbstract + Send + Sync>>,
+    // Single layer views to render to, for layered images.
+    layer_views: Vec<Arc<dyn ImageViewAbstract + Send + Sync>>,
+}
+
+impl<F> RenderGraph<F> {
+    /// Swaps in the images of a recreated swapchain, recreating everything that depends on the backbuffer size.
+    pub fn set_backbuffer(&mut self, backbuffer: Vec<Arc<dyn ImageViewAbstract + Send + Sync>>) {
+        let dimensions = backbuffer[0].image().dimensions().width_height();
+        self.backbuffer = backbuffer;
+
+        if dimensions != self.dimensions {
+            debug!(target: logging::RENDER, ?dimensions, "recreating size-dependent graph images");
+            self.dimensions = dimensions;
+            self.create_images(false);
+        }
+        self.create_framebuffers();
+    }
+
+    /// Records every pass, rendering to backbuffer image `image_num`.
+    pub fn execute(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, image_num: usize, frame: &F) {
+        let views = self.views(image_num);
+
+        for node in self.nodes.iter_mut().filter(|node| node.pass.enabled(frame)) {
+            let _span = tracing::debug_span!(target: logging::RENDER, "pass", name = node.name).entered();
+
+            match node.render_pass {
+                Some(_) => {
+                    for layer in 0..node.layers {
+                        let framebuffer = if node.layers > 1 { node.framebuffers[layer as usize].clone() } else { node.framebuffers[image_num.min(node.framebuffers.len() - 1)].clone() };
+                        let dimensions = [framebuffer.width(), framebuffer.height()];
+                        let dynamic_state = DynamicState {
+                            viewports: Some(vec![Viewport {
+                                                     origin: [0.0, 0.0],
+                                                     dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+                                                     depth_range: 0.0..1.0,
+                                                 }]),
+                            ..DynamicState::none()
+                        };
+
+                        builder.begin_render_pass(framebuffer, SubpassContents::Inline, node.clear_values.iter().cloned())
+                               .unwrap_or_else(|e| panic!("failed to begin render pass of {}: {:?}", node.name, e));
+                        node.pass.record(&mut PassContext {
+                                             builder,
+                                             dynamic_state: &dynamic_state,
+                                             dimensions,
+                                             backbuffer_dimensions: self.dimensions,
+                                             layer,
+                                             images: &views,
+                                             generation: self.generation,
+                                         },
+                                         frame);
+                        builder.end_render_pass().unwrap_or_else(|e| panic!("failed to end render pass of {}: {:?}", node.name, e));
+                    }
+                }
+                None => {
+                    node.pass.record(&mut PassContext {
+                                         builder,
+                                         dynamic_state: &DynamicState::none(),
+                                         dimensions: self.dimensions,
+                                         backbuffer_dimensions: self.dimensions,
+                                         layer: 0,
+                                         images: &views,
+                                         generation: self.generation,
+                                     },
+                                     frame);
+                }
+            }
+        }
+    }
+
+    /// The view of every declared image for this frame, indexed by `ImageId`.
+    fn views(&self, image_num: usize) -> Vec<Arc<dyn ImageViewAbstract + Send + Sync>> {
+        self.assignment
+            .iter()
+            .map(|physical| match physical {
+                Some(physical) => self.physical[*physical].view.clone().unwrap(),
+                None => self.backbuffer[image_num].clone(),
+            })
+            .collect()
+    }
+
+    fn create_images(&mut self, all: bool) {
+        for (index, physical) in self.physical.iter_mut().enumerate() {
+            if !all && physical.view.is_some() && matches!(physical.desc.size, ImageSize::Fixed(_)) {
+                continue;
+            }
+
+            let images = &self.images;
+            let name = self.assignment
+                           .iter()
+                           .enumerate()
+                           .filter(|(_, assigned)| **assigned == Some(index))
+                           .map(|(image, _)| images[image].name)
+                           .collect::<Vec<_>>()
+                           .join("/");
+
+            let dimensions = physical.desc.size.resolve(self.dimensions);
+            let image = PooledImage::render_target(&self.allocator, dimensions, physical.desc.layers, physical.desc.format, physical.desc.samples, physical.usage)
+                .unwrap_or_else(|e| panic!("failed to create render graph image {}: {:?}", name, e));
+            self.namer.name_image(&*image, &name);
+            physical.layer_views = if physical.desc.layers > 1 {
+                (0..physical.desc.layers).map(|layer| ImageView::start(image.clone()).with_type(ImageViewType::Dim2d).with_array_layers(layer..layer + 1).build().unwrap() as Arc<dyn ImageViewAbstract + Send + Sync>)
+                                         .collect()
+            } else {
+                vec![]
+            };
+            physical.view = Some(ImageView::new(image).unwrap());
+        }
+        self.generation += 1;
+    }
+
+    fn create_framebuffers(&mut self) {
+        for image_num in 0..self.backbuffer.len() {
+            let views = self.views(image_num);
+            let (physical, assignment) = (&self.physical, &self.assignment);
+
+            for node in self.nodes.iter_mut() {
+                let render_pass = match &node.render_pass {
+                    Some(render_pass) => render_pass,
+                    None => continue,
+                };
+                if image_num == 0 {
+                    node.framebuffers.clear();
+                } else if !node.accesses.iter().any(|access| access.is_attachment() && access.image() == Some(ImageId(0))) {
+                    continue;
+                }
+
+                if node.layers > 1 {
+                    for layer in 0..node.layers as usize {
+                        let attachments = attachments(&node.accesses).into_iter().map(|(image, _)| physical[assignment[image.0].unwrap()].layer_views[layer].clone()).collect();
+                        node.framebuffers.push(framebuffer(render_pass, attachments));
+                    }
+                } else {
+                    let attachments = attachments(&node.accesses).into_iter().map(|(image, _)| views[image.0].clone()).collect();
+                    node.framebuffers.push(framebuffer(render_pass, attachments));
+                }
+            }
+        }
+    }
+}
+
+/// The attachments of a pass in binding order, colors first.
+fn attachments(accesses: &[Access]) -> Vec<(ImageId, Load)> {
+    let colors = accesses.iter().filter_map(|access| match *access {
+                                    Access::Color(image, load) => Some((image, load)),
+                                    _ => None,
+                                });
+    let depth = accesses.iter().filter_map(|access| match *access {
+                                   Access::Depth(image, load) => Some((image, load)),
+                                   _ => None,
+                               });
+    colors.chain(depth).collect()
+}
+
+fn validate<F>(decl: &PassDecl<F>, accesses: &[Access], images: &[ImageDecl]) {
+    let depths = accesses.iter().filter(|access| matches!(access, Access::Depth(..))).count();
+    assert!(depths <= 1, "pass {} has more than one depth attachment", decl.name);
+
+    let mut sizes = accesses.iter().filter(|access| access.is_attachment()).map(|access| {
+                                                                             let desc = images[access.image().unwrap().0].desc;
+                                                                             (desc.size, desc.layers)
+                                                                         });
+    if let Some(first) = sizes.next() {
+        assert!(sizes.all(|size| size == first), "attachments of pass {} have different sizes or layer counts", decl.name);
+    }
+}
+
+/// Orders passes so that each one runs after the passes it depends on, keeping declaration order where there are
+/// no dependencies. Between two passes touching the same resource the one declared first goes first, except that
+/// a pass reading a graph image nothing has written yet waits for the passes that write it.
+fn sort<F>(declared: &[(PassDecl<F>, Vec<Access>)], images: &[ImageDecl], buffers: usize) -> Vec<usize> {
+    let mut dependencies: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); declared.len()];
+
+    let resources = (0..images.len()).map(|image| (Some(ImageId(image)), None)).chain((0..buffers).map(|buffer| (None, Some(BufferId(buffer)))));
+    for (image, buffer) in resources {
+        let touches = |access: &Access| match *access {
+            Access::ReadBuffer(b) | Access::WriteBuffer(b) => Some(b) == buffer,
+            _ => access.image() == image,
+        };
+
+        let mut last_writer = None;
+        let mut readers = vec![];
+        let mut early_readers = vec![];
+        let mut writers = vec![];
+
+        for (index, (_, accesses)) in declared.iter().enumerate() {
+            let (writes, reads) = accesses.iter().filter(|access| touches(access)).fold((false, false), |(w, r), access| (w || access.is_write(), r || !access.is_write()));
+
+            if writes {
+                dependencies[index].extend(last_writer.iter().chain(readers.iter()).filter(|&&other| other != index));
+                last_writer = Some(index);
+                readers.clear();
+                writers.push(index);
+            } else if reads {
+                match last_writer {
+                    Some(writer) => {
+                        dependencies[index].insert(writer);
+                    }
+                    None => early_readers.push(index),
+                }
+                readers.push(index);
+            }
+        }
+
+        // Previous contents of external buffers and the backbuffer are meaningful, of graph images they are not.
+        if image.is_some_and(|image| image.0 != 0) {
+            for &reader in early_readers.iter() {
+                for &writer in writers.iter() {
+                    dependencies[reader].insert(writer);
+                    dependencies[writer].remove(&reader);
+                }
+            }
+        }
+    }
+
+    let mut order = vec![];
+    let mut done = vec![false; declared.len()];
+    while order.len() < declared.len() {
+        let next = (0..declared.len()).find(|&index| !done[index] && dependencies[index].iter().all(|&dependency| done[dependency]));
+        match next {
+            Some(index) => {
+                done[index] = true;
+                order.push(index);
+            }
+            None => {
+                let stuck: Vec<_> = (0..declared.len()).filter(|&index| !done[index]).map(|index| declared[index].0.name).collect();
+                panic!("render graph has a dependency cycle between {:?}", stuck);
+            }
+        }
+    }
+    order
+}
+
+/// Passes that contribute to the backbuffer or to an external buffer, directly or through other passes.
+fn cull<F>(declared: &[(PassDecl<F>, Vec<Access>)], order: &[usize]) -> HashSet<usize> {
+    let mut needed_images = HashSet::new();
+    let mut live = HashSet::new();
+
+    for &index in order.iter().rev() {
+        let (decl, accesses) = &declared[index];
+        let is_live = accesses.iter().any(|access| match *access {
+                                              Access::WriteBuffer(_) => true,
+                                              _ => access.is_write() && access.image().is_some_and(|image| image.0 == 0 || needed_images.contains(&image)),
+                                          });
+        if !is_live {
+            continue;
+        }
+        live.insert(index);
+
+        for access in accesses.iter() {
+            match *access {
+                // Fully overwritten, so whatever was there before doesn't matter to this pass. Unless it's skipped, when
+                // the passes after it see what was there.
+                Access::Color(image, Load::Clear(_)) | Access::Color(image, Load::DontCare) | Access::Depth(image, Load::Clear(_)) | Access::Depth(image, Load::DontCare) => {
+                    if !decl.conditional {
+                        needed_images.remove(&image);
+                    }
+                }
+                Access::ReadBuffer(_) | Access::WriteBuffer(_) => (),
+                _ => {
+                    needed_images.insert(access.image().unwrap());
+                }
+            }
+        }
+    }
+
+    live
+}
+
+/// First and last node using each declared image, in execution order.
+fn lifetimes<F>(nodes: &[Node<F>], images: usize) -> Vec<Option<(usize, usize)>> {
+    let mut lifetimes = vec![None; images];
+    for (index, node) in nodes.iter().enumerate() {
+        for image in node.accesses.iter().filter_map(Access::image) {
+            let lifetime = lifetimes[image.0].get_or_insert((index, index));
+            lifetime.1 = index;
+        }
+    }
+    lifetimes
+}
+
+/// Images whose last use is node `index`.
+fn last_uses(index: usize, lifetimes: &[Option<(usize, usize)>]) -> HashSet<ImageId> {
+    lifetimes.iter().enumerate().filter(|(_, lifetime)| lifetime.is_some_and(|(_, last)| last == index)).map(|(image, _)| ImageId(image)).collect()
+}
+
+/// Gives every transient image a physical image, reusing one whose previous user is done with it.
+fn assign_images<F>(nodes: &[Node<F>], images: &[ImageDecl]) -> (Vec<PhysicalImage>, Vec<Option<usize>>) {
+    let lifetimes = lifetimes(nodes, images.len());
+
+    let mut by_first_use: Vec<usize> = (1..images.len()).filter(|&image| lifetimes[image].is_some()).collect();
+    by_first_use.sort_by_key(|&image| lifetimes[image].unwrap().0);
+
+    let mut physical: Vec<PhysicalImage> = vec![];
+    let mut physical_last_use: Vec<usize> = vec![];
+    let mut assignment = vec![None; images.len()];
+
+    for image in by_first_use {
+        let (first, last) = lifetimes[image].unwrap();
+        let desc = images[image].desc;
+
+        let reusable = (0..physical.len()).find(|&p| physical[p].desc == desc && physical_last_use[p] < first);
+        let index = match reusable {
+            Some(p) => {
+                debug!(target: logging::RENDER, image = images[image].name, physical = p, "aliasing graph image");
+                physical_last_use[p] = last;
+                p
+            }
+            None => {
+                physical.push(PhysicalImage {
+                    desc,
+                    usage: ImageUsage::none(),
+                    view: None,
+                    layer_views: vec![],
+                });
+                physical_last_use.push(last);
+                physical.len() - 1
+            }
+        };
+        assignment[image] = Some(index);
+
+        for access in nodes.iter().flat_map(|node| node.accesses.iter()).filter(|access| access.image() == Some(ImageId(image))) {
+            let usage = &mut physical[index].usage;
+            match access {
+                Access::Color(..) => usage.color_attachment = true,
+                Access::Depth(..) => usage.depth_stencil_attachment = true,
+                Access::Sampled(_) => usage.sampled = true,
+                _ => (),
+            }
+        }
+    }
+
+    debug!(target: logging::RENDER, declared = images.len() - 1, physical = physical.len(), "assigned graph images");
+    (physical, assignment)
+}
+
+fn create_render_pass<F>(device: &Arc<Device>, last_uses: &HashSet<ImageId>, node: &Node<F>, images: &[ImageDecl]) -> (Arc<RenderPass>, Vec<ClearValue>) {
+    let attachments = attachments(&node.accesses);
+
+    let descs = attachments.iter()
+                           .map(|&(image, load)| {
+                               let desc = images[image.0].desc;
+                               // Nothing reads a transient image after its last use, so there's no need to write it back.
+                               let store = if image.0 != 0 && last_uses.contains(&image) { StoreOp::DontCare } else { StoreOp::Store };
+                               let load = match load {
+                                   Load::Clear(_) => LoadOp::Clear,
+                                   Load::Keep => LoadOp::Load,
+                                   Load::DontCare => LoadOp::DontCare,
+                               };
+                               let layout = if attachment_is_depth(&node.accesses, image) { ImageLayout::DepthStencilAttachmentOptimal } else { ImageLayout::ColorAttachmentOptimal };
+
+                               AttachmentDesc {
+                                   format: desc.format,
+                                   samples: desc.samples,
+                                   load,
+                                   store,
+                                   stencil_load: load,
+                                   stencil_store: store,
+                                   initial_layout: layout,
+                                   final_layout: layout,
+                               }
+                           })
+                           .collect();
+
+    let colors = attachments.iter().enumerate().filter(|(_, &(image, _))| !attachment_is_depth(&node.accesses, image)).map(|(index, _)| (index, ImageLayout::ColorAttachmentOptimal)).collect();
+    let depth = attachments.iter().position(|&(image, _)| attachment_is_depth(&node.accesses, image)).map(|index| (index, ImageLayout::DepthStencilAttachmentOptimal));
+
+    let subpass = SubpassDesc {
+        color_attachments: colors,
+        depth_stencil: depth,
+        input_attachments: vec![],
+        resolve_attachments: vec![],
+        preserve_attachments: vec![],
+    };
+
+    let render_pass = RenderPass::new(device.clone(), RenderPassDesc::new(descs, vec![subpass], vec![]))
+        .unwrap_or_else(|e| panic!("failed to create render pass for {}: {:?}", node.name, e));
+
+    let clear_values = attachments.iter()
+                                  .map(|&(_, load)| match load {
+                                      Load::Clear(value) => value,
+                                      _ => ClearValue::None,
+                                  })
+                                  .collect();
+
+    (Arc::new(render_pass), clear_values)
+}
+
+fn attachment_is_depth(accesses: &[Access], image: ImageId) -> bool {
+    accesses.iter().any(|access| matches!(access, Access::Depth(depth, _) if *depth == image))
+}
+
+/// vulkano's framebuffer builder is typed by its attachments, so a runtime list has to be unrolled.
+fn framebuffer(render_pass: &Arc<RenderPass>, attachments: Vec<Arc<dyn ImageViewAbstract + Send + Sync>>) -> Arc<dyn FramebufferAbstract + Send + Sync> {
+    macro_rules! build {
+        ($($attachment:ident),*) => {{
+            let mut attachments = attachments.into_iter();
+            let builder = Framebuffer::start(render_pass.clone());
+            $(let $attachment = attachments.next().unwrap(); let builder = builder.add($attachment).unwrap();)*
+            Arc::new(builder.build().unwrap()) as Arc<dyn FramebufferAbstract + Send + Sync>
+        }};
+    }
+
+    match attachments.len() {
+        1 => build!(a),
+        2 => build!(a, b),
+        3 => build!(a, b, c),
+        4 => build!(a, b, c, d),
+        5 => build!(a, b, c, d, e),
+        6 => build!(a, b, c, d, e, f),
+        7 => build!(a, b, c, d, e, f, g),
+        8 => build!(a, b, c, d, e, f, g, h),
+        9 => build!(a, b, c, d, e, f, g, h, i),
+        n => panic!("render graph passes support up to 9 attachments, not {}", n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Noop;
+
+    impl Pass for Noop {
+        fn declare(&mut self, _pass: &mut PassBuilder) {}
+
+        fn record(&mut self, _context: &mut PassContext, _frame: &()) {}
+    }
+
+    /// The backbuffer and `count` graph images with the same description.
+    fn images(count: usize) -> Vec<ImageDecl> {
+        (0..=count).map(|_| ImageDecl { name: "image", desc: ImageDesc::new(Format::R8G8B8A8Unorm, ImageSize::Backbuffer) }).collect()
+    }
+
+    fn pass(name: &'static str, conditional: bool, accesses: Vec<Access>) -> (PassDecl<()>, Vec<Access>) {
+        (PassDecl { name, pass: Box::new(Noop), conditional }, accesses)
+    }
+
+    fn names(declared: &[(PassDecl<()>, Vec<Access>)], order: &[usize]) -> Vec<&'static str> {
+        order.iter().map(|&index| declared[index].0.name).collect()
+    }
+
+    fn nodes(declared: Vec<(PassDecl<()>, Vec<Access>)>) -> Vec<Node<()>> {
+        declared.into_iter()
+                .map(|(decl, accesses)| Node {
+                    name: decl.name,
+                    pass: decl.pass,
+                    accesses,
+                    render_pass: None,
+                    clear_values: vec![],
+                    layers: 1,
+                    framebuffers: vec![],
+                })
+                .collect()
+    }
+
+    #[test]
+    fn dependency_order() {
+        let (a, b) = (ImageId(1), ImageId(2));
+        let buffer = BufferId(0);
+        let declared = vec![pass("present", false, vec![Access::Sampled(b), Access::Color(ImageId(0), Load::DontCare)]),
+                            pass("blur", false, vec![Access::Sampled(a), Access::Color(b, Load::DontCare)]),
+                            pass("scene", false, vec![Access::ReadBuffer(buffer), Access::Color(a, Load::Clear([0.0; 4].into()))]),
+                            pass("simulate", false, vec![Access::WriteBuffer(buffer)]),
+                            pass("overlay", false, vec![Access::Color(ImageId(0), Load::Keep)])];
+
+        // Graph images are produced before they're read, but the scene reads what was in the external buffer before
+        // this frame's simulation, and the overlay draws over what was presented.
+        let order = sort(&declared, &images(2), 1);
+        assert_eq!(names(&declared, &order), ["scene", "blur", "present", "simulate", "overlay"]);
+    }
+
+    #[test]
+    fn unread_outputs_are_culled() {
+        let (used, unused, chained) = (ImageId(1), ImageId(2), ImageId(3));
+        let declared = vec![pass("scene", false, vec![Access::Color(used, Load::Clear([0.0; 4].into()))]),
+                            pass("unused", false, vec![Access::Sampled(used), Access::Color(unused, Load::DontCare)]),
+                            pass("feeds unused", false, vec![Access::Color(chained, Load::DontCare)]),
+                            pass("reads chained", false, vec![Access::Sampled(chained), Access::Color(unused, Load::Keep)]),
+                            pass("present", false, vec![Access::Sampled(used), Access::Color(ImageId(0), Load::DontCare)])];
+
+        let order = sort(&declared, &images(3), 0);
+        let live = cull(&declared, &order);
+        let mut live: Vec<_> = live.into_iter().map(|index| declared[index].0.name).collect();
+        live.sort_unstable();
+        assert_eq!(live, ["present", "scene"]);
+    }
+
+    #[test]
+    fn skipped_clears_keep_earlier_writers() {
+        let color = ImageId(1);
+        let declared = vec![pass("forward", false, vec![Access::Color(color, Load::Clear([0.0; 4].into()))]),
+                            pass("deferred", true, vec![Access::Color(color, Load::Clear([0.0; 4].into()))]),
+                            pass("present", false, vec![Access::Sampled(color), Access::Color(ImageId(0), Load::DontCare)])];
+
+        let order = sort(&declared, &images(1), 0);
+        assert_eq!(cull(&declared, &order).len(), 3);
+    }
+
+    #[test]
+    fn disjoint_lifetimes_share_images() {
+        let (first, second, third) = (ImageId(1), ImageId(2), ImageId(3));
+        let declared = vec![pass("first", false, vec![Access::Color(first, Load::DontCare)]),
+                            pass("second", false, vec![Access::Sampled(first), Access::Color(second, Load::DontCare)]),
+                            pass("third", false, vec![Access::Sampled(second), Access::Color(third, Load::DontCare)]),
+                            pass("present", false, vec![Access::Sampled(third), Access::Color(ImageId(0), Load::DontCare)])];
+
+        let images = images(3);
+        let (physical, assignment) = assign_images(&nodes(declared), &images);
+        assert_eq!(physical.len(), 2);
+        assert_eq!(assignment[0], None);
+        // `first` is done by the time `third` is written, `second` overlaps both.
+        assert_eq!(assignment[first.0], assignment[third.0]);
+        assert_ne!(assignment[first.0], assignment[second.0]);
+        assert_ne!(assignment[second.0], assignment[third.0]);
+        assert!(physical[assignment[first.0].unwrap()].usage.sampled && physical[assignment[first.0].unwrap()].usage.color_attachment);
+    }
+
+    #[test]
+    fn different_descriptions_never_share() {
+        let mut images = images(2);
+        images[2].desc.format = Format::R16G16B16A16Sfloat;
+        let declared = vec![pass("first", false, vec![Access::Color(ImageId(1), Load::DontCare)]),
+                            pass("second", false, vec![Access::Color(ImageId(2), Load::DontCare)])];
+
+        let (physical, assignment) = assign_images(&nodes(declared), &images);
+        assert_eq!(physical.len(), 2);
+        assert_ne!(assignment[1], assignment[2]);
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState, PrimaryAutoCommandBuffer};
+use vulkano::device::Device;
+use vulkano::format::ClearValue;
+use vulkano::image::view::ImageViewAbstract;
+use vulkano::render_pass::Subpass;
+
+use crate::render_graph::{BufferId, ImageId};
+
+/// What happens to an attachment's previous contents when a pass starts writing to it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Load {
+    Clear(ClearValue),
+    Keep,
+    DontCare,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum Access {
+    Color(ImageId, Load),
+    Depth(ImageId, Load),
+    Sampled(ImageId),
+    ReadBuffer(BufferId),
+    WriteBuffer(BufferId),
+}
+
+impl Access {
+    pub(crate) fn image(&self) -> Option<ImageId> {
+        match *self {
+            Access::Color(image, _) | Access::Depth(image, _) | Access::Sampled(image) => Some(image),
+            Access::ReadBuffer(_) | Access::WriteBuffer(_) => None,
+        }
+    }
+
+    pub(crate) fn is_write(&self) -> bool {
+        match *self {
+            Access::Color(..) | Access::Depth(..) | Access::WriteBuffer(_) => true,
+            Access::Sampled(_) | Access::ReadBuffer(_) => false,
+        }
+    }
+
+    pub(crate) fn is_attachment(&self) -> bool {
+        matches!(*self, Access::Color(..) | Access::Depth(..))
+    }
+}
+
+/// A node of the render graph.
+///
+/// `F` is whatever per-frame data the renderer hands to `RenderGraph::execute`, like the camera and the scene.
+pub trait Pass<F = ()> {
+    /// Declares every image and buffer the pass touches. Called once, when the graph is built.
+    fn declare(&mut self, pass: &mut PassBuilder);
+
+    /// Called once the pass's render pass exists, to create pipelines against it. `subpass` is `None` for passes
+    /// without attachments, which record outside of a render pass.
+    fn prepare(&mut self, _device: &Arc<Device>, _subpass: Option<Subpass>) {}
+
+    /// Whether the pass runs this frame. A skipped pass doesn't begin its render pass, so it doesn't clear its
+    /// attachments either. Passes that can be skipped have to declare themselves `PassBuilder::conditional`.
+    fn enabled(&self, _frame: &F) -> bool {
+        true
+    }
+
+    /// Records the pass. Passes with attachments are recorded inside their render pass.
+    fn record(&mut self, context: &mut PassContext, frame: &F);
+}
+
+/// Collects the accesses of one pass. Attachments are bound in the order they are declared, colors first.
+#[derive(Default)]
+pub struct PassBuilder {
+    pub(crate) accesses: Vec<Access>,
+    pub(crate) conditional: bool,
+}
+
+impl PassBuilder {
+    /// Marks the pass as one `Pass::enabled` can skip, so the passes writing what it clears are kept for the frames
+    /// it doesn't run.
+    pub fn conditional(&mut self) -> &mut PassBuilder {
+        self.conditional = true;
+        self
+    }
+
+    pub fn color(&mut self, image: ImageId, load: Load) -> &mut PassBuilder {
+        self.accesses.push(Access::Color(image, load));
+        self
+    }
+
+    pub fn depth(&mut self, image: ImageId, load: Load) -> &mut PassBuilder {
+        self.accesses.push(Access::Depth(image, load));
+        self
+    }
+
+    /// Reads `image` from a shader through a sampler or as a sampled image.
+    pub fn sample(&mut self, image: ImageId) -> &mut PassBuilder {
+        self.accesses.push(Access::Sampled(image));
+        self
+    }
+
+    pub fn read(&mut self, buffer: BufferId) -> &mut PassBuilder {
+        self.accesses.push(Access::ReadBuffer(buffer));
+        self
+    }
+
+    pub fn write(&mut self, buffer: BufferId) -> &mut PassBuilder {
+        self.accesses.push(Access::WriteBuffer(buffer));
+        self
+    }
+}
+
+/// What a pass gets to record with.
+pub struct PassContext<'a> {
+    pub builder: &'a mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    /// Viewport covering the pass's attachments. Empty for passes without attachments.
+    pub dynamic_state: &'a DynamicState,
+    /// Size of the pass's attachments, or of the backbuffer for passes without attachments.
+    pub dimensions: [u32; 2],
+    pub backbuffer_dimensions: [u32; 2],
+    /// Layer of the attachments being rendered to, passes with layered attachments are recorded once per layer.
+    pub layer: u32,
+    pub(crate) images: &'a [Arc<dyn ImageViewAbstract + Send + Sync>],
+    pub(crate) generation: u64,
+}
+
+impl<'a> PassContext<'a> {
+    /// The view of a graph image. The view changes when size-dependent images are recreated, see `generation`.
+    pub fn image(&self, image: ImageId) -> Arc<dyn ImageViewAbstract + Send + Sync> {
+        self.images[image.0].clone()
+    }
+
+    /// Increases every time the graph recreates its images, so passes know when to rebuild descriptor sets
+    /// that refer to them.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::ImageDimensions;
+
+use crate::allocator::PooledImage;
+use crate::debug::ObjectNamer;
+use crate::upload::UploadManager;
+
+pub type Texture = Arc<ImageView<Arc<PooledImage>>>;
+
+/// How the alpha of the base color is used.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AlphaMode {
+    /// Alpha is ignored.
+    Opaque,
+    /// Fragments with an alpha below the cutoff are discarded, the rest are opaque.
+    Mask(f32),
+    /// Blended with what's behind after the opaque scene is drawn. See `TransparencyMode` for how overlapping
+    /// blended surfaces are ordered.
+    Blend(BlendMode),
+}
+
+/// How a blended material's shaded color is combined with what's behind it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// Covers what's behind by the base color's alpha.
+    Alpha,
+    /// The base color is already multiplied by alpha, so only diffuse light is scaled by it while reflections and
+    /// emission stay as bright, like on glass. Covers what's behind by alpha.
+    Premultiplied,
+    /// Adds the shaded color times alpha to what's behind, covering nothing, so it's drawn in any order.
+    Additive,
+}
+
+/// A metallic-roughness material with the semantics of glTF's.
+///
+/// Every texture is multiplied with its factor. A missing texture counts as white, or as a flat normal map.
+#[derive(Clone)]
+pub struct Material {
+    pub base_color_factor: [f32; 4],
+    /// sRGB color, linear alpha.
+    pub base_color_texture: Option<Texture>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Linear roughness in green and metalness in blue.
+    pub metallic_roughness_texture: Option<Texture>,
+    /// Tangent space normals, x and y scaled by `normal_scale`.
+    pub normal_texture: Option<Texture>,
+    pub normal_scale: f32,
+    /// How much ambient light reaches the surface, in red. `occlusion_strength` blends it in.
+    pub occlusion_texture: Option<Texture>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: [f32; 3],
+    /// sRGB.
+    pub emissive_texture: Option<Texture>,
+    pub alpha_mode: AlphaMode,
+    /// Back faces are drawn too, lit with the flipped normal.
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material {
+            base_color_factor: [1.0, 1.0, 1.0, 1.0],
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_factor: [0.0, 0.0, 0.0],
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
+
+impl Material {
+    /// Uploads an RGBA8 texture. Color textures (base color, emissive) are `srgb`, data textures are not.
+    pub fn texture(uploads: &mut UploadManager, namer: &ObjectNamer, name: &str, pixels: &[[u8; 4]], dimensions: [u32; 2], srgb: bool) -> Texture {
+        let format = if srgb { Format::R8G8B8A8Srgb } else { Format::R8G8B8A8Unorm };
+        let dimensions = ImageDimensions::Dim2d {
+            width: dimensions[0],
+            height: dimensions[1],
+            array_layers: 1,
+        };
+        let image = uploads.image(pixels, dimensions, format).expect("failed to create texture");
+        namer.name_image(&*image, name);
+        ImageView::new(image).unwrap()
+    }
+}
@@ -0,0 +1,125 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use cgmath::Point3;
+use vulkano::buffer::BufferUsage;
+
+use crate::allocator::{MemoryCategory, PooledBuffer};
+use crate::culling::Aabb;
+use crate::debug::ObjectNamer;
+use crate::upload::UploadManager;
+
+/// Vertex layout of every mesh. `tangent.w` is the handedness of the bitangent, as in glTF: the bitangent is
+/// `cross(normal, tangent.xyz) * tangent.w` and points towards decreasing `uv.y`.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub tangent: [f32; 4],
+    pub uv: [f32; 2],
+}
+vulkano::impl_vertex!(MeshVertex, position, normal, tangent, uv);
+
+/// Indexed triangle list on the CPU, counter-clockwise when seen from the front.
+#[derive(Default, Debug, Clone)]
+pub struct MeshData {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    /// A UV sphere around the origin, with `segments` around the y axis and `rings` from pole to pole.
+    pub fn sphere(radius: f32, segments: u32, rings: u32) -> MeshData {
+        let mut data = MeshData::default();
+
+        for ring in 0..=rings {
+            let theta = PI * ring as f32 / rings as f32;
+            for segment in 0..=segments {
+                let phi = 2.0 * PI * segment as f32 / segments as f32;
+                let normal = [theta.sin() * phi.sin(), theta.cos(), theta.sin() * phi.cos()];
+                data.vertices.push(MeshVertex {
+                    position: [normal[0] * radius, normal[1] * radius, normal[2] * radius],
+                    normal,
+                    tangent: [phi.cos(), 0.0, -phi.sin(), 1.0],
+                    uv: [segment as f32 / segments as f32, ring as f32 / rings as f32],
+                });
+            }
+        }
+
+        let stride = segments + 1;
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let top_left = ring * stride + segment;
+                let bottom_left = top_left + stride;
+                data.indices.extend_from_slice(&[bottom_left, bottom_left + 1, top_left + 1, bottom_left, top_left + 1, top_left]);
+            }
+        }
+        data
+    }
+
+    /// A cube around the origin with its edges `size` long. Every face has the whole texture.
+    pub fn cube(size: f32) -> MeshData {
+        let mut data = MeshData::default();
+        // Normal and the direction of increasing `uv.x` of every face.
+        let faces = [([1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+                     ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+                     ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0]),
+                     ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0]),
+                     ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+                     ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0])];
+        for &(normal, tangent) in faces.iter() {
+            data.face(normal, tangent, size / 2.0, size / 2.0);
+        }
+        data
+    }
+
+    /// A square in the xz plane facing up, with its edges `size` long.
+    pub fn plane(size: f32) -> MeshData {
+        let mut data = MeshData::default();
+        data.face([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], 0.0, size / 2.0);
+        data
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        Aabb::from_points(self.vertices.iter().map(|vertex| Point3::from(vertex.position)))
+    }
+
+    /// Adds a square facing `normal` at `distance` from the origin.
+    fn face(&mut self, normal: [f32; 3], tangent: [f32; 3], distance: f32, half_size: f32) {
+        let bitangent = cross(normal, tangent);
+        let first = self.vertices.len() as u32;
+
+        for &(u, v) in [(0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)].iter() {
+            let (x, y) = ((u * 2.0 - 1.0) * half_size, (1.0 - v * 2.0) * half_size);
+            self.vertices.push(MeshVertex {
+                position: [0, 1, 2].map(|i| normal[i] * distance + tangent[i] * x + bitangent[i] * y),
+                normal,
+                tangent: [tangent[0], tangent[1], tangent[2], 1.0],
+                uv: [u, v],
+            });
+        }
+        self.indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+/// Device-local vertex and index buffers of a `MeshData`.
+pub struct Mesh {
+    pub vertices: Arc<PooledBuffer<[MeshVertex]>>,
+    pub indices: Arc<PooledBuffer<[u32]>>,
+    /// Around the vertices, in model space.
+    pub bounds: Aabb,
+}
+
+impl Mesh {
+    pub fn upload(uploads: &mut UploadManager, namer: &ObjectNamer, name: &str, data: &MeshData) -> Arc<Mesh> {
+        let vertices = uploads.buffer(&data.vertices, BufferUsage::vertex_buffer(), MemoryCategory::Mesh).expect("failed to create vertex buffer");
+        let indices = uploads.buffer(&data.indices, BufferUsage::index_buffer(), MemoryCategory::Mesh).expect("failed to create index buffer");
+        namer.name_buffer(&*vertices, &format!("{} vertices", name));
+        namer.name_buffer(&*indices, &format!("{} indices", name));
+        Arc::new(Mesh { vertices, indices, bounds: data.bounds() })
+    }
+}
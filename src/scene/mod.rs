@@ -0,0 +1,182 @@
+use std::env;
+use std::sync::Arc;
+
+use cgmath::{InnerSpace, Matrix4, Point3, Rad, Vector3, Vector4};
+
+pub use crate::scene::material::{AlphaMode, BlendMode, Material, Texture};
+pub use crate::scene::mesh::{Mesh, MeshData, MeshVertex};
+
+use crate::animation::SkinnedObject;
+use crate::culling::Bvh;
+use crate::debug_draw::DebugDraw;
+use crate::environment::Environment;
+use crate::lod::Lod;
+use crate::particles::Emitter;
+use crate::sprite::{AnimatedSprite, Camera2d, Sprite};
+use crate::text::Text;
+
+mod material;
+mod mesh;
+
+/// Everything the renderer draws in a frame.
+pub struct Scene {
+    pub camera: Camera,
+    /// Light reaching every surface from every direction, standing in for indirect lighting without an environment.
+    pub ambient: [f32; 3],
+    /// Drawn behind the objects and lighting them.
+    pub environment: Option<Environment>,
+    pub lights: Vec<Light>,
+    pub objects: Vec<Object>,
+    /// Bounds of the objects, culled against every view. Has to be updated after the objects change.
+    pub bvh: Bvh,
+    /// Objects posed by a skeleton, drawn with the objects but never culled.
+    pub skinned: Vec<SkinnedObject>,
+    /// Simulated on the GPU and drawn over the objects and the sky.
+    pub emitters: Vec<Emitter>,
+    pub sprite_camera: Camera2d,
+    /// Drawn by the `SpritePass`, after the 3D scene.
+    pub sprites: Vec<Sprite>,
+    /// Drawn with the sprites.
+    pub animated_sprites: Vec<AnimatedSprite>,
+    /// Drawn by the text pass, after the sprites.
+    pub texts: Vec<Text>,
+    /// Drawn over everything else in pixels from the bottom left, filled by `Ui::render`.
+    pub ui_sprites: Vec<Sprite>,
+    /// Drawn over the UI sprites, filled by `Ui::render`.
+    pub ui_texts: Vec<Text>,
+    pub debug: DebugDraw,
+}
+
+/// A mesh drawn with a material.
+pub struct Object {
+    /// Set to the current level by `lod::update` when there's a LOD group.
+    pub mesh: Arc<Mesh>,
+    pub material: Arc<Material>,
+    pub transform: Matrix4<f32>,
+    pub lod: Option<Lod>,
+}
+
+impl Object {
+    /// Whether the object is drawn at all, rather than being too small on screen for its LOD group.
+    pub fn shown(&self) -> bool {
+        self.lod.iter().all(Lod::shown)
+    }
+}
+
+pub struct Camera {
+    pub position: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    pub fov_y: Rad<f32>,
+    pub near: f32,
+    pub far: f32,
+    pub path: RenderPath,
+}
+
+impl Camera {
+    pub fn view(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(self.position, self.target, self.up)
+    }
+
+    /// Projects into Vulkan's clip space, where y points down and depth goes from 0 at `near` to 1 at `far`.
+    pub fn projection(&self, aspect: f32) -> Matrix4<f32> {
+        gl_to_vulkan() * cgmath::perspective(self.fov_y, aspect, self.near, self.far)
+    }
+}
+
+/// How a camera's view is lit.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RenderPath {
+    /// Every object is lit as it's drawn, looping over every light.
+    Forward,
+    /// Objects are drawn into a G-buffer, which every light then lights only where it reaches.
+    Deferred,
+}
+
+impl RenderPath {
+    pub fn next(self) -> RenderPath {
+        match self {
+            RenderPath::Forward => RenderPath::Deferred,
+            RenderPath::Deferred => RenderPath::Forward,
+        }
+    }
+
+    /// Reads `TONIC_RENDER_PATH`, either `forward` or `deferred`, defaulting to forward.
+    pub fn from_env() -> RenderPath {
+        match env::var("TONIC_RENDER_PATH").as_ref().map(|s| s.to_ascii_lowercase()) {
+            Ok(ref s) if s == "deferred" => RenderPath::Deferred,
+            _ => RenderPath::Forward,
+        }
+    }
+}
+
+/// Turns cgmath's OpenGL style projections into Vulkan ones, flipping y and moving depth from [-1, 1] to [0, 1].
+pub fn gl_to_vulkan() -> Matrix4<f32> {
+    Matrix4::from_cols(Vector4::new(1.0, 0.0, 0.0, 0.0), Vector4::new(0.0, -1.0, 0.0, 0.0), Vector4::new(0.0, 0.0, 0.5, 0.0), Vector4::new(0.0, 0.0, 0.5, 1.0))
+}
+
+/// A punctual light with the semantics of glTF's `KHR_lights_punctual`: directional lights are in lux, point and
+/// spot lights in candela.
+#[derive(Debug, Clone)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Linear RGB.
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Distance at which point and spot lights have faded out completely. Without one they fall off with the
+    /// inverse square of the distance forever.
+    pub range: Option<f32>,
+    /// Whether the light gets a shadow map, if there's one left for its kind. See `ShadowSettings`.
+    pub casts_shadows: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum LightKind {
+    /// Shines along `direction` everywhere.
+    Directional { direction: Vector3<f32> },
+    Point { position: Point3<f32> },
+    /// Full intensity within `inner_cone_angle` of `direction`, fading to nothing at `outer_cone_angle`.
+    Spot {
+        position: Point3<f32>,
+        direction: Vector3<f32>,
+        inner_cone_angle: Rad<f32>,
+        outer_cone_angle: Rad<f32>,
+    },
+}
+
+impl Light {
+    pub fn directional(direction: Vector3<f32>, color: [f32; 3], intensity: f32) -> Light {
+        Light {
+            kind: LightKind::Directional { direction: direction.normalize() },
+            color,
+            intensity,
+            range: None,
+            casts_shadows: false,
+        }
+    }
+
+    pub fn point(position: Point3<f32>, color: [f32; 3], intensity: f32) -> Light {
+        Light {
+            kind: LightKind::Point { position },
+            color,
+            intensity,
+            range: None,
+            casts_shadows: false,
+        }
+    }
+
+    pub fn spot(position: Point3<f32>, direction: Vector3<f32>, inner_cone_angle: Rad<f32>, outer_cone_angle: Rad<f32>, color: [f32; 3], intensity: f32) -> Light {
+        Light {
+            kind: LightKind::Spot {
+                position,
+                direction: direction.normalize(),
+                inner_cone_angle,
+                outer_cone_angle,
+            },
+            color,
+            intensity,
+            range: None,
+            casts_shadows: false,
+        }
+    }
+}
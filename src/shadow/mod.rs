@@ -0,0 +1,439 @@
+use std::sync::Arc;
+
+use cgmath::{Deg, EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, SquareMatrix, Vector3, Vector4};
+use vulkano::buffer::{BufferAccess, BufferUsage, CpuBufferPool};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::DescriptorSet;
+use vulkano::device::Device;
+use vulkano::format::{ClearValue, Format};
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::render_pass::Subpass;
+
+use crate::animation::SkinnedVertex;
+use crate::culling::{Culling, CullingMode, Frustum, ViewStats};
+use crate::debug::ObjectNamer;
+use crate::render_graph::{ImageDesc, ImageId, ImageSize, Load, Pass, PassBuilder, PassContext, RenderGraphBuilder};
+use crate::scene;
+use crate::scene::{AlphaMode, Camera, LightKind, Material, MeshVertex, Scene};
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/shadow/shadow.vert"
+    }
+}
+
+mod skinned_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/shadow/skinned.vert"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/shadow/shadow.frag"
+    }
+}
+
+pub const FORMAT: Format = Format::D32Sfloat;
+
+/// How many shadow maps there are, how big they are and how they are sampled.
+///
+/// The first directional light that casts shadows gets cascaded shadow maps. Spot and point lights that cast
+/// shadows get one map (six for point lights, one per cube face) each in scene order, until they run out.
+#[derive(Debug, Copy, Clone)]
+pub struct ShadowSettings {
+    /// Cascades of the directional light shadow, from 1 to 4.
+    pub cascades: u32,
+    pub cascade_resolution: u32,
+    /// Distance from the camera up to which directional light shadows are drawn.
+    pub cascade_distance: f32,
+    /// Where cascades are split, from evenly (0) to logarithmically (1).
+    pub cascade_split_lambda: f32,
+    /// Spot lights that can cast shadows at once, at least 1.
+    pub spot_lights: u32,
+    pub spot_resolution: u32,
+    /// Point lights that can cast shadows at once, at least 1.
+    pub point_lights: u32,
+    pub point_resolution: u32,
+    /// Near plane of spot and point light shadows.
+    pub near: f32,
+    /// Far plane of spot and point light shadows for lights without a range.
+    pub far: f32,
+    /// Subtracted from the depth of every lookup, against shadow acne.
+    pub depth_bias: f32,
+    /// Added to `depth_bias` per unit of the tangent of the angle between the light and the surface normal.
+    pub slope_bias: f32,
+    /// World space distance lookups are moved along the surface normal.
+    pub normal_bias: f32,
+    /// Lookups average a square of (2 * `pcf_radius` + 1)² bilinear depth comparisons.
+    pub pcf_radius: u32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> ShadowSettings {
+        ShadowSettings {
+            cascades: 4,
+            cascade_resolution: 2048,
+            cascade_distance: 50.0,
+            cascade_split_lambda: 0.75,
+            spot_lights: 4,
+            spot_resolution: 1024,
+            point_lights: 2,
+            point_resolution: 512,
+            near: 0.05,
+            far: 50.0,
+            depth_bias: 0.0005,
+            slope_bias: 0.001,
+            normal_bias: 0.02,
+            pcf_radius: 1,
+        }
+    }
+}
+
+/// One of the three layered shadow map images.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ShadowMap {
+    Cascades,
+    Spot,
+    Point,
+}
+
+impl ShadowMap {
+    fn layers(self, settings: &ShadowSettings) -> u32 {
+        match self {
+            ShadowMap::Cascades => settings.cascades,
+            ShadowMap::Spot => settings.spot_lights,
+            ShadowMap::Point => settings.point_lights * 6,
+        }
+    }
+
+    fn resolution(self, settings: &ShadowSettings) -> u32 {
+        match self {
+            ShadowMap::Cascades => settings.cascade_resolution,
+            ShadowMap::Spot => settings.spot_resolution,
+            ShadowMap::Point => settings.point_resolution,
+        }
+    }
+
+    /// Index of the matrix of the map's first layer in `ShadowViews::matrices`.
+    fn first_matrix(self, settings: &ShadowSettings) -> usize {
+        match self {
+            ShadowMap::Cascades => 0,
+            ShadowMap::Spot => settings.cascades as usize,
+            ShadowMap::Point => (settings.cascades + settings.spot_lights) as usize,
+        }
+    }
+}
+
+/// The shadow map images of a render graph, for passes that sample them.
+#[derive(Debug, Copy, Clone)]
+pub struct ShadowMaps {
+    pub settings: ShadowSettings,
+    pub cascades: ImageId,
+    pub spot: ImageId,
+    pub point: ImageId,
+}
+
+/// Adds a depth-only pass rendering each kind of shadow map. They go before the passes sampling the maps.
+pub fn add_passes(graph: &mut RenderGraphBuilder<Scene>, settings: ShadowSettings, culling: &Culling, namer: &ObjectNamer) -> ShadowMaps {
+    assert!((1..=4).contains(&settings.cascades), "there have to be between 1 and 4 shadow cascades");
+    assert!(settings.spot_lights >= 1 && settings.point_lights >= 1, "there has to be room for at least one spot and point light shadow");
+
+    let mut add = |map: ShadowMap, image: &'static str, pass: &'static str| {
+        let resolution = map.resolution(&settings);
+        let desc = ImageDesc { layers: map.layers(&settings), ..ImageDesc::new(FORMAT, ImageSize::Fixed([resolution, resolution])) };
+        let target = graph.image(image, desc);
+        graph.add_pass(pass, ShadowPass::new(map, target, settings, culling.clone(), namer));
+        target
+    };
+
+    ShadowMaps {
+        cascades: add(ShadowMap::Cascades, "cascaded shadow map", "cascaded shadows"),
+        spot: add(ShadowMap::Spot, "spot shadow map", "spot shadows"),
+        point: add(ShadowMap::Point, "point shadow map", "point shadows"),
+        settings,
+    }
+}
+
+/// Shadow map of one light.
+#[derive(Debug, Copy, Clone)]
+pub struct LightShadow {
+    /// First layer in the light's shadow map image: its first cascade or cube face.
+    pub layer: u32,
+    /// Index of the matrix of that layer in `ShadowViews::matrices`.
+    pub matrix: u32,
+}
+
+/// Where every shadow map layer looks from in a frame.
+///
+/// The shadow passes and the passes sampling their maps each compute it from the same scene, settings and
+/// backbuffer aspect ratio, so they agree without sharing state.
+pub struct ShadowViews {
+    /// Light view-projection of every layer: the cascades, then the spot lights, then six per point light.
+    pub matrices: Vec<Matrix4<f32>>,
+    /// View space depth at which each cascade ends.
+    pub cascade_splits: [f32; 4],
+    /// Shadow map of every light of the scene, if it got one.
+    pub lights: Vec<Option<LightShadow>>,
+    // Layers with a light in them, per map.
+    used: [u32; 3],
+}
+
+impl ShadowViews {
+    pub fn new(scene: &Scene, settings: &ShadowSettings, aspect: f32) -> ShadowViews {
+        let mut views = ShadowViews {
+            matrices: vec![Matrix4::identity(); (settings.cascades + settings.spot_lights + settings.point_lights * 6) as usize],
+            cascade_splits: [0.0; 4],
+            lights: vec![None; scene.lights.len()],
+            used: [0; 3],
+        };
+
+        let far = scene.camera.far.min(settings.cascade_distance);
+        for cascade in 0..settings.cascades as usize {
+            let fraction = (cascade + 1) as f32 / settings.cascades as f32;
+            let uniform = scene.camera.near + (far - scene.camera.near) * fraction;
+            let logarithmic = scene.camera.near * (far / scene.camera.near).powf(fraction);
+            views.cascade_splits[cascade] = uniform + (logarithmic - uniform) * settings.cascade_split_lambda;
+        }
+
+        for (index, light) in scene.lights.iter().enumerate().filter(|(_, light)| light.casts_shadows) {
+            let map = match light.kind {
+                LightKind::Directional { .. } => ShadowMap::Cascades,
+                LightKind::Spot { .. } => ShadowMap::Spot,
+                LightKind::Point { .. } => ShadowMap::Point,
+            };
+            let slot = map as usize;
+            let layers = if map == ShadowMap::Point { 6 } else { 1 };
+            // Directional lights take every cascade at once.
+            if views.used[slot] + layers > map.layers(settings) || (map == ShadowMap::Cascades && views.used[slot] > 0) {
+                continue;
+            }
+
+            let layer = views.used[slot];
+            let first = map.first_matrix(settings) + layer as usize;
+            match light.kind {
+                LightKind::Directional { direction } => {
+                    let mut near = scene.camera.near;
+                    for cascade in 0..settings.cascades as usize {
+                        let far = views.cascade_splits[cascade];
+                        views.matrices[cascade] = cascade_matrix(&scene.camera, aspect, near, far, direction, settings);
+                        near = far;
+                    }
+                    views.used[slot] = settings.cascades;
+                }
+                LightKind::Spot { position, direction, outer_cone_angle, .. } => {
+                    let fov = Rad((outer_cone_angle.0 * 2.0).min(Rad::from(Deg(170.0)).0));
+                    let projection = scene::gl_to_vulkan() * cgmath::perspective(fov, 1.0, settings.near, light.range.unwrap_or(settings.far));
+                    views.matrices[first] = projection * Matrix4::look_at_rh(position, position + direction, up_for(direction));
+                    views.used[slot] += 1;
+                }
+                LightKind::Point { position } => {
+                    let projection = scene::gl_to_vulkan() * cgmath::perspective(Deg(90.0), 1.0, settings.near, light.range.unwrap_or(settings.far));
+                    // In the order the fragment shader picks faces in: +x, -x, +y, -y, +z, -z.
+                    let faces = [Vector3::unit_x(), -Vector3::unit_x(), Vector3::unit_y(), -Vector3::unit_y(), Vector3::unit_z(), -Vector3::unit_z()];
+                    for (face, &direction) in faces.iter().enumerate() {
+                        views.matrices[first + face] = projection * Matrix4::look_at_rh(position, position + direction, up_for(direction));
+                    }
+                    views.used[slot] += 6;
+                }
+            }
+            views.lights[index] = Some(LightShadow { layer, matrix: first as u32 });
+        }
+
+        views
+    }
+
+    fn used(&self, map: ShadowMap) -> u32 {
+        self.used[map as usize]
+    }
+}
+
+fn up_for(direction: Vector3<f32>) -> Vector3<f32> {
+    if direction.normalize().y.abs() > 0.99 { Vector3::unit_z() } else { Vector3::unit_y() }
+}
+
+/// Orthographic light view-projection covering the slice of the camera's view between `near` and `far`.
+///
+/// The slice is bounded by a sphere so the cascade keeps its size as the camera turns, and its position is snapped
+/// to whole texels so the shadow edges don't shimmer as the camera moves.
+fn cascade_matrix(camera: &Camera, aspect: f32, near: f32, far: f32, direction: Vector3<f32>, settings: &ShadowSettings) -> Matrix4<f32> {
+    let camera_to_world = camera.view().invert().unwrap();
+    let tan_y = (camera.fov_y.0 / 2.0).tan();
+    let tan_x = tan_y * aspect;
+
+    let mut corners = vec![];
+    for &depth in [near, far].iter() {
+        for &(x, y) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter() {
+            let corner = camera_to_world * Vector4::new(x * tan_x * depth, y * tan_y * depth, -depth, 1.0);
+            corners.push(Point3::from_homogeneous(corner));
+        }
+    }
+    let center = Point3::centroid(&corners);
+    let radius = corners.iter().map(|corner| (corner - center).magnitude()).fold(0.0, f32::max);
+    // Rounded up so floating point noise doesn't change the size of a texel from frame to frame.
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    // Casters up to `cascade_distance` in front of the slice, towards the light, still make it in.
+    let back = radius + settings.cascade_distance;
+    let view = Matrix4::look_at_rh(center - direction * back, center, up_for(direction));
+    let mut projection = scene::gl_to_vulkan() * cgmath::ortho(-radius, radius, -radius, radius, 0.0, back + radius);
+
+    let resolution = settings.cascade_resolution as f32;
+    let origin = (projection * view) * Vector4::new(0.0, 0.0, 0.0, 1.0);
+    let texels = [origin.x * resolution / 2.0, origin.y * resolution / 2.0];
+    projection.w.x += (texels[0].round() - texels[0]) * 2.0 / resolution;
+    projection.w.y += (texels[1].round() - texels[1]) * 2.0 / resolution;
+
+    projection * view
+}
+
+/// Renders the depth of every object into each layer of one shadow map, skipping the ones outside the layer's view
+/// unless culling is off.
+pub struct ShadowPass {
+    map: ShadowMap,
+    target: ImageId,
+    settings: ShadowSettings,
+    culling: Culling,
+    namer: ObjectNamer,
+    pipeline: Option<Arc<dyn GraphicsPipelineAbstract + Send + Sync>>,
+    skinned_pipeline: Option<Arc<dyn GraphicsPipelineAbstract + Send + Sync>>,
+    joints: Option<CpuBufferPool<[[f32; 4]; 4]>>,
+    // Computed when the first layer is recorded, with the joint matrices of every skinned object.
+    views: Option<ShadowViews>,
+    joint_sets: Vec<Arc<dyn DescriptorSet + Send + Sync>>,
+    visible: Vec<usize>,
+}
+
+impl ShadowPass {
+    pub fn new(map: ShadowMap, target: ImageId, settings: ShadowSettings, culling: Culling, namer: &ObjectNamer) -> ShadowPass {
+        ShadowPass {
+            map,
+            target,
+            settings,
+            culling,
+            namer: namer.clone(),
+            pipeline: None,
+            skinned_pipeline: None,
+            joints: None,
+            views: None,
+            joint_sets: vec![],
+            visible: vec![],
+        }
+    }
+
+    fn stats_name(&self) -> String {
+        format!("{:?} shadows", self.map).to_lowercase()
+    }
+}
+
+impl Pass<Scene> for ShadowPass {
+    fn declare(&mut self, pass: &mut PassBuilder) {
+        pass.depth(self.target, Load::Clear(ClearValue::Depth(1.0)));
+    }
+
+    fn prepare(&mut self, device: &Arc<Device>, subpass: Option<Subpass>) {
+        let vs = vs::Shader::load(device.clone()).unwrap();
+        let skinned_vs = skinned_vs::Shader::load(device.clone()).unwrap();
+        let fs = fs::Shader::load(device.clone()).unwrap();
+        let subpass = subpass.expect("shadow passes render to a depth attachment");
+
+        // Both sides are drawn, so open meshes and double sided materials cast shadows too.
+        let pipeline = Arc::new(GraphicsPipeline::start().vertex_input_single_buffer::<MeshVertex>()
+                                                         .vertex_shader(vs.main_entry_point(), ())
+                                                         .triangle_list()
+                                                         .viewports_dynamic_scissors_irrelevant(1)
+                                                         .fragment_shader(fs.main_entry_point(), ())
+                                                         .depth_stencil_simple_depth()
+                                                         .cull_mode_disabled()
+                                                         .render_pass(subpass.clone())
+                                                         .build(device.clone())
+                                                         .unwrap());
+        let skinned_pipeline = Arc::new(GraphicsPipeline::start().vertex_input_single_buffer::<SkinnedVertex>()
+                                                                 .vertex_shader(skinned_vs.main_entry_point(), ())
+                                                                 .triangle_list()
+                                                                 .viewports_dynamic_scissors_irrelevant(1)
+                                                                 .fragment_shader(fs.main_entry_point(), ())
+                                                                 .depth_stencil_simple_depth()
+                                                                 .cull_mode_disabled()
+                                                                 .render_pass(subpass)
+                                                                 .build(device.clone())
+                                                                 .unwrap());
+        self.namer.name(&*pipeline, &format!("{:?} shadow pipeline", self.map));
+        self.namer.name(&*skinned_pipeline, &format!("{:?} skinned shadow pipeline", self.map));
+        self.pipeline = Some(pipeline);
+        self.skinned_pipeline = Some(skinned_pipeline);
+        self.joints = Some(CpuBufferPool::new(device.clone(), BufferUsage { storage_buffer: true, ..BufferUsage::none() }));
+    }
+
+    fn record(&mut self, context: &mut PassContext, scene: &Scene) {
+        if context.layer == 0 {
+            let aspect = context.backbuffer_dimensions[0] as f32 / context.backbuffer_dimensions[1] as f32;
+            self.views = Some(ShadowViews::new(scene, &self.settings, aspect));
+
+            let layout = self.skinned_pipeline.as_ref().unwrap().descriptor_set_layout(0).unwrap().clone();
+            let joints = self.joints.as_ref().unwrap();
+            self.joint_sets = scene.skinned
+                                   .iter()
+                                   .map(|object| {
+                                       let joints = joints.chunk(object.joint_matrices().into_iter().map(Into::into).collect::<Vec<_>>()).unwrap();
+                                       Arc::new(PersistentDescriptorSet::start(layout.clone()).add_buffer(joints).unwrap().build().unwrap()) as Arc<dyn DescriptorSet + Send + Sync>
+                                   })
+                                   .collect();
+            self.culling.stats.lock().unwrap().views.insert(self.stats_name(), ViewStats::default());
+        }
+        let views = self.views.as_ref().unwrap();
+        // Layers without a light are only cleared.
+        if context.layer >= views.used(self.map) {
+            return;
+        }
+        let light_view_projection = views.matrices[self.map.first_matrix(&self.settings) + context.layer as usize];
+
+        if *self.culling.mode.lock().unwrap() == CullingMode::Off {
+            self.visible.clear();
+            self.visible.extend(0..scene.objects.len());
+        } else {
+            scene.bvh.cull(&Frustum::from_matrix(light_view_projection), &mut self.visible);
+        }
+        if let Some(view) = self.culling.stats.lock().unwrap().views.get_mut(&self.stats_name()) {
+            view.drawn += self.visible.len();
+            view.culled += scene.objects.len() - self.visible.len();
+        }
+
+        // Objects take their current LOD level right away in shadows, without crossfading.
+        for object in self.visible.iter().map(|&index| &scene.objects[index]).filter(|object| object.shown() && casts_shadow(&object.material)) {
+            let model_view_projection: [[f32; 4]; 4] = (light_view_projection * object.transform).into();
+            context.builder
+                   .draw_indexed(self.pipeline.clone().unwrap(),
+                                 context.dynamic_state,
+                                 vec![object.mesh.vertices.clone() as Arc<dyn BufferAccess + Send + Sync>],
+                                 object.mesh.indices.clone(),
+                                 (),
+                                 model_view_projection,
+                                 vec![])
+                   .unwrap();
+        }
+
+        for (object, joint_set) in scene.skinned.iter().zip(self.joint_sets.iter()) {
+            let model_view_projection: [[f32; 4]; 4] = (light_view_projection * object.transform).into();
+            for part in object.parts.iter().filter(|part| casts_shadow(&part.material)) {
+                context.builder
+                       .draw_indexed(self.skinned_pipeline.clone().unwrap(),
+                                     context.dynamic_state,
+                                     vec![part.mesh.vertices.clone() as Arc<dyn BufferAccess + Send + Sync>],
+                                     part.mesh.indices.clone(),
+                                     joint_set.clone(),
+                                     model_view_projection,
+                                     vec![])
+                       .unwrap();
+            }
+        }
+    }
+}
+
+/// Blended surfaces let light through, so they don't cast shadows.
+fn casts_shadow(material: &Material) -> bool {
+    !matches!(material.alpha_mode, AlphaMode::Blend(_))
+}
@@ -0,0 +1,11 @@
+#version 450
+
+layout(location = 0) in vec3 position;
+
+layout(push_constant) uniform Draw {
+    mat4 model_view_projection;
+} draw;
+
+void main() {
+    gl_Position = draw.model_view_projection * vec4(position, 1.0);
+}
@@ -0,0 +1,18 @@
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 4) in uvec4 joints;
+layout(location = 5) in vec4 weights;
+
+layout(set = 0, binding = 0) readonly buffer Joints {
+    mat4 joint_matrices[];
+};
+
+layout(push_constant) uniform Draw {
+    mat4 model_view_projection;
+} draw;
+
+void main() {
+    mat4 skin = weights.x * joint_matrices[joints.x] + weights.y * joint_matrices[joints.y] + weights.z * joint_matrices[joints.z] + weights.w * joint_matrices[joints.w];
+    gl_Position = draw.model_view_projection * skin * vec4(position, 1.0);
+}
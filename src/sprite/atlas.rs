@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+use image::{imageops, RgbaImage};
+
+use crate::debug::ObjectNamer;
+use crate::scene::{Material, Texture};
+use crate::sprite::Sprite;
+use crate::upload::UploadManager;
+
+/// How images are packed into atlas pages.
+#[derive(Debug, Copy, Clone)]
+pub struct AtlasSettings {
+    /// Largest width and height of a page. Pages shrink to what they use.
+    pub max_size: u32,
+    /// Empty pixels between packed images.
+    pub padding: u32,
+    /// Pixels every image's border is repeated outwards by, so filtering at its edges doesn't pick up neighbours.
+    pub extrude: u32,
+    /// Whether the pixels are sRGB colors rather than data.
+    pub srgb: bool,
+}
+
+impl Default for AtlasSettings {
+    fn default() -> AtlasSettings {
+        AtlasSettings {
+            max_size: 2048,
+            padding: 2,
+            extrude: 1,
+            srgb: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum AtlasError {
+    /// An image is larger than a page, even by itself.
+    TooLarge { key: String, size: [u32; 2] },
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AtlasError::TooLarge { key, size } => write!(f, "{} is {}x{}, too large for an atlas page", key, size[0], size[1]),
+        }
+    }
+}
+
+/// Where a packed image ended up.
+#[derive(Clone)]
+pub struct AtlasRegion {
+    pub texture: Texture,
+    /// Minimum and maximum UV, as in `Sprite::region`.
+    pub uv: [f32; 4],
+    /// Size in pixels.
+    pub size: [u32; 2],
+}
+
+impl AtlasRegion {
+    /// A sprite showing this region, centered on `position`, `pixels_per_unit` pixels of it to a world unit.
+    pub fn sprite(&self, position: [f32; 2], pixels_per_unit: f32) -> Sprite {
+        Sprite {
+            region: self.uv,
+            ..Sprite::new(self.texture.clone(), position, [self.size[0] as f32 / pixels_per_unit, self.size[1] as f32 / pixels_per_unit])
+        }
+    }
+}
+
+/// Images packed into as few textures as possible, found by key.
+pub struct Atlas<K = String> {
+    regions: HashMap<K, AtlasRegion>,
+}
+
+impl<K: Hash + Eq> Atlas<K> {
+    pub fn region(&self, key: &K) -> Option<&AtlasRegion> {
+        self.regions.get(key)
+    }
+}
+
+/// Collects images and packs them into an `Atlas`, tallest first, each page with a skyline bottom-left packer.
+pub struct AtlasBuilder<K = String> {
+    settings: AtlasSettings,
+    images: Vec<(K, RgbaImage)>,
+}
+
+impl<K: Hash + Eq + Clone + fmt::Debug> AtlasBuilder<K> {
+    pub fn new(settings: AtlasSettings) -> AtlasBuilder<K> {
+        AtlasBuilder { settings, images: vec![] }
+    }
+
+    pub fn add(&mut self, key: K, image: RgbaImage) -> &mut AtlasBuilder<K> {
+        self.images.push((key, image));
+        self
+    }
+
+    /// Packs and uploads the pages, named `name` and their index.
+    pub fn build(mut self, uploads: &mut UploadManager, namer: &ObjectNamer, name: &str) -> Result<Atlas<K>, AtlasError> {
+        let AtlasSettings { max_size, padding, extrude, srgb } = self.settings;
+        let border = extrude * 2 + padding;
+
+        if let Some((key, image)) = self.images.iter().find(|(_, image)| image.width() + border > max_size || image.height() + border > max_size) {
+            return Err(AtlasError::TooLarge { key: format!("{:?}", key), size: [image.width(), image.height()] });
+        }
+
+        self.images.sort_by(|(_, a), (_, b)| b.height().cmp(&a.height()).then(b.width().cmp(&a.width())));
+
+        // Every image goes on the first page it fits on, a new page when none has room.
+        let mut skylines: Vec<Skyline> = vec![];
+        let mut placements = vec![];
+        for (_, image) in self.images.iter() {
+            let size = [image.width() + border, image.height() + border];
+            let placed = skylines.iter_mut().enumerate().find_map(|(page, skyline)| skyline.insert(size).map(|position| (page, position)));
+            let (page, position) = placed.unwrap_or_else(|| {
+                                             let mut skyline = Skyline::new(max_size);
+                                             let position = skyline.insert(size).unwrap();
+                                             skylines.push(skyline);
+                                             (skylines.len() - 1, position)
+                                         });
+            placements.push((page, [position[0] + extrude, position[1] + extrude]));
+        }
+
+        let mut pages: Vec<RgbaImage> = skylines.iter().map(|skyline| RgbaImage::new(skyline.used[0], skyline.used[1])).collect();
+        for ((_, image), &(page, position)) in self.images.iter().zip(placements.iter()) {
+            blit_extruded(&mut pages[page], image, position, extrude);
+        }
+
+        let textures: Vec<Texture> = pages.iter()
+                                          .enumerate()
+                                          .map(|(index, page)| {
+                                              let pixels: Vec<[u8; 4]> = page.pixels().map(|pixel| pixel.0).collect();
+                                              Material::texture(uploads, namer, &format!("{} {}", name, index), &pixels, [page.width(), page.height()], srgb)
+                                          })
+                                          .collect();
+
+        let regions = self.images
+                          .into_iter()
+                          .zip(placements)
+                          .map(|((key, image), (page, position))| {
+                              let (page_width, page_height) = (pages[page].width() as f32, pages[page].height() as f32);
+                              let region = AtlasRegion {
+                                  texture: textures[page].clone(),
+                                  uv: [position[0] as f32 / page_width,
+                                       position[1] as f32 / page_height,
+                                       (position[0] + image.width()) as f32 / page_width,
+                                       (position[1] + image.height()) as f32 / page_height],
+                                  size: [image.width(), image.height()],
+                              };
+                              (key, region)
+                          })
+                          .collect();
+
+        Ok(Atlas { regions })
+    }
+}
+
+/// Copies `image` to `position` on `page` and repeats its outermost pixels `extrude` times around it.
+fn blit_extruded(page: &mut RgbaImage, image: &RgbaImage, position: [u32; 2], extrude: u32) {
+    imageops::replace(page, image, position[0], position[1]);
+
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let extrude = extrude as i64;
+    for y in -extrude..height + extrude {
+        for x in -extrude..width + extrude {
+            if x >= 0 && x < width && y >= 0 && y < height {
+                continue;
+            }
+            let source = image.get_pixel(x.clamp(0, width - 1) as u32, y.clamp(0, height - 1) as u32);
+            page.put_pixel((position[0] as i64 + x) as u32, (position[1] as i64 + y) as u32, *source);
+        }
+    }
+}
+
+/// The top edge of what's packed so far, as segments from left to right.
+pub(crate) struct Skyline {
+    max_size: u32,
+    // x, y and width of every segment.
+    segments: Vec<(u32, u32, u32)>,
+    // Extent of the packed rectangles.
+    used: [u32; 2],
+}
+
+impl Skyline {
+    pub(crate) fn new(max_size: u32) -> Skyline {
+        Skyline {
+            max_size,
+            segments: vec![(0, 0, max_size)],
+            used: [0, 0],
+        }
+    }
+
+    /// Places a rectangle as close to the top as it fits, then as far left, and returns its top left corner.
+    pub(crate) fn insert(&mut self, size: [u32; 2]) -> Option<[u32; 2]> {
+        let mut best: Option<(usize, u32, u32)> = None;
+        for index in 0..self.segments.len() {
+            let x = self.segments[index].0;
+            if x + size[0] > self.max_size {
+                break;
+            }
+            // Resting on the highest segment under the rectangle.
+            let mut y = 0;
+            let mut covered = 0;
+            for &(_, segment_y, segment_width) in self.segments[index..].iter() {
+                y = y.max(segment_y);
+                covered += segment_width;
+                if covered >= size[0] {
+                    break;
+                }
+            }
+            let better = match best {
+                Some((_, _, best_y)) => y < best_y,
+                None => true,
+            };
+            if y + size[1] <= self.max_size && better {
+                best = Some((index, x, y));
+            }
+        }
+
+        let (index, x, y) = best?;
+        self.segments.insert(index, (x, y + size[1], size[0]));
+
+        // Cut the segments now under the new one.
+        let right = x + size[0];
+        let next = index + 1;
+        while next < self.segments.len() {
+            let (segment_x, segment_y, segment_width) = self.segments[next];
+            if segment_x >= right {
+                break;
+            }
+            if segment_x + segment_width <= right {
+                self.segments.remove(next);
+            } else {
+                self.segments[next] = (right, segment_y, segment_x + segment_width - right);
+                break;
+            }
+        }
+
+        // Merge neighbours at the same height.
+        let mut merged: Vec<(u32, u32, u32)> = Vec::with_capacity(self.segments.len());
+        for &segment in self.segments.iter() {
+            match merged.last_mut() {
+                Some(last) if last.1 == segment.1 => last.2 += segment.2,
+                _ => merged.push(segment),
+            }
+        }
+        self.segments = merged;
+
+        self.used = [self.used[0].max(right), self.used[1].max(y + size[1])];
+        Some([x, y])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skyline_starts_a_new_row() {
+        let mut skyline = Skyline::new(64);
+        assert_eq!(skyline.insert([40, 10]), Some([0, 0]));
+        // Too wide for what's left of the first row, so it goes on top of the first rectangle.
+        assert_eq!(skyline.insert([30, 10]), Some([0, 10]));
+        // Narrow enough for the first row again.
+        assert_eq!(skyline.insert([20, 20]), Some([40, 0]));
+        assert_eq!(skyline.insert([10, 5]), Some([30, 10]));
+        assert_eq!(skyline.used, [60, 20]);
+    }
+
+    #[test]
+    fn skyline_overflow() {
+        let mut skyline = Skyline::new(64);
+        assert_eq!(skyline.insert([65, 1]), None);
+        assert_eq!(skyline.insert([64, 60]), Some([0, 0]));
+        assert_eq!(skyline.insert([1, 5]), None);
+        assert_eq!(skyline.insert([64, 4]), Some([0, 60]));
+        assert_eq!(skyline.used, [64, 64]);
+    }
+}
@@ -0,0 +1,57 @@
+use cgmath::Matrix4;
+
+use crate::scene;
+
+/// Orthographic camera for sprites. The world's y points up and a world unit is `pixels_per_unit` pixels at zoom 1.
+#[derive(Debug, Copy, Clone)]
+pub struct Camera2d {
+    /// World position at the center of the screen.
+    pub position: [f32; 2],
+    pub zoom: f32,
+    pub pixels_per_unit: f32,
+    /// Rounds the zoom to a whole number and keeps the camera on the pixel grid, so every texel of sprites with
+    /// `pixels_per_unit` texels per unit covers the same whole number of pixels.
+    pub pixel_perfect: bool,
+}
+
+impl Default for Camera2d {
+    fn default() -> Camera2d {
+        Camera2d {
+            position: [0.0, 0.0],
+            zoom: 1.0,
+            pixels_per_unit: 1.0,
+            pixel_perfect: false,
+        }
+    }
+}
+
+impl Camera2d {
+    /// Pixels per world unit.
+    pub fn scale(&self) -> f32 {
+        let zoom = if self.pixel_perfect { self.zoom.round().max(1.0) } else { self.zoom };
+        self.pixels_per_unit * zoom
+    }
+
+    pub fn view_projection(&self, viewport: [u32; 2]) -> Matrix4<f32> {
+        let (scale, center) = (self.scale(), self.center(viewport));
+        let (half_width, half_height) = (viewport[0] as f32 / 2.0 / scale, viewport[1] as f32 / 2.0 / scale);
+        scene::gl_to_vulkan() * cgmath::ortho(center[0] - half_width, center[0] + half_width, center[1] - half_height, center[1] + half_height, -1.0, 1.0)
+    }
+
+    // World position at the center of the viewport, after snapping.
+    fn center(&self, viewport: [u32; 2]) -> [f32; 2] {
+        let scale = self.scale();
+        let mut center = self.position;
+        if self.pixel_perfect {
+            for axis in 0..2 {
+                center[axis] = (center[axis] * scale).round() / scale;
+                // With an odd number of pixels the center is in the middle of one, which would put pixel edges
+                // halfway across texels.
+                if viewport[axis] % 2 == 1 {
+                    center[axis] += 0.5 / scale;
+                }
+            }
+        }
+        center
+    }
+}
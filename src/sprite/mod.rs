@@ -0,0 +1,256 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use cgmath::Rad;
+use tracing::trace;
+use vulkano::buffer::{BufferAccess, BufferSlice, BufferUsage, CpuBufferPool};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::image::view::ImageView;
+use vulkano::pipeline::vertex::OneVertexOneInstanceDefinition;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::render_pass::Subpass;
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+pub use crate::sprite::atlas::{AtlasBuilder, AtlasSettings};
+pub use crate::sprite::camera::Camera2d;
+pub use crate::sprite::sheet::{AnimatedSprite, SpriteSheet};
+pub(crate) use crate::sprite::atlas::Skyline;
+
+use crate::allocator::{MemoryCategory, PooledBuffer, PooledImage};
+use crate::debug::ObjectNamer;
+use crate::logging;
+use crate::render_graph::{ImageId, Load, Pass, PassBuilder, PassContext};
+use crate::scene::{Scene, Texture};
+use crate::upload::UploadManager;
+
+mod atlas;
+mod camera;
+mod sheet;
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/sprite/sprite.vert"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/sprite/sprite.frag"
+    }
+}
+
+/// A textured quad.
+#[derive(Clone)]
+pub struct Sprite {
+    pub texture: Texture,
+    /// The part of the texture drawn, as minimum and maximum UV. Lets sprites share an atlas.
+    pub region: [f32; 4],
+    /// World position of `origin`.
+    pub position: [f32; 2],
+    /// Size in world units. Negative sizes mirror the sprite.
+    pub size: [f32; 2],
+    /// Point the sprite is placed and rotated by, from (0, 0) at its bottom left to (1, 1) at its top right.
+    pub origin: [f32; 2],
+    /// Counter-clockwise.
+    pub rotation: Rad<f32>,
+    /// Linear RGBA the texture is multiplied with.
+    pub color: [f32; 4],
+    /// Sprites on higher layers are drawn over those on lower ones.
+    pub layer: i32,
+    /// Order within a layer, higher is in front. Sprites with the same layer and z are drawn in scene order.
+    pub z: f32,
+}
+
+impl Sprite {
+    /// The whole of `texture`, centered on `position`, untinted on layer 0.
+    pub fn new(texture: Texture, position: [f32; 2], size: [f32; 2]) -> Sprite {
+        Sprite {
+            texture,
+            region: [0.0, 0.0, 1.0, 1.0],
+            position,
+            size,
+            origin: [0.5, 0.5],
+            rotation: Rad(0.0),
+            color: [1.0, 1.0, 1.0, 1.0],
+            layer: 0,
+            z: 0.0,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+struct QuadCorner {
+    corner: [f32; 2],
+}
+vulkano::impl_vertex!(QuadCorner, corner);
+
+/// What `sprite.vert` gets per sprite.
+#[derive(Default, Debug, Clone, Copy)]
+struct SpriteInstance {
+    position: [f32; 2],
+    size: [f32; 2],
+    origin: [f32; 2],
+    rotation: [f32; 2],
+    region: [f32; 4],
+    color: [f32; 4],
+}
+vulkano::impl_vertex!(SpriteInstance, position, size, origin, rotation, region, color);
+
+impl SpriteInstance {
+    fn new(sprite: &Sprite) -> SpriteInstance {
+        let Rad(rotation) = sprite.rotation;
+        SpriteInstance {
+            position: sprite.position,
+            size: sprite.size,
+            origin: sprite.origin,
+            rotation: [rotation.cos(), rotation.sin()],
+            region: sprite.region,
+            color: sprite.color,
+        }
+    }
+}
+
+type SpritePipeline = GraphicsPipeline<OneVertexOneInstanceDefinition<QuadCorner, SpriteInstance>, Box<dyn PipelineLayoutAbstract + Send + Sync>>;
+
+type TextureSets = HashMap<*const ImageView<Arc<PooledImage>>, (Texture, Arc<dyn DescriptorSet + Send + Sync>)>;
+
+/// Draws the scene's sprites with alpha blending over `target`, seen through the scene's `Camera2d`.
+///
+/// Sprites are sorted by layer and z, and every run of sprites sharing a texture is drawn as one instanced draw.
+/// Sprites packed into an atlas therefore take a single draw call however many there are.
+pub struct SpritePass {
+    target: ImageId,
+    load: Load,
+    // Draws the scene's UI sprites in pixels instead.
+    ui: bool,
+    quad: Arc<PooledBuffer<[QuadCorner]>>,
+    sampler: Arc<Sampler>,
+    instances: CpuBufferPool<SpriteInstance>,
+    namer: ObjectNamer,
+    pipeline: Option<Arc<SpritePipeline>>,
+    // Descriptor set of every texture drawn lately, keyed by its address. Holding the texture keeps the address
+    // from being reused.
+    textures: TextureSets,
+}
+
+impl SpritePass {
+    /// `filter` is how textures are magnified, `Filter::Nearest` for pixel art.
+    pub fn new(device: Arc<Device>, target: ImageId, load: Load, filter: Filter, uploads: &mut UploadManager, namer: &ObjectNamer) -> SpritePass {
+        let corners = [QuadCorner { corner: [0.0, 0.0] }, QuadCorner { corner: [1.0, 0.0] }, QuadCorner { corner: [0.0, 1.0] }, QuadCorner { corner: [1.0, 1.0] }];
+        let quad = uploads.buffer(&corners, BufferUsage::vertex_buffer(), MemoryCategory::Mesh).expect("failed to create sprite quad");
+        namer.name_buffer(&*quad, "sprite quad");
+
+        let sampler = Sampler::new(device.clone(),
+                                   filter,
+                                   Filter::Linear,
+                                   MipmapMode::Nearest,
+                                   SamplerAddressMode::ClampToEdge,
+                                   SamplerAddressMode::ClampToEdge,
+                                   SamplerAddressMode::ClampToEdge,
+                                   0.0,
+                                   1.0,
+                                   0.0,
+                                   0.0).unwrap();
+
+        SpritePass {
+            target,
+            load,
+            ui: false,
+            quad,
+            sampler,
+            instances: CpuBufferPool::vertex_buffer(device),
+            namer: namer.clone(),
+            pipeline: None,
+            textures: HashMap::new(),
+        }
+    }
+
+    /// Draws the scene's UI sprites instead, in pixels from the bottom left of `target`.
+    pub fn ui(device: Arc<Device>, target: ImageId, load: Load, filter: Filter, uploads: &mut UploadManager, namer: &ObjectNamer) -> SpritePass {
+        SpritePass { ui: true, ..SpritePass::new(device, target, load, filter, uploads, namer) }
+    }
+
+    fn descriptor_set(&mut self, texture: &Texture) -> Arc<dyn DescriptorSet + Send + Sync> {
+        let (pipeline, sampler) = (&self.pipeline, &self.sampler);
+        let (_, set) = self.textures.entry(Arc::as_ptr(texture)).or_insert_with(|| {
+            let layout = pipeline.as_ref().unwrap().descriptor_set_layout(0).unwrap().clone();
+            let set = Arc::new(PersistentDescriptorSet::start(layout).add_sampler(sampler.clone()).unwrap().add_image(texture.clone()).unwrap().build().unwrap());
+            (texture.clone(), set as Arc<dyn DescriptorSet + Send + Sync>)
+        });
+        set.clone()
+    }
+}
+
+impl Pass<Scene> for SpritePass {
+    fn declare(&mut self, pass: &mut PassBuilder) {
+        pass.color(self.target, self.load);
+    }
+
+    fn prepare(&mut self, device: &Arc<Device>, subpass: Option<Subpass>) {
+        let vs = vs::Shader::load(device.clone()).unwrap();
+        let fs = fs::Shader::load(device.clone()).unwrap();
+        let pipeline = Arc::new(GraphicsPipeline::start().vertex_input(OneVertexOneInstanceDefinition::<QuadCorner, SpriteInstance>::new())
+                                                         .vertex_shader(vs.main_entry_point(), ())
+                                                         .triangle_strip()
+                                                         .viewports_dynamic_scissors_irrelevant(1)
+                                                         .fragment_shader(fs.main_entry_point(), ())
+                                                         .blend_alpha_blending()
+                                                         .cull_mode_disabled()
+                                                         .render_pass(subpass.expect("the sprite pass renders to an attachment"))
+                                                         .build(device.clone())
+                                                         .unwrap());
+        self.namer.name(&*pipeline, "sprite pipeline");
+        self.pipeline = Some(pipeline);
+        self.textures.clear();
+    }
+
+    fn record(&mut self, context: &mut PassContext, scene: &Scene) {
+        // Forget textures the scene no longer uses.
+        self.textures.retain(|_, (texture, _)| Arc::strong_count(texture) > 1);
+
+        let sprites = if self.ui { &scene.ui_sprites } else { &scene.sprites };
+        let animated: Vec<Sprite> = if self.ui { vec![] } else { scene.animated_sprites.iter().map(AnimatedSprite::sprite).collect() };
+        if sprites.is_empty() && animated.is_empty() {
+            return;
+        }
+
+        // Stable, so ties keep scene order.
+        let mut order: Vec<&Sprite> = sprites.iter().chain(animated.iter()).collect();
+        order.sort_by(|a, b| a.layer.cmp(&b.layer).then(a.z.partial_cmp(&b.z).unwrap_or(Ordering::Equal)));
+
+        let instances = Arc::new(self.instances.chunk(order.iter().map(|sprite| SpriteInstance::new(sprite))).unwrap());
+        let camera = if self.ui {
+            Camera2d { position: [context.dimensions[0] as f32 / 2.0, context.dimensions[1] as f32 / 2.0], ..Camera2d::default() }
+        } else {
+            scene.sprite_camera
+        };
+        let view_projection: [[f32; 4]; 4] = camera.view_projection(context.dimensions).into();
+
+        let mut batches = 0;
+        let mut start = 0;
+        while start < order.len() {
+            let texture = &order[start].texture;
+            let end = start + order[start..].iter().take_while(|sprite| Arc::ptr_eq(&sprite.texture, texture)).count();
+
+            let set = self.descriptor_set(texture);
+            let batch = BufferSlice::from_typed_buffer_access(instances.clone()).slice(start..end).unwrap();
+            context.builder
+                   .draw(self.pipeline.clone().unwrap(),
+                         context.dynamic_state,
+                         vec![self.quad.clone() as Arc<dyn BufferAccess + Send + Sync>, Arc::new(batch)],
+                         set,
+                         view_projection,
+                         vec![])
+                   .unwrap();
+
+            batches += 1;
+            start = end;
+        }
+        trace!(target: logging::RENDER, ui = self.ui, sprites = sprites.len(), batches, "drew sprites");
+    }
+}
@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use image::imageops;
+use serde::de::{MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+
+use crate::debug::ObjectNamer;
+use crate::sprite::atlas::{Atlas, AtlasBuilder, AtlasError, AtlasSettings};
+use crate::sprite::Sprite;
+use crate::upload::UploadManager;
+
+/// How long frames without a duration are shown, Aseprite's default.
+pub const DEFAULT_FRAME_DURATION: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+pub enum SheetError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Image(image::ImageError),
+    Atlas(AtlasError),
+    /// A frame tag or animation refers to frames the sheet doesn't have.
+    UnknownFrame { animation: String },
+}
+
+impl fmt::Display for SheetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SheetError::Io(e) => write!(f, "{}", e),
+            SheetError::Json(e) => write!(f, "invalid sprite sheet: {}", e),
+            SheetError::Image(e) => write!(f, "invalid sprite sheet image: {}", e),
+            SheetError::Atlas(e) => write!(f, "{}", e),
+            SheetError::UnknownFrame { animation } => write!(f, "animation {:?} refers to a frame the sheet doesn't have", animation),
+        }
+    }
+}
+
+impl From<io::Error> for SheetError {
+    fn from(e: io::Error) -> SheetError {
+        SheetError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SheetError {
+    fn from(e: serde_json::Error) -> SheetError {
+        SheetError::Json(e)
+    }
+}
+
+impl From<image::ImageError> for SheetError {
+    fn from(e: image::ImageError) -> SheetError {
+        SheetError::Image(e)
+    }
+}
+
+impl From<AtlasError> for SheetError {
+    fn from(e: AtlasError) -> SheetError {
+        SheetError::Atlas(e)
+    }
+}
+
+/// One frame of a sprite sheet. Trimmed frames only keep the part of the original image that isn't transparent.
+#[derive(Debug, Clone)]
+pub struct SheetFrame {
+    pub name: String,
+    pub duration: Duration,
+    /// Size of the original image, before trimming.
+    pub source_size: [u32; 2],
+    /// Where the trimmed frame was in the original image, from its top left.
+    pub offset: [u32; 2],
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Direction {
+    Forward,
+    Reverse,
+    /// Forward, then backward without repeating the ends.
+    PingPong,
+}
+
+/// A looping sequence of frames.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    /// Indices into `SpriteSheet::frames`, in forward order.
+    pub frames: Vec<usize>,
+    pub direction: Direction,
+}
+
+impl Animation {
+    /// The frames in the order they're played in one loop.
+    pub fn sequence(&self) -> Vec<usize> {
+        match self.direction {
+            Direction::Forward => self.frames.clone(),
+            Direction::Reverse => self.frames.iter().rev().cloned().collect(),
+            Direction::PingPong => {
+                let back = self.frames.iter().rev().skip(1).take(self.frames.len().saturating_sub(2));
+                self.frames.iter().chain(back).cloned().collect()
+            }
+        }
+    }
+
+    /// The frame showing `time` after the animation started.
+    pub fn frame_at(&self, frames: &[SheetFrame], time: Duration) -> usize {
+        let sequence = self.sequence();
+        let length: Duration = sequence.iter().map(|&frame| frames[frame].duration).sum();
+        if length == Duration::from_secs(0) {
+            return sequence[0];
+        }
+
+        let mut time = Duration::from_nanos((time.as_nanos() % length.as_nanos()) as u64);
+        for &frame in sequence.iter() {
+            if time < frames[frame].duration {
+                return frame;
+            }
+            time -= frames[frame].duration;
+        }
+        *sequence.last().unwrap()
+    }
+}
+
+/// A sprite sheet exported as JSON by Aseprite or TexturePacker, hash or array style.
+///
+/// The frames are cut out of the sheet's image and packed again into an atlas, which adds the padding and extrusion
+/// exporters tend to leave out and undoes TexturePacker's rotation. Animations come from Aseprite's frame tags and
+/// the `animations` of TexturePacker's PixiJS export.
+pub struct SpriteSheet {
+    /// Keyed by frame index.
+    pub atlas: Atlas<usize>,
+    pub frames: Vec<SheetFrame>,
+    pub animations: HashMap<String, Animation>,
+}
+
+impl SpriteSheet {
+    /// Loads the sheet at `path`, whose image is relative to it.
+    pub fn load(path: &Path, settings: AtlasSettings, uploads: &mut UploadManager, namer: &ObjectNamer) -> Result<SpriteSheet, SheetError> {
+        let parsed = parse(&fs::read_to_string(path)?)?;
+        let image_path = path.parent().unwrap_or_else(|| Path::new("")).join(&parsed.image);
+        let image = image::open(&image_path)?.to_rgba8();
+
+        let mut atlas = AtlasBuilder::new(settings);
+        for (index, &(RectJson { x, y, w, h }, rotated)) in parsed.rects.iter().enumerate() {
+            // Rotated frames are stored turned 90° clockwise.
+            let pixels = if rotated { imageops::rotate270(&imageops::crop_imm(&image, x, y, h, w).to_image()) } else { imageops::crop_imm(&image, x, y, w, h).to_image() };
+            atlas.add(index, pixels);
+        }
+
+        let atlas = atlas.build(uploads, namer, &path.display().to_string())?;
+        Ok(SpriteSheet { atlas, frames: parsed.frames, animations: parsed.animations })
+    }
+
+    /// A sprite showing `frame` with the center of its untrimmed image on `position`, `pixels_per_unit` pixels of
+    /// it to a world unit. Trimmed frames stay where they were in the original image.
+    pub fn sprite(&self, frame: usize, position: [f32; 2], pixels_per_unit: f32) -> Sprite {
+        let region = self.atlas.region(&frame).unwrap();
+        let info = &self.frames[frame];
+        let (width, height) = (region.size[0] as f32, region.size[1] as f32);
+        let (source_width, source_height) = (info.source_size[0] as f32, info.source_size[1] as f32);
+        // The sprite's origin is from its bottom left, the trim offset from the top left.
+        let bottom = source_height - info.offset[1] as f32 - height;
+        Sprite {
+            origin: [(source_width / 2.0 - info.offset[0] as f32) / width, (source_height / 2.0 - bottom) / height],
+            ..region.sprite(position, pixels_per_unit)
+        }
+    }
+}
+
+/// A sprite playing the animations of a sheet, looping the current one.
+#[derive(Clone)]
+pub struct AnimatedSprite {
+    pub sheet: Arc<SpriteSheet>,
+    pub position: [f32; 2],
+    pub pixels_per_unit: f32,
+    animation: String,
+    time: Duration,
+}
+
+impl AnimatedSprite {
+    /// Plays the sheet's `animation`, `None` if the sheet doesn't have it.
+    pub fn new(sheet: Arc<SpriteSheet>, animation: &str, position: [f32; 2], pixels_per_unit: f32) -> Option<AnimatedSprite> {
+        let mut sprite = AnimatedSprite { sheet, position, pixels_per_unit, animation: String::new(), time: Duration::from_secs(0) };
+        if sprite.play(animation) {
+            Some(sprite)
+        } else {
+            None
+        }
+    }
+
+    /// Starts over with the sheet's `animation`, or keeps playing the current one if the sheet doesn't have it.
+    pub fn play(&mut self, animation: &str) -> bool {
+        if !self.sheet.animations.contains_key(animation) {
+            return false;
+        }
+        self.animation = animation.to_string();
+        self.time = Duration::from_secs(0);
+        true
+    }
+
+    pub fn update(&mut self, elapsed: Duration) {
+        self.time += elapsed;
+    }
+
+    /// The current frame.
+    pub fn sprite(&self) -> Sprite {
+        let frame = self.sheet.animations[&self.animation].frame_at(&self.sheet.frames, self.time);
+        self.sheet.sprite(frame, self.position, self.pixels_per_unit)
+    }
+}
+
+/// A sheet's JSON, before its frames are cut out of the image.
+struct ParsedSheet {
+    image: String,
+    frames: Vec<SheetFrame>,
+    // Where each frame is in the image, and whether it's rotated.
+    rects: Vec<(RectJson, bool)>,
+    animations: HashMap<String, Animation>,
+}
+
+fn parse(json: &str) -> Result<ParsedSheet, SheetError> {
+    let json: SheetJson = serde_json::from_str(json)?;
+
+    let frames_json = match json.frames {
+        FramesJson::Array(frames) => frames.into_iter().map(|frame| (frame.filename, frame.frame)).collect(),
+        FramesJson::Hash(OrderedFrames(frames)) => frames,
+    };
+
+    let mut frames = vec![];
+    let mut rects = vec![];
+    for (name, frame) in frames_json {
+        let RectJson { w, h, .. } = frame.frame;
+        let trim = frame.sprite_source_size.unwrap_or(RectJson { x: 0, y: 0, w, h });
+        frames.push(SheetFrame {
+            name,
+            duration: frame.duration.map_or(DEFAULT_FRAME_DURATION, Duration::from_millis),
+            source_size: frame.source_size.map_or([w, h], |size| [size.w, size.h]),
+            offset: [trim.x, trim.y],
+        });
+        rects.push((frame.frame, frame.rotated));
+    }
+
+    let mut animations = HashMap::new();
+    for tag in json.meta.frame_tags {
+        if tag.from > tag.to || tag.to >= frames.len() {
+            return Err(SheetError::UnknownFrame { animation: tag.name });
+        }
+        let direction = match tag.direction.as_str() {
+            "reverse" => Direction::Reverse,
+            "pingpong" => Direction::PingPong,
+            _ => Direction::Forward,
+        };
+        animations.insert(tag.name, Animation { frames: (tag.from..=tag.to).collect(), direction });
+    }
+    for (name, frame_names) in json.animations {
+        let indices: Option<Vec<usize>> = frame_names.iter().map(|frame_name| frames.iter().position(|frame| &frame.name == frame_name)).collect();
+        match indices {
+            Some(indices) if !indices.is_empty() => {
+                animations.insert(name, Animation { frames: indices, direction: Direction::Forward });
+            }
+            _ => return Err(SheetError::UnknownFrame { animation: name }),
+        }
+    }
+
+    Ok(ParsedSheet { image: json.meta.image, frames, rects, animations })
+}
+
+#[derive(Deserialize)]
+struct SheetJson {
+    frames: FramesJson,
+    meta: MetaJson,
+    #[serde(default)]
+    animations: HashMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FramesJson {
+    Array(Vec<ArrayFrameJson>),
+    Hash(OrderedFrames),
+}
+
+#[derive(Deserialize)]
+struct ArrayFrameJson {
+    #[serde(default)]
+    filename: String,
+    #[serde(flatten)]
+    frame: FrameJson,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FrameJson {
+    frame: RectJson,
+    #[serde(default)]
+    rotated: bool,
+    sprite_source_size: Option<RectJson>,
+    source_size: Option<SizeJson>,
+    /// Milliseconds, only in Aseprite's sheets.
+    duration: Option<u64>,
+}
+
+#[derive(Deserialize, Copy, Clone)]
+struct RectJson {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize, Copy, Clone)]
+struct SizeJson {
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MetaJson {
+    image: String,
+    #[serde(default)]
+    frame_tags: Vec<FrameTagJson>,
+}
+
+#[derive(Deserialize)]
+struct FrameTagJson {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: String,
+}
+
+// Hash style frames in file order, which frame tags index into.
+struct OrderedFrames(Vec<(String, FrameJson)>);
+
+impl<'de> Deserialize<'de> for OrderedFrames {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<OrderedFrames, D::Error> {
+        struct FramesVisitor;
+
+        impl<'de> Visitor<'de> for FramesVisitor {
+            type Value = OrderedFrames;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a map of frame names to frames")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<OrderedFrames, A::Error> {
+                let mut frames = vec![];
+                while let Some(entry) = map.next_entry()? {
+                    frames.push(entry);
+                }
+                Ok(OrderedFrames(frames))
+            }
+        }
+
+        deserializer.deserialize_map(FramesVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Aseprite's hash style, with a trimmed frame and a ping-pong tag.
+    const ASEPRITE: &str = r#"{
+        "frames": {
+            "walk 0": { "frame": { "x": 0, "y": 0, "w": 16, "h": 16 }, "duration": 100 },
+            "walk 1": { "frame": { "x": 16, "y": 0, "w": 8, "h": 12 }, "spriteSourceSize": { "x": 4, "y": 2, "w": 8, "h": 12 }, "sourceSize": { "w": 16, "h": 16 }, "duration": 50 },
+            "walk 2": { "frame": { "x": 24, "y": 0, "w": 16, "h": 16 }, "duration": 200 }
+        },
+        "meta": { "image": "walk.png", "frameTags": [{ "name": "walk", "from": 0, "to": 2, "direction": "pingpong" }] }
+    }"#;
+
+    // TexturePacker's array style, with a rotated frame and a PixiJS animation.
+    const TEXTURE_PACKER: &str = r#"{
+        "frames": [
+            { "filename": "idle", "frame": { "x": 0, "y": 0, "w": 10, "h": 20 }, "rotated": true },
+            { "filename": "jump", "frame": { "x": 20, "y": 0, "w": 10, "h": 10 } }
+        ],
+        "animations": { "hop": ["idle", "jump", "idle"] },
+        "meta": { "image": "sheet.png" }
+    }"#;
+
+    #[test]
+    fn aseprite_sheet() {
+        let sheet = parse(ASEPRITE).unwrap();
+        assert_eq!(sheet.image, "walk.png");
+        assert_eq!(sheet.frames.iter().map(|frame| frame.name.as_str()).collect::<Vec<_>>(), ["walk 0", "walk 1", "walk 2"]);
+        assert_eq!(sheet.frames[1].duration, Duration::from_millis(50));
+        assert_eq!(sheet.frames[1].source_size, [16, 16]);
+        assert_eq!(sheet.frames[1].offset, [4, 2]);
+        assert_eq!(sheet.frames[0].offset, [0, 0]);
+
+        let walk = &sheet.animations["walk"];
+        assert_eq!(walk.direction, Direction::PingPong);
+        assert_eq!(walk.sequence(), [0, 1, 2, 1]);
+        // 100, 50, 200 and 50 milliseconds, then around again.
+        let frame_at = |millis| walk.frame_at(&sheet.frames, Duration::from_millis(millis));
+        assert_eq!([frame_at(0), frame_at(120), frame_at(150), frame_at(360), frame_at(420)], [0, 1, 2, 1, 0]);
+    }
+
+    #[test]
+    fn texture_packer_sheet() {
+        let sheet = parse(TEXTURE_PACKER).unwrap();
+        assert!(sheet.rects[0].1 && !sheet.rects[1].1);
+        assert_eq!(sheet.frames[0].duration, DEFAULT_FRAME_DURATION);
+        assert_eq!(sheet.animations["hop"].frames, [0, 1, 0]);
+        assert_eq!(sheet.animations["hop"].direction, Direction::Forward);
+    }
+
+    #[test]
+    fn unknown_frames() {
+        let tag = ASEPRITE.replace(r#""to": 2"#, r#""to": 3"#);
+        assert!(matches!(parse(&tag), Err(SheetError::UnknownFrame { animation }) if animation == "walk"));
+        let animation = TEXTURE_PACKER.replace(r#"["idle", "jump", "idle"]"#, r#"["idle", "fall"]"#);
+        assert!(matches!(parse(&animation), Err(SheetError::UnknownFrame { animation }) if animation == "hop"));
+    }
+}
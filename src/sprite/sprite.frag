@@ -0,0 +1,13 @@
+#version 450
+
+layout(location = 0) in vec2 v_uv;
+layout(location = 1) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler sprite_sampler;
+layout(set = 0, binding = 1) uniform texture2D sprite_texture;
+
+void main() {
+    f_color = texture(sampler2D(sprite_texture, sprite_sampler), v_uv) * v_color;
+}
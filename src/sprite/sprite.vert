@@ -0,0 +1,31 @@
+#version 450
+
+// Per vertex: the corner of the quad, from (0, 0) at the bottom left to (1, 1) at the top right.
+layout(location = 0) in vec2 corner;
+
+// Per instance, see `SpriteInstance`.
+layout(location = 1) in vec2 position;
+layout(location = 2) in vec2 size;
+layout(location = 3) in vec2 origin;
+// Cosine and sine of the rotation.
+layout(location = 4) in vec2 rotation;
+// Minimum and maximum UV.
+layout(location = 5) in vec4 region;
+layout(location = 6) in vec4 color;
+
+layout(location = 0) out vec2 v_uv;
+layout(location = 1) out vec4 v_color;
+
+layout(push_constant) uniform Camera {
+    mat4 view_projection;
+} camera;
+
+void main() {
+    vec2 local = (corner - origin) * size;
+    vec2 rotated = vec2(local.x * rotation.x - local.y * rotation.y, local.x * rotation.y + local.y * rotation.x);
+    gl_Position = camera.view_projection * vec4(position + rotated, 0.0, 1.0);
+
+    // The world's y points up, the texture's v down.
+    v_uv = mix(region.xy, region.zw, vec2(corner.x, 1.0 - corner.y));
+    v_color = color;
+}
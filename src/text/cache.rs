@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use ab_glyph::{point, Font as _, GlyphId, PxScale};
+
+use crate::sprite::Skyline;
+use crate::text::Font;
+
+/// Width and height of the glyph atlas.
+pub const ATLAS_SIZE: u32 = 1024;
+/// Size signed distance field glyphs are rasterized at, whatever size they are drawn at.
+pub const SDF_SIZE: u32 = 48;
+/// Pixels the distance field reaches beyond the outline on either side.
+pub const SDF_SPREAD: u32 = 6;
+// Empty pixels around every glyph so linear filtering doesn't reach its neighbours.
+const PADDING: u32 = 1;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font: u64,
+    glyph: GlyphId,
+    /// Pixel size the glyph was rasterized at.
+    size: u32,
+    sdf: bool,
+}
+
+/// Where a glyph is in the atlas and where it goes relative to its origin.
+#[derive(Debug, Copy, Clone)]
+pub struct CachedGlyph {
+    /// Minimum and maximum UV.
+    pub uv: [f32; 4],
+    /// Minimum and maximum corner relative to the glyph's origin on the baseline, in pixels at the size it was
+    /// rasterized at, y down.
+    pub bounds: [f32; 4],
+}
+
+/// The atlas is full, `GlyphCache::clear` it and try again.
+#[derive(Debug, Copy, Clone)]
+pub struct AtlasFull;
+
+/// Glyphs rasterized on demand into a single channel atlas, either as coverage or as a signed distance field.
+///
+/// Nothing is evicted on its own. Once the atlas is full it has to be cleared and filled again with what's
+/// still in use.
+pub struct GlyphCache {
+    pixels: Vec<u8>,
+    skyline: Skyline,
+    glyphs: HashMap<GlyphKey, Option<CachedGlyph>>,
+    // Range of rows changed since `take_dirty`.
+    dirty: Option<(u32, u32)>,
+}
+
+impl GlyphCache {
+    pub fn new() -> GlyphCache {
+        GlyphCache {
+            pixels: vec![0; (ATLAS_SIZE * ATLAS_SIZE) as usize],
+            skyline: Skyline::new(ATLAS_SIZE),
+            glyphs: HashMap::new(),
+            dirty: Some((0, ATLAS_SIZE)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.glyphs.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.pixels.iter_mut().for_each(|pixel| *pixel = 0);
+        self.skyline = Skyline::new(ATLAS_SIZE);
+        self.glyphs.clear();
+        self.dirty = Some((0, ATLAS_SIZE));
+    }
+
+    /// `glyph` of `font` rasterized at `size` pixels, or a distance field at `SDF_SIZE` when `sdf` is set. Glyphs
+    /// without an outline, like spaces, are `None`.
+    pub fn glyph(&mut self, font: &Font, glyph: GlyphId, size: u32, sdf: bool) -> Result<Option<CachedGlyph>, AtlasFull> {
+        let size = if sdf { SDF_SIZE } else { size.max(1) };
+        let key = GlyphKey { font: font.id, glyph, size, sdf };
+        if let Some(cached) = self.glyphs.get(&key) {
+            return Ok(*cached);
+        }
+
+        let cached = match rasterize(font, glyph, size, sdf) {
+            Some((pixels, dimensions, bounds)) => {
+                let position = self.skyline.insert([dimensions[0] + PADDING * 2, dimensions[1] + PADDING * 2]).ok_or(AtlasFull)?;
+                let (x, y) = (position[0] + PADDING, position[1] + PADDING);
+                for row in 0..dimensions[1] {
+                    let start = ((y + row) * ATLAS_SIZE + x) as usize;
+                    let source = (row * dimensions[0]) as usize;
+                    self.pixels[start..start + dimensions[0] as usize].copy_from_slice(&pixels[source..source + dimensions[0] as usize]);
+                }
+                self.dirty = Some(self.dirty.map_or((y, y + dimensions[1]), |(start, end)| (start.min(y), end.max(y + dimensions[1]))));
+
+                let atlas = ATLAS_SIZE as f32;
+                Some(CachedGlyph {
+                    uv: [x as f32 / atlas, y as f32 / atlas, (x + dimensions[0]) as f32 / atlas, (y + dimensions[1]) as f32 / atlas],
+                    bounds,
+                })
+            }
+            None => None,
+        };
+        self.glyphs.insert(key, cached);
+        Ok(cached)
+    }
+
+    /// The first row changed since the last call and the pixels from there to the last changed row.
+    pub fn take_dirty(&mut self) -> Option<(u32, &[u8])> {
+        let (start, end) = self.dirty.take()?;
+        Some((start, &self.pixels[(start * ATLAS_SIZE) as usize..(end * ATLAS_SIZE) as usize]))
+    }
+}
+
+/// The pixels of a glyph, their width and height and its bounds as in `CachedGlyph`.
+fn rasterize(font: &Font, glyph: GlyphId, size: u32, sdf: bool) -> Option<(Vec<u8>, [u32; 2], [f32; 4])> {
+    let outline = font.font.outline_glyph(glyph.with_scale_and_position(PxScale::from(size as f32), point(0.0, 0.0)))?;
+    let bounds = outline.px_bounds();
+    let border = if sdf { SDF_SPREAD } else { 0 };
+    let dimensions = [bounds.width() as u32 + border * 2, bounds.height() as u32 + border * 2];
+
+    let mut coverage = vec![0.0; (dimensions[0] * dimensions[1]) as usize];
+    outline.draw(|x, y, value| {
+               if x < bounds.width() as u32 && y < bounds.height() as u32 {
+                   coverage[((y + border) * dimensions[0] + x + border) as usize] = value;
+               }
+           });
+
+    let pixels = if sdf { signed_distance(&coverage, dimensions) } else { coverage.iter().map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8).collect() };
+    let border = border as f32;
+    Some((pixels, dimensions, [bounds.min.x - border, bounds.min.y - border, bounds.max.x + border, bounds.max.y + border]))
+}
+
+/// Distance from every pixel's center to the outline, 0.5 on it, rising inside and falling outside until
+/// `SDF_SPREAD` pixels from it.
+fn signed_distance(coverage: &[f32], dimensions: [u32; 2]) -> Vec<u8> {
+    let (width, height) = (dimensions[0] as i32, dimensions[1] as i32);
+    let spread = SDF_SPREAD as i32;
+    let inside = |x: i32, y: i32| coverage[(y * width + x) as usize] >= 0.5;
+
+    let mut pixels = Vec::with_capacity(coverage.len());
+    for y in 0..height {
+        for x in 0..width {
+            let is_inside = inside(x, y);
+            // Nearest pixel on the other side of the outline, which runs halfway between the two.
+            let mut nearest = (spread * spread) as f32;
+            for other_y in (y - spread).max(0)..(y + spread + 1).min(height) {
+                for other_x in (x - spread).max(0)..(x + spread + 1).min(width) {
+                    if inside(other_x, other_y) != is_inside {
+                        let (dx, dy) = ((other_x - x) as f32, (other_y - y) as f32);
+                        nearest = nearest.min(dx * dx + dy * dy);
+                    }
+                }
+            }
+            let distance = (nearest.sqrt() - 0.5).min(spread as f32);
+            let signed = if is_inside { distance } else { -distance };
+            pixels.push(((0.5 + signed / (2.0 * spread as f32)).clamp(0.0, 1.0) * 255.0).round() as u8);
+        }
+    }
+    pixels
+}
@@ -0,0 +1,198 @@
+use ab_glyph::{Font as _, GlyphId, PxScale, ScaleFont};
+use serde::Deserialize;
+
+use crate::text::Font;
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// How text is broken into lines and placed within its box.
+#[derive(Debug, Copy, Clone)]
+pub struct TextLayout {
+    /// Lines longer than this wrap after their last space, or inside words too long for a line by themselves.
+    pub max_width: Option<f32>,
+    /// Where lines go within the box, which is `max_width` wide or as wide as the widest line.
+    pub align: Align,
+    /// Multiplies the font's distance between baselines.
+    pub line_spacing: f32,
+}
+
+impl Default for TextLayout {
+    fn default() -> TextLayout {
+        TextLayout {
+            max_width: None,
+            align: Align::Left,
+            line_spacing: 1.0,
+        }
+    }
+}
+
+/// A glyph placed by `Font::layout`.
+#[derive(Debug, Copy, Clone)]
+pub struct LaidOutGlyph {
+    pub id: GlyphId,
+    /// Where the glyph's origin is on its baseline, from the top left of the box with y down.
+    pub position: [f32; 2],
+}
+
+/// Text broken into lines and placed, without the whitespace.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pub glyphs: Vec<LaidOutGlyph>,
+    /// Size of the box, from the top of the first line to the bottom of the last.
+    pub size: [f32; 2],
+}
+
+// A glyph of the line being laid out, with what wrapping needs to know about it.
+struct LineGlyph {
+    id: GlyphId,
+    x: f32,
+    advance: f32,
+    whitespace: bool,
+}
+
+impl Font {
+    /// Lays out `text` at `size`, the distance from the font's highest ascender to its lowest descender.
+    ///
+    /// Characters map to glyphs one to one, with kerning between neighbours. That covers Latin and other scripts
+    /// without contextual shaping. Tabs are spaces and other control characters are skipped.
+    pub fn layout(&self, text: &str, size: f32, settings: &TextLayout) -> Layout {
+        let font = self.font.as_scaled(PxScale::from(size));
+        let line_advance = (font.ascent() - font.descent() + font.line_gap()) * settings.line_spacing;
+
+        let mut lines: Vec<(Vec<LineGlyph>, f32)> = vec![];
+        for paragraph in text.split('\n') {
+            let mut line: Vec<LineGlyph> = vec![];
+            // Index in `line` after its last whitespace, where it can be wrapped.
+            let mut wrap_at = None;
+            let mut x = 0.0;
+            let mut previous = None;
+
+            for c in paragraph.chars() {
+                let c = if c == '\t' { ' ' } else { c };
+                if c.is_control() {
+                    continue;
+                }
+
+                let id = font.glyph_id(c);
+                if let Some(previous) = previous {
+                    x += font.kern(previous, id);
+                }
+                let advance = font.h_advance(id);
+                let whitespace = c.is_whitespace();
+
+                let overflows = matches!(settings.max_width, Some(max_width) if x + advance > max_width);
+                if overflows && !whitespace && line.iter().any(|glyph| !glyph.whitespace) {
+                    let mut rest = line.split_off(wrap_at.unwrap_or(line.len()));
+                    let shift = rest.first().map_or(x, |glyph| glyph.x);
+                    for glyph in rest.iter_mut() {
+                        glyph.x -= shift;
+                    }
+                    x -= shift;
+
+                    let width = line_width(&line);
+                    lines.push((line, width));
+                    line = rest;
+                    wrap_at = None;
+                }
+
+                line.push(LineGlyph { id, x, advance, whitespace });
+                if whitespace {
+                    wrap_at = Some(line.len());
+                }
+                x += advance;
+                previous = Some(id);
+            }
+
+            let width = line_width(&line);
+            lines.push((line, width));
+        }
+
+        let width = settings.max_width.unwrap_or_else(|| lines.iter().map(|(_, width)| *width).fold(0.0, f32::max));
+        let mut glyphs = vec![];
+        for (index, (line, line_width)) in lines.iter().enumerate() {
+            let offset = match settings.align {
+                Align::Left => 0.0,
+                Align::Center => (width - line_width) / 2.0,
+                Align::Right => width - line_width,
+            };
+            let baseline = font.ascent() + index as f32 * line_advance;
+            glyphs.extend(line.iter().filter(|glyph| !glyph.whitespace).map(|glyph| LaidOutGlyph { id: glyph.id, position: [offset + glyph.x, baseline] }));
+        }
+
+        Layout {
+            glyphs,
+            size: [width, font.ascent() - font.descent() + (lines.len() - 1) as f32 * line_advance],
+        }
+    }
+}
+
+/// Width up to the end of the last glyph that isn't whitespace.
+fn line_width(line: &[LineGlyph]) -> f32 {
+    line.iter().rev().find(|glyph| !glyph.whitespace).map_or(0.0, |glyph| glyph.x + glyph.advance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hack, the monospace font egui comes with, so every glyph advances as far.
+    fn font() -> (Font, f32) {
+        let data = egui::FontDefinitions::default().font_data["Hack"].to_vec();
+        let font = Font::from_bytes(data).unwrap();
+        let advance = font.font.as_scaled(PxScale::from(20.0)).h_advance(font.font.glyph_id('a'));
+        (font, advance)
+    }
+
+    /// Baselines of the glyphs, and where each line's glyphs start.
+    fn lines(layout: &Layout) -> Vec<(f32, f32)> {
+        let mut lines: Vec<(f32, f32)> = vec![];
+        for glyph in layout.glyphs.iter() {
+            if lines.last().is_none_or(|&(baseline, _)| baseline != glyph.position[1]) {
+                lines.push((glyph.position[1], glyph.position[0]));
+            }
+        }
+        lines
+    }
+
+    #[test]
+    fn wraps_at_width() {
+        let (font, advance) = font();
+        let settings = TextLayout { max_width: Some(advance * 7.5), ..TextLayout::default() };
+        let layout = font.layout("aaa bbb cccccccccc", 20.0, &settings);
+
+        // The space before "ccc..." is dropped, and the word too long for a line of its own breaks inside.
+        assert_eq!(layout.glyphs.len(), 16);
+        let lines = lines(&layout);
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|&(_, x)| x == 0.0));
+        assert_eq!(layout.glyphs[5].position, [advance * 6.0, lines[0].0]);
+        assert_eq!(layout.glyphs[12].position, [advance * 6.0, lines[1].0]);
+        assert_eq!(layout.glyphs[13].position, [0.0, lines[2].0]);
+        assert_eq!(layout.size[0], advance * 7.5);
+        assert!(layout.glyphs.iter().all(|glyph| glyph.position[0] + advance <= advance * 7.5));
+    }
+
+    #[test]
+    fn explicit_newline() {
+        let (font, advance) = font();
+        let single = font.layout("ab", 20.0, &TextLayout::default());
+        let layout = font.layout("ab\ncde", 20.0, &TextLayout { align: Align::Right, ..TextLayout::default() });
+
+        let lines = lines(&layout);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].0, single.glyphs[0].position[1]);
+        // The box is as wide as the longer line, and the shorter one is aligned to its right.
+        assert_eq!(layout.size[0], advance * 3.0);
+        assert_eq!(lines[0].1, advance);
+        assert_eq!(lines[1].1, 0.0);
+        let line_advance = lines[1].0 - lines[0].0;
+        assert!(line_advance > 0.0);
+        assert_eq!(layout.size[1], single.size[1] + line_advance);
+    }
+}
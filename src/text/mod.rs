@@ -0,0 +1,152 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use ab_glyph::FontArc;
+use cgmath::{Matrix4, Point3};
+use vulkano::device::Device;
+
+pub use crate::text::layout::{Align, TextLayout};
+
+use crate::allocator::GpuAllocator;
+use crate::debug::ObjectNamer;
+use crate::render_graph::{BufferId, ImageId, RenderGraphBuilder};
+use crate::scene::Scene;
+use crate::text::pass::{GlyphUploadPass, TextPass, TextState};
+use crate::upload::UploadManager;
+
+mod cache;
+mod layout;
+mod pass;
+
+/// Pixel size bitmap glyphs in world space are rasterized at, as how large they end up on screen isn't known.
+pub const WORLD_BITMAP_SIZE: u32 = 32;
+
+static NEXT_FONT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug)]
+pub enum FontError {
+    Io(io::Error),
+    /// Not a TrueType or OpenType font.
+    Invalid,
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FontError::Io(e) => write!(f, "{}", e),
+            FontError::Invalid => write!(f, "not a TrueType or OpenType font"),
+        }
+    }
+}
+
+impl From<io::Error> for FontError {
+    fn from(e: io::Error) -> FontError {
+        FontError::Io(e)
+    }
+}
+
+/// A TrueType or OpenType font. Clones share the font and its glyphs in the atlas.
+#[derive(Clone)]
+pub struct Font {
+    id: u64,
+    font: FontArc,
+}
+
+impl Font {
+    pub fn load(path: &Path) -> Result<Font, FontError> {
+        Font::from_bytes(fs::read(path)?)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Font, FontError> {
+        let font = FontArc::try_from_vec(data).map_err(|_| FontError::Invalid)?;
+        Ok(Font { id: NEXT_FONT_ID.fetch_add(1, Ordering::Relaxed), font })
+    }
+}
+
+/// How glyphs are rasterized into the atlas.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GlyphMode {
+    /// Coverage at the size the text is drawn at. Sharpest on screen, but every size takes its own glyphs.
+    Bitmap,
+    /// Signed distance fields rasterized once and drawn at any size, scale and angle, with slightly rounder corners.
+    Sdf,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum TextSpace {
+    /// `position` is in pixels of the target from its top left, y down.
+    Screen { position: [f32; 2] },
+    /// The text lies on the xy plane of `transform`, y up, and is seen through the scene's camera.
+    World { transform: Matrix4<f32> },
+    /// In pixels like on screen, placed where `position` in the world ends up on it. Not drawn when it's behind
+    /// the camera, but over whatever is in front of it.
+    Label { position: Point3<f32> },
+}
+
+/// A block of text drawn by the text pass.
+#[derive(Clone)]
+pub struct Text {
+    pub content: String,
+    pub font: Font,
+    /// From the font's highest ascender to its lowest descender, in world units in the world and pixels otherwise.
+    pub size: f32,
+    pub space: TextSpace,
+    /// Point of the text's box placed at its position, from (0, 0) at the top left to (1, 1) at the bottom right.
+    pub origin: [f32; 2],
+    pub layout: TextLayout,
+    /// Linear RGBA.
+    pub color: [f32; 4],
+    pub mode: GlyphMode,
+}
+
+impl Text {
+    /// White bitmap text with its top left `position` pixels from the target's.
+    pub fn screen(content: &str, font: Font, size: f32, position: [f32; 2]) -> Text {
+        Text {
+            content: content.to_string(),
+            font,
+            size,
+            space: TextSpace::Screen { position },
+            origin: [0.0, 0.0],
+            layout: TextLayout::default(),
+            color: [1.0, 1.0, 1.0, 1.0],
+            mode: GlyphMode::Bitmap,
+        }
+    }
+
+    /// White distance field text centered on the origin of `transform`.
+    pub fn world(content: &str, font: Font, size: f32, transform: Matrix4<f32>) -> Text {
+        Text {
+            space: TextSpace::World { transform },
+            origin: [0.5, 0.5],
+            mode: GlyphMode::Sdf,
+            ..Text::screen(content, font, size, [0.0, 0.0])
+        }
+    }
+}
+
+/// The glyph atlas of the text passes, which the UI's text pass shares.
+pub struct GlyphAtlas {
+    state: Arc<Mutex<TextState>>,
+    glyphs: BufferId,
+}
+
+/// Adds the passes drawing the scene's texts over `target`: one laying them out and copying new glyphs into the
+/// atlas, which can't happen within a render pass, and one drawing them.
+pub fn add_passes(graph: &mut RenderGraphBuilder<Scene>, target: ImageId, device: &Arc<Device>, allocator: &GpuAllocator, uploads: &mut UploadManager, namer: &ObjectNamer) -> GlyphAtlas {
+    let state = Arc::new(Mutex::new(TextState::new(allocator, namer)));
+    // Orders drawing after the upload, the atlas itself is owned by the passes.
+    let glyphs = graph.buffer("glyph atlas");
+    graph.add_pass("glyph upload", GlyphUploadPass::new(glyphs, state.clone()));
+    graph.add_pass("text", TextPass::new(device.clone(), target, false, glyphs, state.clone(), uploads, namer));
+    GlyphAtlas { state, glyphs }
+}
+
+/// Adds the pass drawing the scene's UI texts over `target`, laid out along with the others by `add_passes`.
+pub fn add_ui_pass(graph: &mut RenderGraphBuilder<Scene>, target: ImageId, atlas: &GlyphAtlas, device: &Arc<Device>, uploads: &mut UploadManager, namer: &ObjectNamer) {
+    graph.add_pass("ui text", TextPass::new(device.clone(), target, true, atlas.glyphs, atlas.state.clone(), uploads, namer));
+}
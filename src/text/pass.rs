@@ -0,0 +1,320 @@
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use cgmath::{Matrix4, Point3, Vector3};
+use tracing::{debug, trace, warn};
+use vulkano::buffer::{BufferAccess, BufferSlice, BufferUsage, CpuAccessibleBuffer, CpuBufferPool};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{ImageCreateFlags, ImageDimensions};
+use vulkano::pipeline::vertex::OneVertexOneInstanceDefinition;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::render_pass::Subpass;
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+use crate::allocator::{GpuAllocator, MemoryCategory, PooledBuffer, PooledImage};
+use crate::debug::ObjectNamer;
+use crate::logging;
+use crate::render_graph::{BufferId, ImageId, Load, Pass, PassBuilder, PassContext};
+use crate::scene::Scene;
+use crate::text::cache::{AtlasFull, GlyphCache, ATLAS_SIZE, SDF_SIZE};
+use crate::text::{GlyphMode, Text, TextSpace, WORLD_BITMAP_SIZE};
+use crate::upload::UploadManager;
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/text/text.vert"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/text/text.frag"
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+struct QuadCorner {
+    corner: [f32; 2],
+}
+vulkano::impl_vertex!(QuadCorner, corner);
+
+/// What `text.vert` gets per glyph.
+#[derive(Default, Debug, Clone, Copy)]
+struct GlyphInstance {
+    rect: [f32; 4],
+    uv: [f32; 4],
+    color: [f32; 4],
+}
+vulkano::impl_vertex!(GlyphInstance, rect, uv, color);
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct TextPush {
+    transform: [[f32; 4]; 4],
+    sdf: u32,
+}
+
+/// Where the glyphs of a `TextDraw` are.
+enum DrawSpace {
+    /// In pixels of the target.
+    Screen,
+    /// Transformed into the world.
+    World(Matrix4<f32>),
+    /// In pixels from where this point in the world is on screen.
+    Label(Point3<f32>),
+}
+
+/// The glyphs of one text.
+struct TextDraw {
+    instances: Range<usize>,
+    space: DrawSpace,
+    sdf: bool,
+    /// Drawn by the UI's text pass.
+    ui: bool,
+}
+
+/// What the glyph upload pass hands to the text pass every frame.
+pub(crate) struct TextState {
+    cache: GlyphCache,
+    atlas: Arc<PooledImage>,
+    view: Arc<ImageView<Arc<PooledImage>>>,
+    instances: Vec<GlyphInstance>,
+    draws: Vec<TextDraw>,
+}
+
+impl TextState {
+    pub(crate) fn new(allocator: &GpuAllocator, namer: &ObjectNamer) -> TextState {
+        let dimensions = ImageDimensions::Dim2d { width: ATLAS_SIZE, height: ATLAS_SIZE, array_layers: 1 };
+        let atlas = PooledImage::texture(allocator, dimensions, Format::R8Unorm, 1, ImageCreateFlags::none(), None).expect("failed to create glyph atlas");
+        namer.name_image(&*atlas, "glyph atlas");
+
+        TextState {
+            cache: GlyphCache::new(),
+            view: ImageView::new(atlas.clone()).unwrap(),
+            atlas,
+            instances: vec![],
+            draws: vec![],
+        }
+    }
+
+    /// Lays out every text, and whether it's the UI's, and rasterizes the glyphs the atlas doesn't have yet.
+    fn prepare(&mut self, texts: &[(&Text, bool)]) {
+        if self.prepare_texts(texts).is_ok() || self.cache.is_empty() {
+            return;
+        }
+
+        // Start over with only the glyphs still in use.
+        debug!(target: logging::RENDER, "glyph atlas full, clearing it");
+        self.cache.clear();
+        if self.prepare_texts(texts).is_err() {
+            warn!(target: logging::RENDER, "the text drawn needs more glyphs than fit in the glyph atlas, some are missing");
+        }
+    }
+
+    fn prepare_texts(&mut self, texts: &[(&Text, bool)]) -> Result<(), AtlasFull> {
+        self.instances.clear();
+        self.draws.clear();
+
+        let mut result = Ok(());
+        for &(text, ui) in texts {
+            let layout = text.font.layout(&text.content, text.size, &text.layout);
+            let sdf = text.mode == GlyphMode::Sdf;
+            let box_offset = [-text.origin[0] * layout.size[0], -text.origin[1] * layout.size[1]];
+            let (offset, space, raster_size) = match text.space {
+                TextSpace::Screen { position } => ([position[0] + box_offset[0], position[1] + box_offset[1]], DrawSpace::Screen, text.size.round() as u32),
+                // The text's y points down, the world's up.
+                TextSpace::World { transform } => (box_offset, DrawSpace::World(transform * Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0)), WORLD_BITMAP_SIZE),
+                TextSpace::Label { position } => (box_offset, DrawSpace::Label(position), text.size.round() as u32),
+            };
+            let scale = text.size / if sdf { SDF_SIZE } else { raster_size.max(1) } as f32;
+            // Bitmap glyphs in pixels are rasterized at the size drawn, on whole pixels they stay sharp.
+            let snap = !sdf && !matches!(space, DrawSpace::World(_));
+
+            let start = self.instances.len();
+            for glyph in layout.glyphs {
+                let cached = match self.cache.glyph(&text.font, glyph.id, raster_size, sdf) {
+                    Ok(Some(cached)) => cached,
+                    Ok(None) => continue,
+                    Err(full) => {
+                        result = Err(full);
+                        continue;
+                    }
+                };
+
+                let mut origin = [offset[0] + glyph.position[0], offset[1] + glyph.position[1]];
+                if snap {
+                    origin = [origin[0].round(), origin[1].round()];
+                }
+                let bounds = cached.bounds;
+                self.instances.push(GlyphInstance {
+                                        rect: [origin[0] + bounds[0] * scale, origin[1] + bounds[1] * scale, origin[0] + bounds[2] * scale, origin[1] + bounds[3] * scale],
+                                        uv: cached.uv,
+                                        color: text.color,
+                                    });
+            }
+            self.draws.push(TextDraw { instances: start..self.instances.len(), space, sdf, ui });
+        }
+        result
+    }
+}
+
+/// Lays out the scene's texts, debug labels and UI texts and copies newly rasterized glyphs into the atlas, outside
+/// of any render pass.
+pub(crate) struct GlyphUploadPass {
+    glyphs: BufferId,
+    state: Arc<Mutex<TextState>>,
+    device: Option<Arc<Device>>,
+}
+
+impl GlyphUploadPass {
+    pub(crate) fn new(glyphs: BufferId, state: Arc<Mutex<TextState>>) -> GlyphUploadPass {
+        GlyphUploadPass { glyphs, state, device: None }
+    }
+}
+
+impl Pass<Scene> for GlyphUploadPass {
+    fn declare(&mut self, pass: &mut PassBuilder) {
+        pass.write(self.glyphs);
+    }
+
+    fn prepare(&mut self, device: &Arc<Device>, _subpass: Option<Subpass>) {
+        self.device = Some(device.clone());
+    }
+
+    fn record(&mut self, context: &mut PassContext, scene: &Scene) {
+        let labels = scene.debug.label_texts();
+        let texts: Vec<(&Text, bool)> = scene.texts.iter().chain(labels.iter()).map(|text| (text, false)).chain(scene.ui_texts.iter().map(|text| (text, true))).collect();
+        let mut state = self.state.lock().unwrap();
+        state.prepare(&texts);
+
+        let atlas = state.atlas.clone();
+        if let Some((row, pixels)) = state.cache.take_dirty() {
+            let rows = pixels.len() as u32 / ATLAS_SIZE;
+            let staging = CpuAccessibleBuffer::from_iter(self.device.clone().unwrap(), BufferUsage::transfer_source(), false, pixels.iter().cloned()).unwrap();
+            context.builder.copy_buffer_to_image_dimensions(staging, atlas, [0, row, 0], [ATLAS_SIZE, rows, 1], 0, 1, 0).unwrap();
+            trace!(target: logging::RENDER, row, rows, "uploaded glyphs");
+        }
+    }
+}
+
+type TextPipeline = GraphicsPipeline<OneVertexOneInstanceDefinition<QuadCorner, GlyphInstance>, Box<dyn PipelineLayoutAbstract + Send + Sync>>;
+
+/// Draws the scene's texts and debug labels, or its UI texts, with alpha blending over `target`. Screen space text
+/// is in pixels of `target`, world space text is seen through the scene's camera without being hidden by what's in
+/// front of it.
+pub(crate) struct TextPass {
+    target: ImageId,
+    ui: bool,
+    glyphs: BufferId,
+    state: Arc<Mutex<TextState>>,
+    quad: Arc<PooledBuffer<[QuadCorner]>>,
+    sampler: Arc<Sampler>,
+    instances: CpuBufferPool<GlyphInstance>,
+    namer: ObjectNamer,
+    pipeline: Option<Arc<TextPipeline>>,
+    set: Option<Arc<dyn DescriptorSet + Send + Sync>>,
+}
+
+impl TextPass {
+    pub(crate) fn new(device: Arc<Device>, target: ImageId, ui: bool, glyphs: BufferId, state: Arc<Mutex<TextState>>, uploads: &mut UploadManager, namer: &ObjectNamer) -> TextPass {
+        let corners = [QuadCorner { corner: [0.0, 0.0] }, QuadCorner { corner: [1.0, 0.0] }, QuadCorner { corner: [0.0, 1.0] }, QuadCorner { corner: [1.0, 1.0] }];
+        let quad = uploads.buffer(&corners, BufferUsage::vertex_buffer(), MemoryCategory::Mesh).expect("failed to create glyph quad");
+        namer.name_buffer(&*quad, "glyph quad");
+
+        let sampler = Sampler::new(device.clone(),
+                                   Filter::Linear,
+                                   Filter::Linear,
+                                   MipmapMode::Nearest,
+                                   SamplerAddressMode::ClampToEdge,
+                                   SamplerAddressMode::ClampToEdge,
+                                   SamplerAddressMode::ClampToEdge,
+                                   0.0,
+                                   1.0,
+                                   0.0,
+                                   0.0).unwrap();
+
+        TextPass {
+            target,
+            ui,
+            glyphs,
+            state,
+            quad,
+            sampler,
+            instances: CpuBufferPool::vertex_buffer(device),
+            namer: namer.clone(),
+            pipeline: None,
+            set: None,
+        }
+    }
+}
+
+impl Pass<Scene> for TextPass {
+    fn declare(&mut self, pass: &mut PassBuilder) {
+        pass.color(self.target, Load::Keep).read(self.glyphs);
+    }
+
+    fn prepare(&mut self, device: &Arc<Device>, subpass: Option<Subpass>) {
+        let vs = vs::Shader::load(device.clone()).unwrap();
+        let fs = fs::Shader::load(device.clone()).unwrap();
+        let pipeline = Arc::new(GraphicsPipeline::start().vertex_input(OneVertexOneInstanceDefinition::<QuadCorner, GlyphInstance>::new())
+                                                         .vertex_shader(vs.main_entry_point(), ())
+                                                         .triangle_strip()
+                                                         .viewports_dynamic_scissors_irrelevant(1)
+                                                         .fragment_shader(fs.main_entry_point(), ())
+                                                         .blend_alpha_blending()
+                                                         .cull_mode_disabled()
+                                                         .render_pass(subpass.expect("the text pass renders to an attachment"))
+                                                         .build(device.clone())
+                                                         .unwrap());
+        self.namer.name(&*pipeline, "text pipeline");
+
+        let layout = pipeline.descriptor_set_layout(0).unwrap().clone();
+        let view = self.state.lock().unwrap().view.clone();
+        self.set = Some(Arc::new(PersistentDescriptorSet::start(layout).add_sampler(self.sampler.clone()).unwrap().add_image(view).unwrap().build().unwrap()));
+        self.pipeline = Some(pipeline);
+    }
+
+    fn record(&mut self, context: &mut PassContext, scene: &Scene) {
+        let state = self.state.lock().unwrap();
+        if state.instances.is_empty() {
+            return;
+        }
+
+        let instances = Arc::new(self.instances.chunk(state.instances.iter().cloned()).unwrap());
+        let (width, height) = (context.dimensions[0] as f32, context.dimensions[1] as f32);
+        let screen = Matrix4::from_translation(Vector3::new(-1.0, -1.0, 0.0)) * Matrix4::from_nonuniform_scale(2.0 / width, 2.0 / height, 1.0);
+        let view_projection = scene.camera.projection(width / height) * scene.camera.view();
+
+        for draw in state.draws.iter().filter(|draw| draw.ui == self.ui && !draw.instances.is_empty()) {
+            let transform = match draw.space {
+                DrawSpace::Screen => screen,
+                DrawSpace::World(transform) => view_projection * transform,
+                DrawSpace::Label(position) => {
+                    let clip = view_projection * position.to_homogeneous();
+                    if clip.w <= 0.0 {
+                        continue;
+                    }
+                    let anchor = [(clip.x / clip.w + 1.0) / 2.0 * width, (clip.y / clip.w + 1.0) / 2.0 * height];
+                    screen * Matrix4::from_translation(Vector3::new(anchor[0].round(), anchor[1].round(), 0.0))
+                }
+            };
+            let push = TextPush { transform: transform.into(), sdf: draw.sdf as u32 };
+            let batch = BufferSlice::from_typed_buffer_access(instances.clone()).slice(draw.instances.clone()).unwrap();
+            context.builder
+                   .draw(self.pipeline.clone().unwrap(),
+                         context.dynamic_state,
+                         vec![self.quad.clone() as Arc<dyn BufferAccess + Send + Sync>, Arc::new(batch)],
+                         self.set.clone().unwrap(),
+                         push,
+                         vec![])
+                   .unwrap();
+        }
+        trace!(target: logging::RENDER, ui = self.ui, texts = state.draws.len(), glyphs = state.instances.len(), "drew text");
+    }
+}
@@ -0,0 +1,21 @@
+#version 450
+
+layout(location = 0) in vec2 v_uv;
+layout(location = 1) in vec4 v_color;
+layout(location = 2) flat in uint v_sdf;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler glyph_sampler;
+layout(set = 0, binding = 1) uniform texture2D glyphs;
+
+void main() {
+    float value = texture(sampler2D(glyphs, glyph_sampler), v_uv).r;
+    float alpha = value;
+    if (v_sdf != 0) {
+        // The outline is at 0.5, smoothed over about a pixel whatever the scale.
+        float width = 0.5 * fwidth(value);
+        alpha = smoothstep(0.5 - width, 0.5 + width, value);
+    }
+    f_color = vec4(v_color.rgb, v_color.a * alpha);
+}
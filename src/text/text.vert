@@ -0,0 +1,28 @@
+#version 450
+
+// Per vertex: the corner of the quad, from (0, 0) to (1, 1).
+layout(location = 0) in vec2 corner;
+
+// Per instance, see `GlyphInstance`.
+// Minimum and maximum corner in the text's space.
+layout(location = 1) in vec4 rect;
+// Minimum and maximum UV.
+layout(location = 2) in vec4 uv;
+layout(location = 3) in vec4 color;
+
+layout(location = 0) out vec2 v_uv;
+layout(location = 1) out vec4 v_color;
+layout(location = 2) flat out uint v_sdf;
+
+layout(push_constant) uniform Text {
+    // From the text's space to clip space.
+    mat4 transform;
+    uint sdf;
+} text;
+
+void main() {
+    gl_Position = text.transform * vec4(mix(rect.xy, rect.zw, corner), 0.0, 1.0);
+    v_uv = mix(uv.xy, uv.zw, corner);
+    v_color = color;
+    v_sdf = text.sdf;
+}
@@ -0,0 +1,23 @@
+#version 450
+
+// Resolves the weighted sums of the weighted blended pass into their average, blended over the scene by how much
+// of it the transparent surfaces cover.
+
+layout(location = 0) in vec2 v_uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler buffer_sampler;
+layout(set = 0, binding = 1) uniform texture2D accum_buffer;
+layout(set = 0, binding = 2) uniform texture2D revealage_buffer;
+
+void main() {
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    float revealage = texelFetch(sampler2D(revealage_buffer, buffer_sampler), pixel, 0).r;
+    // Nothing transparent covers the pixel.
+    if (revealage >= 1.0) {
+        discard;
+    }
+    vec4 accum = texelFetch(sampler2D(accum_buffer, buffer_sampler), pixel, 0);
+    f_color = vec4(accum.rgb / max(accum.a, 0.00001), 1.0 - revealage);
+}
@@ -0,0 +1,121 @@
+use std::sync::{Arc, Mutex};
+
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::pipeline::blend::AttachmentBlend;
+use vulkano::pipeline::vertex::{BufferlessDefinition, BufferlessVertices};
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::render_pass::Subpass;
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+use crate::debug::ObjectNamer;
+use crate::render_graph::{ImageId, Load, Pass, PassBuilder, PassContext};
+use crate::scene::Scene;
+use crate::transparency::TransparencyMode;
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "src/post/fullscreen.vert"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "src/transparency/composite.frag"
+    }
+}
+
+type CompositePipeline = GraphicsPipeline<BufferlessDefinition, Box<dyn PipelineLayoutAbstract + Send + Sync>>;
+
+/// Blends what the weighted blended pass accumulated over `color`, when transparency is
+/// `TransparencyMode::WeightedBlended`.
+pub struct CompositePass {
+    color: ImageId,
+    accum: ImageId,
+    revealage: ImageId,
+    mode: Arc<Mutex<TransparencyMode>>,
+    namer: ObjectNamer,
+    sampler: Arc<Sampler>,
+    pipeline: Option<Arc<CompositePipeline>>,
+    // Descriptor set and the graph generation it was made for.
+    set: Option<(u64, Arc<dyn DescriptorSet + Send + Sync>)>,
+}
+
+impl CompositePass {
+    pub fn new(device: &Arc<Device>, color: ImageId, accum: ImageId, revealage: ImageId, mode: Arc<Mutex<TransparencyMode>>, namer: &ObjectNamer) -> CompositePass {
+        let sampler = Sampler::new(device.clone(),
+                                   Filter::Nearest,
+                                   Filter::Nearest,
+                                   MipmapMode::Nearest,
+                                   SamplerAddressMode::ClampToEdge,
+                                   SamplerAddressMode::ClampToEdge,
+                                   SamplerAddressMode::ClampToEdge,
+                                   0.0,
+                                   1.0,
+                                   0.0,
+                                   0.0).unwrap();
+        namer.name(&*sampler, "transparency composite sampler");
+
+        CompositePass {
+            color,
+            accum,
+            revealage,
+            mode,
+            namer: namer.clone(),
+            sampler,
+            pipeline: None,
+            set: None,
+        }
+    }
+}
+
+impl Pass<Scene> for CompositePass {
+    fn declare(&mut self, pass: &mut PassBuilder) {
+        pass.color(self.color, Load::Keep).sample(self.accum).sample(self.revealage).conditional();
+    }
+
+    fn prepare(&mut self, device: &Arc<Device>, subpass: Option<Subpass>) {
+        let vs = vs::Shader::load(device.clone()).unwrap();
+        let fs = fs::Shader::load(device.clone()).unwrap();
+        let pipeline = Arc::new(GraphicsPipeline::start().vertex_input(BufferlessDefinition)
+                                                         .vertex_shader(vs.main_entry_point(), ())
+                                                         .triangle_list()
+                                                         .viewports_dynamic_scissors_irrelevant(1)
+                                                         .fragment_shader(fs.main_entry_point(), ())
+                                                         .blend_collective(AttachmentBlend::alpha_blending())
+                                                         .render_pass(subpass.expect("the transparency composite pass renders to an attachment"))
+                                                         .build(device.clone())
+                                                         .unwrap());
+        self.namer.name(&*pipeline, "transparency composite pipeline");
+        self.pipeline = Some(pipeline);
+        self.set = None;
+    }
+
+    fn enabled(&self, _scene: &Scene) -> bool {
+        *self.mode.lock().unwrap() == TransparencyMode::WeightedBlended
+    }
+
+    fn record(&mut self, context: &mut PassContext, _scene: &Scene) {
+        let pipeline = self.pipeline.clone().unwrap();
+
+        if self.set.as_ref().map(|(generation, _)| *generation) != Some(context.generation()) {
+            let layout = pipeline.descriptor_set_layout(0).unwrap().clone();
+            let set = Arc::new(PersistentDescriptorSet::start(layout).add_sampler(self.sampler.clone())
+                                                                     .unwrap()
+                                                                     .add_image(context.image(self.accum))
+                                                                     .unwrap()
+                                                                     .add_image(context.image(self.revealage))
+                                                                     .unwrap()
+                                                                     .build()
+                                                                     .unwrap());
+            self.set = Some((context.generation(), set));
+        }
+
+        context.builder
+               .draw(pipeline, context.dynamic_state, BufferlessVertices { vertices: 3, instances: 1 }, self.set.as_ref().unwrap().1.clone(), (), vec![])
+               .unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+use std::env;
+use std::sync::{Arc, Mutex};
+
+use tracing::warn;
+use vulkano::device::Device;
+use vulkano::format::Format;
+
+use crate::culling::Culling;
+use crate::debug::ObjectNamer;
+use crate::environment::EnvironmentMaps;
+use crate::logging;
+use crate::pbr::{ForwardPass, ForwardTarget};
+use crate::render_graph::{ImageDesc, ImageId, ImageSize, Load, RenderGraphBuilder};
+use crate::scene::Scene;
+use crate::shadow::ShadowMaps;
+use crate::transparency::composite::CompositePass;
+use crate::upload::UploadManager;
+
+mod composite;
+
+/// How overlapping blended surfaces are combined. Additive materials don't depend on the order, so they're always
+/// drawn sorted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TransparencyMode {
+    /// Objects are blended one after the other, the farthest from the camera first. Exact unless objects intersect
+    /// or overlap themselves.
+    Sorted,
+    /// Weighted blended order-independent transparency: every surface is accumulated in any order and resolved by a
+    /// weighted average, approximating the order by the distance to the camera.
+    WeightedBlended,
+}
+
+impl TransparencyMode {
+    pub fn next(self) -> TransparencyMode {
+        match self {
+            TransparencyMode::Sorted => TransparencyMode::WeightedBlended,
+            TransparencyMode::WeightedBlended => TransparencyMode::Sorted,
+        }
+    }
+
+    /// Reads `TONIC_TRANSPARENCY`, either `sorted` or `oit`, defaulting to sorted.
+    pub fn from_env() -> TransparencyMode {
+        match env::var("TONIC_TRANSPARENCY").as_ref().map(|s| s.to_ascii_lowercase()) {
+            Ok(ref s) if s == "oit" => TransparencyMode::WeightedBlended,
+            _ => TransparencyMode::Sorted,
+        }
+    }
+}
+
+/// Adds the passes blending the scene's blended materials over `color`, tested against `depth`. They have to be
+/// added after the passes drawing the opaque scene and the sky. Returns the mode, for changing it while running.
+///
+/// Weighted blended transparency needs the device's `independent_blend` feature. Without it the mode stays sorted.
+#[allow(clippy::too_many_arguments)]
+pub fn add_passes(graph: &mut RenderGraphBuilder<Scene>,
+                  color: ImageId,
+                  depth: ImageId,
+                  mode: TransparencyMode,
+                  shadows: ShadowMaps,
+                  environment: EnvironmentMaps,
+                  culling: Culling,
+                  device: &Arc<Device>,
+                  uploads: &mut UploadManager,
+                  namer: &ObjectNamer)
+                  -> Arc<Mutex<TransparencyMode>> {
+    let weighted_blended = device.enabled_features().independent_blend;
+    if mode == TransparencyMode::WeightedBlended && !weighted_blended {
+        warn!(target: logging::RENDER, "weighted blended transparency needs the independent blend feature, sorting instead");
+    }
+    let mode = Arc::new(Mutex::new(if weighted_blended { mode } else { TransparencyMode::Sorted }));
+    if weighted_blended {
+        // 32 bit, since the weighted sums of HDR radiance overflow 16 bit floats.
+        let accum = graph.image("transparency accum", ImageDesc::new(Format::R32G32B32A32Sfloat, ImageSize::Backbuffer));
+        let revealage = graph.image("transparency revealage", ImageDesc::new(Format::R16Sfloat, ImageSize::Backbuffer));
+        graph.add_pass("weighted blended transparency",
+                       ForwardPass::new(device.clone(),
+                                        ForwardTarget::WeightedBlended { accum, revealage, mode: mode.clone() },
+                                        depth,
+                                        Load::Keep,
+                                        shadows,
+                                        environment.clone(),
+                                        culling.clone(),
+                                        uploads,
+                                        namer));
+        graph.add_pass("transparency composite", CompositePass::new(device, color, accum, revealage, mode.clone(), namer));
+    }
+    graph.add_pass("sorted transparency",
+                   ForwardPass::new(device.clone(),
+                                    ForwardTarget::Sorted { color, mode: mode.clone() },
+                                    depth,
+                                    Load::Keep,
+                                    shadows,
+                                    environment,
+                                    culling,
+                                    uploads,
+                                    namer));
+    mode
+}
@@ -0,0 +1,228 @@
+use crate::text::TextLayout;
+use crate::ui::style::{AlignItems, Direction, Justify, Style};
+use crate::ui::{NodeId, Rect, Ui, Widget};
+
+impl Ui {
+    /// Places every shown node, the root filling `viewport`.
+    ///
+    /// Layout is a subset of flexbox: children go along their parent's `direction` with `gap` between them,
+    /// grow into the space left by their `grow` share, and are justified and aligned in it. They don't shrink
+    /// when there isn't enough space, but overflow.
+    pub(crate) fn layout(&mut self, viewport: [u32; 2]) {
+        let viewport = [viewport[0] as f32, viewport[1] as f32];
+        let root = self.root;
+        self.measure(root, viewport);
+        self.arrange(root, Rect { x: 0.0, y: 0.0, width: viewport[0], height: viewport[1] });
+    }
+
+    /// Size of `id` with its padding but without its margin, as large as its content where its style doesn't
+    /// say otherwise. `available` is the size percentages are of.
+    fn measure(&mut self, id: NodeId, available: [f32; 2]) -> [f32; 2] {
+        let style = self.node(id).style.clone();
+        let fixed = [style.width.resolve(available[0]), style.height.resolve(available[1])];
+        let padding = [style.padding.horizontal(), style.padding.vertical()];
+        let inner = [fixed[0].map_or(available[0], |width| width - padding[0]).max(0.0), fixed[1].map_or(available[1], |height| height - padding[1]).max(0.0)];
+
+        let content = match &self.node(id).widget {
+            Widget::Label { text } | Widget::Button { text } => Some(self.font(&style).layout(text, style.font_size, &text_layout(&style, fixed[0].map(|_| inner[0]))).size),
+            Widget::Image { image } => Some(image.size),
+            Widget::Slider { .. } => Some([0.0, 0.0]),
+            Widget::Panel | Widget::List { .. } => None,
+        };
+        let content = content.unwrap_or_else(|| self.measure_children(id, &style, inner));
+
+        let measured = [fixed[0].unwrap_or(content[0] + padding[0]), fixed[1].unwrap_or(content[1] + padding[1])];
+        self.node_mut(id).measured = measured;
+        measured
+    }
+
+    /// Size of the children of `id` laid out next to each other.
+    fn measure_children(&mut self, id: NodeId, style: &Style, inner: [f32; 2]) -> [f32; 2] {
+        let children = self.laid_out_children(id);
+        let (main, cross) = if style.direction == Direction::Row { (0, 1) } else { (1, 0) };
+        let mut size = [0.0f32, 0.0f32];
+        for &child in children.iter() {
+            let measured = self.measure(child, inner);
+            let margin = self.node(child).style.margin;
+            let outer = [measured[0] + margin.horizontal(), measured[1] + margin.vertical()];
+            size[main] += outer[main];
+            size[cross] = size[cross].max(outer[cross]);
+        }
+        size[main] += style.gap * children.len().saturating_sub(1) as f32;
+        size
+    }
+
+    fn arrange(&mut self, id: NodeId, rect: Rect) {
+        self.node_mut(id).rect = rect;
+        let style = self.node(id).style.clone();
+        let children = self.laid_out_children(id);
+        if children.is_empty() {
+            return;
+        }
+
+        let inner = rect.inset(&style.padding);
+        let row = style.direction == Direction::Row;
+        let (main_size, cross_size) = if row { (inner.width, inner.height) } else { (inner.height, inner.width) };
+
+        // Main and cross size of every child, without margins.
+        let mut sizes: Vec<[f32; 2]> = children.iter()
+                                               .map(|&child| {
+                                                   let node = self.node(child);
+                                                   let (main_length, cross_length) = if row { (node.style.width, node.style.height) } else { (node.style.height, node.style.width) };
+                                                   let (main_measured, cross_measured) = if row { (node.measured[0], node.measured[1]) } else { (node.measured[1], node.measured[0]) };
+                                                   let cross_margin = if row { node.style.margin.vertical() } else { node.style.margin.horizontal() };
+                                                   let main = main_length.resolve(main_size).unwrap_or(main_measured);
+                                                   let cross = match cross_length.resolve(cross_size) {
+                                                       Some(cross) => cross,
+                                                       None if style.align_items == AlignItems::Stretch => (cross_size - cross_margin).max(0.0),
+                                                       None => cross_measured,
+                                                   };
+                                                   [main, cross]
+                                               })
+                                               .collect();
+
+        let styles: Vec<Style> = children.iter().map(|&child| self.node(child).style.clone()).collect();
+        let main_margin = |style: &Style| if row { (style.margin.left, style.margin.right) } else { (style.margin.top, style.margin.bottom) };
+        let cross_margin = |style: &Style| if row { (style.margin.top, style.margin.bottom) } else { (style.margin.left, style.margin.right) };
+
+        let used: f32 = sizes.iter().zip(styles.iter()).map(|(size, style)| size[0] + main_margin(style).0 + main_margin(style).1).sum::<f32>() + style.gap * (children.len() - 1) as f32;
+        let mut free = (main_size - used).max(0.0);
+        let grow: f32 = styles.iter().map(|style| style.grow).sum();
+        if free > 0.0 && grow > 0.0 {
+            for (size, style) in sizes.iter_mut().zip(styles.iter()) {
+                size[0] += free * style.grow / grow;
+            }
+            free = 0.0;
+        }
+
+        let count = children.len() as f32;
+        let (start, between) = match style.justify {
+            Justify::Start => (0.0, 0.0),
+            Justify::Center => (free / 2.0, 0.0),
+            Justify::End => (free, 0.0),
+            Justify::SpaceBetween if children.len() > 1 => (0.0, free / (count - 1.0)),
+            Justify::SpaceBetween => (0.0, 0.0),
+            Justify::SpaceAround => (free / count / 2.0, free / count),
+        };
+
+        let mut cursor = start;
+        for ((&child, size), child_style) in children.iter().zip(sizes.iter()).zip(styles.iter()) {
+            let (before, after) = main_margin(child_style);
+            let (cross_before, cross_after) = cross_margin(child_style);
+            cursor += before;
+            let cross = match style.align_items {
+                AlignItems::Start | AlignItems::Stretch => cross_before,
+                AlignItems::Center => (cross_size - size[1]) / 2.0,
+                AlignItems::End => cross_size - size[1] - cross_after,
+            };
+            let child_rect = if row {
+                Rect { x: inner.x + cursor, y: inner.y + cross, width: size[0], height: size[1] }
+            } else {
+                Rect { x: inner.x + cross, y: inner.y + cursor, width: size[1], height: size[0] }
+            };
+            self.arrange(child, child_rect);
+            cursor += size[0] + after + style.gap + between;
+        }
+    }
+}
+
+/// How the text of a node with `style` is laid out, wrapped at `max_width` when its width is known.
+pub(crate) fn text_layout(style: &Style, max_width: Option<f32>) -> TextLayout {
+    TextLayout { max_width, align: style.text_align, ..TextLayout::default() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::Font;
+    use crate::ui::StyleSheet;
+
+    /// A UI styled by `style`, with a `row` panel in its root holding panels with the classes in `children`.
+    fn row(style: &str, children: &[&str]) -> (Ui, NodeId, Vec<NodeId>) {
+        let font = Font::from_bytes(egui::FontDefinitions::default().font_data["Hack"].to_vec()).unwrap();
+        let mut ui = Ui::with_white(StyleSheet::parse(style).unwrap(), font, None);
+        let row = ui.add(ui.root(), Widget::Panel, &["row"]);
+        let children = children.iter().map(|&class| ui.add(row, Widget::Panel, &[class])).collect();
+        (ui, row, children)
+    }
+
+    fn layout(ui: &mut Ui, viewport: [u32; 2]) {
+        let root = ui.root();
+        ui.restyle(root, None, false);
+        ui.layout(viewport);
+    }
+
+    fn rects(ui: &Ui, ids: &[NodeId]) -> Vec<[f32; 4]> {
+        ids.iter().map(|&id| ui.node(id).rect).map(|rect| [rect.x, rect.y, rect.width, rect.height]).collect()
+    }
+
+    #[test]
+    fn grow_shares_free_space() {
+        let style = r#"[
+            { "selector": ".row", "style": { "direction": "row", "gap": 10, "height": { "px": 100 } } },
+            { "selector": ".a", "style": { "width": { "px": 100 }, "grow": 1 } },
+            { "selector": ".b", "style": { "width": { "px": 100 }, "grow": 3 } },
+            { "selector": ".c", "style": { "width": { "px": 100 } } }
+        ]"#;
+        let (mut ui, row, children) = row(style, &["a", "b", "c"]);
+        layout(&mut ui, [600, 400]);
+
+        // 280 pixels are left after the widths and gaps, a quarter for `a` and the rest for `b`. Stretched across.
+        assert_eq!(rects(&ui, &[row]), [[0.0, 0.0, 600.0, 100.0]]);
+        assert_eq!(rects(&ui, &children), [[0.0, 0.0, 170.0, 100.0], [180.0, 0.0, 310.0, 100.0], [500.0, 0.0, 100.0, 100.0]]);
+    }
+
+    #[test]
+    fn overflowing_children_keep_their_size() {
+        let style = r#"[
+            { "selector": ".row", "style": { "direction": "row", "justify": "center" } },
+            { "selector": ".a", "style": { "width": { "px": 300 }, "height": { "px": 20 }, "grow": 1 } }
+        ]"#;
+        let (mut ui, _, children) = row(style, &["a", "a", "a"]);
+        layout(&mut ui, [600, 400]);
+
+        // Nothing shrinks, grows or is centered without free space, the last child is just past the end.
+        assert_eq!(rects(&ui, &children), [[0.0, 0.0, 300.0, 20.0], [300.0, 0.0, 300.0, 20.0], [600.0, 0.0, 300.0, 20.0]]);
+    }
+
+    #[test]
+    fn space_between_and_center() {
+        let style = r#"[
+            { "selector": ".row", "style": { "direction": "row", "justify": "space_between", "align_items": "center", "height": { "px": 100 } } },
+            { "selector": ".small", "style": { "width": { "px": 100 }, "height": { "px": 20 } } },
+            { "selector": ".large", "style": { "width": { "px": 100 }, "height": { "px": 40 } } }
+        ]"#;
+        let (mut ui, _, children) = row(style, &["small", "large", "small"]);
+        layout(&mut ui, [600, 400]);
+
+        assert_eq!(rects(&ui, &children), [[0.0, 40.0, 100.0, 20.0], [250.0, 30.0, 100.0, 40.0], [500.0, 40.0, 100.0, 20.0]]);
+    }
+
+    #[test]
+    fn end_with_padding_and_margin() {
+        let style = r#"[
+            { "selector": ".row", "style": { "direction": "row", "justify": "end", "align_items": "end", "height": { "px": 100 }, "padding": [10, 10, 10, 10] } },
+            { "selector": ".a", "style": { "width": { "px": 100 }, "height": { "px": 20 }, "margin": [0, 5, 5, 0] } }
+        ]"#;
+        let (mut ui, _, children) = row(style, &["a"]);
+        layout(&mut ui, [600, 400]);
+
+        // The margin keeps it 5 pixels off the right and bottom of the padding.
+        assert_eq!(rects(&ui, &children), [[485.0, 65.0, 100.0, 20.0]]);
+    }
+
+    #[test]
+    fn column_measures_its_content() {
+        let style = r#"[
+            { "selector": ".row", "style": { "gap": 4, "padding": [2, 2, 2, 2], "align_items": "start" } },
+            { "selector": ".a", "style": { "width": { "px": 50 }, "height": { "px": 10 } } },
+            { "selector": ".b", "style": { "width": { "percent": 50 }, "height": { "px": 30 } } }
+        ]"#;
+        let (mut ui, row, children) = row(style, &["a", "b"]);
+        layout(&mut ui, [600, 400]);
+
+        // A column as tall as its children, gap and padding, and percentages of the width inside the padding.
+        assert_eq!(rects(&ui, &[row]), [[0.0, 0.0, 600.0, 48.0]]);
+        assert_eq!(rects(&ui, &children), [[2.0, 2.0, 50.0, 10.0], [2.0, 16.0, 298.0, 30.0]]);
+    }
+}
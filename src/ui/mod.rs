@@ -0,0 +1,666 @@
+use std::sync::Arc;
+
+use vulkano::device::Device;
+use vulkano::sampler::Filter;
+use winit::event::{ElementState, MouseButton, VirtualKeyCode, WindowEvent};
+
+pub use crate::ui::style::{Edges, Style, StyleProps, StyleSheet};
+
+use crate::debug::ObjectNamer;
+use crate::render_graph::{ImageId, Load, RenderGraphBuilder};
+use crate::scene::{Material, Scene, Texture};
+use crate::sprite::{Sprite, SpritePass};
+use crate::text::{self, GlyphAtlas, Font, Text};
+use crate::ui::layout::text_layout;
+use crate::ui::style::{Direction, NodeState};
+use crate::upload::UploadManager;
+
+mod layout;
+mod style;
+
+/// A node of a `Ui`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+/// Pixels from the top left of the target, y down.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn contains(&self, point: [f32; 2]) -> bool {
+        point[0] >= self.x && point[0] < self.x + self.width && point[1] >= self.y && point[1] < self.y + self.height
+    }
+
+    pub fn center(&self) -> [f32; 2] {
+        [self.x + self.width / 2.0, self.y + self.height / 2.0]
+    }
+
+    pub fn inset(&self, edges: &Edges) -> Rect {
+        Rect {
+            x: self.x + edges.left,
+            y: self.y + edges.top,
+            width: (self.width - edges.horizontal()).max(0.0),
+            height: (self.height - edges.vertical()).max(0.0),
+        }
+    }
+}
+
+/// Part of a texture drawn by the UI.
+#[derive(Clone)]
+pub struct UiImage {
+    pub texture: Texture,
+    /// Minimum and maximum UV.
+    pub region: [f32; 4],
+    /// Pixel size of the region, how large image widgets are unless their style says otherwise.
+    pub size: [f32; 2],
+    /// Pixels of the region's edges that keep their size when it's stretched. The corners stay as they are, the
+    /// edges stretch along them and the middle both ways.
+    pub slices: Option<Edges>,
+}
+
+impl UiImage {
+    /// The whole of `texture`, `size` pixels large.
+    pub fn new(texture: Texture, size: [f32; 2]) -> UiImage {
+        UiImage { texture, region: [0.0, 0.0, 1.0, 1.0], size, slices: None }
+    }
+
+    /// Nine-sliced with `slices` pixels on each side.
+    pub fn sliced(self, slices: Edges) -> UiImage {
+        UiImage { slices: Some(slices), ..self }
+    }
+}
+
+/// What kind of widget a node is, `panel`, `label`, `button`, `image`, `slider` or `list` in style sheets.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WidgetKind {
+    Panel,
+    Label,
+    Button,
+    Image,
+    Slider,
+    List,
+}
+
+impl WidgetKind {
+    fn from_name(name: &str) -> Option<WidgetKind> {
+        Some(match name {
+            "panel" => WidgetKind::Panel,
+            "label" => WidgetKind::Label,
+            "button" => WidgetKind::Button,
+            "image" => WidgetKind::Image,
+            "slider" => WidgetKind::Slider,
+            "list" => WidgetKind::List,
+            _ => return None,
+        })
+    }
+}
+
+pub enum Widget {
+    /// Holds other nodes.
+    Panel,
+    Label { text: String },
+    Button { text: String },
+    Image { image: UiImage },
+    /// Left and right move it by `step` while it has focus.
+    Slider { value: f32, min: f32, max: f32, step: f32 },
+    /// Its children are rows, `rows` of them shown from `scroll`. Up and down move the selection while it has
+    /// focus, scrolling to keep it shown.
+    List { selected: usize, scroll: usize, rows: usize },
+}
+
+impl Widget {
+    pub fn label(text: &str) -> Widget {
+        Widget::Label { text: text.to_string() }
+    }
+
+    pub fn button(text: &str) -> Widget {
+        Widget::Button { text: text.to_string() }
+    }
+
+    pub fn image(image: UiImage) -> Widget {
+        Widget::Image { image }
+    }
+
+    pub fn slider(value: f32, min: f32, max: f32, step: f32) -> Widget {
+        Widget::Slider { value, min, max, step }
+    }
+
+    pub fn list(rows: usize) -> Widget {
+        Widget::List { selected: 0, scroll: 0, rows }
+    }
+
+    pub fn kind(&self) -> WidgetKind {
+        match self {
+            Widget::Panel => WidgetKind::Panel,
+            Widget::Label { .. } => WidgetKind::Label,
+            Widget::Button { .. } => WidgetKind::Button,
+            Widget::Image { .. } => WidgetKind::Image,
+            Widget::Slider { .. } => WidgetKind::Slider,
+            Widget::List { .. } => WidgetKind::List,
+        }
+    }
+
+    fn focusable(&self) -> bool {
+        matches!(self, Widget::Button { .. } | Widget::Slider { .. } | Widget::List { .. })
+    }
+}
+
+/// What the UI reports back to the game, see `Ui::take_events`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum UiEvent {
+    Clicked(NodeId),
+    /// A slider's new value.
+    Changed(NodeId, f32),
+    /// A list's row was picked, by clicking it or activating the list.
+    Selected(NodeId, usize),
+    /// Back was pressed, to leave a menu.
+    Back,
+}
+
+/// Focus navigation, from the keyboard through `from_key` or from anything else, like a gamepad's d-pad and face
+/// buttons, through `Ui::navigate`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UiAction {
+    Up,
+    Down,
+    Left,
+    Right,
+    Next,
+    Previous,
+    Activate,
+    Back,
+}
+
+impl UiAction {
+    /// Arrows and WASD move, Tab and Shift+Tab go through widgets in order, Enter and Space activate and Escape
+    /// goes back.
+    pub fn from_key(key: VirtualKeyCode, shift: bool) -> Option<UiAction> {
+        Some(match key {
+            VirtualKeyCode::Up | VirtualKeyCode::W => UiAction::Up,
+            VirtualKeyCode::Down | VirtualKeyCode::S => UiAction::Down,
+            VirtualKeyCode::Left | VirtualKeyCode::A => UiAction::Left,
+            VirtualKeyCode::Right | VirtualKeyCode::D => UiAction::Right,
+            VirtualKeyCode::Tab if shift => UiAction::Previous,
+            VirtualKeyCode::Tab => UiAction::Next,
+            VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter | VirtualKeyCode::Space => UiAction::Activate,
+            VirtualKeyCode::Escape | VirtualKeyCode::Back => UiAction::Back,
+            _ => return None,
+        })
+    }
+}
+
+struct Node {
+    widget: Widget,
+    classes: Vec<String>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    disabled: bool,
+    hidden: bool,
+    /// Computed every frame before layout.
+    style: Style,
+    /// Size layout measured, with padding.
+    measured: [f32; 2],
+    rect: Rect,
+}
+
+/// A retained tree of widgets for menus and HUDs, laid out like flexbox, styled by a `StyleSheet` and drawn
+/// through the sprite and text passes.
+///
+/// The game builds the tree once and changes it as it goes, feeding it window events with `handle_event` or
+/// navigation with `navigate`, and reacting to what `take_events` returns. `render` lays it out and puts it in the
+/// scene every frame. All text is drawn over all images, so images shouldn't overlap text of other widgets.
+pub struct Ui {
+    nodes: Vec<Node>,
+    root: NodeId,
+    pub style: StyleSheet,
+    /// Text without a font of its own in the style sheet.
+    pub font: Font,
+    /// Behind plain quads. Only missing in tests, which lay out without drawing.
+    white: Option<Texture>,
+    focus: Option<NodeId>,
+    hovered: Option<NodeId>,
+    pressed: Option<NodeId>,
+    pointer: Option<[f32; 2]>,
+    shift: bool,
+    events: Vec<UiEvent>,
+}
+
+impl Ui {
+    /// An empty UI with a root panel covering the target.
+    pub fn new(style: StyleSheet, font: Font, uploads: &mut UploadManager, namer: &ObjectNamer) -> Ui {
+        let white = Material::texture(uploads, namer, "ui white texture", &[[255, 255, 255, 255]], [1, 1], false);
+        Ui::with_white(style, font, Some(white))
+    }
+
+    fn with_white(style: StyleSheet, font: Font, white: Option<Texture>) -> Ui {
+        let mut ui = Ui {
+            nodes: vec![],
+            root: NodeId(0),
+            style,
+            font,
+            white,
+            focus: None,
+            hovered: None,
+            pressed: None,
+            pointer: None,
+            shift: false,
+            events: vec![],
+        };
+        ui.root = ui.insert(None, Widget::Panel, &[]);
+        ui
+    }
+
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    /// Adds `widget` after the other children of `parent`, with style sheet classes.
+    pub fn add(&mut self, parent: NodeId, widget: Widget, classes: &[&str]) -> NodeId {
+        self.insert(Some(parent), widget, classes)
+    }
+
+    pub fn widget(&self, id: NodeId) -> &Widget {
+        &self.node(id).widget
+    }
+
+    pub fn widget_mut(&mut self, id: NodeId) -> &mut Widget {
+        &mut self.node_mut(id).widget
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.node(id).children
+    }
+
+    /// Disabled nodes and everything in them can't be focused or used, and are styled as `:disabled`.
+    pub fn set_disabled(&mut self, id: NodeId, disabled: bool) {
+        self.node_mut(id).disabled = disabled;
+    }
+
+    /// Hidden nodes and everything in them take no space and aren't drawn.
+    pub fn set_hidden(&mut self, id: NodeId, hidden: bool) {
+        self.node_mut(id).hidden = hidden;
+    }
+
+    pub fn focus(&mut self, id: Option<NodeId>) {
+        self.focus = id;
+    }
+
+    /// What happened since the last call, in order.
+    pub fn take_events(&mut self) -> Vec<UiEvent> {
+        self.events.split_off(0)
+    }
+
+    /// Passes a window event on to the UI. True when the UI used it, in which case the game should ignore it: the
+    /// pointer is over a widget or a background, or a navigation key was pressed while something has focus.
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.shift = modifiers.shift();
+                false
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let pointer = [position.x as f32, position.y as f32];
+                self.pointer = Some(pointer);
+                self.hovered = self.interactive_at(pointer);
+                if let Some(pressed) = self.pressed {
+                    self.drag(pressed, pointer);
+                }
+                self.covers(pointer)
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.pointer = None;
+                self.hovered = None;
+                false
+            }
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                let pointer = match self.pointer {
+                    Some(pointer) => pointer,
+                    None => return false,
+                };
+                match state {
+                    ElementState::Pressed => {
+                        self.pressed = self.hovered;
+                        if let Some(pressed) = self.pressed {
+                            self.focus = Some(pressed);
+                            self.drag(pressed, pointer);
+                        }
+                    }
+                    ElementState::Released => {
+                        if let Some(pressed) = self.pressed.take() {
+                            if self.hovered == Some(pressed) {
+                                self.click(pressed, pointer);
+                            }
+                        }
+                    }
+                }
+                self.covers(pointer)
+            }
+            WindowEvent::KeyboardInput { input, .. } if input.state == ElementState::Pressed => match input.virtual_keycode.and_then(|key| UiAction::from_key(key, self.shift)) {
+                Some(action) => self.navigate(action),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Moves focus, changes the focused widget or activates it, focusing the first widget when none is. True when
+    /// there is anything to navigate.
+    pub fn navigate(&mut self, action: UiAction) -> bool {
+        let focusable = self.focusable();
+        if focusable.is_empty() {
+            return false;
+        }
+        if action == UiAction::Back {
+            self.events.push(UiEvent::Back);
+            return true;
+        }
+
+        let focus = match self.focus.filter(|focus| focusable.contains(focus)) {
+            Some(focus) => focus,
+            None => {
+                self.focus = focusable.first().copied();
+                return self.focus.is_some();
+            }
+        };
+
+        let row = self.node(focus).style.direction == Direction::Row;
+        let rows = self.node(focus).children.len();
+        let node = &mut self.nodes[focus.0];
+        match (&mut node.widget, action) {
+            (Widget::Slider { value, min, max, step }, UiAction::Left | UiAction::Right) => {
+                let delta = if action == UiAction::Left { -*step } else { *step };
+                let changed = (*value + delta).clamp(*min, *max);
+                if changed != *value {
+                    *value = changed;
+                    self.events.push(UiEvent::Changed(focus, changed));
+                }
+                return true;
+            }
+            (Widget::List { selected, scroll, rows: shown }, _) if rows > 0 && matches!((row, action), (false, UiAction::Up) | (false, UiAction::Down) | (true, UiAction::Left) | (true, UiAction::Right)) => {
+                let forward = matches!(action, UiAction::Down | UiAction::Right);
+                // Past either end focus moves on instead.
+                if (forward && *selected + 1 < rows) || (!forward && *selected > 0) {
+                    *selected = if forward { *selected + 1 } else { *selected - 1 };
+                    *scroll = (*scroll).min(*selected).max((*selected + 1).saturating_sub(*shown));
+                    return true;
+                }
+            }
+            (Widget::Button { .. }, UiAction::Activate) => {
+                self.events.push(UiEvent::Clicked(focus));
+                return true;
+            }
+            (Widget::List { selected, .. }, UiAction::Activate) if rows > 0 => {
+                let selected = *selected;
+                self.events.push(UiEvent::Selected(focus, selected));
+                return true;
+            }
+            _ => {}
+        }
+
+        let position = focusable.iter().position(|&id| id == focus).unwrap();
+        let next = match action {
+            UiAction::Next => Some(focusable[(position + 1) % focusable.len()]),
+            UiAction::Previous => Some(focusable[(position + focusable.len() - 1) % focusable.len()]),
+            UiAction::Up | UiAction::Down | UiAction::Left | UiAction::Right => self.nearest(focus, action, &focusable),
+            UiAction::Activate | UiAction::Back => None,
+        };
+        if let Some(next) = next {
+            self.focus = Some(next);
+        }
+        true
+    }
+
+    /// Lays the UI out to fill `viewport` and replaces the scene's UI sprites and texts with it.
+    pub fn render(&mut self, viewport: [u32; 2], scene: &mut Scene) {
+        let root = self.root;
+        self.restyle(root, None, false);
+        self.layout(viewport);
+
+        scene.ui_sprites.clear();
+        scene.ui_texts.clear();
+        let white = self.white.as_ref().unwrap();
+        let height = viewport[1] as f32;
+        for id in self.shown(root) {
+            let node = self.node(id);
+            let style = &node.style;
+
+            if let Some(image) = style.background_image.as_ref().and_then(|name| self.style.image(name)) {
+                let color = style.background_color.unwrap_or([1.0, 1.0, 1.0, 1.0]);
+                self.draw_image(image, node.rect, color, height, &mut scene.ui_sprites);
+            } else if let Some(color) = style.background_color {
+                scene.ui_sprites.push(self.quad(white, [0.0, 0.0, 1.0, 1.0], node.rect, color, height));
+            }
+
+            let content = node.rect.inset(&style.padding);
+            match &node.widget {
+                Widget::Label { text } | Widget::Button { text } => {
+                    let font = self.font(style).clone();
+                    let layout = text_layout(style, Some(content.width));
+                    let size = font.layout(text, style.font_size, &layout).size;
+                    // Centered vertically when the box is taller than the text.
+                    let position = [content.x, content.y + ((content.height - size[1]) / 2.0).max(0.0)];
+                    scene.ui_texts.push(Text { layout, color: style.color, ..Text::screen(text, font, style.font_size, position) });
+                }
+                Widget::Image { image } => self.draw_image(image, content, [1.0, 1.0, 1.0, 1.0], height, &mut scene.ui_sprites),
+                Widget::Slider { value, min, max, .. } => {
+                    let fraction = if max > min { ((value - min) / (max - min)).clamp(0.0, 1.0) } else { 0.0 };
+                    let filled = Rect { width: content.width * fraction, ..content };
+                    let dimmed = [style.accent[0], style.accent[1], style.accent[2], style.accent[3] * 0.5];
+                    scene.ui_sprites.push(self.quad(white, [0.0, 0.0, 1.0, 1.0], filled, dimmed, height));
+                    // A square thumb a bit taller than the track.
+                    let side = content.height + 8.0;
+                    let thumb = Rect { x: content.x + filled.width - side / 2.0, y: content.y - 4.0, width: side, height: side };
+                    scene.ui_sprites.push(self.quad(white, [0.0, 0.0, 1.0, 1.0], thumb, style.accent, height));
+                }
+                Widget::Panel | Widget::List { .. } => {}
+            }
+        }
+    }
+
+    fn insert(&mut self, parent: Option<NodeId>, widget: Widget, classes: &[&str]) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Node {
+                            widget,
+                            classes: classes.iter().map(|class| class.to_string()).collect(),
+                            parent,
+                            children: vec![],
+                            disabled: false,
+                            hidden: false,
+                            style: Style::default(),
+                            measured: [0.0, 0.0],
+                            rect: Rect::default(),
+                        });
+        if let Some(parent) = parent {
+            self.node_mut(parent).children.push(id);
+        }
+        id
+    }
+
+    fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0]
+    }
+
+    fn font(&self, style: &Style) -> &Font {
+        style.font.as_ref().and_then(|name| self.style.font(name)).unwrap_or(&self.font)
+    }
+
+    /// The children of `id` layout places, which for lists are the rows scrolled to.
+    fn laid_out_children(&self, id: NodeId) -> Vec<NodeId> {
+        let node = self.node(id);
+        let shown = node.children.iter().copied().filter(|&child| !self.node(child).hidden);
+        match node.widget {
+            Widget::List { scroll, rows, .. } => shown.skip(scroll).take(rows).collect(),
+            _ => shown.collect(),
+        }
+    }
+
+    /// `id` and what's laid out in it, parents before children.
+    fn shown(&self, id: NodeId) -> Vec<NodeId> {
+        let mut shown = vec![id];
+        for child in self.laid_out_children(id) {
+            shown.extend(self.shown(child));
+        }
+        shown
+    }
+
+    fn enabled(&self, id: NodeId) -> bool {
+        let node = self.node(id);
+        !node.disabled
+        && match node.parent {
+            Some(parent) => self.enabled(parent),
+            None => true,
+        }
+    }
+
+    /// The shown widgets that can take focus, in tree order.
+    fn focusable(&self) -> Vec<NodeId> {
+        self.shown(self.root).into_iter().filter(|&id| self.node(id).widget.focusable() && self.enabled(id)).collect()
+    }
+
+    fn restyle(&mut self, id: NodeId, parent: Option<&Style>, disabled: bool) {
+        let node = self.node(id);
+        let disabled = disabled || node.disabled;
+        let selected = match node.parent.map(|parent| &self.node(parent).widget) {
+            Some(Widget::List { selected, .. }) => self.node(node.parent.unwrap()).children.get(*selected) == Some(&id),
+            _ => false,
+        };
+        let state = NodeState {
+            hovered: self.hovered == Some(id),
+            focused: self.focus == Some(id),
+            pressed: self.pressed == Some(id),
+            disabled,
+            selected,
+        };
+        let style = self.style.compute(node.widget.kind(), &node.classes, state, parent);
+        for child in self.laid_out_children(id) {
+            self.restyle(child, Some(&style), disabled);
+        }
+        self.node_mut(id).style = style;
+    }
+
+    /// The topmost enabled widget that reacts to the pointer at `point`.
+    fn interactive_at(&self, point: [f32; 2]) -> Option<NodeId> {
+        self.shown(self.root).into_iter().rev().find(|&id| self.node(id).widget.focusable() && self.node(id).rect.contains(point) && self.enabled(id))
+    }
+
+    /// Whether anything drawn is under `point`.
+    fn covers(&self, point: [f32; 2]) -> bool {
+        let drawn = |node: &Node| node.widget.focusable() || node.style.background_color.is_some() || node.style.background_image.is_some();
+        self.pressed.is_some() || self.shown(self.root).into_iter().any(|id| drawn(self.node(id)) && self.node(id).rect.contains(point))
+    }
+
+    /// Sets a slider pressed at `pointer` to the value there.
+    fn drag(&mut self, id: NodeId, pointer: [f32; 2]) {
+        let node = &mut self.nodes[id.0];
+        let content = node.rect.inset(&node.style.padding);
+        if let Widget::Slider { value, min, max, step } = &mut node.widget {
+            let fraction = ((pointer[0] - content.x) / content.width.max(1.0)).clamp(0.0, 1.0);
+            let mut changed = *min + fraction * (*max - *min);
+            if *step > 0.0 {
+                changed = (*min + ((changed - *min) / *step).round() * *step).min(*max);
+            }
+            if changed != *value {
+                *value = changed;
+                self.events.push(UiEvent::Changed(id, changed));
+            }
+        }
+    }
+
+    fn click(&mut self, id: NodeId, pointer: [f32; 2]) {
+        let row = self.laid_out_children(id).into_iter().position(|child| self.node(child).rect.contains(pointer));
+        let scrolled = match &self.node(id).widget {
+            Widget::Button { .. } => {
+                self.events.push(UiEvent::Clicked(id));
+                return;
+            }
+            Widget::List { scroll, .. } => *scroll,
+            _ => return,
+        };
+        if let Some(row) = row {
+            if let Widget::List { selected, .. } = &mut self.node_mut(id).widget {
+                *selected = scrolled + row;
+            }
+            self.events.push(UiEvent::Selected(id, scrolled + row));
+        }
+    }
+
+    /// The widget in `focusable` closest to `from` in the direction of `action`, favouring those straight ahead.
+    fn nearest(&self, from: NodeId, action: UiAction, focusable: &[NodeId]) -> Option<NodeId> {
+        let origin = self.node(from).rect.center();
+        let score = |id: NodeId| {
+            let center = self.node(id).rect.center();
+            let (dx, dy) = (center[0] - origin[0], center[1] - origin[1]);
+            let (ahead, aside) = match action {
+                UiAction::Up => (-dy, dx),
+                UiAction::Down => (dy, dx),
+                UiAction::Left => (-dx, dy),
+                _ => (dx, dy),
+            };
+            if ahead > 0.0 {
+                Some(ahead + aside.abs() * 2.0)
+            } else {
+                None
+            }
+        };
+        focusable.iter()
+                 .filter(|&&id| id != from)
+                 .filter_map(|&id| score(id).map(|score| (id, score)))
+                 .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                 .map(|(id, _)| id)
+    }
+
+    /// `rect` as a sprite showing `region` of `texture`. Sprites are in pixels from the bottom left, y up.
+    fn quad(&self, texture: &Texture, region: [f32; 4], rect: Rect, color: [f32; 4], height: f32) -> Sprite {
+        Sprite {
+            region,
+            origin: [0.0, 0.0],
+            color,
+            ..Sprite::new(texture.clone(), [rect.x, height - rect.y - rect.height], [rect.width, rect.height])
+        }
+    }
+
+    /// `image` stretched over `rect`, nine-sliced if it has slices.
+    fn draw_image(&self, image: &UiImage, rect: Rect, color: [f32; 4], height: f32, sprites: &mut Vec<Sprite>) {
+        let slices = match image.slices {
+            Some(slices) => slices,
+            None => {
+                sprites.push(self.quad(&image.texture, image.region, rect, color, height));
+                return;
+            }
+        };
+
+        // Slices shrink evenly when the rectangle is smaller than they are.
+        let scale_x = (rect.width / slices.horizontal().max(1.0)).min(1.0);
+        let scale_y = (rect.height / slices.vertical().max(1.0)).min(1.0);
+        let xs = [rect.x, rect.x + slices.left * scale_x, rect.x + rect.width - slices.right * scale_x, rect.x + rect.width];
+        let ys = [rect.y, rect.y + slices.top * scale_y, rect.y + rect.height - slices.bottom * scale_y, rect.y + rect.height];
+        let [u0, v0, u1, v1] = image.region;
+        let (du, dv) = ((u1 - u0) / image.size[0], (v1 - v0) / image.size[1]);
+        let us = [u0, u0 + slices.left * du, u1 - slices.right * du, u1];
+        let vs = [v0, v0 + slices.top * dv, v1 - slices.bottom * dv, v1];
+        for row in 0..3 {
+            for column in 0..3 {
+                let piece = Rect { x: xs[column], y: ys[row], width: xs[column + 1] - xs[column], height: ys[row + 1] - ys[row] };
+                if piece.width > 0.0 && piece.height > 0.0 {
+                    sprites.push(self.quad(&image.texture, [us[column], vs[row], us[column + 1], vs[row + 1]], piece, color, height));
+                }
+            }
+        }
+    }
+}
+
+/// Adds the passes drawing the scene's UI over `target` after everything else: its images as sprites, then its
+/// texts with the glyphs of `glyphs`.
+pub fn add_passes(graph: &mut RenderGraphBuilder<Scene>, target: ImageId, glyphs: &GlyphAtlas, device: &Arc<Device>, uploads: &mut UploadManager, namer: &ObjectNamer) {
+    graph.add_pass("ui sprites", SpritePass::ui(device.clone(), target, Load::Keep, Filter::Linear, uploads, namer));
+    text::add_ui_pass(graph, target, glyphs, device, uploads, namer);
+}
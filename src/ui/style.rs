@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::text::{Align, Font};
+use crate::ui::{UiImage, WidgetKind};
+
+// The look every `StyleSheet::default` starts from.
+const DEFAULT_THEME: &str = include_str!("theme.json");
+
+#[derive(Debug)]
+pub enum StyleError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// A selector that isn't `kind.class:state` with every part optional.
+    Selector(String),
+}
+
+impl fmt::Display for StyleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StyleError::Io(e) => write!(f, "{}", e),
+            StyleError::Json(e) => write!(f, "{}", e),
+            StyleError::Selector(selector) => write!(f, "invalid selector {:?}", selector),
+        }
+    }
+}
+
+impl From<io::Error> for StyleError {
+    fn from(e: io::Error) -> StyleError {
+        StyleError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for StyleError {
+    fn from(e: serde_json::Error) -> StyleError {
+        StyleError::Json(e)
+    }
+}
+
+/// Width or height of a node, `"auto"`, `{"px": 120}` or `{"percent": 50}` in style sheets.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Length {
+    /// As large as the content, or stretched by the parent.
+    Auto,
+    Px(f32),
+    /// Of the parent's size inside its padding.
+    Percent(f32),
+}
+
+impl Length {
+    /// The length in pixels when it's known without the content.
+    pub fn resolve(self, parent: f32) -> Option<f32> {
+        match self {
+            Length::Auto => None,
+            Length::Px(px) => Some(px),
+            Length::Percent(percent) => Some(parent * percent / 100.0),
+        }
+    }
+}
+
+/// Axis children are laid out along.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Row,
+    Column,
+}
+
+/// Where children go along the main axis when they don't fill it.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Justify {
+    Start,
+    Center,
+    End,
+    SpaceBetween,
+    SpaceAround,
+}
+
+/// Where children go across the main axis.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlignItems {
+    Start,
+    Center,
+    End,
+    /// As large as the parent, unless they have a size of their own.
+    Stretch,
+}
+
+/// Space on each side, `[top, right, bottom, left]` in style sheets like CSS.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Deserialize)]
+#[serde(from = "[f32; 4]")]
+pub struct Edges {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+impl Edges {
+    pub fn all(value: f32) -> Edges {
+        Edges { top: value, right: value, bottom: value, left: value }
+    }
+
+    pub fn horizontal(&self) -> f32 {
+        self.left + self.right
+    }
+
+    pub fn vertical(&self) -> f32 {
+        self.top + self.bottom
+    }
+}
+
+impl From<[f32; 4]> for Edges {
+    fn from([top, right, bottom, left]: [f32; 4]) -> Edges {
+        Edges { top, right, bottom, left }
+    }
+}
+
+/// How a node is laid out and drawn, after every rule matching it has been applied.
+#[derive(Debug, Clone)]
+pub struct Style {
+    pub direction: Direction,
+    pub justify: Justify,
+    pub align_items: AlignItems,
+    /// Between children.
+    pub gap: f32,
+    pub padding: Edges,
+    pub margin: Edges,
+    pub width: Length,
+    pub height: Length,
+    /// Share of the parent's leftover space along its main axis.
+    pub grow: f32,
+    /// Linear RGBA filling the node, or tinting `background_image`.
+    pub background_color: Option<[f32; 4]>,
+    /// Name of an image added to the style sheet, nine-sliced if it has slices.
+    pub background_image: Option<String>,
+    /// Linear RGBA of a slider's fill and thumb.
+    pub accent: [f32; 4],
+    /// Linear RGBA of text. Inherited like the other text properties.
+    pub color: [f32; 4],
+    /// Name of a font added to the style sheet, the UI's font without one.
+    pub font: Option<String>,
+    pub font_size: f32,
+    pub text_align: Align,
+}
+
+impl Default for Style {
+    fn default() -> Style {
+        Style {
+            direction: Direction::Column,
+            justify: Justify::Start,
+            align_items: AlignItems::Stretch,
+            gap: 0.0,
+            padding: Edges::default(),
+            margin: Edges::default(),
+            width: Length::Auto,
+            height: Length::Auto,
+            grow: 0.0,
+            background_color: None,
+            background_image: None,
+            accent: [1.0, 1.0, 1.0, 1.0],
+            color: [1.0, 1.0, 1.0, 1.0],
+            font: None,
+            font_size: 16.0,
+            text_align: Align::Left,
+        }
+    }
+}
+
+impl Style {
+    /// Default style with the text properties of `parent`.
+    fn inherit(parent: &Style) -> Style {
+        Style {
+            color: parent.color,
+            font: parent.font.clone(),
+            font_size: parent.font_size,
+            text_align: parent.text_align,
+            ..Style::default()
+        }
+    }
+}
+
+/// The properties a rule sets, any of `Style`'s.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StyleProps {
+    pub direction: Option<Direction>,
+    pub justify: Option<Justify>,
+    pub align_items: Option<AlignItems>,
+    pub gap: Option<f32>,
+    pub padding: Option<Edges>,
+    pub margin: Option<Edges>,
+    pub width: Option<Length>,
+    pub height: Option<Length>,
+    pub grow: Option<f32>,
+    pub background_color: Option<[f32; 4]>,
+    pub background_image: Option<String>,
+    pub accent: Option<[f32; 4]>,
+    pub color: Option<[f32; 4]>,
+    pub font: Option<String>,
+    pub font_size: Option<f32>,
+    pub text_align: Option<Align>,
+}
+
+impl StyleProps {
+    fn apply(&self, style: &mut Style) {
+        macro_rules! set {
+            ($($field:ident),*) => {
+                $(if let Some(value) = self.$field {
+                    style.$field = value;
+                })*
+            };
+        }
+        set!(direction, justify, align_items, gap, padding, margin, width, height, grow, accent, color, font_size, text_align);
+        if let Some(color) = self.background_color {
+            style.background_color = Some(color);
+        }
+        if let Some(image) = &self.background_image {
+            style.background_image = Some(image.clone());
+        }
+        if let Some(font) = &self.font {
+            style.font = Some(font.clone());
+        }
+    }
+}
+
+/// What a node is doing, which rules can select with `:state`.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct NodeState {
+    pub hovered: bool,
+    pub focused: bool,
+    pub pressed: bool,
+    pub disabled: bool,
+    /// The selected row of a list.
+    pub selected: bool,
+}
+
+impl NodeState {
+    fn has(&self, state: &str) -> bool {
+        match state {
+            "hovered" => self.hovered,
+            "focused" => self.focused,
+            "pressed" => self.pressed,
+            "disabled" => self.disabled,
+            "selected" => self.selected,
+            _ => false,
+        }
+    }
+}
+
+/// `kind.class.class:state:state`, where every part is optional and `*` matches any kind.
+#[derive(Debug, Clone)]
+struct Selector {
+    kind: Option<WidgetKind>,
+    classes: Vec<String>,
+    states: Vec<String>,
+}
+
+impl Selector {
+    fn parse(selector: &str) -> Result<Selector, StyleError> {
+        let invalid = || StyleError::Selector(selector.to_string());
+        let (rest, states) = match selector.find(':') {
+            Some(index) => (&selector[..index], selector[index + 1..].split(':').map(str::to_string).collect()),
+            None => (selector, vec![]),
+        };
+        let mut parts = rest.split('.');
+        let kind = match parts.next().unwrap_or("") {
+            "" | "*" => None,
+            name => Some(WidgetKind::from_name(name).ok_or_else(invalid)?),
+        };
+        let classes: Vec<String> = parts.map(str::to_string).collect();
+
+        let valid = ["hovered", "focused", "pressed", "disabled", "selected"];
+        if classes.iter().any(String::is_empty) || !states.iter().all(|state: &String| valid.contains(&state.as_str())) {
+            return Err(invalid());
+        }
+        Ok(Selector { kind, classes, states })
+    }
+
+    fn matches(&self, kind: WidgetKind, classes: &[String], state: NodeState) -> bool {
+        !matches!(self.kind, Some(selected) if selected != kind) && self.classes.iter().all(|class| classes.contains(class)) && self.states.iter().all(|name| state.has(name))
+    }
+
+    /// Rules with more classes and states win, then those naming a kind, then later ones.
+    fn specificity(&self) -> usize {
+        (self.classes.len() + self.states.len()) * 2 + self.kind.is_some() as usize
+    }
+}
+
+#[derive(Deserialize)]
+struct RuleDef {
+    selector: String,
+    style: StyleProps,
+}
+
+struct Rule {
+    selector: Selector,
+    props: StyleProps,
+}
+
+/// Rules styling the nodes they select, with the images and fonts they refer to by name.
+///
+/// Style sheets are JSON arrays of `{"selector": "button.primary:hovered", "style": {...}}`, where the style has
+/// any of `Style`'s fields. Every matching rule applies, the most specific last. Text color, font, size and
+/// alignment are inherited from the parent, everything else starts from `Style::default`.
+pub struct StyleSheet {
+    rules: Vec<Rule>,
+    images: HashMap<String, UiImage>,
+    fonts: HashMap<String, Font>,
+}
+
+impl Default for StyleSheet {
+    /// The engine's default look.
+    fn default() -> StyleSheet {
+        StyleSheet::parse(DEFAULT_THEME).expect("invalid default theme")
+    }
+}
+
+impl StyleSheet {
+    pub fn empty() -> StyleSheet {
+        StyleSheet { rules: vec![], images: HashMap::new(), fonts: HashMap::new() }
+    }
+
+    pub fn load(path: &Path) -> Result<StyleSheet, StyleError> {
+        StyleSheet::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(json: &str) -> Result<StyleSheet, StyleError> {
+        let mut sheet = StyleSheet::empty();
+        for rule in serde_json::from_str::<Vec<RuleDef>>(json)? {
+            sheet.rule(&rule.selector, rule.style)?;
+        }
+        Ok(sheet)
+    }
+
+    /// Adds a rule after the others.
+    pub fn rule(&mut self, selector: &str, props: StyleProps) -> Result<&mut StyleSheet, StyleError> {
+        self.rules.push(Rule { selector: Selector::parse(selector)?, props });
+        Ok(self)
+    }
+
+    /// Adds the rules, images and fonts of `other`, its rules after these.
+    pub fn extend(&mut self, other: StyleSheet) -> &mut StyleSheet {
+        self.rules.extend(other.rules);
+        self.images.extend(other.images);
+        self.fonts.extend(other.fonts);
+        self
+    }
+
+    /// Makes `image` available to `background_image`.
+    pub fn add_image(&mut self, name: &str, image: UiImage) -> &mut StyleSheet {
+        self.images.insert(name.to_string(), image);
+        self
+    }
+
+    pub fn add_font(&mut self, name: &str, font: Font) -> &mut StyleSheet {
+        self.fonts.insert(name.to_string(), font);
+        self
+    }
+
+    pub fn image(&self, name: &str) -> Option<&UiImage> {
+        self.images.get(name)
+    }
+
+    pub fn font(&self, name: &str) -> Option<&Font> {
+        self.fonts.get(name)
+    }
+
+    /// The style of a node with `parent`'s style.
+    pub fn compute(&self, kind: WidgetKind, classes: &[String], state: NodeState, parent: Option<&Style>) -> Style {
+        let mut style = parent.map_or_else(Style::default, Style::inherit);
+        let mut matching: Vec<&Rule> = self.rules.iter().filter(|rule| rule.selector.matches(kind, classes, state)).collect();
+        // Stable, so equally specific rules apply in order.
+        matching.sort_by_key(|rule| rule.selector.specificity());
+        for rule in matching {
+            rule.props.apply(&mut style);
+        }
+        style
+    }
+}
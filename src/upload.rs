@@ -0,0 +1,148 @@
+use std::mem;
+use std::slice;
+use std::sync::Arc;
+
+use tracing::debug;
+use vulkano::buffer::cpu_pool::CpuBufferPoolChunk;
+use vulkano::buffer::{BufferSlice, BufferUsage, CpuBufferPool};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer};
+use vulkano::device::Device;
+use vulkano::format::{Format, Pixel};
+use vulkano::image::ImageCreationError;
+use vulkano::image::{ImageCreateFlags, ImageDimensions};
+use vulkano::memory::pool::StdMemoryPool;
+use vulkano::memory::DeviceMemoryAllocError;
+use vulkano::sync;
+use vulkano::sync::GpuFuture;
+
+use crate::allocator::{GpuAllocator, MemoryCategory, PooledBuffer, PooledImage};
+use crate::logging;
+use crate::queues;
+use crate::queues::Queues;
+
+/// Unit the staging ring is allocated in. Keeping every chunk 16-byte aligned satisfies the buffer offset
+/// alignment of buffer to image copies for every uncompressed format.
+#[derive(Copy, Clone, Default)]
+#[repr(C, align(16))]
+struct StagingBlock([u8; 16]);
+
+type Record = Box<dyn FnOnce(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) + Send>;
+
+/// Moves data into device-local buffers and images.
+///
+/// Data is copied into a host-visible staging ring right away and the GPU copies are queued. `flush` records every
+/// queued copy into one command buffer on the transfer queue, so many small uploads cost one submission a frame.
+/// The staging memory is handed back to the ring once the GPU is done with it.
+pub struct UploadManager {
+    device: Arc<Device>,
+    allocator: GpuAllocator,
+    queues: Queues,
+    staging: CpuBufferPool<StagingBlock>,
+    pending: Vec<Record>,
+    pending_bytes: usize,
+    batch: u64,
+}
+
+impl UploadManager {
+    pub fn new(device: Arc<Device>, allocator: &GpuAllocator, queues: &Queues) -> UploadManager {
+        UploadManager {
+            staging: CpuBufferPool::upload(device.clone()),
+            device,
+            allocator: allocator.clone(),
+            queues: queues.clone(),
+            pending: vec![],
+            pending_bytes: 0,
+            batch: 0,
+        }
+    }
+
+    /// The allocator the uploaded resources come from.
+    pub fn allocator(&self) -> &GpuAllocator {
+        &self.allocator
+    }
+
+    /// Creates a device-local buffer with `usage`, accounted to `category`, and queues copying `data` into it. The
+    /// buffer is usable by work that waits on the future returned by the next `flush`.
+    pub fn buffer<T>(&mut self, data: &[T], usage: BufferUsage, category: MemoryCategory) -> Result<Arc<PooledBuffer<[T]>>, DeviceMemoryAllocError>
+        where T: Copy + Send + Sync + 'static
+    {
+        let usage = BufferUsage { transfer_destination: true, ..usage };
+        let buffer = PooledBuffer::array(&self.allocator, category, data.len(), usage, self.queues.families())?;
+        if data.is_empty() {
+            return Ok(buffer);
+        }
+        let staging = self.stage(data)?;
+
+        let len = data.len();
+        let destination = buffer.clone();
+        self.pending.push(Box::new(move |builder| {
+            // The chunk was allocated from `data` of exactly this type, padded up to whole blocks.
+            let source = unsafe { BufferSlice::from_typed_buffer_access(staging).reinterpret::<[T]>() }.slice(0..len).unwrap();
+            builder.copy_buffer(source, destination).expect("failed to record staging buffer copy");
+        }));
+
+        Ok(buffer)
+    }
+
+    /// Creates a sampled, single mip level image and queues copying `pixels` into it, like `buffer`.
+    pub fn image<Px>(&mut self, pixels: &[Px], dimensions: ImageDimensions, format: Format) -> Result<Arc<PooledImage>, ImageCreationError>
+        where Px: Pixel + Copy + Send + Sync + 'static
+    {
+        let image = PooledImage::texture(&self.allocator, dimensions, format, 1, ImageCreateFlags::none(), self.queues.families())?;
+        if pixels.is_empty() {
+            return Ok(image);
+        }
+        let staging = self.stage(pixels)?;
+
+        let len = pixels.len();
+        let destination = image.clone();
+        self.pending.push(Box::new(move |builder| {
+            let source = unsafe { BufferSlice::from_typed_buffer_access(staging).reinterpret::<[Px]>() }.slice(0..len).unwrap();
+            builder.copy_buffer_to_image(source, destination).expect("failed to record staging image copy");
+        }));
+
+        Ok(image)
+    }
+
+    /// Submits every queued copy. Work that uses the uploaded resources must wait on the returned future,
+    /// which is ready to be joined into a submission on the graphics queue.
+    pub fn flush(&mut self) -> Option<Box<dyn GpuFuture>> {
+        self.submit().map(|future| queues::handoff(future, &self.queues.graphics))
+    }
+
+    fn submit(&mut self) -> Option<Box<dyn GpuFuture>> {
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        let _span = tracing::debug_span!(target: logging::ASSETS, "upload", batch = self.batch).entered();
+        debug!(target: logging::ASSETS, copies = self.pending.len(), bytes = self.pending_bytes, "flushing uploads");
+
+        let mut builder = AutoCommandBufferBuilder::primary(self.device.clone(), self.queues.transfer.family(), CommandBufferUsage::OneTimeSubmit).unwrap();
+        for record in self.pending.drain(..) {
+            record(&mut builder);
+        }
+        let command_buffer = builder.build().unwrap();
+
+        let future = sync::now(self.device.clone()).then_execute(self.queues.transfer.clone(), command_buffer)
+                                                   .expect("failed to submit uploads");
+
+        self.pending_bytes = 0;
+        self.batch += 1;
+
+        Some(future.boxed())
+    }
+
+    fn stage<T: Copy>(&mut self, data: &[T]) -> Result<CpuBufferPoolChunk<StagingBlock, Arc<StdMemoryPool>>, DeviceMemoryAllocError> {
+        let bytes = unsafe { slice::from_raw_parts(data.as_ptr() as *const u8, mem::size_of_val(data)) };
+        self.pending_bytes += bytes.len();
+
+        let blocks = bytes.chunks(mem::size_of::<StagingBlock>()).map(|chunk| {
+            let mut block = StagingBlock::default();
+            block.0[..chunk.len()].copy_from_slice(chunk);
+            block
+        });
+
+        self.staging.chunk(blocks.collect::<Vec<_>>())
+    }
+}